@@ -0,0 +1,249 @@
+//! OAuth 2.0 authorization code grant with PKCE (RFC 7636), redirected to a
+//! loopback listener (RFC 8252).
+//!
+//! The browser is sent to the provider's sign-in page; once the user approves,
+//! the provider redirects it to `http://127.0.0.1:<port>/callback`, where a
+//! listener bound for the duration of the login picks up the code and trades
+//! it, with the PKCE verifier, for a token.
+
+use std::time::Duration;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use reqwest::{Client, Url};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::debug;
+
+use super::device_flow::{ProviderToken, TokenResponse};
+use super::providers::ProviderConfig;
+use crate::output::Reporter;
+
+const CALLBACK_PATH: &str = "/callback";
+
+/// How long the user has to finish signing in.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Largest redirect request read; the interesting part is the first line.
+const MAX_REQUEST_SIZE: usize = 16 * 1024;
+
+/// Errors raised during the browser flow.
+#[derive(Debug, Error)]
+pub enum BrowserFlowError {
+    #[error("failed to listen for the sign-in redirect on localhost")]
+    Listen(#[source] std::io::Error),
+
+    #[error("the sign-in was not finished within {} minutes; run `desk auth login` again", LOGIN_TIMEOUT.as_secs() / 60)]
+    Expired,
+
+    #[error("authorization was denied")]
+    Denied,
+
+    #[error("the sign-in redirect did not match this login; run `desk auth login` again")]
+    StateMismatch,
+
+    #[error("failed to generate a login secret")]
+    Random(#[from] getrandom::Error),
+
+    #[error("{provider} rejected the request: {message}")]
+    Provider { provider: String, message: String },
+
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+/// What the redirect to the callback carried.
+enum Callback {
+    Code(String),
+    Error {
+        error: String,
+        description: Option<String>,
+    },
+}
+
+/// Signs in through the browser, returning the provider's token.
+pub async fn authorize(
+    client: &Client,
+    config: &ProviderConfig,
+    reporter: &mut dyn Reporter,
+) -> Result<ProviderToken, BrowserFlowError> {
+    let listener = TcpListener::bind(("127.0.0.1", 0))
+        .await
+        .map_err(BrowserFlowError::Listen)?;
+    let port = listener
+        .local_addr()
+        .map_err(BrowserFlowError::Listen)?
+        .port();
+    let redirect_uri = format!("http://127.0.0.1:{port}{CALLBACK_PATH}");
+
+    let verifier = random_token(32)?;
+    let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+    let state = random_token(16)?;
+    let scope = config.scopes.join(" ");
+    let url = Url::parse_with_params(
+        &config.auth_url,
+        &[
+            ("response_type", "code"),
+            ("client_id", &config.client_id),
+            ("redirect_uri", &redirect_uri),
+            ("scope", &scope),
+            ("state", &state),
+            ("code_challenge", &challenge),
+            ("code_challenge_method", "S256"),
+        ],
+    )
+    .map_err(|e| BrowserFlowError::Provider {
+        provider: config.provider.to_string(),
+        message: format!("invalid authorization URL {}: {e}", config.auth_url),
+    })?;
+
+    // Instructions go to stderr so they never mix with json-lines progress.
+    if let Err(e) = open::that(url.as_str()) {
+        debug!("failed to open a browser: {e}");
+        eprintln!("Open this page to sign in:\n{url}");
+    } else {
+        eprintln!("Continue signing in in your browser, or open:\n{url}");
+    }
+    reporter.progress(1, None, "waiting for the browser");
+
+    let callback = tokio::time::timeout(LOGIN_TIMEOUT, wait_for_callback(&listener, &state))
+        .await
+        .map_err(|_| BrowserFlowError::Expired)??;
+    let code = match callback {
+        Callback::Code(code) => code,
+        Callback::Error { error, .. } if error == "access_denied" => {
+            return Err(BrowserFlowError::Denied)
+        },
+        Callback::Error { error, description } => {
+            return Err(BrowserFlowError::Provider {
+                provider: config.provider.to_string(),
+                message: description.unwrap_or(error),
+            })
+        },
+    };
+
+    let response = client
+        .post(&config.token_url)
+        .header("Accept", "application/json")
+        .form(&[
+            ("client_id", config.client_id.as_str()),
+            ("grant_type", "authorization_code"),
+            ("code", &code),
+            ("redirect_uri", &redirect_uri),
+            ("code_verifier", &verifier),
+        ])
+        .send()
+        .await?;
+    match response.json::<TokenResponse>().await? {
+        TokenResponse::Token(token) => Ok(token),
+        TokenResponse::Error {
+            error,
+            error_description,
+        } => Err(BrowserFlowError::Provider {
+            provider: config.provider.to_string(),
+            message: error_description.unwrap_or(error),
+        }),
+    }
+}
+
+/// Answers requests to the listener until the provider's redirect arrives.
+async fn wait_for_callback(
+    listener: &TcpListener,
+    state: &str,
+) -> Result<Callback, BrowserFlowError> {
+    loop {
+        let (mut stream, _) = listener.accept().await.map_err(BrowserFlowError::Listen)?;
+        let Some(target) = read_request_target(&mut stream).await else {
+            continue;
+        };
+        let Some(params) = callback_params(&target) else {
+            // Browsers also ask for things like /favicon.ico.
+            respond(&mut stream, "404 Not Found", "Not found").await;
+            continue;
+        };
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+
+        if param("state").as_deref() != Some(state) {
+            respond(
+                &mut stream,
+                "400 Bad Request",
+                "This sign-in does not belong to the running desk auth login.",
+            )
+            .await;
+            return Err(BrowserFlowError::StateMismatch);
+        }
+        if let Some(code) = param("code") {
+            respond(
+                &mut stream,
+                "200 OK",
+                "Authorized. You can close this tab and return to the terminal.",
+            )
+            .await;
+            return Ok(Callback::Code(code));
+        }
+        respond(
+            &mut stream,
+            "200 OK",
+            "Sign-in failed. Check the terminal for details.",
+        )
+        .await;
+        return Ok(Callback::Error {
+            error: param("error").unwrap_or_else(|| "no authorization code".to_string()),
+            description: param("error_description"),
+        });
+    }
+}
+
+/// Reads an HTTP request's head and returns its target, e.g.
+/// `/callback?code=...`.
+async fn read_request_target(stream: &mut TcpStream) -> Option<String> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+        let read = stream.read(&mut buffer).await.ok()?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    let head = String::from_utf8_lossy(&request);
+    let mut parts = head.lines().next()?.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => Some(target.to_string()),
+        _ => None,
+    }
+}
+
+/// The query parameters of `target` if it is the callback.
+fn callback_params(target: &str) -> Option<Vec<(String, String)>> {
+    let url = Url::parse("http://127.0.0.1").ok()?.join(target).ok()?;
+    (url.path() == CALLBACK_PATH).then(|| url.query_pairs().into_owned().collect())
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, message: &str) {
+    let body = format!(
+        "<!doctype html><title>desk</title><p style=\"font-family: sans-serif\">{message}</p>"
+    );
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()).await {
+        debug!("failed to answer the browser: {e}");
+    }
+    let _ = stream.shutdown().await;
+}
+
+/// `bytes` random bytes, base64url-encoded as PKCE and OAuth `state` allow.
+fn random_token(bytes: usize) -> Result<String, getrandom::Error> {
+    let mut buffer = vec![0; bytes];
+    getrandom::getrandom(&mut buffer)?;
+    Ok(URL_SAFE_NO_PAD.encode(buffer))
+}
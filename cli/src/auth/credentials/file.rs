@@ -0,0 +1,160 @@
+//! Credentials in an encrypted file, for machines without a usable keyring.
+//!
+//! The file is encrypted with age, under `DESK_CREDENTIALS_PASSPHRASE` when it
+//! is set and otherwise under a machine key: a random key kept next to the
+//! file, mixed with the machine ID where the platform has one. With a machine
+//! ID, a copy of the config directory is useless elsewhere; without one, the
+//! key in the copy decrypts the file anywhere, so set the passphrase on such
+//! machines. The machine key protects against the file leaking on its own,
+//! not against someone who can read the user's files on this machine.
+
+use std::path::{Path, PathBuf};
+
+use age::secrecy::SecretString;
+use sha2::{Digest, Sha256};
+
+use super::{write_private, CredentialBackend, CredentialError, Credentials};
+
+/// Environment variable holding the passphrase for the credentials file.
+pub const PASSPHRASE_ENV: &str = "DESK_CREDENTIALS_PASSPHRASE";
+
+const CREDENTIALS_FILE: &str = "credentials.age";
+const MACHINE_KEY_FILE: &str = "credentials.key";
+
+/// Where Linux distributions keep the machine ID.
+const MACHINE_ID_FILES: &[&str] = &["/etc/machine-id", "/var/lib/dbus/machine-id"];
+
+/// scrypt work factor for the machine key. The key is random, so stretching
+/// it would only slow down every command.
+const MACHINE_KEY_WORK_FACTOR: u8 = 10;
+
+/// Keeps [`Credentials`] in `credentials.age` in a directory.
+#[derive(Debug)]
+pub struct FileBackend {
+    dir: PathBuf,
+}
+
+impl FileBackend {
+    pub const fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(CREDENTIALS_FILE)
+    }
+
+    /// Whether credentials were saved to the file.
+    pub fn exists(&self) -> bool {
+        self.path().is_file()
+    }
+
+    /// The random half of the machine key, if it was created.
+    fn load_machine_secret(&self) -> Result<Option<String>, CredentialError> {
+        let path = self.dir.join(MACHINE_KEY_FILE);
+        match std::fs::read_to_string(&path) {
+            Ok(secret) => Ok(Some(secret.trim().to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(io_error(&path, source)),
+        }
+    }
+
+    fn create_machine_secret(&self) -> Result<String, CredentialError> {
+        let path = self.dir.join(MACHINE_KEY_FILE);
+        let mut bytes = [0; 32];
+        getrandom::getrandom(&mut bytes)?;
+        let secret = hex::encode(bytes);
+        write_private(&path, secret.as_bytes()).map_err(|source| io_error(&path, source))?;
+        Ok(secret)
+    }
+}
+
+impl CredentialBackend for FileBackend {
+    fn location(&self) -> String {
+        self.path().display().to_string()
+    }
+
+    fn load(&self) -> Result<Option<Credentials>, CredentialError> {
+        let path = self.path();
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => return Err(io_error(&path, source)),
+        };
+        let key = match passphrase() {
+            Some(passphrase) => passphrase,
+            None => match self.load_machine_secret()? {
+                Some(secret) => machine_key(&secret),
+                None => return Err(CredentialError::Decrypt { path, source: None }),
+            },
+        };
+        let json = age::decrypt(&age::scrypt::Identity::new(key), &bytes).map_err(|source| {
+            CredentialError::Decrypt {
+                path: path.clone(),
+                source: Some(source),
+            }
+        })?;
+        serde_json::from_slice(&json)
+            .map(Some)
+            .map_err(CredentialError::Corrupt)
+    }
+
+    fn save(&self, credentials: &Credentials) -> Result<(), CredentialError> {
+        let json = serde_json::to_vec(credentials).map_err(CredentialError::Corrupt)?;
+        let recipient = if let Some(passphrase) = passphrase() {
+            age::scrypt::Recipient::new(passphrase)
+        } else {
+            let secret = match self.load_machine_secret()? {
+                Some(secret) => secret,
+                None => self.create_machine_secret()?,
+            };
+            let mut recipient = age::scrypt::Recipient::new(machine_key(&secret));
+            recipient.set_work_factor(MACHINE_KEY_WORK_FACTOR);
+            recipient
+        };
+        let encrypted = age::encrypt(&recipient, &json)?;
+        let path = self.path();
+        write_private(&path, &encrypted).map_err(|source| io_error(&path, source))
+    }
+
+    fn clear(&self) -> Result<(), CredentialError> {
+        let path = self.path();
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(source) => Err(io_error(&path, source)),
+        }
+    }
+}
+
+fn passphrase() -> Option<SecretString> {
+    std::env::var(PASSPHRASE_ENV)
+        .ok()
+        .filter(|p| !p.is_empty())
+        .map(Into::into)
+}
+
+/// Binds `secret` to this machine.
+fn machine_key(secret: &str) -> SecretString {
+    let mut hasher = Sha256::new();
+    hasher.update(secret.as_bytes());
+    if let Some(machine_id) = machine_id() {
+        hasher.update(machine_id.as_bytes());
+    }
+    hex::encode(hasher.finalize()).into()
+}
+
+fn machine_id() -> Option<String> {
+    MACHINE_ID_FILES.iter().find_map(|path| {
+        std::fs::read_to_string(path)
+            .ok()
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+    })
+}
+
+fn io_error(path: &Path, source: std::io::Error) -> CredentialError {
+    CredentialError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
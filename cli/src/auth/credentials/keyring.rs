@@ -0,0 +1,58 @@
+//! Credentials in the platform keyring.
+
+use super::{CredentialBackend, CredentialError, Credentials};
+use crate::core::paths;
+
+const KEYRING_SERVICE: &str = "desk-cli";
+const KEYRING_USER: &str = "api";
+
+/// Keeps [`Credentials`] in the macOS Keychain, Windows Credential Manager,
+/// or the Linux kernel keyring.
+#[derive(Debug)]
+pub struct KeyringBackend {
+    entry: keyring::Entry,
+}
+
+impl KeyringBackend {
+    pub fn new() -> Result<Self, CredentialError> {
+        Ok(Self {
+            entry: keyring::Entry::new(KEYRING_SERVICE, &user())?,
+        })
+    }
+}
+
+/// Each profile signs in separately.
+fn user() -> String {
+    paths::profile().map_or_else(
+        || KEYRING_USER.to_string(),
+        |profile| format!("{KEYRING_USER}:{profile}"),
+    )
+}
+
+impl CredentialBackend for KeyringBackend {
+    fn location(&self) -> String {
+        "the system keyring".to_string()
+    }
+
+    fn load(&self) -> Result<Option<Credentials>, CredentialError> {
+        match self.entry.get_password() {
+            Ok(json) => serde_json::from_str(&json)
+                .map(Some)
+                .map_err(CredentialError::Corrupt),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&self, credentials: &Credentials) -> Result<(), CredentialError> {
+        let json = serde_json::to_string(credentials).map_err(CredentialError::Corrupt)?;
+        Ok(self.entry.set_password(&json)?)
+    }
+
+    fn clear(&self) -> Result<(), CredentialError> {
+        match self.entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,357 @@
+//! Desk API credentials.
+//!
+//! `desk auth login` keeps them in a [`CredentialBackend`] chosen by
+//! `[auth] storage`: the system keyring, or an [encrypted file](file) in the
+//! config directory for machines without a usable keyring. By default the
+//! keyring is tried first and the file taken when it fails.
+//!
+//! Machines that cannot sign in at all, such as CI runners, can use a plain
+//! token instead: from `DESK_API_TOKEN`, or saved by `desk auth token` to a
+//! file only the user can read. Tokens take precedence over stored
+//! credentials, whose storage is then never touched.
+
+pub mod file;
+pub mod keyring;
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use self::file::FileBackend;
+use self::keyring::KeyringBackend;
+use super::providers::AuthProvider;
+use crate::core::config::{AuthConfig, CredentialStorage};
+use crate::core::paths;
+
+/// Environment variable holding a desk API token.
+pub const TOKEN_ENV: &str = "DESK_API_TOKEN";
+
+/// File in the config directory holding the token saved by `desk auth token`.
+const TOKEN_FILE: &str = "token";
+
+/// The `[auth] storage` setting, recorded by [`configure`].
+static CONFIGURED_STORAGE: OnceLock<CredentialStorage> = OnceLock::new();
+
+/// Applies the `[auth]` settings. Called once, right after the configuration
+/// is loaded.
+pub fn configure(auth: &AuthConfig) {
+    // Later calls keep the first setting; the process only ever has one.
+    let _ = CONFIGURED_STORAGE.set(auth.storage);
+}
+
+/// Errors raised while reading or writing credentials.
+#[derive(Debug, Error)]
+pub enum CredentialError {
+    #[error("cannot access the system keyring")]
+    Keyring(#[from] ::keyring::Error),
+
+    #[error("stored credentials are unreadable; run `desk auth login` again")]
+    Corrupt(#[source] serde_json::Error),
+
+    #[error("cannot locate the desk config directory")]
+    ConfigDir(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("cannot decrypt {}; set DESK_CREDENTIALS_PASSPHRASE to the passphrase it was saved with, or run `desk auth login` again", path.display())]
+    Decrypt {
+        path: PathBuf,
+        #[source]
+        source: Option<age::DecryptError>,
+    },
+
+    #[error("failed to encrypt credentials")]
+    Encrypt(#[from] age::EncryptError),
+
+    #[error("failed to generate a credentials key")]
+    Random(#[from] getrandom::Error),
+
+    #[error("failed to access {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// Where [`Credentials`] came from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialSource {
+    /// Saved by `desk auth login` in a [`CredentialBackend`].
+    #[default]
+    Login,
+    /// [`TOKEN_ENV`].
+    Environment,
+    /// Saved by `desk auth token`.
+    TokenFile,
+}
+
+impl CredentialSource {
+    /// Names the credentials in messages.
+    pub const fn describe(self) -> &'static str {
+        match self {
+            Self::Login => "the stored credentials",
+            Self::Environment => "the token in DESK_API_TOKEN",
+            Self::TokenFile => "the token saved by `desk auth token`",
+        }
+    }
+}
+
+/// Tokens issued by the desk API after signing in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credentials {
+    pub access_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<DateTime<Utc>>,
+    pub provider: AuthProvider,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    #[serde(skip)]
+    pub source: CredentialSource,
+}
+
+impl Credentials {
+    /// A bare token from `source`, which neither expires nor refreshes as
+    /// far as desk knows.
+    pub fn token(access_token: String, source: CredentialSource) -> Self {
+        Self {
+            access_token,
+            refresh_token: None,
+            expires_at: None,
+            // Only used to sign in again, which tokens never do.
+            provider: AuthProvider::default(),
+            user_id: None,
+            source,
+        }
+    }
+
+    /// Whether the access token has passed its expiry time.
+    pub fn is_expired(&self) -> bool {
+        self.expires_within(chrono::Duration::zero())
+    }
+
+    /// Whether the access token expires within `window` from now.
+    pub fn expires_within(&self, window: chrono::Duration) -> bool {
+        self.expires_at.is_some_and(|at| at <= Utc::now() + window)
+    }
+}
+
+/// Somewhere to keep the credentials from `desk auth login`.
+pub trait CredentialBackend: std::fmt::Debug + Send + Sync {
+    /// Describes where the credentials are kept, for messages.
+    fn location(&self) -> String;
+
+    /// Returns the stored credentials, or `None` when not logged in.
+    fn load(&self) -> Result<Option<Credentials>, CredentialError>;
+
+    fn save(&self, credentials: &Credentials) -> Result<(), CredentialError>;
+
+    /// Removes stored credentials; succeeds when there were none.
+    fn clear(&self) -> Result<(), CredentialError>;
+}
+
+/// Stores [`Credentials`] in the backend selected by `[auth] storage`.
+#[derive(Debug)]
+pub struct CredentialStore {
+    backend: Box<dyn CredentialBackend>,
+}
+
+impl CredentialStore {
+    pub fn new() -> Result<Self, CredentialError> {
+        let file = FileBackend::new(credentials_dir()?);
+        let backend: Box<dyn CredentialBackend> =
+            match CONFIGURED_STORAGE.get().copied().unwrap_or_default() {
+                CredentialStorage::Keyring => Box::new(KeyringBackend::new()?),
+                CredentialStorage::File => Box::new(file),
+                CredentialStorage::Auto => Box::new(AutoBackend {
+                    keyring: OnceLock::new(),
+                    file,
+                }),
+            };
+        Ok(Self { backend })
+    }
+
+    /// Describes where the credentials are kept.
+    pub fn location(&self) -> String {
+        self.backend.location()
+    }
+
+    /// Returns the stored credentials, or `None` when not logged in.
+    pub fn load(&self) -> Result<Option<Credentials>, CredentialError> {
+        self.backend.load()
+    }
+
+    pub fn save(&self, credentials: &Credentials) -> Result<(), CredentialError> {
+        self.backend.save(credentials)
+    }
+
+    /// Removes stored credentials; succeeds when there were none.
+    pub fn clear(&self) -> Result<(), CredentialError> {
+        self.backend.clear()
+    }
+}
+
+/// The keyring, falling back to the encrypted file once it fails. The file
+/// wins while it exists, so credentials are always read from where they were
+/// last saved.
+#[derive(Debug)]
+struct AutoBackend {
+    /// Opened on first use, as it is not needed while the file wins.
+    keyring: OnceLock<Option<KeyringBackend>>,
+    file: FileBackend,
+}
+
+impl AutoBackend {
+    fn keyring(&self) -> Option<&KeyringBackend> {
+        self.keyring
+            .get_or_init(|| {
+                KeyringBackend::new()
+                    .map_err(|e| debug!("system keyring unavailable: {e}"))
+                    .ok()
+            })
+            .as_ref()
+    }
+
+    /// The keyring, unless the file wins.
+    fn active_keyring(&self) -> Option<&KeyringBackend> {
+        if self.file.exists() {
+            None
+        } else {
+            self.keyring()
+        }
+    }
+}
+
+impl CredentialBackend for AutoBackend {
+    fn location(&self) -> String {
+        self.active_keyring()
+            .map_or_else(|| self.file.location(), CredentialBackend::location)
+    }
+
+    fn load(&self) -> Result<Option<Credentials>, CredentialError> {
+        let Some(keyring) = self.active_keyring() else {
+            return self.file.load();
+        };
+        keyring.load().or_else(|e| {
+            debug!("system keyring unavailable: {e}");
+            Ok(None)
+        })
+    }
+
+    fn save(&self, credentials: &Credentials) -> Result<(), CredentialError> {
+        if let Some(keyring) = self.active_keyring() {
+            match keyring.save(credentials) {
+                Ok(()) => return Ok(()),
+                Err(e) => warn!(
+                    "cannot use the system keyring ({e}); saving credentials to {} instead",
+                    self.file.location()
+                ),
+            }
+        }
+        self.file.save(credentials)
+    }
+
+    fn clear(&self) -> Result<(), CredentialError> {
+        if let Some(keyring) = self.keyring() {
+            if let Err(e) = keyring.clear() {
+                debug!("system keyring unavailable: {e}");
+            }
+        }
+        self.file.clear()
+    }
+}
+
+/// Finds the credentials to use: [`TOKEN_ENV`], then a token saved by
+/// `desk auth token`, then the keyring.
+pub fn load() -> Result<Option<Credentials>, CredentialError> {
+    if let Some(token) = std::env::var(TOKEN_ENV)
+        .ok()
+        .filter(|t| !t.trim().is_empty())
+    {
+        return Ok(Some(Credentials::token(
+            token.trim().to_string(),
+            CredentialSource::Environment,
+        )));
+    }
+    if let Some(token) = TokenFile::new()?.load()? {
+        return Ok(Some(Credentials::token(token, CredentialSource::TokenFile)));
+    }
+    CredentialStore::new()?.load()
+}
+
+/// The token saved by `desk auth token`, readable only by the user.
+#[derive(Debug)]
+pub struct TokenFile {
+    path: PathBuf,
+}
+
+impl TokenFile {
+    pub fn new() -> Result<Self, CredentialError> {
+        Ok(Self {
+            path: credentials_dir()?.join(TOKEN_FILE),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the saved token, if any.
+    pub fn load(&self) -> Result<Option<String>, CredentialError> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(token) => Ok(Some(token.trim().to_string()).filter(|t| !t.is_empty())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(self.io_error(source)),
+        }
+    }
+
+    pub fn save(&self, token: &str) -> Result<(), CredentialError> {
+        write_private(&self.path, format!("{token}\n").as_bytes())
+            .map_err(|source| self.io_error(source))
+    }
+
+    /// Removes the saved token; succeeds when there was none.
+    pub fn clear(&self) -> Result<(), CredentialError> {
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(source) => Err(self.io_error(source)),
+        }
+    }
+
+    fn io_error(&self, source: std::io::Error) -> CredentialError {
+        CredentialError::Io {
+            path: self.path.clone(),
+            source,
+        }
+    }
+}
+
+fn credentials_dir() -> Result<PathBuf, CredentialError> {
+    paths::credentials_dir().map_err(|e| CredentialError::ConfigDir(e.into()))
+}
+
+/// Replaces `path` with `contents`, readable only by the user.
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(&tmp)?.write_all(contents)?;
+    std::fs::rename(&tmp, path)
+}
@@ -0,0 +1,139 @@
+//! OAuth 2.0 device authorization grant (RFC 8628).
+
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::providers::ProviderConfig;
+use crate::output::Reporter;
+
+const DEVICE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Errors raised during the device flow.
+#[derive(Debug, Error)]
+pub enum DeviceFlowError {
+    #[error("the login code expired before it was entered; run `desk auth login` again")]
+    Expired,
+
+    #[error("authorization was denied")]
+    Denied,
+
+    #[error("{provider} rejected the request: {message}")]
+    Provider { provider: String, message: String },
+
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+/// The code the user has to enter, and where.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    #[serde(default)]
+    pub verification_uri_complete: Option<String>,
+    pub expires_in: u64,
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+const fn default_interval() -> u64 {
+    5
+}
+
+/// Token issued by the provider once the user approved the login.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderToken {
+    pub access_token: String,
+}
+
+/// A provider's token endpoint answer, which may be an error with a success
+/// status.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(super) enum TokenResponse {
+    Token(ProviderToken),
+    Error {
+        error: String,
+        #[serde(default)]
+        error_description: Option<String>,
+    },
+}
+
+/// Asks the provider for a device and user code.
+pub async fn request_device_code(
+    client: &Client,
+    config: &ProviderConfig,
+) -> Result<DeviceAuthorization, DeviceFlowError> {
+    let scope = config.scopes.join(" ");
+    let response = client
+        .post(&config.device_url)
+        .header("Accept", "application/json")
+        .form(&[("client_id", config.client_id.as_str()), ("scope", &scope)])
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(DeviceFlowError::Provider {
+            provider: config.provider.to_string(),
+            message: response.text().await.unwrap_or_default(),
+        });
+    }
+    Ok(response.json().await?)
+}
+
+/// Polls the provider until the user approves or denies the login, or the
+/// code expires.
+pub async fn poll_for_token(
+    client: &Client,
+    config: &ProviderConfig,
+    authorization: &DeviceAuthorization,
+    reporter: &mut dyn Reporter,
+) -> Result<ProviderToken, DeviceFlowError> {
+    let mut interval = Duration::from_secs(authorization.interval);
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(authorization.expires_in);
+    let mut attempt = 0;
+
+    loop {
+        tokio::time::sleep(interval).await;
+        if tokio::time::Instant::now() >= deadline {
+            return Err(DeviceFlowError::Expired);
+        }
+        attempt += 1;
+        reporter.progress(attempt, None, "waiting for authorization");
+
+        let response = client
+            .post(&config.token_url)
+            .header("Accept", "application/json")
+            .form(&[
+                ("client_id", config.client_id.as_str()),
+                ("device_code", &authorization.device_code),
+                ("grant_type", DEVICE_GRANT_TYPE),
+            ])
+            .send()
+            .await?;
+
+        match response.json::<TokenResponse>().await? {
+            TokenResponse::Token(token) => return Ok(token),
+            TokenResponse::Error {
+                error,
+                error_description,
+            } => match error.as_str() {
+                "authorization_pending" => {},
+                "slow_down" => interval += Duration::from_secs(5),
+                "expired_token" => return Err(DeviceFlowError::Expired),
+                // Microsoft says `authorization_declined` instead.
+                "access_denied" | "authorization_declined" => return Err(DeviceFlowError::Denied),
+                _ => {
+                    return Err(DeviceFlowError::Provider {
+                        provider: config.provider.to_string(),
+                        message: error_description.unwrap_or(error),
+                    })
+                },
+            },
+        }
+    }
+}
@@ -0,0 +1,11 @@
+//! Signing in to the desk API.
+//!
+//! `desk auth login` runs an OAuth device flow (or, with `--flow browser`, an
+//! authorization code flow) against a sign-in provider, exchanges the
+//! provider's token for desk API credentials, and keeps those in the system
+//! keyring.
+
+pub mod browser_flow;
+pub mod credentials;
+pub mod device_flow;
+pub mod providers;
@@ -0,0 +1,40 @@
+//! Sign-in with GitHub or a GitHub Enterprise Server instance.
+
+use super::{AuthProvider, ProviderConfig};
+use crate::core::config::ProviderSettings;
+
+/// OAuth app registered for desk.
+const DEFAULT_CLIENT_ID: &str = "Ov23liDeskCli0000001";
+
+const GITHUB_URL: &str = "https://github.com";
+
+/// Builds the GitHub provider configuration, against the Enterprise Server
+/// in `settings` if there is one.
+pub fn get_config(settings: &ProviderSettings) -> ProviderConfig {
+    let base_url = settings
+        .base_url
+        .as_deref()
+        .map(|url| url.trim().trim_end_matches('/'))
+        .filter(|url| !url.is_empty())
+        .unwrap_or(GITHUB_URL);
+    // Enterprise Server serves its REST API under the instance itself.
+    let api_url = if base_url == GITHUB_URL {
+        "https://api.github.com".to_string()
+    } else {
+        format!("{base_url}/api/v3")
+    };
+    let client_id = settings
+        .client_id
+        .clone()
+        .unwrap_or_else(|| DEFAULT_CLIENT_ID.to_string());
+    ProviderConfig {
+        provider: AuthProvider::GitHub,
+        auth_url: format!("{base_url}/login/oauth/authorize"),
+        token_url: format!("{base_url}/login/oauth/access_token"),
+        device_url: format!("{base_url}/login/device/code"),
+        revoke_url: Some(format!("{api_url}/applications/{client_id}/token")),
+        client_id,
+        scopes: vec!["read:user".to_string(), "user:email".to_string()],
+        tenant: None,
+    }
+}
@@ -0,0 +1,40 @@
+//! Sign-in with Microsoft Entra ID work and school accounts.
+
+use super::{AuthProvider, ProviderConfig};
+use crate::core::config::ProviderSettings;
+
+/// Multi-tenant app registered for desk.
+const DEFAULT_CLIENT_ID: &str = "8d5a2c3e-0e7b-4f4a-9c1d-6b2f0d3e5a71";
+
+/// Accepts accounts from any organization's directory, but not personal
+/// Microsoft accounts.
+const DEFAULT_TENANT: &str = "organizations";
+
+/// Builds the Microsoft provider configuration, using the tenant from
+/// `settings` so sign-ins are limited to one organization's directory.
+pub fn get_config(settings: &ProviderSettings) -> ProviderConfig {
+    let tenant = settings
+        .tenant
+        .as_deref()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .unwrap_or(DEFAULT_TENANT);
+    let endpoint = format!("https://login.microsoftonline.com/{tenant}/oauth2/v2.0");
+    ProviderConfig {
+        provider: AuthProvider::Microsoft,
+        client_id: settings
+            .client_id
+            .clone()
+            .unwrap_or_else(|| DEFAULT_CLIENT_ID.to_string()),
+        auth_url: format!("{endpoint}/authorize"),
+        token_url: format!("{endpoint}/token"),
+        device_url: format!("{endpoint}/devicecode"),
+        revoke_url: None,
+        scopes: vec![
+            "openid".to_string(),
+            "profile".to_string(),
+            "email".to_string(),
+        ],
+        tenant: Some(tenant.to_string()),
+    }
+}
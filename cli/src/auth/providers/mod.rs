@@ -0,0 +1,62 @@
+//! OAuth providers users can sign in with.
+
+pub mod github;
+pub mod microsoft;
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::config::AuthConfig;
+
+/// A supported sign-in provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthProvider {
+    #[default]
+    #[value(name = "github")]
+    #[serde(rename = "github")]
+    GitHub,
+    Microsoft,
+}
+
+impl AuthProvider {
+    /// Identifier used in config files and API requests.
+    pub const fn id(self) -> &'static str {
+        match self {
+            Self::GitHub => "github",
+            Self::Microsoft => "microsoft",
+        }
+    }
+}
+
+impl fmt::Display for AuthProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::GitHub => "GitHub",
+            Self::Microsoft => "Microsoft",
+        })
+    }
+}
+
+/// Endpoints and client settings for one provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderConfig {
+    pub provider: AuthProvider,
+    pub client_id: String,
+    pub auth_url: String,
+    pub token_url: String,
+    pub device_url: String,
+    pub revoke_url: Option<String>,
+    pub scopes: Vec<String>,
+    /// Directory the sign-in is limited to, for providers that have them.
+    pub tenant: Option<String>,
+}
+
+/// Builds the configuration for `provider` from the `[auth]` section.
+pub fn get_config(provider: AuthProvider, auth: &AuthConfig) -> ProviderConfig {
+    match provider {
+        AuthProvider::GitHub => github::get_config(&auth.providers.github),
+        AuthProvider::Microsoft => microsoft::get_config(&auth.providers.microsoft),
+    }
+}
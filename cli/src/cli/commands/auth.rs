@@ -0,0 +1,436 @@
+//! `desk auth`: sign in to the desk API.
+
+use std::io::{BufRead, IsTerminal, Write};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::Subcommand;
+use qrcode::render::unicode::Dense1x2;
+use qrcode::QrCode;
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::auth::browser_flow;
+use crate::auth::credentials::{
+    self, CredentialSource, CredentialStore, Credentials, TokenFile, TOKEN_ENV,
+};
+use crate::auth::device_flow::{poll_for_token, request_device_code};
+use crate::auth::providers::{self, AuthProvider};
+use crate::cli::{Exit, GlobalOptions};
+use crate::client::api::{ApiError, DeskApiClient};
+use crate::client::backend::BackendError;
+use crate::client::http;
+use crate::core::config::DeskConfig;
+use crate::output::timestamp::timestamp;
+
+#[derive(Debug, Subcommand)]
+pub enum AuthCommand {
+    /// Sign in with a device code
+    Login {
+        /// Provider to sign in with (default: `auth.default_provider`)
+        #[arg(long, value_enum)]
+        provider: Option<AuthProvider>,
+
+        /// How to sign in
+        #[arg(long, value_enum, default_value_t)]
+        flow: LoginFlow,
+    },
+    /// Save a desk API token for machines without a keyring or browser
+    Token {
+        /// The token; read from stdin when omitted, which keeps it out of
+        /// shell history
+        token: Option<String>,
+    },
+    /// Remove stored credentials and the saved token
+    Logout,
+    /// Show who is signed in; exits with status 1 when not signed in or the
+    /// session has expired
+    Status {
+        /// Print the status as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the account and plan the credentials belong to
+    Whoami,
+    /// List the devices signed in to your account
+    Sessions,
+    /// Sign a device out of your account
+    RevokeSession {
+        /// Session to revoke, as listed by `desk auth sessions`
+        id: String,
+    },
+}
+
+/// How `desk auth login` signs in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum LoginFlow {
+    /// Enter a code on the provider's site, from any device
+    #[default]
+    Device,
+    /// Sign in with a browser on this machine
+    Browser,
+}
+
+/// Handles `desk auth`.
+pub async fn handle_auth(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    command: &AuthCommand,
+) -> Result<()> {
+    match command {
+        AuthCommand::Login { provider, flow } => {
+            handle_login(
+                globals,
+                config,
+                &CredentialStore::new()?,
+                provider.unwrap_or(config.auth.default_provider),
+                *flow,
+            )
+            .await?;
+            if let Some(credentials) = credentials::load()? {
+                if credentials.source != CredentialSource::Login {
+                    eprintln!(
+                        "Note: {} takes precedence over this login",
+                        credentials.source.describe()
+                    );
+                }
+            }
+            Ok(())
+        },
+        AuthCommand::Token { token } => handle_token(config, token.as_deref()).await,
+        AuthCommand::Whoami => handle_whoami(globals, config).await,
+        AuthCommand::Sessions => handle_sessions(globals, config).await,
+        AuthCommand::RevokeSession { id } => handle_revoke_session(globals, config, id).await,
+        AuthCommand::Logout => {
+            TokenFile::new()?.clear()?;
+            CredentialStore::new()?.clear()?;
+            println!("Logged out");
+            if std::env::var_os(TOKEN_ENV).is_some() {
+                eprintln!("Note: {TOKEN_ENV} is still set");
+            }
+            Ok(())
+        },
+        AuthCommand::Status { json } => handle_status(*json),
+    }
+}
+
+/// `desk auth status --json`.
+#[derive(Debug, Serialize)]
+struct AuthStatus {
+    logged_in: bool,
+    source: Option<CredentialSource>,
+    provider: Option<AuthProvider>,
+    user_id: Option<String>,
+    token_expires_at: Option<DateTime<Utc>>,
+    seconds_remaining: Option<i64>,
+    expired: bool,
+}
+
+/// Handles `desk auth status`.
+fn handle_status(json: bool) -> Result<()> {
+    let credentials = credentials::load()?;
+    let expired = credentials.as_ref().is_some_and(Credentials::is_expired);
+
+    if json {
+        let login = credentials
+            .as_ref()
+            .filter(|c| c.source == CredentialSource::Login);
+        let expires_at = credentials.as_ref().and_then(|c| c.expires_at);
+        let status = AuthStatus {
+            logged_in: credentials.is_some(),
+            source: credentials.as_ref().map(|c| c.source),
+            provider: login.map(|c| c.provider),
+            user_id: login.and_then(|c| c.user_id.clone()),
+            token_expires_at: expires_at,
+            seconds_remaining: expires_at.map(|at| (at - Utc::now()).num_seconds().max(0)),
+            expired,
+        };
+        println!("{}", serde_json::to_string_pretty(&status)?);
+    } else {
+        match &credentials {
+            Some(credentials) if credentials.source != CredentialSource::Login => {
+                println!("Using {}", credentials.source.describe());
+            },
+            Some(credentials) => {
+                let user = credentials.user_id.as_deref().unwrap_or("unknown user");
+                println!("Logged in with {} as {user}", credentials.provider);
+                match credentials.expires_at {
+                    Some(at) if expired => {
+                        println!("Session expired {}", timestamp(at));
+                    },
+                    Some(at) => println!("Session expires {}", timestamp(at)),
+                    None => {},
+                }
+                println!("Stored in {}", CredentialStore::new()?.location());
+            },
+            None => println!("Not logged in"),
+        }
+    }
+
+    if credentials.is_none() || expired {
+        return Err(Exit(1).into());
+    }
+    Ok(())
+}
+
+/// Makes sure `client` can talk to the API before a long operation: refreshes
+/// stale credentials and, in a terminal, offers to log in again when they
+/// cannot be refreshed.
+pub async fn ensure_session(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    client: &mut DeskApiClient,
+) -> Result<()> {
+    let error = match client.ensure_session().await {
+        Ok(refreshed) => {
+            if refreshed {
+                debug!("refreshed desk API session");
+            }
+            return Ok(());
+        },
+        Err(e) if e.requires_reauth() => anyhow::Error::from(e),
+        Err(e) => return Err(e.into()),
+    };
+    // Logging in would not help: the token wins over the keyring.
+    if let Some(source @ (CredentialSource::Environment | CredentialSource::TokenFile)) =
+        client.credentials().map(|c| c.source)
+    {
+        bail!("the desk API rejected {}", source.describe());
+    }
+    if login_again(globals, config, client, &error).await? {
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+/// Whether `error` came from the desk API rejecting the credentials.
+pub fn requires_reauth(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<ApiError>()
+            .is_some_and(ApiError::requires_reauth)
+            || cause
+                .downcast_ref::<BackendError>()
+                .is_some_and(BackendError::requires_reauth)
+    })
+}
+
+/// Offers to log in again after `error`, returning whether the user did.
+///
+/// Only asks when logging in would fix the error and there is a terminal to
+/// ask in. On success `client` has the new credentials, so the failed
+/// operation can be retried.
+pub async fn login_again(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    client: &mut DeskApiClient,
+    error: &anyhow::Error,
+) -> Result<bool> {
+    let from_login = client
+        .credentials()
+        .map_or(true, |c| c.source == CredentialSource::Login);
+    if !requires_reauth(error) || !from_login || !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+
+    eprintln!("{error}");
+    eprint!("Log in now? [Y/n] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    if answer.trim().eq_ignore_ascii_case("n") {
+        return Ok(false);
+    }
+
+    let provider = client
+        .credentials()
+        .map_or(config.auth.default_provider, |c| c.provider);
+    let store = CredentialStore::new()?;
+    handle_login(globals, config, &store, provider, LoginFlow::Device).await?;
+    client.load_credentials()?;
+    client.ensure_session().await?;
+    Ok(true)
+}
+
+/// Handles `desk auth whoami`.
+async fn handle_whoami(globals: &GlobalOptions, config: &DeskConfig) -> Result<()> {
+    let mut client = DeskApiClient::new(&config.api)?;
+    client.load_credentials()?;
+    ensure_session(globals, config, &mut client).await?;
+    let user = match client.get_current_user().await {
+        Ok(user) => user,
+        Err(e) => {
+            let error = e.into();
+            if !login_again(globals, config, &mut client, &error).await? {
+                return Err(error);
+            }
+            client.get_current_user().await?
+        },
+    };
+
+    match &user.display_name {
+        Some(name) => println!("Signed in as {name} <{}>", user.email),
+        None => println!("Signed in as {}", user.email),
+    }
+    println!("  Plan        {}", user.plan);
+    match (user.workspace_count, user.workspace_quota) {
+        (Some(count), Some(quota)) => println!("  Workspaces  {count} of {quota}"),
+        (Some(count), None) => println!("  Workspaces  {count} (unlimited)"),
+        (None, Some(quota)) => println!("  Workspaces  up to {quota}"),
+        (None, None) => {},
+    }
+    println!("  Account ID  {}", user.id);
+    Ok(())
+}
+
+/// Handles `desk auth sessions`.
+async fn handle_sessions(globals: &GlobalOptions, config: &DeskConfig) -> Result<()> {
+    let mut client = DeskApiClient::new(&config.api)?;
+    client.load_credentials()?;
+    ensure_session(globals, config, &mut client).await?;
+    let mut sessions = client.list_sessions().await?;
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.last_used_at.unwrap_or(s.created_at)));
+
+    if sessions.is_empty() {
+        println!("No active sessions");
+        return Ok(());
+    }
+    for session in &sessions {
+        let device = session.device.as_deref().unwrap_or("unknown device");
+        let used = session.last_used_at.unwrap_or(session.created_at);
+        let current = if session.current {
+            " (this machine)"
+        } else {
+            ""
+        };
+        println!(
+            "{:<24} {:<16}  {device}{current}",
+            session.id,
+            timestamp(used)
+        );
+    }
+    Ok(())
+}
+
+/// Handles `desk auth revoke-session`.
+async fn handle_revoke_session(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    id: &str,
+) -> Result<()> {
+    let mut client = DeskApiClient::new(&config.api)?;
+    client.load_credentials()?;
+    ensure_session(globals, config, &mut client).await?;
+    let sessions = client.list_sessions().await?;
+    let Some(session) = sessions.iter().find(|s| s.id == id) else {
+        bail!("no session {id}; see `desk auth sessions`");
+    };
+
+    client.revoke_session(id).await?;
+    println!("Revoked session {id}");
+    if session.current {
+        // The stored credentials belonged to the session and no longer work.
+        if client.credentials().map(|c| c.source) == Some(CredentialSource::Login) {
+            CredentialStore::new()?.clear()?;
+        }
+        eprintln!("That was this machine's session; run `desk auth login` to sign in again");
+    }
+    Ok(())
+}
+
+/// Handles `desk auth token`: checks the token and saves it.
+async fn handle_token(config: &DeskConfig, token: Option<&str>) -> Result<()> {
+    let token = match token {
+        Some(token) => token.to_string(),
+        None if std::io::stdin().is_terminal() => {
+            rpassword::prompt_password("Desk API token: ").context("failed to read the token")?
+        },
+        None => {
+            let mut line = String::new();
+            std::io::stdin().lock().read_line(&mut line)?;
+            line
+        },
+    };
+    let token = token.trim();
+    if token.is_empty() {
+        bail!("the token is empty");
+    }
+
+    // Catch a mistyped token now rather than at the next sync.
+    let client = DeskApiClient::new(&config.api)?.with_credentials(Credentials::token(
+        token.to_string(),
+        CredentialSource::TokenFile,
+    ));
+    match client.ping().await {
+        Ok(()) => {},
+        Err(e) if e.requires_reauth() => bail!("the desk API rejected the token"),
+        Err(e) => warn!("could not check the token: {e}"),
+    }
+
+    let file = TokenFile::new()?;
+    file.save(token)?;
+    println!("Saved the token to {}", file.path().display());
+    if std::env::var_os(TOKEN_ENV).is_some() {
+        eprintln!("Note: {TOKEN_ENV} takes precedence over the saved token");
+    }
+    Ok(())
+}
+
+async fn handle_login(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    store: &CredentialStore,
+    provider: AuthProvider,
+    flow: LoginFlow,
+) -> Result<()> {
+    let provider_config = providers::get_config(provider, &config.auth);
+    let http = http::client_builder(&config.api)?.build()?;
+
+    let mut reporter = globals.reporter();
+    let token = match flow {
+        LoginFlow::Device => {
+            let authorization = request_device_code(&http, &provider_config).await?;
+            // Instructions go to stderr so they never mix with json-lines progress.
+            if std::io::stderr().is_terminal() {
+                let url = authorization
+                    .verification_uri_complete
+                    .as_deref()
+                    .unwrap_or(&authorization.verification_uri);
+                if let Some(qr) = qr_code(url) {
+                    eprintln!("Scan to sign in on another device:\n{qr}");
+                }
+            }
+            eprintln!(
+                "Open {} and enter the code {}",
+                authorization.verification_uri, authorization.user_code
+            );
+            reporter.begin("login", None);
+            poll_for_token(&http, &provider_config, &authorization, reporter.as_mut()).await?
+        },
+        LoginFlow::Browser => {
+            reporter.begin("login", None);
+            browser_flow::authorize(&http, &provider_config, reporter.as_mut()).await?
+        },
+    };
+
+    let client = DeskApiClient::new(&config.api)?;
+    let credentials = client.exchange_token(provider, &token.access_token).await?;
+    store.save(&credentials)?;
+    reporter.finish(&format!("Logged in with {provider}"));
+    Ok(())
+}
+
+/// `text` as a QR code drawn with block characters, light on dark so it reads
+/// on the usual dark terminal background.
+fn qr_code(text: &str) -> Option<String> {
+    let code = QrCode::new(text.as_bytes())
+        .map_err(|e| debug!("cannot draw {text} as a QR code: {e}"))
+        .ok()?;
+    Some(
+        code.render::<Dense1x2>()
+            .dark_color(Dense1x2::Light)
+            .light_color(Dense1x2::Dark)
+            .build(),
+    )
+}
@@ -0,0 +1,104 @@
+//! `desk backup` and `desk restore-backup`: archives of all local data.
+
+use std::path::{Path, PathBuf};
+
+use age::secrecy::SecretString;
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+
+use crate::core::backup::{
+    create_backup, default_file_name, is_encrypted, restore_backup, BackupLocations,
+};
+use crate::core::lock::MutationGuard;
+use crate::core::paths;
+use crate::output::timestamp::timestamp;
+
+/// Environment variable holding the backup passphrase for scripted use.
+const PASSPHRASE_ENV: &str = "DESK_BACKUP_PASSPHRASE";
+
+/// Handles `desk backup`.
+pub fn handle_backup(output: Option<&Path>, encrypt: bool) -> Result<()> {
+    let locations = locations()?;
+    let dest = match output {
+        Some(path) if path.is_dir() => path.join(default_file_name(Utc::now(), encrypt)),
+        Some(path) => path.to_path_buf(),
+        None => PathBuf::from(default_file_name(Utc::now(), encrypt)),
+    };
+    let passphrase = if encrypt {
+        Some(passphrase(true)?)
+    } else {
+        None
+    };
+
+    std::fs::create_dir_all(&locations.data_dir)?;
+    let _guard = MutationGuard::acquire(&locations.data_dir, None)?;
+    let manifest = create_backup(&locations, &dest, passphrase)?;
+
+    println!(
+        "Backed up {} workspace{}{} to {}",
+        manifest.workspaces,
+        if manifest.workspaces == 1 { "" } else { "s" },
+        if manifest.has_config {
+            " and config"
+        } else {
+            ""
+        },
+        dest.display()
+    );
+    Ok(())
+}
+
+/// Handles `desk restore-backup`.
+pub fn handle_restore_backup(archive: &Path) -> Result<()> {
+    let locations = locations()?;
+    let passphrase = if is_encrypted(archive)? {
+        Some(passphrase(false)?)
+    } else {
+        None
+    };
+
+    std::fs::create_dir_all(&locations.data_dir)?;
+    let _guard = MutationGuard::acquire(&locations.data_dir, None)?;
+    let summary = restore_backup(archive, &locations, passphrase)?;
+
+    println!(
+        "Restored {} workspace{} from a backup taken {} by desk {}",
+        summary.manifest.workspaces,
+        if summary.manifest.workspaces == 1 {
+            ""
+        } else {
+            "s"
+        },
+        timestamp(summary.manifest.created_at),
+        summary.manifest.desk_version
+    );
+    if let Some(previous) = summary.previous {
+        println!("Previous data was moved to {}", previous.display());
+    }
+    Ok(())
+}
+
+fn locations() -> Result<BackupLocations> {
+    Ok(BackupLocations {
+        config_dir: paths::config_dir()?,
+        data_dir: paths::data_dir()?,
+    })
+}
+
+/// Reads the passphrase from the environment or prompts for it, asking
+/// twice when it is being chosen.
+fn passphrase(confirm: bool) -> Result<SecretString> {
+    if let Ok(value) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(value.into());
+    }
+
+    let value = rpassword::prompt_password("Backup passphrase: ")
+        .with_context(|| format!("failed to read passphrase (or set {PASSPHRASE_ENV})"))?;
+    if value.is_empty() {
+        bail!("passphrase cannot be empty");
+    }
+    if confirm && rpassword::prompt_password("Confirm passphrase: ")? != value {
+        bail!("passphrases do not match");
+    }
+    Ok(value.into())
+}
@@ -0,0 +1,94 @@
+//! `desk budget`: time budgets per workspace.
+
+use anyhow::Result;
+use clap::Subcommand;
+
+use crate::cli::GlobalOptions;
+use crate::core::budget::BudgetStatus;
+use crate::core::state::DeskState;
+use crate::utils::duration::{format_duration, parse_duration};
+
+#[derive(Debug, Subcommand)]
+pub enum BudgetCommand {
+    /// Set the time budget for a workspace (e.g. `4h`, `1h30m`)
+    Set { workspace: String, duration: String },
+    /// Remove the time budget from a workspace
+    Clear { workspace: String },
+    /// Show time spent against budgets
+    Status {
+        /// Only show this workspace
+        workspace: Option<String>,
+    },
+}
+
+/// Handles `desk budget`.
+pub fn handle_budget(globals: &GlobalOptions, command: &BudgetCommand) -> Result<()> {
+    let store = globals.workspace_store()?;
+
+    match command {
+        BudgetCommand::Set {
+            workspace,
+            duration,
+        } => {
+            let limit = parse_duration(duration)?;
+            let mut ws = store.load(workspace)?;
+            ws.metadata.time_budget_secs = Some(limit.as_secs());
+            store.save(&ws)?;
+            println!("Budget for '{workspace}' set to {}", format_duration(limit));
+        },
+        BudgetCommand::Clear { workspace } => {
+            let mut ws = store.load(workspace)?;
+            ws.metadata.time_budget_secs = None;
+            store.save(&ws)?;
+            println!("Budget for '{workspace}' cleared");
+        },
+        BudgetCommand::Status { workspace } => {
+            let state = DeskState::load()?;
+            let workspaces = match workspace {
+                Some(name) => vec![store.load(name)?],
+                None => store.list()?,
+            };
+
+            let mut shown = 0;
+            for ws in &workspaces {
+                let Some(status) = BudgetStatus::for_workspace(ws, &state) else {
+                    continue;
+                };
+                shown += 1;
+                let marker = if status.is_exceeded() { "!" } else { " " };
+                println!(
+                    "{marker} {:<24} {} of {}",
+                    ws.name,
+                    format_duration(status.spent),
+                    format_duration(status.limit)
+                );
+            }
+            if shown == 0 {
+                println!("No workspaces have a time budget");
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Prints a warning when the workspace open in the current repository is
+/// close to or over its budget.
+pub fn warn_if_over_budget(globals: &GlobalOptions) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let crate::core::store::Namespace::Repo(repo) = store.namespace() else {
+        return Ok(());
+    };
+    let state = DeskState::load()?;
+    let Some(name) = state.current_workspace(repo) else {
+        return Ok(());
+    };
+    let Ok(workspace) = store.load(name) else {
+        return Ok(());
+    };
+    if let Some(warning) =
+        BudgetStatus::for_workspace(&workspace, &state).and_then(|s| s.warning(&workspace.name))
+    {
+        eprintln!("warning: {warning}");
+    }
+    Ok(())
+}
@@ -0,0 +1,293 @@
+//! `desk config`: read and change settings without editing `config.toml` by
+//! hand.
+
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use clap::Subcommand;
+use toml_edit::{DocumentMut, TableLike};
+
+use crate::cli::Exit;
+use crate::client::api::DeskApiClient;
+use crate::core::config::{
+    edit_config_at, load_config_from, preview_config_edit_at, ConfigError, DeskConfig,
+    ENV_OVERRIDES,
+};
+use crate::core::config_check::{check_config, Finding, Severity};
+use crate::core::paths;
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print a setting, such as `api.timeout_secs`, as environment variables
+    /// leave it
+    Get { key: String },
+    /// Change a setting, keeping the file's comments and layout
+    Set { key: String, value: String },
+    /// Print every setting with its current value, environment variables
+    /// included
+    List,
+    /// Open the configuration file in $VISUAL or $EDITOR
+    Edit,
+    /// Print where the configuration file is
+    Path,
+    /// Check the configuration for unknown settings, bad URLs, and options
+    /// that contradict each other, and that the desk API answers
+    Validate {
+        /// Skip contacting the desk API
+        #[arg(long)]
+        offline: bool,
+    },
+}
+
+/// Handles `desk config`.
+///
+/// Runs before the configuration is loaded, so a broken file can still be
+/// found and fixed.
+pub async fn handle_config(command: &ConfigCommand, profile: Option<&str>) -> Result<()> {
+    let path = paths::config_file()?;
+    match command {
+        ConfigCommand::Get { key } => {
+            let config = load_config_from(&path, profile)?.with_env_overrides();
+            match lookup(&config, &split_key(key)?)? {
+                Some(toml::Value::String(value)) => println!("{value}"),
+                Some(toml::Value::Table(table)) => print!("{}", toml::to_string(&table)?),
+                Some(value) => println!("{value}"),
+                None => bail!("{key} is not set"),
+            }
+            Ok(())
+        },
+        ConfigCommand::Set { key, value } => handle_set(&path, profile, key, value),
+        ConfigCommand::List => {
+            let config = load_config_from(&path, profile)?.with_env_overrides();
+            let mut settings = Vec::new();
+            flatten("", toml::Value::try_from(&config)?, &mut settings);
+            for (key, value) in settings {
+                // Secrets stay out of terminals and screen shares; `get` prints them.
+                if key.rsplit('.').next() == Some("token") {
+                    println!("{key} = \"<set>\"");
+                } else {
+                    println!("{key} = {value}");
+                }
+            }
+            Ok(())
+        },
+        ConfigCommand::Edit => handle_edit(&path),
+        ConfigCommand::Path => {
+            println!("{}", path.display());
+            Ok(())
+        },
+        ConfigCommand::Validate { offline } => handle_validate(&path, profile, *offline).await,
+    }
+}
+
+/// Handles `desk config validate`, failing if anything is an error rather
+/// than a warning.
+async fn handle_validate(
+    path: &std::path::Path,
+    profile: Option<&str>,
+    offline: bool,
+) -> Result<()> {
+    let mut report = check_config(path, profile)?;
+    // A file that is already known to be broken says nothing about the API.
+    if let (Some(config), false, 0) = (&report.config, offline, report.errors()) {
+        let reached = match DeskApiClient::new(&config.api) {
+            Ok(client) => client.capabilities().await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = reached {
+            report.findings.push(Finding {
+                severity: Severity::Error,
+                line: None,
+                message: format!("{:#}", anyhow::Error::new(e)),
+            });
+        }
+    }
+
+    for finding in &report.findings {
+        match finding.line {
+            Some(line) => eprintln!(
+                "{}:{line}: {}: {}",
+                path.display(),
+                finding.severity,
+                finding.message
+            ),
+            None => eprintln!(
+                "{}: {}: {}",
+                path.display(),
+                finding.severity,
+                finding.message
+            ),
+        }
+    }
+    let (errors, warnings) = (report.errors(), report.warnings());
+    if errors + warnings == 0 {
+        println!("{}: no problems found", path.display());
+    } else {
+        println!(
+            "{}: {errors} {}, {warnings} {}",
+            path.display(),
+            if errors == 1 { "error" } else { "errors" },
+            if warnings == 1 { "warning" } else { "warnings" }
+        );
+    }
+    if errors > 0 {
+        return Err(Exit(1).into());
+    }
+    Ok(())
+}
+
+/// Handles `desk config set`, changing the selected profile if there is one.
+fn handle_set(path: &std::path::Path, profile: Option<&str>, key: &str, raw: &str) -> Result<()> {
+    let segments = split_key(key)?;
+    let current = match load_config_from(path, profile) {
+        // Setting something is how a profile comes to exist.
+        Err(ConfigError::UnknownProfile { .. }) => load_config_from(path, None)?,
+        current => current?,
+    };
+    for end in 1..segments.len() {
+        if lookup(&current, &segments[..end])?.is_some_and(|v| !v.is_table()) {
+            bail!("unknown setting {key}; see `desk config list`");
+        }
+    }
+    // `60` and `true` keep their type, but a setting that wants a string gets
+    // one whatever it looks like.
+    let typed = raw.parse::<toml_edit::Value>().ok().map(|mut value| {
+        value.decor_mut().clear();
+        value
+    });
+    let mut target = profile.map_or_else(Vec::new, |profile| vec!["profiles", profile]);
+    target.extend(&segments);
+    let mut message = None;
+    for value in typed.into_iter().chain([toml_edit::Value::from(raw)]) {
+        let preview =
+            preview_config_edit_at(path, profile, |doc| set_key(doc, &target, value.clone()));
+        match preview {
+            Ok(config) if lookup(&config, &segments)?.is_some() => {
+                let shown = value.to_string();
+                edit_config_at(path, |doc| set_key(doc, &target, value))?;
+                match profile {
+                    Some(profile) => println!("Set {key} to {shown} in profile {profile}"),
+                    None => println!("Set {key} to {shown}"),
+                }
+                if let Some((var, _)) = ENV_OVERRIDES
+                    .iter()
+                    .find(|(var, setting)| *setting == key && std::env::var_os(var).is_some())
+                {
+                    eprintln!("Note: {var} is set and takes precedence");
+                }
+                return Ok(());
+            },
+            Ok(_) => bail!("unknown setting {key}; see `desk config list`"),
+            Err(e) => {
+                let source = match &e {
+                    ConfigError::Parse { source, .. } => source,
+                    ConfigError::Profile { source, .. } => source.as_ref(),
+                    _ => return Err(e.into()),
+                };
+                // The typed attempt explains the problem best.
+                message.get_or_insert_with(|| source.message().to_string());
+            },
+        }
+    }
+    bail!(
+        "invalid value for {key}: {}",
+        message.unwrap_or_else(|| raw.to_string())
+    )
+}
+
+/// Handles `desk config edit`, checking the file once the editor exits.
+fn handle_edit(path: &std::path::Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| default_editor().to_string());
+    // Editors are often configured with arguments, like `code --wait`.
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or(default_editor());
+    let status = Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .with_context(|| format!("failed to run {editor}"))?;
+    if !status.success() {
+        bail!("{editor} exited with {status}");
+    }
+    load_config_from(path, None)
+        .context("the configuration is no longer valid; run `desk config edit` to fix it")?;
+    Ok(())
+}
+
+const fn default_editor() -> &'static str {
+    if cfg!(windows) {
+        "notepad"
+    } else {
+        "vi"
+    }
+}
+
+fn split_key(key: &str) -> Result<Vec<&str>> {
+    let segments: Vec<_> = key.split('.').collect();
+    if segments.iter().any(|s| s.trim().is_empty()) {
+        bail!("invalid setting name '{key}'; use dotted names like api.timeout_secs");
+    }
+    Ok(segments)
+}
+
+/// The value of the setting at `segments`, if it has one.
+fn lookup(config: &DeskConfig, segments: &[&str]) -> Result<Option<toml::Value>> {
+    let mut value = toml::Value::try_from(config)?;
+    for segment in segments {
+        let toml::Value::Table(mut table) = value else {
+            return Ok(None);
+        };
+        let Some(next) = table.remove(*segment) else {
+            return Ok(None);
+        };
+        value = next;
+    }
+    Ok(Some(value))
+}
+
+/// Sets the setting at `segments`, adding the tables it lives in. Leaves the
+/// document alone if part of the way is not a table.
+fn set_key(doc: &mut DocumentMut, segments: &[&str], value: toml_edit::Value) {
+    let Some((name, parents)) = segments.split_last() else {
+        return;
+    };
+    let mut table: &mut dyn TableLike = doc.as_table_mut();
+    for segment in parents {
+        let item = table.entry(segment).or_insert_with(|| {
+            let mut table = toml_edit::Table::new();
+            // No empty `[sync]` header just because `[sync.git]` is needed.
+            table.set_implicit(true);
+            toml_edit::Item::Table(table)
+        });
+        let Some(next) = item.as_table_like_mut() else {
+            return;
+        };
+        table = next;
+    }
+    table.insert(name, toml_edit::value(value));
+}
+
+/// Collects the settings under `value` as dotted keys.
+fn flatten(prefix: &str, value: toml::Value, settings: &mut Vec<(String, toml::Value)>) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let key = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten(&key, value, settings);
+            }
+        },
+        value => settings.push((prefix.to_string(), value)),
+    }
+}
@@ -0,0 +1,651 @@
+//! `desk daemon`: background work for the CLI.
+//!
+//! The daemon keeps the desk API session fresh, syncs and collects garbage on
+//! a schedule, reminds of workspaces left alone, and watches open workspaces
+//! so `desk prompt` answers at once.
+//! The CLI reaches it through [`crate::daemon`].
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::Subcommand;
+use tokio::io::BufReader;
+use tokio::time::{interval_at, Instant, Interval, MissedTickBehavior};
+use tracing::debug;
+
+use super::shell::SHELL_FILE_ENV;
+use super::status::prompt_info;
+use crate::cli::Exit;
+use crate::client::api::{ApiError, DeskApiClient};
+use crate::core::budget::BudgetAlert;
+use crate::core::config::{DeskConfig, SyncBackendKind};
+use crate::core::paths;
+use crate::core::reminders::stale_workspaces;
+use crate::core::state::DeskState;
+use crate::daemon::{self, ipc, DaemonError, DaemonStatus, JobRun, PromptInfo, Request, Response};
+use crate::output::notify;
+use crate::output::timestamp::timestamp;
+
+#[derive(Debug, Subcommand)]
+pub enum DaemonCommand {
+    /// Start the daemon in the background
+    Start,
+    /// Run the daemon in the foreground, as service managers like systemd do
+    Run,
+    /// Stop the running daemon
+    Stop,
+    /// Show whether the daemon runs and what it did last; exits with status 1
+    /// when it does not run
+    Status,
+}
+
+/// How often the daemon makes sure the desk API session is good, refreshing
+/// it before it expires.
+const SESSION_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Longest the daemon spends on one connection.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long `desk daemon start` and `stop` wait for the daemon to come up
+/// or go away.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Handles `desk daemon`.
+pub async fn handle_daemon(config: &DeskConfig, command: &DaemonCommand) -> Result<()> {
+    let data_dir = paths::data_dir()?;
+    match command {
+        DaemonCommand::Start => handle_start(&data_dir).await,
+        DaemonCommand::Run => run(config, &data_dir).await,
+        DaemonCommand::Stop => handle_stop(&data_dir).await,
+        DaemonCommand::Status => handle_status(config, &data_dir).await,
+    }
+}
+
+/// Handles `desk daemon start`, returning once the daemon answers.
+async fn handle_start(data_dir: &Path) -> Result<()> {
+    if let Some(status) = running(data_dir).await {
+        println!("The desk daemon is already running (pid {})", status.pid);
+        return Ok(());
+    }
+    std::fs::create_dir_all(data_dir)
+        .with_context(|| format!("failed to create {}", data_dir.display()))?;
+    let log_path = daemon::log_path(data_dir);
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .with_context(|| format!("failed to open {}", log_path.display()))?;
+
+    let mut command = desk_command()?;
+    command
+        .args(["daemon", "run"])
+        .stdin(Stdio::null())
+        .stdout(log.try_clone()?)
+        .stderr(log);
+    detach(&mut command);
+    let mut child = command.spawn().context("failed to start the desk daemon")?;
+
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    loop {
+        if let Some(status) = running(data_dir).await {
+            println!(
+                "Started the desk daemon (pid {}); it logs to {}",
+                status.pid,
+                log_path.display()
+            );
+            return Ok(());
+        }
+        if let Some(exit) = child.try_wait()? {
+            bail!(
+                "the desk daemon stopped right away ({exit}); see {}",
+                log_path.display()
+            );
+        }
+        if Instant::now() >= deadline {
+            bail!(
+                "the desk daemon does not answer; see {}",
+                log_path.display()
+            );
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Handles `desk daemon stop`, returning once the daemon is gone.
+async fn handle_stop(data_dir: &Path) -> Result<()> {
+    if daemon::request(data_dir, &Request::Shutdown)
+        .await?
+        .is_none()
+    {
+        println!("The desk daemon is not running");
+        return Ok(());
+    }
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    while running(data_dir).await.is_some() {
+        if Instant::now() >= deadline {
+            bail!("the desk daemon did not stop");
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    println!("Stopped the desk daemon");
+    Ok(())
+}
+
+/// Handles `desk daemon status`.
+async fn handle_status(config: &DeskConfig, data_dir: &Path) -> Result<()> {
+    let Some(status) = running(data_dir).await else {
+        println!("The desk daemon is not running; start it with: desk daemon start");
+        return Err(Exit(1).into());
+    };
+    println!(
+        "The desk daemon is running (pid {}, desk {}), started {}",
+        status.pid,
+        status.version,
+        timestamp(status.started_at)
+    );
+    let plural = if status.watched == 1 { "y" } else { "ies" };
+    println!(
+        "Watching:   {} repositor{plural} with an open workspace",
+        status.watched
+    );
+    let sync = (config.daemon.sync_interval_mins > 0).then_some(&status.last_sync);
+    println!(
+        "Last sync:  {}",
+        describe_run(sync, "daemon.sync_interval_mins")
+    );
+    let gc = (config.daemon.gc_interval_hours > 0).then_some(&status.last_gc);
+    println!(
+        "Last gc:    {}",
+        describe_run(gc, "daemon.gc_interval_hours")
+    );
+    if config.sync.backend == SyncBackendKind::Api {
+        println!(
+            "Session:    {}",
+            describe_run(Some(&status.last_session_check), "")
+        );
+    }
+    println!("Log:        {}", daemon::log_path(data_dir).display());
+    Ok(())
+}
+
+/// Notifies that the workspace `info` describes, open in `repo`, has
+/// nearly used up or gone over its time budget.
+fn notify_budget(repo: &Path, info: &PromptInfo) {
+    let name = &info.workspace;
+    let message = match info.budget {
+        Some(BudgetAlert::Over) => format!("'{name}' is over its time budget"),
+        Some(BudgetAlert::Nearly) => format!("'{name}' has nearly used up its time budget"),
+        None => return,
+    };
+    log(format!("{}: {message}", repo.display()));
+    notify::desktop(&message, "See desk budget status");
+}
+
+/// A scheduled job's last run; `None` if the job is turned off with
+/// `setting`.
+fn describe_run(run: Option<&Option<JobRun>>, setting: &str) -> String {
+    match run {
+        None => format!("off (set {setting} to turn it on)"),
+        Some(None) => "not yet".to_string(),
+        Some(Some(run)) if run.ok => format!("{}: {}", timestamp(run.at), run.summary),
+        Some(Some(run)) => format!("{}: failed: {}", timestamp(run.at), run.summary),
+    }
+}
+
+/// What the daemon serving `data_dir` reports, if one is running.
+async fn running(data_dir: &Path) -> Option<DaemonStatus> {
+    match daemon::request(data_dir, &Request::Status).await {
+        Ok(Some(Response::Status(status))) => Some(status),
+        _ => None,
+    }
+}
+
+/// Handles `desk daemon run`, until `desk daemon stop`, Ctrl-C, or SIGTERM.
+async fn run(config: &DeskConfig, data_dir: &Path) -> Result<()> {
+    if let Some(status) = running(data_dir).await {
+        bail!("the desk daemon is already running (pid {})", status.pid);
+    }
+    std::fs::create_dir_all(data_dir)
+        .with_context(|| format!("failed to create {}", data_dir.display()))?;
+    let mut listener = ipc::Listener::bind(data_dir).context("failed to listen for desk")?;
+    let daemon = Arc::new(Daemon {
+        config: config.clone(),
+        data_dir: data_dir.to_path_buf(),
+        started_at: Utc::now(),
+        activity: Mutex::default(),
+        busy: AtomicBool::new(false),
+    });
+    log(format!(
+        "desk daemon {} started (pid {})",
+        env!("CARGO_PKG_VERSION"),
+        std::process::id()
+    ));
+
+    let settings = &config.daemon;
+    let mut watch = every(
+        Duration::from_secs(settings.watch_interval_secs.max(1)),
+        true,
+    );
+    let mut session = every(SESSION_INTERVAL, true);
+    let mut sync = (settings.sync_interval_mins > 0)
+        .then(|| every(Duration::from_secs(settings.sync_interval_mins * 60), true));
+    let mut gc = (settings.gc_interval_hours > 0).then(|| {
+        every(
+            Duration::from_secs(settings.gc_interval_hours * 3600),
+            false,
+        )
+    });
+    let mut reminders = (settings.reminder_interval_hours > 0
+        && config.reminders.stale_after_days > 0)
+        .then(|| {
+            every(
+                Duration::from_secs(settings.reminder_interval_hours * 3600),
+                true,
+            )
+        });
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(stream) => {
+                    if daemon.serve(stream).await {
+                        break;
+                    }
+                },
+                Err(e) => log(format!("failed to accept a connection: {e}")),
+            },
+            _ = watch.tick() => daemon.spawn_watch(),
+            _ = session.tick() => daemon.spawn_session_check(),
+            () = tick(&mut sync) => daemon.spawn_job(Job::Sync),
+            () = tick(&mut gc) => daemon.spawn_job(Job::Gc),
+            () = tick(&mut reminders) => daemon.spawn_reminders(),
+            () = &mut shutdown => break,
+        }
+    }
+    log("desk daemon stopped");
+    Ok(())
+}
+
+/// Background work the daemon runs as `desk` commands.
+#[derive(Debug, Clone, Copy)]
+enum Job {
+    Sync,
+    Gc,
+}
+
+impl Job {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Sync => "sync",
+            Self::Gc => "gc",
+        }
+    }
+}
+
+/// What the daemon found out last.
+#[derive(Debug, Default)]
+struct Activity {
+    /// Open workspaces by repository, as of the last check.
+    prompts: HashMap<PathBuf, PromptInfo>,
+    last_sync: Option<JobRun>,
+    last_gc: Option<JobRun>,
+    last_session_check: Option<JobRun>,
+}
+
+struct Daemon {
+    config: DeskConfig,
+    data_dir: PathBuf,
+    started_at: DateTime<Utc>,
+    activity: Mutex<Activity>,
+    /// Set while a job runs, so that slow runs do not pile up.
+    busy: AtomicBool,
+}
+
+impl Daemon {
+    fn activity(&self) -> MutexGuard<'_, Activity> {
+        self.activity.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Answers one connection, returning whether the daemon was asked to
+    /// stop.
+    async fn serve(self: &Arc<Self>, stream: ipc::Stream) -> bool {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let exchange = async {
+            let mut reader = BufReader::new(reader);
+            let Some(request) = daemon::read_message::<_, Request>(&mut reader).await? else {
+                return Ok(false);
+            };
+            let stop = request == Request::Shutdown;
+            let response = self.answer(request).await;
+            daemon::write_message(&mut writer, &response).await?;
+            Ok::<_, DaemonError>(stop)
+        };
+        match tokio::time::timeout(CONNECTION_TIMEOUT, exchange).await {
+            Ok(Ok(stop)) => stop,
+            Ok(Err(e)) => {
+                debug!("failed to answer a connection: {e}");
+                false
+            },
+            Err(_) => {
+                debug!("gave up on a connection that stayed silent");
+                false
+            },
+        }
+    }
+
+    async fn answer(self: &Arc<Self>, request: Request) -> Response {
+        match request {
+            Request::Status => Response::Status(self.status()),
+            Request::Prompt { repo } => {
+                let daemon = Arc::clone(self);
+                match tokio::task::spawn_blocking(move || daemon.prompt(&repo)).await {
+                    Ok(Ok(prompt)) => Response::Prompt { prompt },
+                    Ok(Err(e)) => Response::Error {
+                        message: format!("{e:#}"),
+                    },
+                    Err(e) => Response::Error {
+                        message: e.to_string(),
+                    },
+                }
+            },
+            Request::Shutdown => {
+                log("stopping on request");
+                Response::Stopping
+            },
+        }
+    }
+
+    fn status(&self) -> DaemonStatus {
+        let activity = self.activity();
+        DaemonStatus {
+            pid: std::process::id(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: self.started_at,
+            watched: activity.prompts.len(),
+            last_sync: activity.last_sync.clone(),
+            last_gc: activity.last_gc.clone(),
+            last_session_check: activity.last_session_check.clone(),
+        }
+    }
+
+    /// The workspace open in `repo`: as of the last check if it is still the
+    /// one open, or else worked out now.
+    fn prompt(&self, repo: &Path) -> Result<Option<PromptInfo>> {
+        let state = DeskState::load()?;
+        let current = state.current_workspace(repo);
+        let cached = self.activity().prompts.get(repo).cloned();
+        if let Some(cached) = cached.filter(|p| Some(p.workspace.as_str()) == current) {
+            return Ok(Some(cached));
+        }
+        let info = prompt_info(&self.data_dir, &state, repo, true)?;
+        let mut activity = self.activity();
+        match &info {
+            Some(info) => activity.prompts.insert(repo.to_path_buf(), info.clone()),
+            None => activity.prompts.remove(repo),
+        };
+        drop(activity);
+        Ok(info)
+    }
+
+    /// Checks every repository with an open workspace for drift.
+    fn spawn_watch(self: &Arc<Self>) {
+        let daemon = Arc::clone(self);
+        tokio::task::spawn_blocking(move || daemon.watch());
+    }
+
+    fn watch(&self) {
+        let state = match DeskState::load() {
+            Ok(state) => state,
+            Err(e) => return log(format!("failed to load state: {e:#}")),
+        };
+        let mut prompts = HashMap::new();
+        for repo in state.current_workspaces.keys().map(PathBuf::from) {
+            let info = match prompt_info(&self.data_dir, &state, &repo, true) {
+                Ok(Some(info)) => info,
+                Ok(None) => continue,
+                // Repositories that were moved or deleted stay in the state.
+                Err(e) => {
+                    debug!("cannot check {}: {e:#}", repo.display());
+                    continue;
+                },
+            };
+            let before = self.activity().prompts.get(&repo).cloned();
+            let drifted = before
+                .as_ref()
+                .is_some_and(|b| b.workspace == info.workspace && b.drift.is_empty());
+            if drifted && !info.drift.is_empty() {
+                log(format!(
+                    "{}: checkout drifted from '{}': {}",
+                    repo.display(),
+                    info.workspace,
+                    info.drift.join(", ")
+                ));
+            }
+            let alerted = before
+                .filter(|b| b.workspace == info.workspace)
+                .and_then(|b| b.budget);
+            if info.budget.is_some() && info.budget != alerted {
+                notify_budget(&repo, &info);
+            }
+            prompts.insert(repo, info);
+        }
+        self.activity().prompts = prompts;
+    }
+
+    /// Notifies about the workspaces `desk reminders` lists, if there are
+    /// any.
+    fn spawn_reminders(self: &Arc<Self>) {
+        let daemon = Arc::clone(self);
+        tokio::task::spawn_blocking(move || daemon.remind());
+    }
+
+    fn remind(&self) {
+        let days = self.config.reminders.stale_after_days;
+        let stale =
+            DeskState::load().and_then(|state| Ok(stale_workspaces(&self.data_dir, &state, days)?));
+        let stale = match stale {
+            Ok(stale) => stale,
+            Err(e) => return log(format!("failed to look for workspaces left alone: {e:#}")),
+        };
+        if stale.is_empty() {
+            return;
+        }
+        let names: Vec<_> = stale.iter().map(|w| w.name.as_str()).collect();
+        let plural = if names.len() == 1 { "" } else { "s" };
+        log(format!(
+            "reminding of {} workspace{plural} left alone: {}",
+            names.len(),
+            names.join(", ")
+        ));
+        notify::desktop(
+            &format!(
+                "{} workspace{plural} untouched for over {days} days",
+                names.len()
+            ),
+            &format!(
+                "{}; finish or delete them, or see desk reminders",
+                names.join(", ")
+            ),
+        );
+    }
+
+    /// Refreshes the desk API session if it is about to expire, so commands
+    /// never have to.
+    fn spawn_session_check(self: &Arc<Self>) {
+        if self.config.sync.backend != SyncBackendKind::Api {
+            return;
+        }
+        let daemon = Arc::clone(self);
+        tokio::spawn(async move {
+            let checked = async {
+                let mut client = DeskApiClient::new(&daemon.config.api)?;
+                client.load_credentials()?;
+                client.ensure_session().await
+            };
+            let (ok, summary) = match checked.await {
+                Ok(true) => (true, "refreshed the session".to_string()),
+                Ok(false) => (true, "signed in".to_string()),
+                Err(ApiError::NotAuthenticated) => (false, "not signed in".to_string()),
+                Err(e) => {
+                    log(format!("session check failed: {e}"));
+                    (false, e.to_string())
+                },
+            };
+            daemon.activity().last_session_check = Some(JobRun {
+                at: Utc::now(),
+                ok,
+                summary,
+            });
+        });
+    }
+
+    fn spawn_job(self: &Arc<Self>, job: Job) {
+        if self.busy.swap(true, Ordering::SeqCst) {
+            log(format!(
+                "skipping {}: the last job is still running",
+                job.name()
+            ));
+            return;
+        }
+        let daemon = Arc::clone(self);
+        tokio::task::spawn_blocking(move || {
+            let outcome = match job {
+                Job::Sync => run_desk(&["sync", "push"]).and_then(|pushed| {
+                    run_desk(&["sync", "pull"]).map(|pulled| format!("{pushed}; {pulled}"))
+                }),
+                Job::Gc => run_desk(&["gc"]),
+            };
+            let run = match outcome {
+                Ok(summary) => JobRun {
+                    at: Utc::now(),
+                    ok: true,
+                    summary,
+                },
+                Err(e) => JobRun {
+                    at: Utc::now(),
+                    ok: false,
+                    summary: format!("{e:#}"),
+                },
+            };
+            log(format!("{}: {}", job.name(), run.summary));
+            let mut activity = daemon.activity();
+            match job {
+                Job::Sync => activity.last_sync = Some(run),
+                Job::Gc => activity.last_gc = Some(run),
+            }
+            drop(activity);
+            daemon.busy.store(false, Ordering::SeqCst);
+        });
+    }
+}
+
+/// Runs `desk <args>` with the daemon's profile, returning the last line it
+/// printed.
+fn run_desk(args: &[&str]) -> Result<String> {
+    let output = desk_command()?
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("failed to run desk {}", args.join(" ")))?;
+    let last_line = |bytes: &[u8]| {
+        String::from_utf8_lossy(bytes)
+            .lines()
+            .map(str::trim)
+            .rfind(|line| !line.is_empty())
+            .map(String::from)
+    };
+    if output.status.success() {
+        return Ok(last_line(&output.stdout).unwrap_or_else(|| "done".to_string()));
+    }
+    let reason = last_line(&output.stderr)
+        .or_else(|| last_line(&output.stdout))
+        .unwrap_or_else(|| output.status.to_string());
+    bail!("desk {}: {reason}", args.join(" "))
+}
+
+/// This `desk`, with the profile it runs under.
+pub(super) fn desk_command() -> Result<Command> {
+    let exe = std::env::current_exe().context("cannot find the desk executable")?;
+    let mut command = Command::new(exe);
+    // Changing directory is for the shell that ran this desk, not its children.
+    command.env_remove(SHELL_FILE_ENV);
+    if let Some(profile) = paths::profile() {
+        command.args(["--profile", profile]);
+    }
+    Ok(command)
+}
+
+/// Keeps the daemon running when the terminal that started it closes.
+#[cfg(unix)]
+fn detach(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    command.process_group(0);
+}
+
+#[cfg(windows)]
+fn detach(command: &mut Command) {
+    use std::os::windows::process::CommandExt;
+
+    const DETACHED_PROCESS: u32 = 0x0000_0008;
+    const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+    command.creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP);
+}
+
+/// Ticks every `period`, starting now or one period from now.
+fn every(period: Duration, now: bool) -> Interval {
+    let start = if now {
+        Instant::now()
+    } else {
+        Instant::now() + period
+    };
+    let mut interval = interval_at(start, period);
+    // A laptop waking from sleep runs a late job once, not once per miss.
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    interval
+}
+
+/// Waits for the next tick of `interval`, or forever if there is none.
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        },
+        None => std::future::pending().await,
+    }
+}
+
+/// Resolves on Ctrl-C, or SIGTERM where there is one.
+pub(super) async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {},
+                _ = terminate.recv() => {},
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Writes a line to the daemon's log, which is its standard error.
+fn log(message: impl Display) {
+    eprintln!(
+        "{} {message}",
+        Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+    );
+}
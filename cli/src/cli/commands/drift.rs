@@ -0,0 +1,184 @@
+//! `desk drift`: how the checkout has moved on from a saved workspace, for
+//! deciding whether to restore it or save the checkout over it.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+use crate::cli::GlobalOptions;
+use crate::core::changes;
+use crate::core::config::DeskConfig;
+use crate::core::state::DeskState;
+use crate::core::store::{FileWorkspaceStore, Namespace};
+use crate::core::workspace::Workspace;
+use crate::integrations::git::{Git2Operations, StashContents};
+use crate::output::timestamp::timestamp;
+
+/// Handles `desk drift`.
+///
+/// Compares the checkout with workspace `name`, the open one by default:
+/// its branch, the commits since the one it was saved on, and the files
+/// changed here against the uncommitted changes it saved.
+pub fn handle_drift(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    name: Option<&str>,
+) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let Namespace::Repo(repo) = store.namespace() else {
+        bail!("desk drift needs a git repository; run it inside one");
+    };
+    let state = DeskState::load()?;
+    let open = state.current_workspace(repo);
+    let Some(name) = name.or(open) else {
+        bail!("no workspace is open; pass the one to compare with: desk drift <name>");
+    };
+    let workspace = store.load(name)?;
+    let is_open = open == Some(name);
+    let checkout = workspace
+        .state
+        .worktree
+        .clone()
+        .unwrap_or_else(|| repo.clone());
+    let git = Git2Operations::new(&checkout);
+    let saved = &workspace.state;
+
+    println!(
+        "Comparing {} with '{name}', saved {}",
+        checkout.display(),
+        timestamp(workspace.updated_at)
+    );
+    let branch = git.current_branch().ok();
+    match branch.as_deref() {
+        Some(branch) if branch == saved.branch => println!("Branch:   {branch}, as saved"),
+        Some(branch) => println!("Branch:   {branch}, saved on {}", saved.branch),
+        None => println!("Branch:   detached HEAD, saved on {}", saved.branch),
+    }
+    println!("Commits:  {}", describe_commits(&git, &saved.commit)?);
+
+    let here = git
+        .working_changes(config.capture.include_untracked)?
+        .map(|contents| contents.files())
+        .transpose()?
+        .unwrap_or_default();
+    let (stored, label) = match saved_changes(&store, &git, &workspace, is_open)? {
+        Some((contents, label)) => (contents.files()?, label),
+        None => (BTreeMap::new(), "nothing saved".to_string()),
+    };
+    let files = compare(&here, &stored);
+    if files.is_empty() {
+        println!("Changes:  none here, and {label}");
+    } else {
+        println!("Changes:  here against {label}");
+        let width = files.iter().map(|(path, _)| path.len()).max().unwrap_or(0);
+        for (path, how) in &files {
+            println!("  {path:<width$}  {how}");
+        }
+    }
+
+    let drifted = branch.as_deref() != Some(saved.branch.as_str())
+        || git.current_commit().ok().as_deref() != Some(saved.commit.as_str())
+        || !files.is_empty();
+    println!();
+    if !drifted {
+        println!("The checkout matches '{name}'");
+    } else if is_open {
+        println!("Record the checkout as '{name}' with: desk save");
+    } else {
+        println!(
+            "Restore '{name}' with `desk open {name}`, or save the checkout over it with \
+             `desk open {name} --force`"
+        );
+    }
+    Ok(())
+}
+
+/// Where `HEAD` is next to `commit`, the one the workspace was saved on.
+fn describe_commits(git: &Git2Operations, commit: &str) -> Result<String> {
+    let saved = short(commit);
+    if !git.has_commit(commit)? {
+        return Ok(format!(
+            "the saved commit {saved} is not in this clone; a force-push may have rewritten it"
+        ));
+    }
+    Ok(match git.ahead_behind(commit)? {
+        None => "nothing committed yet".to_string(),
+        Some((0, 0)) => format!("at the saved commit {saved}"),
+        Some((ahead, 0)) => format!("{} since the saved commit {saved}", commits(ahead)),
+        Some((0, behind)) => format!("{} behind the saved commit {saved}", commits(behind)),
+        Some((ahead, behind)) => format!(
+            "{} since the saved commit {saved}, which has {} not checked out",
+            commits(ahead),
+            commits(behind)
+        ),
+    })
+}
+
+fn commits(count: usize) -> String {
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{count} commit{plural}")
+}
+
+/// The uncommitted changes `workspace` would restore and where they are
+/// kept; for the open workspace, its latest checkpoint.
+fn saved_changes(
+    store: &FileWorkspaceStore,
+    git: &Git2Operations,
+    workspace: &Workspace,
+    open: bool,
+) -> Result<Option<(StashContents, String)>> {
+    let name = &workspace.name;
+    if let Some(pulled) = changes::load_pulled(store, name)? {
+        return Ok(Some((
+            pulled,
+            "the changes pulled from another machine".to_string(),
+        )));
+    }
+    if let Some(captured) = changes::load_captured(store, name)? {
+        return Ok(Some((captured, "the saved patch".to_string())));
+    }
+    if let Some(stash) = &workspace.state.stash_name {
+        if let Some(contents) = git.stash_contents(stash)? {
+            return Ok(Some((contents, format!("stash '{stash}'"))));
+        }
+    }
+    if open {
+        if let Some((contents, taken)) = changes::load_checkpoint(store, name)? {
+            return Ok(Some((
+                contents,
+                format!("the checkpoint from {}", timestamp(taken)),
+            )));
+        }
+    }
+    Ok(None)
+}
+
+/// Each file changed in the checkout or the saved changes, and how the two
+/// compare on it.
+fn compare(
+    here: &BTreeMap<PathBuf, Vec<u8>>,
+    saved: &BTreeMap<PathBuf, Vec<u8>>,
+) -> Vec<(String, &'static str)> {
+    let paths: BTreeSet<&Path> = here
+        .keys()
+        .chain(saved.keys())
+        .map(PathBuf::as_path)
+        .collect();
+    paths
+        .into_iter()
+        .map(|path| {
+            let how = match (here.get(path), saved.get(path)) {
+                (Some(a), Some(b)) if a == b => "the same in both",
+                (Some(_), Some(_)) => "changed in both, differently",
+                (Some(_), None) => "changed here only",
+                (None, _) => "saved only",
+            };
+            (path.display().to_string(), how)
+        })
+        .collect()
+}
+
+fn short(commit: &str) -> &str {
+    commit.get(..7).unwrap_or(commit)
+}
@@ -0,0 +1,175 @@
+//! `desk env`: environment variables per workspace, handed to direnv
+//! through `.envrc.desk` while the workspace is open.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use clap::Subcommand;
+
+use crate::cli::GlobalOptions;
+use crate::core::config::DeskConfig;
+use crate::core::context;
+use crate::core::envrc::{self, DirenvHook, ENVRC_FILE, SOURCE_LINE};
+use crate::core::state::DeskState;
+use crate::core::workspace::Workspace;
+use crate::output::{Reporter, StepEvent, StepKind};
+
+#[derive(Debug, Subcommand)]
+pub enum EnvCommand {
+    /// Show the environment variables of a workspace
+    List { workspace: String },
+    /// Set environment variables: `NAME=VALUE`, or `NAME` to take its value
+    /// from the current environment
+    Set {
+        workspace: String,
+        #[arg(required = true)]
+        vars: Vec<String>,
+    },
+    /// Remove environment variables from a workspace
+    Unset {
+        workspace: String,
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+}
+
+/// Handles `desk env`.
+pub fn handle_env(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    command: &EnvCommand,
+) -> Result<()> {
+    let store = globals.workspace_store()?;
+
+    match command {
+        EnvCommand::List { workspace } => {
+            let ws = store.load(workspace)?;
+            if ws.metadata.env.is_empty() {
+                println!("No environment variables for '{workspace}'; add one with: desk env set {workspace} NAME=VALUE");
+            }
+            for (name, value) in &ws.metadata.env {
+                println!("{name}={value}");
+            }
+        },
+        EnvCommand::Set { workspace, vars } => {
+            let mut ws = store.load(workspace)?;
+            for var in vars {
+                let (name, value) = match var.split_once('=') {
+                    Some((name, value)) => (name, value.to_string()),
+                    None => match std::env::var(var) {
+                        Ok(value) => (var.as_str(), value),
+                        Err(_) => bail!("{var} is not set here; pass {var}=VALUE"),
+                    },
+                };
+                if !envrc::is_valid_name(name) {
+                    bail!("invalid environment variable name '{name}'");
+                }
+                ws.metadata.env.insert(name.to_string(), value);
+            }
+            ws.touch();
+            store.save(&ws)?;
+            let summary = format!("Set {} for '{workspace}'", vars_list(vars));
+            finish(*globals, config, &ws, &summary)?;
+        },
+        EnvCommand::Unset { workspace, names } => {
+            let mut ws = store.load(workspace)?;
+            let removed: Vec<_> = names
+                .iter()
+                .filter(|name| ws.metadata.env.remove(name.as_str()).is_some())
+                .cloned()
+                .collect();
+            if removed.is_empty() {
+                println!("'{workspace}' has none of {}", names.join(", "));
+                return Ok(());
+            }
+            ws.touch();
+            store.save(&ws)?;
+            let summary = format!("Removed {} from '{workspace}'", removed.join(", "));
+            finish(*globals, config, &ws, &summary)?;
+        },
+    }
+    Ok(())
+}
+
+/// The names in `NAME=VALUE` arguments.
+fn vars_list(vars: &[String]) -> String {
+    vars.iter()
+        .map(|var| var.split_once('=').map_or(var.as_str(), |(name, _)| name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Rewrites `.envrc.desk` when `workspace` is the one open, then reports
+/// `summary`.
+fn finish(
+    globals: GlobalOptions,
+    config: &DeskConfig,
+    workspace: &Workspace,
+    summary: &str,
+) -> Result<()> {
+    let mut reporter = globals.reporter();
+    let state = DeskState::load()?;
+    if state.current_workspace(&workspace.repo_path) == Some(workspace.name.as_str()) {
+        write_envrc(config, workspace, reporter.as_mut())?;
+    }
+    reporter.finish(summary);
+    Ok(())
+}
+
+/// Writes the environment of `workspace`, just opened, to `.envrc.desk`
+/// when `[direnv]` asks for it.
+pub fn write_envrc(
+    config: &DeskConfig,
+    workspace: &Workspace,
+    reporter: &mut dyn Reporter,
+) -> Result<()> {
+    if !config.direnv.enabled {
+        return Ok(());
+    }
+    // The workspace's own variables win over the Compose ones saved with it.
+    let mut env: BTreeMap<String, String> = context::compose_env(workspace)
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+    env.extend(workspace.metadata.env.clone());
+    let Some(hook) = envrc::write(checkout(workspace), &workspace.name, &env)? else {
+        return Ok(());
+    };
+    let plural = if env.len() == 1 { "" } else { "s" };
+    reporter.step(StepEvent::done(
+        StepKind::State,
+        format!(
+            "Wrote {} environment variable{plural} to {ENVRC_FILE}",
+            env.len()
+        ),
+    ));
+    // Without these direnv never loads the file, so they show at any
+    // verbosity.
+    match hook {
+        DirenvHook::Present => {},
+        DirenvHook::Created => {
+            eprintln!("Created .envrc to load {ENVRC_FILE}; run `direnv allow` to trust it");
+        },
+        DirenvHook::Missing => {
+            eprintln!("warning: .envrc does not load {ENVRC_FILE}; add `{SOURCE_LINE}` to it");
+        },
+    }
+    Ok(())
+}
+
+/// Removes `.envrc.desk` as `workspace` is closed.
+pub fn remove_envrc(config: &DeskConfig, workspace: &Workspace) -> Result<()> {
+    if config.direnv.enabled {
+        envrc::remove(checkout(workspace))?;
+    }
+    Ok(())
+}
+
+fn checkout(workspace: &Workspace) -> &Path {
+    workspace
+        .state
+        .worktree
+        .as_deref()
+        .unwrap_or(&workspace.repo_path)
+}
@@ -0,0 +1,135 @@
+//! `desk exec`: running a command in another workspace and switching back.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use super::workspace::{handle_close, handle_open, Existing};
+use crate::cli::{Exit, GlobalOptions};
+use crate::core::config::DeskConfig;
+use crate::core::state::DeskState;
+use crate::core::store::Namespace;
+use crate::integrations::git::{Git2Operations, SwitchOptions};
+
+/// Handles `desk exec`.
+///
+/// Opens workspace `name`, runs `command` in its checkout, and opens the
+/// workspace that was open before again, or closes `name` and checks out
+/// the branch the checkout was on. A workspace in its own worktree is left
+/// where it is and the command runs there. Exits with the command's status.
+pub async fn handle_exec(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    name: &str,
+    command: &[String],
+    yes: bool,
+) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let Namespace::Repo(repo) = store.namespace() else {
+        bail!("desk exec needs a git repository; run it inside one");
+    };
+    let workspace = store.load(name)?;
+    let previous = DeskState::load()?.current_workspace(repo).map(String::from);
+
+    if let Some(worktree) = &workspace.state.worktree {
+        return finish(run(&within(repo, worktree), name, command).await);
+    }
+    if previous.as_deref() == Some(name) {
+        return finish(run(&within(repo, repo), name, command).await);
+    }
+
+    let git = Git2Operations::new(repo);
+    // Changes outside any workspace would have nowhere to go meanwhile.
+    let branch = match &previous {
+        Some(_) => None,
+        None if git.has_changes(config.capture.include_untracked)? => bail!(
+            "no workspace is open and the checkout has uncommitted changes; commit or stash \
+             them, or save them as a workspace with `desk open <name>`, first"
+        ),
+        None => Some(
+            git.current_branch()
+                .context("desk exec needs a branch checked out to come back to")?,
+        ),
+    };
+
+    handle_open(globals, config, Some(name), None, Existing::Restore, yes)?;
+    let ran = run(&within(repo, repo), name, command).await;
+
+    let back = match (&previous, &branch) {
+        (Some(previous), _) => handle_open(
+            globals,
+            config,
+            Some(previous),
+            None,
+            Existing::Restore,
+            true,
+        ),
+        (None, Some(branch)) => match handle_close(globals, config).await {
+            Ok(()) => git
+                .switch_branch(branch, &SwitchOptions::default())
+                .map_err(Into::into),
+            Err(e) => Err(e),
+        },
+        (None, None) => Ok(()),
+    };
+    if let Err(e) = back {
+        let undo = previous.map_or_else(
+            || format!("desk close && git switch {}", branch.unwrap_or_default()),
+            |previous| format!("desk open {previous}"),
+        );
+        return Err(e.context(format!(
+            "ran the command in '{name}', but could not switch back; run `{undo}`"
+        )));
+    }
+    finish(ran)
+}
+
+/// The directory in `checkout` that the current one is at in `repo`, or
+/// else the root of `checkout`.
+fn within(repo: &Path, checkout: &Path) -> PathBuf {
+    std::env::current_dir()
+        .ok()
+        .and_then(|cwd| cwd.strip_prefix(repo).ok().map(|rel| checkout.join(rel)))
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(|| checkout.to_path_buf())
+}
+
+/// Runs `command` in `dir`, returning its exit code. Ctrl-C reaches the
+/// command alone, so that desk can still switch back after it.
+async fn run(dir: &Path, workspace: &str, command: &[String]) -> Result<i32> {
+    let Some((program, args)) = command.split_first() else {
+        bail!("no command given; pass one after --");
+    };
+    let mut child = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .env("DESK_WORKSPACE", workspace)
+        .spawn()
+        .with_context(|| format!("failed to run {program}"))?;
+    let interrupts = tokio::spawn(async { while tokio::signal::ctrl_c().await.is_ok() {} });
+    let status = tokio::task::spawn_blocking(move || child.wait()).await?;
+    interrupts.abort();
+    let status = status.with_context(|| format!("failed to wait for {program}"))?;
+    // Killed by a signal: report it the way shells do.
+    Ok(status.code().unwrap_or_else(|| 128 + signal(status)))
+}
+
+#[cfg(unix)]
+fn signal(status: std::process::ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal().unwrap_or(0)
+}
+
+#[cfg(not(unix))]
+const fn signal(_: std::process::ExitStatus) -> i32 {
+    0
+}
+
+/// Passes on the command's exit code, once desk has cleaned up.
+fn finish(ran: Result<i32>) -> Result<()> {
+    match ran? {
+        0 => Ok(()),
+        code => Err(Exit(code).into()),
+    }
+}
@@ -0,0 +1,106 @@
+//! `desk open --from-pr` and `--from-issue`: workspaces for GitHub pull
+//! requests and issues.
+
+use anyhow::{bail, Context, Result};
+
+use super::workspace::{handle_open, Existing, NewWorkspace};
+use crate::cli::GlobalOptions;
+use crate::core::config::DeskConfig;
+use crate::core::store::Namespace;
+use crate::integrations::git::Git2Operations;
+use crate::integrations::github::{self, GitHubClient, GitHubRepo};
+
+/// Remote whose GitHub repository pull requests and issues are looked up in.
+const REMOTE: &str = "origin";
+
+/// What on GitHub a workspace is opened for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Link {
+    PullRequest(u64),
+    Issue(u64),
+}
+
+/// Handles `desk open --from-pr` and `--from-issue`.
+///
+/// A pull request's head is fetched and checked out as its branch, or as
+/// `pr-N` when it comes from a fork. An issue gets a new branch at the
+/// current commit, named after its number and title. Either way the
+/// workspace is named `pr-N` or `issue-N` unless `name` is given, tagged
+/// that way, and described with the title. A workspace that exists already
+/// is opened as it is.
+pub async fn handle_open_linked(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    name: Option<&str>,
+    link: Link,
+    yes: bool,
+) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let Namespace::Repo(repo) = store.namespace() else {
+        bail!("--from-pr and --from-issue need a git repository; run desk inside one");
+    };
+    let tag = match link {
+        Link::PullRequest(number) => format!("pr-{number}"),
+        Link::Issue(number) => format!("issue-{number}"),
+    };
+    let name = name.unwrap_or(&tag);
+    if store.exists(name) {
+        return handle_open(globals, config, Some(name), None, Existing::Restore, yes);
+    }
+
+    let git = Git2Operations::new(repo);
+    let url = git
+        .remote_url(REMOTE)?
+        .with_context(|| format!("the repository has no `{REMOTE}` remote to find GitHub in"))?;
+    let github_repo =
+        GitHubRepo::from_remote_url(&url, config.auth.providers.github.base_url.as_deref())?;
+    let client = GitHubClient::new(&github_repo.host)?;
+
+    let new = match link {
+        Link::PullRequest(number) => {
+            let pr = client.pull_request(&github_repo, number).await?;
+            github::fetch_pull_request(repo, REMOTE, number)?;
+            let same_repo = pr.head.repo.as_ref().is_some_and(|head| {
+                head.full_name
+                    .eq_ignore_ascii_case(&github_repo.to_string())
+            });
+            let branch = if same_repo {
+                pr.head.branch
+            } else {
+                format!("pr-{number}")
+            };
+            // A branch that exists keeps whatever was committed to it here.
+            git.create_branch(&branch, &pr.head.sha)?;
+            NewWorkspace {
+                branch,
+                description: Some(pr.title),
+                tags: vec![tag.clone()],
+                notes: Some(pr.html_url),
+                issue: None,
+            }
+        },
+        Link::Issue(number) => {
+            let issue = client.issue(&github_repo, number).await?;
+            if issue.pull_request.is_some() {
+                bail!("#{number} is a pull request; open it with --from-pr {number}");
+            }
+            let branch = github::issue_branch(number, &issue.title);
+            git.create_branch(&branch, &git.current_commit()?)?;
+            NewWorkspace {
+                branch,
+                description: Some(issue.title),
+                tags: vec![tag.clone()],
+                notes: Some(issue.html_url),
+                issue: None,
+            }
+        },
+    };
+    handle_open(
+        globals,
+        config,
+        Some(name),
+        Some(&new),
+        Existing::Restore,
+        yes,
+    )
+}
@@ -0,0 +1,232 @@
+//! `desk group`: named sets of workspaces, often in different repositories,
+//! opened together with one command.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Subcommand;
+
+use crate::cli::commands::workspace::{self, Existing};
+use crate::cli::{Exit, GlobalOptions};
+use crate::core::config::DeskConfig;
+use crate::core::lock::MutationGuard;
+use crate::core::paths;
+use crate::core::state::{DeskState, GroupMember};
+use crate::core::store::{FileWorkspaceStore, Namespace};
+
+#[derive(Debug, Subcommand)]
+pub enum GroupCommand {
+    /// Create a group of workspaces to open together
+    Create {
+        /// Name of the group
+        name: String,
+
+        /// Workspaces in the group, each as `<name>`, or as `<repo>/<name>`
+        /// for one saved in another repository
+        #[arg(required = true)]
+        members: Vec<String>,
+    },
+
+    /// Restore every workspace in a group, each in its own repository
+    Open {
+        /// Group to open
+        name: String,
+
+        /// Restore the workspaces without showing what each will do first
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// List groups and the workspaces in them
+    #[command(visible_alias = "ls")]
+    List,
+
+    /// Delete a group, leaving its workspaces as they are
+    #[command(visible_alias = "rm")]
+    Delete {
+        /// Group to delete
+        name: String,
+    },
+}
+
+/// Handles `desk group`.
+pub fn handle_group(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    command: &GroupCommand,
+) -> Result<()> {
+    match command {
+        GroupCommand::Create { name, members } => create(*globals, name, members),
+        GroupCommand::Open { name, yes } => open(*globals, config, name, *yes),
+        GroupCommand::List => list(),
+        GroupCommand::Delete { name } => {
+            let _guard = MutationGuard::acquire(&paths::data_dir()?, None)?;
+            let mut state = DeskState::load()?;
+            if state.groups.remove(name).is_none() {
+                bail!("group '{name}' not found");
+            }
+            state.save()?;
+            println!("Deleted group '{name}'; its workspaces are left as they are");
+            Ok(())
+        },
+    }
+}
+
+fn create(globals: GlobalOptions, name: &str, members: &[String]) -> Result<()> {
+    if name.trim().is_empty() {
+        bail!("group names cannot be empty");
+    }
+    let data_dir = paths::data_dir()?;
+    let _guard = MutationGuard::acquire(&data_dir, None)?;
+    let mut state = DeskState::load()?;
+    if state.groups.contains_key(name) {
+        bail!("group '{name}' already exists; delete it first with: desk group delete {name}");
+    }
+    let mut resolved: Vec<GroupMember> = Vec::new();
+    for member in members {
+        let member = resolve(globals, &data_dir, member)?;
+        if !resolved.contains(&member) {
+            resolved.push(member);
+        }
+    }
+    let count = resolved.len();
+    state.groups.insert(name.to_string(), resolved);
+    state.save()?;
+    let plural = if count == 1 { "" } else { "s" };
+    println!(
+        "Created group '{name}' of {count} workspace{plural}; open it with: desk group open {name}"
+    );
+    Ok(())
+}
+
+/// The workspace `arg` names: `<repo>/<name>` in that repository, or else
+/// `<name>` in the current one, or in the one other repository that has it.
+fn resolve(globals: GlobalOptions, data_dir: &Path, arg: &str) -> Result<GroupMember> {
+    let path = Path::new(arg);
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .with_context(|| format!("'{arg}' does not name a workspace"))?;
+    if let Some(dir) = dir {
+        let dir = dir
+            .canonicalize()
+            .with_context(|| format!("repository {} not found", dir.display()))?;
+        let Namespace::Repo(repo) = Namespace::resolve(&dir, false) else {
+            bail!("{} is not in a git repository", dir.display());
+        };
+        let store = FileWorkspaceStore::new(data_dir, Namespace::Repo(repo.clone()));
+        if !store.exists(name) {
+            bail!("workspace '{name}' not found in {}", repo.display());
+        }
+        return Ok(member(&repo, name));
+    }
+
+    let store = globals.workspace_store()?;
+    if let Namespace::Repo(repo) = store.namespace() {
+        if store.exists(name) {
+            return Ok(member(repo, name));
+        }
+    }
+    let found: Vec<PathBuf> = FileWorkspaceStore::all(data_dir)?
+        .into_iter()
+        .filter(|store| store.exists(name))
+        .filter_map(|store| match store.namespace() {
+            Namespace::Repo(repo) => Some(repo.clone()),
+            Namespace::Global => None,
+        })
+        .collect();
+    match found.as_slice() {
+        [] => bail!("workspace '{name}' not found in any repository"),
+        [repo] => Ok(member(repo, name)),
+        repos => {
+            let repos: Vec<_> = repos
+                .iter()
+                .map(|repo| repo.display().to_string())
+                .collect();
+            bail!(
+                "workspace '{name}' is saved in {}; pick one as <repo>/{name}",
+                repos.join(", ")
+            )
+        },
+    }
+}
+
+fn member(repo: &Path, name: &str) -> GroupMember {
+    GroupMember {
+        repo: DeskState::repo_key(repo),
+        workspace: name.to_string(),
+    }
+}
+
+/// Opens each workspace of group `name` in turn, in its own repository,
+/// going on past the ones that fail and reporting on all of them at the end.
+fn open(globals: GlobalOptions, config: &DeskConfig, name: &str, yes: bool) -> Result<()> {
+    let state = DeskState::load()?;
+    let Some(members) = state.groups.get(name) else {
+        bail!("group '{name}' not found; create it with: desk group create {name} <workspace>...");
+    };
+    let cwd = std::env::current_dir()?;
+    let mut results = Vec::new();
+    for member in members {
+        println!("Opening '{}' in {}", member.workspace, member.repo);
+        let opened = std::env::set_current_dir(&member.repo)
+            .with_context(|| format!("repository {} not found", member.repo))
+            .and_then(|()| {
+                workspace::handle_open(
+                    &globals,
+                    config,
+                    Some(&member.workspace),
+                    None,
+                    Existing::Restore,
+                    yes,
+                )
+            });
+        results.push((member, opened));
+    }
+    std::env::set_current_dir(&cwd)
+        .with_context(|| format!("failed to change back to {}", cwd.display()))?;
+
+    println!();
+    println!("Group '{name}':");
+    let width = results
+        .iter()
+        .map(|(member, _)| member.repo.len())
+        .max()
+        .unwrap_or(0);
+    let mut failed = 0;
+    for (member, opened) in &results {
+        let repo = &member.repo;
+        match opened {
+            Ok(()) => println!("  {repo:<width$}  '{}' open", member.workspace),
+            Err(e) => {
+                failed += 1;
+                println!("  {repo:<width$}  '{}' failed: {e:#}", member.workspace);
+            },
+        }
+    }
+    if failed > 0 {
+        return Err(Exit(1).into());
+    }
+    Ok(())
+}
+
+fn list() -> Result<()> {
+    let state = DeskState::load()?;
+    if state.groups.is_empty() {
+        println!("No groups yet; create one with: desk group create <name> <workspace>...");
+        return Ok(());
+    }
+    for (name, members) in &state.groups {
+        println!("{name}");
+        let width = members
+            .iter()
+            .map(|member| member.workspace.len())
+            .max()
+            .unwrap_or(0);
+        for member in members {
+            println!("  {:<width$}  {}", member.workspace, member.repo);
+        }
+    }
+    Ok(())
+}
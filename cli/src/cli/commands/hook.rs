@@ -0,0 +1,140 @@
+//! `desk hook`: the commands configured in `[hooks]`, in `config.toml` and a
+//! repository's `.desk.toml`, and how they last ran.
+
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use clap::Subcommand;
+
+use crate::core::config::{DeskConfig, HooksConfig};
+use crate::core::hooks::{self, HookRuns, HookStage};
+use crate::core::paths;
+use crate::core::repo_config::{RepoConfigFile, TrustedConfigs, REPO_CONFIG_FILE};
+use crate::core::store::Namespace;
+use crate::output::timestamp::timestamp;
+
+#[derive(Debug, Subcommand)]
+pub enum HookCommand {
+    /// Show the configured hooks with their timeout, failure policy, and last
+    /// run
+    List,
+    /// Let the hooks in this repository's `.desk.toml` run, until the file
+    /// changes
+    Trust,
+    /// Stop running the hooks in this repository's `.desk.toml`
+    Untrust,
+}
+
+/// Handles `desk hook`.
+pub fn handle_hook(config: &DeskConfig, command: &HookCommand) -> Result<()> {
+    let data_dir = paths::data_dir()?;
+    let root = repo_root()?;
+    let shared = match &root {
+        Some(root) => RepoConfigFile::load(root)?,
+        None => None,
+    };
+    match command {
+        HookCommand::List => {
+            let runs = HookRuns::load(&data_dir)?;
+            let shared = shared.filter(|f| has_hooks(&f.config.hooks));
+            if shared.is_none() && !has_hooks(&config.hooks) {
+                println!("No hooks configured; add them under [hooks] in config.toml");
+                return Ok(());
+            }
+            if let Some(file) = shared {
+                let trust = if TrustedConfigs::load(&data_dir)?.trusts(&file) {
+                    "trusted"
+                } else {
+                    "not trusted; run `desk hook trust` to allow them"
+                };
+                println!("{} ({trust}):", file.path.display());
+                print_hooks(&file.config.hooks, &runs);
+            }
+            if has_hooks(&config.hooks) {
+                println!("{}:", paths::config_file()?.display());
+                print_hooks(&config.hooks, &runs);
+            }
+            println!(
+                "\nOutput is logged to {}",
+                hooks::log_path(&data_dir).display()
+            );
+        },
+        HookCommand::Trust => {
+            let Some(file) = shared else {
+                bail!("no {REPO_CONFIG_FILE} at the root of this repository");
+            };
+            let mut trusted = TrustedConfigs::load(&data_dir)?;
+            trusted.trust(&file);
+            trusted.save(&data_dir)?;
+            println!("Trusted the hooks in {}:", file.path.display());
+            for stage in HookStage::ALL {
+                for hook in stage.hooks(&file.config.hooks) {
+                    println!("  {stage}: {}", hook.command());
+                }
+            }
+        },
+        HookCommand::Untrust => {
+            let Some(root) = root else {
+                bail!("this command needs a git repository; run it inside one");
+            };
+            let path = root.join(REPO_CONFIG_FILE);
+            let mut trusted = TrustedConfigs::load(&data_dir)?;
+            if trusted.revoke(&path) {
+                trusted.save(&data_dir)?;
+                println!("{} is no longer trusted", path.display());
+            } else {
+                println!("{} was not trusted", path.display());
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Root of the repository desk runs in, if any.
+fn repo_root() -> Result<Option<PathBuf>> {
+    match Namespace::resolve(&std::env::current_dir()?, false) {
+        Namespace::Repo(root) => Ok(Some(root)),
+        Namespace::Global => Ok(None),
+    }
+}
+
+fn has_hooks(config: &HooksConfig) -> bool {
+    HookStage::ALL
+        .iter()
+        .any(|stage| !stage.hooks(config).is_empty())
+}
+
+fn print_hooks(config: &HooksConfig, runs: &HookRuns) {
+    for stage in HookStage::ALL {
+        let hooks = stage.hooks(config);
+        if hooks.is_empty() {
+            continue;
+        }
+        println!("  {stage}:");
+        for hook in hooks {
+            let timeout = hook.timeout(config).map_or_else(
+                || "no timeout".to_string(),
+                |t| format!("timeout {}s", t.as_secs()),
+            );
+            let mut settings = format!("{}, {timeout}", hook.shell(config));
+            // Hooks that run after the fact have nothing to abort.
+            if stage.can_stop() {
+                let _ = write!(settings, ", on failure: {}", hook.on_failure(config));
+            }
+            println!("    {}  ({settings})", hook.command());
+            match runs.last(stage, hook.command()) {
+                Some(run) => {
+                    println!(
+                        "      last run {}: {} in {:.1}s",
+                        timestamp(run.started_at),
+                        run.outcome,
+                        Duration::from_millis(run.duration_ms).as_secs_f64()
+                    );
+                },
+                None => println!("      never run"),
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! `desk open --issue`: workspaces for Jira and Linear tickets, moved along
+//! their workflow on `desk close`.
+
+use anyhow::{bail, Result};
+
+use super::workspace::{handle_open, Existing, NewWorkspace};
+use crate::cli::GlobalOptions;
+use crate::client::http;
+use crate::core::config::DeskConfig;
+use crate::core::store::Namespace;
+use crate::core::workspace::{IssueLink, IssueTracker, Workspace};
+use crate::integrations::git::Git2Operations;
+use crate::integrations::issues::{self, IssueClient};
+use crate::output::{Reporter, StepEvent, StepKind};
+
+/// Handles `desk open --issue`.
+///
+/// Looks `key` up in the configured tracker and creates a workspace named
+/// after it, unless `name` is given, on a new branch at the current commit,
+/// described by the ticket's title. A workspace that exists already is
+/// opened as it is.
+pub async fn handle_open_issue(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    name: Option<&str>,
+    key: &str,
+    yes: bool,
+) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let Namespace::Repo(repo) = store.namespace() else {
+        bail!("--issue needs a git repository; run desk inside one");
+    };
+    let name = name.unwrap_or(key);
+    if store.exists(name) {
+        return handle_open(globals, config, Some(name), None, Existing::Restore, yes);
+    }
+
+    let client = IssueClient::new(http::client_builder(&config.api)?.build()?, &config.issues)?;
+    let ticket = client.ticket(key).await?;
+    let git = Git2Operations::new(repo);
+    let branch = issues::branch_name(&ticket);
+    // A branch that exists keeps whatever was committed to it here.
+    git.create_branch(&branch, &git.current_commit()?)?;
+    let new = NewWorkspace {
+        branch,
+        description: Some(ticket.title),
+        tags: Vec::new(),
+        notes: Some(ticket.url),
+        issue: Some(IssueLink {
+            tracker: client.tracker(),
+            key: ticket.key,
+        }),
+    };
+    handle_open(
+        globals,
+        config,
+        Some(name),
+        Some(&new),
+        Existing::Restore,
+        yes,
+    )
+}
+
+/// Moves the ticket of `workspace`, just closed, to the state configured
+/// for its tracker. Failing to is a warning: the workspace is closed either
+/// way.
+pub async fn move_on_close(
+    config: &DeskConfig,
+    workspace: &Workspace,
+    reporter: &mut dyn Reporter,
+) {
+    let Some(link) = &workspace.metadata.issue else {
+        return;
+    };
+    let to = match link.tracker {
+        IssueTracker::Jira => config.issues.jira.close_transition.as_deref(),
+        IssueTracker::Linear => config.issues.linear.close_state.as_deref(),
+    };
+    let Some(to) = to else {
+        return;
+    };
+    let moved = async {
+        let http = http::client_builder(&config.api)?.build()?;
+        let client = IssueClient::for_tracker(http, &config.issues, link.tracker)?;
+        anyhow::Ok(client.transition(&link.key, to).await?)
+    };
+    match moved.await {
+        Ok(true) => reporter.step(StepEvent::done(
+            StepKind::State,
+            format!("Moved {} to {to}", link.key),
+        )),
+        Ok(false) => {},
+        Err(e) => eprintln!("warning: could not move {link} to '{to}': {e:#}"),
+    }
+}
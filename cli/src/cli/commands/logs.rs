@@ -0,0 +1,112 @@
+//! `desk logs`: reading back desk's own log.
+
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+
+use crate::core::{logs, paths};
+
+/// How often `desk logs --follow` looks for new lines.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Handles `desk logs`.
+///
+/// Prints the last `lines` lines of the log, reaching into rotated files when
+/// the current one has fewer, and with `follow` keeps printing lines as they
+/// are written until stopped.
+pub async fn handle_logs(lines: usize, follow: bool) -> Result<()> {
+    let data_dir = paths::data_dir()?;
+    let path = logs::log_path(&data_dir);
+    if !path.is_file() && !follow {
+        eprintln!(
+            "Nothing logged yet; desk writes its log to {}",
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let mut tail = Vec::new();
+    let mut read = 0;
+    if let Some(text) = read_lossy(&path)? {
+        read = text.len() as u64;
+        tail = last_lines(&text, lines);
+    }
+    for rotated in logs::rotated_paths(&data_dir) {
+        if tail.len() >= lines {
+            break;
+        }
+        if let Some(text) = read_lossy(&rotated)? {
+            let mut older = last_lines(&text, lines - tail.len());
+            older.append(&mut tail);
+            tail = older;
+        }
+    }
+    if !print(&mut std::io::stdout().lock(), tail.concat().as_bytes())? {
+        return Ok(());
+    }
+    if follow {
+        return follow_from(&path, read).await;
+    }
+    Ok(())
+}
+
+/// Prints what is appended to `path` past `offset` as it is written, starting
+/// over when the file is rotated.
+async fn follow_from(path: &Path, mut offset: u64) -> Result<()> {
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let Ok(mut file) = File::open(path) else {
+            offset = 0;
+            continue;
+        };
+        let len = file.metadata()?.len();
+        if len < offset {
+            offset = 0;
+        }
+        if len == offset {
+            continue;
+        }
+        file.seek(SeekFrom::Start(offset))?;
+        let mut new = Vec::new();
+        file.take(len - offset).read_to_end(&mut new)?;
+        // A line still being written is printed once it is complete.
+        let Some(end) = new.iter().rposition(|&b| b == b'\n') else {
+            continue;
+        };
+        offset += end as u64 + 1;
+        if !print(&mut std::io::stdout().lock(), &new[..=end])? {
+            return Ok(());
+        }
+    }
+}
+
+/// The contents of `path`, or `None` if there is no such file.
+fn read_lossy(path: &Path) -> Result<Option<String>> {
+    match std::fs::read(path) {
+        Ok(bytes) => Ok(Some(String::from_utf8_lossy(&bytes).into_owned())),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// The last `count` lines of `text`, each with its line break.
+fn last_lines(text: &str, count: usize) -> Vec<String> {
+    let lines: Vec<_> = text.split_inclusive('\n').collect();
+    lines[lines.len().saturating_sub(count)..]
+        .iter()
+        .map(|line| (*line).to_string())
+        .collect()
+}
+
+/// Writes `bytes` to `out`; `false` once whoever reads it has gone, such as
+/// `head`.
+fn print(out: &mut impl Write, bytes: &[u8]) -> Result<bool> {
+    match out.write_all(bytes).and_then(|()| out.flush()) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,274 @@
+//! `desk mcp`: workspaces as Model Context Protocol tools, so coding
+//! assistants can see and switch development contexts.
+//!
+//! Reading tools work on the store directly. Tools that change the checkout
+//! run `desk open` as a child process, so that its progress, prompts, and
+//! hooks cannot write over the protocol on stdout.
+
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::daemon::desk_command;
+use super::status::prompt_info;
+use crate::core::paths;
+use crate::core::state::DeskState;
+use crate::core::store::{FileWorkspaceStore, Namespace};
+use crate::core::sync::{classify_local, sync_key, sync_scope};
+use crate::mcp::{self, Tool, ToolOutput, ToolProvider};
+
+/// Handles `desk mcp`, until the client closes stdin.
+pub fn handle_mcp() -> Result<()> {
+    mcp::serve(
+        std::io::stdin().lock(),
+        std::io::stdout().lock(),
+        &DeskTools,
+    )?;
+    Ok(())
+}
+
+/// Arguments naming the repository, and optionally a workspace, a tool acts
+/// on.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Target {
+    name: Option<String>,
+    /// Repository, or a directory inside it; defaults to where `desk mcp`
+    /// was started.
+    repo: Option<PathBuf>,
+    /// Workspaces not tied to a repository.
+    global: bool,
+}
+
+impl Target {
+    fn dir(&self) -> Result<PathBuf> {
+        match &self.repo {
+            Some(repo) if !repo.is_dir() => bail!("{} is not a directory", repo.display()),
+            Some(repo) => Ok(repo.clone()),
+            None => Ok(std::env::current_dir()?),
+        }
+    }
+
+    fn store(&self) -> Result<FileWorkspaceStore> {
+        Ok(FileWorkspaceStore::new(
+            &paths::data_dir()?,
+            Namespace::resolve(&self.dir()?, self.global),
+        ))
+    }
+
+    fn name(&self) -> Result<&str> {
+        self.name
+            .as_deref()
+            .filter(|name| !name.trim().is_empty())
+            .context("`name` is required")
+    }
+}
+
+/// A workspace as `list_workspaces` describes it.
+#[derive(Debug, Serialize)]
+struct Summary<'a> {
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    branch: &'a str,
+    open: bool,
+    locked: bool,
+    tags: &'a [String],
+    has_notes: bool,
+    updated_at: DateTime<Utc>,
+    sync: &'static str,
+}
+
+struct DeskTools;
+
+impl ToolProvider for DeskTools {
+    fn tools(&self) -> Vec<Tool> {
+        let target = |extra: Value| {
+            let mut properties = json!({
+                "repo": {
+                    "type": "string",
+                    "description": "Path of the git repository; defaults to the directory desk was started in",
+                },
+                "global": {
+                    "type": "boolean",
+                    "description": "Use workspaces that are not tied to a repository",
+                },
+            });
+            let mut required = Vec::new();
+            if let Value::Object(extra) = extra {
+                for (key, schema) in extra {
+                    required.push(key.clone());
+                    properties[key] = schema;
+                }
+            }
+            json!({ "type": "object", "properties": properties, "required": required })
+        };
+        let name = || json!({ "name": { "type": "string", "description": "Workspace name" } });
+        vec![
+            Tool {
+                name: "list_workspaces",
+                description: "List the workspaces of a repository with their branch, tags, \
+                    whether one is open, and their sync state",
+                input_schema: target(Value::Null),
+            },
+            Tool {
+                name: "show_workspace",
+                description: "Show everything saved for one workspace, including its notes, \
+                    description, commit, and time spent",
+                input_schema: target(name()),
+            },
+            Tool {
+                name: "workspace_status",
+                description: "Show the workspace open in a repository, since when, and how \
+                    the checkout drifted from it (other branch, new commits, uncommitted changes)",
+                input_schema: target(Value::Null),
+            },
+            Tool {
+                name: "open_workspace",
+                description: "Save the open workspace and switch the checkout to another, \
+                    creating it from the current branch if it does not exist",
+                input_schema: target(name()),
+            },
+            Tool {
+                name: "save_workspace",
+                description: "Save the current checkout as a workspace, overwriting what it \
+                    had saved, and make it the open one",
+                input_schema: target(name()),
+            },
+        ]
+    }
+
+    fn call(&self, name: &str, arguments: &Value) -> Option<ToolOutput> {
+        let result = serde_json::from_value::<Target>(arguments.clone())
+            .context("invalid arguments")
+            .and_then(|target| {
+                Ok(Some(match name {
+                    "list_workspaces" => list(&target)?,
+                    "show_workspace" => show(&target)?,
+                    "workspace_status" => status(&target)?,
+                    "open_workspace" => run_desk(&target, &["open", target.name()?, "--yes"])?,
+                    "save_workspace" => {
+                        run_desk(&target, &["open", target.name()?, "--force", "--yes"])?
+                    },
+                    _ => return Ok(None),
+                }))
+            });
+        match result {
+            Ok(output) => output,
+            Err(e) => Some(ToolOutput::error(format!("{e:#}"))),
+        }
+    }
+}
+
+fn list(target: &Target) -> Result<ToolOutput> {
+    let store = target.store()?;
+    let state = DeskState::load()?;
+    let current = current_workspace(&store, &state);
+    let scope = sync_scope(store.namespace());
+    let workspaces = store.list()?;
+    let summaries: Vec<_> = workspaces
+        .iter()
+        .map(|workspace| Summary {
+            name: &workspace.name,
+            description: workspace.description.as_deref(),
+            branch: &workspace.state.branch,
+            open: current == Some(workspace.name.as_str()),
+            locked: workspace.metadata.locked,
+            tags: &workspace.metadata.tags,
+            has_notes: workspace.metadata.notes.is_some(),
+            updated_at: workspace.updated_at,
+            sync: classify_local(
+                workspace,
+                state.sync.get(&sync_key(&scope, &workspace.name)),
+            )
+            .label(),
+        })
+        .collect();
+    json_output(&json!({
+        "namespace": store.namespace().to_string(),
+        "workspaces": summaries,
+    }))
+}
+
+fn show(target: &Target) -> Result<ToolOutput> {
+    let store = target.store()?;
+    let name = target.name()?;
+    if !store.exists(name) {
+        return Ok(ToolOutput::error(format!(
+            "no workspace '{name}' in {}",
+            store.namespace()
+        )));
+    }
+    let workspace = store.load(name)?;
+    let state = DeskState::load()?;
+    let open = current_workspace(&store, &state) == Some(name);
+    let mut value = serde_json::to_value(&workspace)?;
+    value["open"] = json!(open);
+    // Environment variables often hold secrets; their names are enough.
+    if let Some(env) = value["metadata"].get_mut("env") {
+        *env = json!(workspace.metadata.env.keys().collect::<Vec<_>>());
+    }
+    json_output(&value)
+}
+
+fn status(target: &Target) -> Result<ToolOutput> {
+    let store = target.store()?;
+    let Namespace::Repo(repo) = store.namespace() else {
+        return Ok(ToolOutput::error(
+            "not in a git repository; pass `repo` to name one",
+        ));
+    };
+    let state = DeskState::load()?;
+    let open = prompt_info(&paths::data_dir()?, &state, repo, true)?;
+    json_output(&json!({
+        "repository": repo,
+        "open": open,
+        "opened_at": open.as_ref().and_then(|_| state.opened_at(repo)),
+    }))
+}
+
+/// The workspace open where `store` keeps workspaces, if any.
+fn current_workspace<'a>(store: &FileWorkspaceStore, state: &'a DeskState) -> Option<&'a str> {
+    match store.namespace() {
+        Namespace::Repo(repo) => state.current_workspace(repo),
+        Namespace::Global => None,
+    }
+}
+
+/// Runs `desk <args>` in the target repository, answering with everything
+/// it printed.
+fn run_desk(target: &Target, args: &[&str]) -> Result<ToolOutput> {
+    let dir = target.dir()?;
+    let mut command = desk_command()?;
+    if target.global {
+        command.arg("--global");
+    }
+    let output = command
+        .args(args)
+        .args(["--verbosity", "steps"])
+        .current_dir(&dir)
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("failed to run desk {}", args.join(" ")))?;
+    let mut text = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let errors = String::from_utf8_lossy(&output.stderr);
+    if !errors.trim().is_empty() {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(errors.trim());
+    }
+    if output.status.success() {
+        Ok(ToolOutput::ok(text))
+    } else {
+        Ok(ToolOutput::error(text))
+    }
+}
+
+fn json_output(value: &impl Serialize) -> Result<ToolOutput> {
+    Ok(ToolOutput::ok(serde_json::to_string_pretty(value)?))
+}
@@ -0,0 +1,33 @@
+//! Command handlers.
+
+pub mod auth;
+pub mod backup;
+pub mod budget;
+pub mod config;
+pub mod daemon;
+pub mod drift;
+pub mod env;
+pub mod exec;
+pub mod github;
+pub mod group;
+pub mod hook;
+pub mod issues;
+pub mod logs;
+pub mod mcp;
+pub mod plugin;
+pub mod reminders;
+pub mod search;
+pub mod share;
+pub mod shell;
+pub mod standup;
+pub mod state;
+pub mod stats;
+pub mod status;
+pub mod sync;
+pub mod time;
+pub mod todo;
+pub mod trash;
+pub mod usage;
+pub mod watch;
+pub mod workspace;
+pub mod worktree;
@@ -0,0 +1,117 @@
+//! External subcommands: `desk foo` runs a `desk-foo` executable found on
+//! `PATH`, the way git and cargo do, so desk can be extended without
+//! changing it.
+//!
+//! The executable gets the arguments after the subcommand and what desk
+//! knows about where it runs in environment variables:
+//!
+//! - `DESK_BIN`: the desk executable, for calling back into desk
+//! - `DESK_DATA_DIR`: the data directory, and `DESK_PROFILE` when a profile
+//!   is selected, so that desk called back uses the same ones
+//! - `DESK_REPO`: the repository root, unset outside a repository
+//! - `DESK_WORKSPACE`: the workspace open there, unset when there is none
+//! - `DESK_SIGNED_IN`: `1` when desk has credentials for the desk API,
+//!   `0` otherwise; the credentials themselves are not passed
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+use tracing::debug;
+
+use crate::auth::credentials;
+use crate::cli::GlobalOptions;
+use crate::core::config::PROFILE_ENV;
+use crate::core::paths::{self, DATA_DIR_ENV};
+use crate::core::state::DeskState;
+use crate::core::store::Namespace;
+
+/// Prefix of the executables that provide external subcommands.
+const PREFIX: &str = "desk-";
+
+/// Handles a subcommand desk does not know, `args[0]`, by running the
+/// `desk-<name>` executable with the rest of `args`, exiting with its status.
+pub fn handle_external(globals: &GlobalOptions, args: &[String]) -> Result<()> {
+    let Some((name, args)) = args.split_first() else {
+        bail!("no subcommand given; see desk --help");
+    };
+    let Some(program) = find_executable(&format!("{PREFIX}{name}")) else {
+        bail!("'{name}' is not a desk command, and there is no {PREFIX}{name} on PATH; see desk --help");
+    };
+    debug!("running {} for `desk {name}`", program.display());
+
+    let mut command = Command::new(&program);
+    command
+        .args(args)
+        .env(DATA_DIR_ENV, paths::data_dir()?)
+        .env("DESK_SIGNED_IN", if signed_in() { "1" } else { "0" })
+        .env_remove("DESK_REPO")
+        .env_remove("DESK_WORKSPACE");
+    if let Ok(desk) = std::env::current_exe() {
+        command.env("DESK_BIN", desk);
+    }
+    if let Some(profile) = paths::profile() {
+        command.env(PROFILE_ENV, profile);
+    }
+    let cwd = std::env::current_dir()?;
+    if let Namespace::Repo(repo) = Namespace::resolve(&cwd, globals.global) {
+        if let Some(workspace) = DeskState::load()?.current_workspace(&repo) {
+            command.env("DESK_WORKSPACE", workspace);
+        }
+        command.env("DESK_REPO", repo);
+    }
+    run(command, &program)
+}
+
+/// Replaces desk with `command`, so signals and the exit status are the
+/// executable's own.
+#[cfg(unix)]
+fn run(mut command: Command, program: &Path) -> Result<()> {
+    use std::os::unix::process::CommandExt;
+    let error = command.exec();
+    Err(error).with_context(|| format!("failed to run {}", program.display()))
+}
+
+#[cfg(not(unix))]
+fn run(mut command: Command, program: &Path) -> Result<()> {
+    let status = command
+        .status()
+        .with_context(|| format!("failed to run {}", program.display()))?;
+    match status.code() {
+        Some(0) => Ok(()),
+        code => Err(crate::cli::Exit(code.unwrap_or(1)).into()),
+    }
+}
+
+/// Whether desk has credentials for the desk API, without checking that
+/// they still work.
+fn signed_in() -> bool {
+    credentials::load()
+        .map_err(|e| debug!("cannot read credentials: {e}"))
+        .is_ok_and(|credentials| credentials.is_some())
+}
+
+/// The first executable called `name` in a directory on `PATH`; none for a
+/// name that is a path rather than a file name, which could reach outside
+/// the directory.
+fn find_executable(name: &str) -> Option<PathBuf> {
+    if name.contains(['/', '\\']) || name.contains("..") {
+        return None;
+    }
+    let file = format!("{name}{}", std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(&file))
+        .find(|path| is_executable(path))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
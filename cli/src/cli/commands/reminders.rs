@@ -0,0 +1,63 @@
+//! `desk reminders`: workspaces left alone long enough that they may be
+//! done with, or forgotten.
+
+use anyhow::Result;
+
+use crate::core::config::DeskConfig;
+use crate::core::paths;
+use crate::core::reminders::stale_workspaces;
+use crate::core::state::DeskState;
+use crate::output::table::{terminal_width, Table};
+use crate::output::timestamp::timestamp;
+
+/// Handles `desk reminders`.
+///
+/// Lists the workspaces in every repository that were not opened or saved
+/// for `days` days, `reminders.stale_after_days` by default, with what they
+/// were for and how many files they keep uncommitted changes to.
+pub fn handle_reminders(config: &DeskConfig, days: Option<u64>) -> Result<()> {
+    let days = days.unwrap_or(config.reminders.stale_after_days);
+    if days == 0 {
+        println!("Reminders are off; set reminders.stale_after_days to turn them on");
+        return Ok(());
+    }
+    let stale = stale_workspaces(&paths::data_dir()?, &DeskState::load()?, days)?;
+    let plural = if days == 1 { "" } else { "s" };
+    if stale.is_empty() {
+        println!("Every workspace was opened or saved in the last {days} day{plural}");
+        return Ok(());
+    }
+
+    println!("Untouched for over {days} day{plural}:");
+    println!();
+    let mut table = Table::new(&[
+        "NAME",
+        "REPOSITORY",
+        "BRANCH",
+        "CHANGES",
+        "LAST USED",
+        "DESCRIPTION",
+    ]);
+    for workspace in &stale {
+        table.row(vec![
+            workspace.name.clone(),
+            workspace.namespace.to_string(),
+            workspace.branch.clone(),
+            workspace.changed_files.map_or_else(String::new, |count| {
+                let plural = if count == 1 { "" } else { "s" };
+                format!("{count} file{plural}")
+            }),
+            timestamp(workspace.last_used),
+            workspace.description.clone().unwrap_or_default(),
+        ]);
+    }
+    for line in table.render(terminal_width()) {
+        println!("{line}");
+    }
+    println!();
+    println!(
+        "Finish one with `desk open <name>` in its repository, or put it away with `desk delete \
+         <name>`"
+    );
+    Ok(())
+}
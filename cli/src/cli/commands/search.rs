@@ -0,0 +1,58 @@
+//! `desk search`: find workspaces by name, branch, tags, description, or notes.
+
+use anyhow::Result;
+
+use crate::cli::GlobalOptions;
+use crate::core::paths;
+use crate::core::search::{search, trim_note_matches};
+use crate::core::store::{FileWorkspaceStore, Namespace};
+use crate::output::style::{highlight, use_color};
+
+/// Handles `desk search`.
+///
+/// Searches every repository's workspaces, or only global ones with `--global`.
+pub fn handle_search(globals: &GlobalOptions, query: &[String], limit: usize) -> Result<()> {
+    let data_dir = paths::data_dir()?;
+    let stores = if globals.global {
+        vec![FileWorkspaceStore::new(&data_dir, Namespace::Global)]
+    } else {
+        FileWorkspaceStore::all(&data_dir)?
+    };
+
+    let mut workspaces = Vec::new();
+    for store in &stores {
+        workspaces.extend(store.list()?);
+    }
+
+    let query = query.join(" ");
+    let hits = search(&workspaces, &query);
+    if hits.is_empty() {
+        println!("No workspaces match '{query}'");
+        return Ok(());
+    }
+
+    let color = use_color();
+    for hit in hits.iter().take(limit) {
+        println!(
+            "{}  ({})",
+            hit.workspace.name,
+            hit.workspace.repo_path.display()
+        );
+        for m in trim_note_matches(&hit.matches) {
+            println!(
+                "    {:<12} {}",
+                format!("{}:", m.field.label()),
+                highlight(&m.text, &m.ranges, color)
+            );
+        }
+    }
+
+    if hits.len() > limit {
+        println!();
+        println!(
+            "{} more matches; use --limit to show more",
+            hits.len() - limit
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,82 @@
+//! `desk share`: publish read-only views of a workspace.
+
+use anyhow::Result;
+use clap::Subcommand;
+
+use crate::cli::GlobalOptions;
+use crate::core::config::{DeskConfig, ShareTarget};
+use crate::core::snapshot;
+use crate::core::state::DeskState;
+use crate::core::status_cache;
+use crate::integrations::git::Git2Operations;
+use crate::integrations::paste::{self, ShareError};
+
+#[derive(Debug, Subcommand)]
+pub enum ShareCommand {
+    /// Publish a sanitized Markdown snapshot of a workspace and print its URL
+    Snapshot {
+        /// Workspace to share
+        name: String,
+
+        /// Where to publish (defaults to `share.target` in config.toml)
+        #[arg(long, value_enum)]
+        to: Option<ShareTarget>,
+
+        /// Print the snapshot instead of uploading it
+        #[arg(long)]
+        stdout: bool,
+    },
+}
+
+/// Handles `desk share`.
+pub async fn handle_share(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    command: &ShareCommand,
+) -> Result<()> {
+    let ShareCommand::Snapshot { name, to, stdout } = command;
+    let store = globals.workspace_store()?;
+    let workspace = store.load(name)?;
+
+    // Uncommitted changes on disk only belong to this workspace while it is open.
+    let state = DeskState::load()?;
+    let open = state.current_workspace(&workspace.repo_path) == Some(workspace.name.as_str());
+    let diff = if open && !globals.no_status {
+        status_cache::diff_stat(&Git2Operations::new(&workspace.repo_path)).ok()
+    } else {
+        None
+    };
+    let markdown = snapshot::render(&workspace, diff.as_ref());
+
+    if *stdout {
+        print!("{markdown}");
+        return Ok(());
+    }
+
+    let client = paste::http_client()?;
+    let url = match to.unwrap_or(config.share.target) {
+        ShareTarget::Gist => {
+            let token = paste::github_token_from_env().ok_or(ShareError::MissingToken)?;
+            paste::create_gist(
+                &client,
+                &token,
+                &format!("{}.md", workspace.name),
+                &format!("desk snapshot: {}", workspace.name),
+                &markdown,
+                config.share.public,
+            )
+            .await?
+        },
+        ShareTarget::Paste => {
+            let url = config
+                .share
+                .paste_url
+                .as_deref()
+                .ok_or(ShareError::MissingPasteUrl)?;
+            paste::post_paste(&client, url, &markdown).await?
+        },
+    };
+
+    println!("{url}");
+    Ok(())
+}
@@ -0,0 +1,234 @@
+//! `desk shell-init`: a `desk` shell function that follows desk around.
+//!
+//! A program cannot change the directory or environment of the shell that
+//! started it. The function from `desk shell-init` hands desk a file to
+//! write shell commands to in `DESK_SHELL_FILE`, and sources it once desk
+//! exits: `desk open` asks it to change into the workspace's checkout and
+//! export `DESK_WORKSPACE`, and `desk close` to unset it again.
+//!
+//! It also runs `desk shell-hint` on changing into another repository, which
+//! points out a workspace that was active there when the checkout has moved
+//! to another branch.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use anyhow::Result;
+use tracing::{debug, warn};
+
+use crate::core::paths;
+use crate::core::state::DeskState;
+use crate::core::store::{FileWorkspaceStore, Namespace};
+use crate::core::suggest::recent;
+use crate::core::workspace::Workspace;
+use crate::integrations::git::Git2Operations;
+
+/// File the shell function sources after desk exits.
+pub const SHELL_FILE_ENV: &str = "DESK_SHELL_FILE";
+
+/// Shell the function runs in, which decides the syntax desk writes.
+const SHELL_ENV: &str = "DESK_SHELL";
+
+/// Shells `desk shell-init` knows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Handles `desk shell-init`; without `hints`, changing directory runs
+/// nothing.
+pub fn handle_shell_init(shell: Shell, hints: bool) -> Result<()> {
+    let (function, hook) = match shell {
+        Shell::Bash => (posix_function("bash"), BASH_HOOK),
+        Shell::Zsh => (posix_function("zsh"), ZSH_HOOK),
+        Shell::Fish => (FISH_FUNCTION.to_string(), FISH_HOOK),
+    };
+    print!("{function}");
+    if hints {
+        print!("{hook}");
+    }
+    Ok(())
+}
+
+fn posix_function(shell: &str) -> String {
+    format!(
+        r#"# desk shell integration: eval "$(desk shell-init {shell})"
+desk() {{
+    local desk_shell_file desk_status
+    desk_shell_file="$(mktemp -t desk-shell.XXXXXX)" || {{
+        command desk "$@"
+        return
+    }}
+    DESK_SHELL={shell} DESK_SHELL_FILE="$desk_shell_file" command desk "$@"
+    desk_status=$?
+    if [ -s "$desk_shell_file" ]; then
+        . "$desk_shell_file"
+    fi
+    rm -f "$desk_shell_file"
+    return $desk_status
+}}
+"#
+    )
+}
+
+const FISH_FUNCTION: &str = r"# desk shell integration: desk shell-init fish | source
+function desk --wraps desk --description 'desk, changing into the workspaces it opens'
+    set -l desk_shell_file (mktemp -t desk-shell.XXXXXX)
+    or begin
+        command desk $argv
+        return
+    end
+    DESK_SHELL=fish DESK_SHELL_FILE=$desk_shell_file command desk $argv
+    set -l desk_status $status
+    if test -s $desk_shell_file
+        source $desk_shell_file
+    end
+    rm -f $desk_shell_file
+    return $desk_status
+end
+";
+
+// Bash has no hook for changing directory, so the prompt checks for it.
+const BASH_HOOK: &str = r#"__desk_hint() {
+    local desk_status=$?
+    if [ "$PWD" != "${__desk_pwd-}" ]; then
+        [ -n "${__desk_pwd-}" ] && command desk shell-hint --from "$__desk_pwd"
+        __desk_pwd=$PWD
+    fi
+    return $desk_status
+}
+if [[ ";${PROMPT_COMMAND-};" != *";__desk_hint;"* ]]; then
+    PROMPT_COMMAND="__desk_hint${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+fi
+"#;
+
+const ZSH_HOOK: &str = r#"__desk_hint() {
+    command desk shell-hint --from "$OLDPWD"
+}
+autoload -Uz add-zsh-hook
+add-zsh-hook chpwd __desk_hint
+"#;
+
+const FISH_HOOK: &str = r"function __desk_hint --on-variable PWD
+    command desk shell-hint --from $dirprev[-1]
+end
+";
+
+/// Handles `desk shell-hint`: after the shell changed from `from` into
+/// another repository, a line about the workspace that was active there if
+/// the checkout has moved to another branch since.
+///
+/// It runs on every change of directory, so it stays quiet about anything
+/// that goes wrong.
+pub fn handle_shell_hint(from: Option<&Path>) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let Namespace::Repo(repo) = Namespace::resolve(&cwd, false) else {
+        return Ok(());
+    };
+    let from = from.filter(|from| !from.as_os_str().is_empty());
+    if from.is_some_and(|from| Namespace::resolve(from, false) == Namespace::Repo(repo.clone())) {
+        return Ok(());
+    }
+    match hint(&repo) {
+        Ok(Some(hint)) => eprintln!("desk: {hint}"),
+        Ok(None) => {},
+        Err(e) => debug!("no shell hint for {}: {e:#}", repo.display()),
+    }
+    Ok(())
+}
+
+/// What to say about `repo`: the open workspace, or else the one opened
+/// there last, if the checkout is no longer on its branch.
+fn hint(repo: &Path) -> Result<Option<String>> {
+    let state = DeskState::load()?;
+    let store = FileWorkspaceStore::new(&paths::data_dir()?, Namespace::Repo(repo.to_path_buf()));
+    let open = state.current_workspace(repo).map(String::from);
+    let Some(name) = open.clone().or_else(|| {
+        recent(state.history_for(Some(repo)))
+            .into_iter()
+            .map(|(name, _)| name)
+            .find(|name| store.exists(name))
+    }) else {
+        return Ok(None);
+    };
+    let workspace = store.load(&name)?;
+    // A workspace in its own worktree leaves the main checkout alone.
+    if workspace.state.worktree.is_some() {
+        return Ok(None);
+    }
+    let branch = Git2Operations::new(repo).current_branch().ok();
+    if branch.as_deref() == Some(workspace.state.branch.as_str()) {
+        return Ok(None);
+    }
+    let branch = branch.unwrap_or_else(|| "a detached HEAD".to_string());
+    Ok(Some(if open.is_some() {
+        format!("workspace '{name}' is open here, but the checkout moved to {branch}; see `desk status`")
+    } else {
+        format!("workspace '{name}' was active here — run `desk open {name}`?")
+    }))
+}
+
+/// Asks the shell function, if desk runs under one, to change into the
+/// checkout of `workspace` and export its name.
+pub fn enter(workspace: &Workspace) {
+    let dir = workspace
+        .state
+        .worktree
+        .as_deref()
+        .unwrap_or(&workspace.repo_path);
+    // Staying in a subdirectory beats being thrown back to the root.
+    let inside = std::env::current_dir().is_ok_and(|cwd| cwd.starts_with(dir));
+    let fish = is_fish();
+    let mut script = String::new();
+    if !inside {
+        let _ = writeln!(script, "cd {}", quote(&dir.to_string_lossy(), fish));
+    }
+    let name = quote(&workspace.name, fish);
+    if fish {
+        let _ = writeln!(script, "set -gx DESK_WORKSPACE {name}");
+    } else {
+        let _ = writeln!(script, "export DESK_WORKSPACE={name}");
+    }
+    write(&script);
+}
+
+/// Asks the shell function, if desk runs under one, to forget the workspace
+/// it exported.
+pub fn leave() {
+    if is_fish() {
+        write("set -e DESK_WORKSPACE\n");
+    } else {
+        write("unset DESK_WORKSPACE\n");
+    }
+}
+
+fn is_fish() -> bool {
+    std::env::var(SHELL_ENV).is_ok_and(|shell| shell == "fish")
+}
+
+/// `value` as a single-quoted word in the shell's syntax.
+fn quote(value: &str, fish: bool) -> String {
+    if fish {
+        format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+/// Appends `script` to the file the shell function sources; nothing
+/// without one.
+fn write(script: &str) {
+    let Some(path) = std::env::var_os(SHELL_FILE_ENV) else {
+        return;
+    };
+    let path = Path::new(&path);
+    let written = std::fs::OpenOptions::new()
+        .append(true)
+        .open(path)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, script.as_bytes()));
+    if let Err(e) = written {
+        warn!("failed to write shell commands to {}: {e}", path.display());
+    }
+}
@@ -0,0 +1,83 @@
+//! `desk standup`: what was worked on yesterday and today, ready to paste
+//! into a standup.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{Datelike, Days, Local, NaiveDate, Utc, Weekday};
+
+use crate::core::config::DeskConfig;
+use crate::core::paths;
+use crate::core::standup;
+use crate::core::state::DeskState;
+use crate::core::store::{FileWorkspaceStore, Namespace};
+use crate::core::time_log::{self, local_midnight, TimeLog};
+use crate::output::clipboard;
+
+/// Handles `desk standup`.
+///
+/// Prints the workspaces worked on the last working day and today as
+/// markdown, with the time spent in each and what each is about, and with
+/// `copy` puts the summary on the clipboard too.
+pub fn handle_standup(config: &DeskConfig, copy: bool) -> Result<()> {
+    let now = Utc::now();
+    let data_dir = paths::data_dir()?;
+    let state = DeskState::load()?;
+    let mut intervals = TimeLog::new(&data_dir).read()?;
+    if config.state.track_time {
+        intervals.extend(time_log::open_intervals(&state, now));
+    }
+
+    let today = Local::now().date_naive();
+    let previous = previous_workday(today);
+    let title = if Some(previous) == today.pred_opt() {
+        "Yesterday".to_string()
+    } else {
+        previous.format("%A").to_string()
+    };
+    let days = [
+        (
+            title,
+            standup::worked_on(
+                &intervals,
+                &state.history,
+                local_midnight(previous),
+                local_midnight(today),
+            ),
+        ),
+        (
+            "Today".to_string(),
+            standup::worked_on(&intervals, &state.history, local_midnight(today), now),
+        ),
+    ];
+    let summary = standup::markdown(&days, |worked| {
+        let namespace = Namespace::Repo(PathBuf::from(&worked.repo));
+        FileWorkspaceStore::new(&data_dir, namespace)
+            .load(&worked.workspace)
+            .ok()
+            .as_ref()
+            .and_then(standup::gloss)
+    });
+    print!("{summary}");
+
+    if copy {
+        clipboard::copy(&summary)?;
+        eprintln!("Copied to the clipboard");
+    }
+    if !config.state.track_time {
+        eprintln!(
+            "Time tracking is off, so no times are shown; turn it on with: desk config set \
+             state.track_time true"
+        );
+    }
+    Ok(())
+}
+
+/// The working day before `today`: yesterday, or Friday after a weekend.
+fn previous_workday(today: NaiveDate) -> NaiveDate {
+    let mut day = today - Days::new(1);
+    while matches!(day.weekday(), Weekday::Sat | Weekday::Sun) {
+        day = day - Days::new(1);
+    }
+    day
+}
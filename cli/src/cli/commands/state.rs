@@ -0,0 +1,54 @@
+//! `desk state`: looking after desk's record of open workspaces, switches,
+//! and syncs.
+
+use std::fmt::Write as _;
+
+use anyhow::Result;
+use clap::Subcommand;
+
+use crate::core::lock::MutationGuard;
+use crate::core::paths;
+use crate::core::state::DeskState;
+
+#[derive(Debug, Subcommand)]
+pub enum StateCommand {
+    /// Recover what can still be read from state files that were set aside
+    /// as corrupted
+    Repair,
+}
+
+/// Handles `desk state`.
+pub fn handle_state(command: &StateCommand) -> Result<()> {
+    match command {
+        StateCommand::Repair => {
+            let _guard = MutationGuard::acquire(&paths::data_dir()?, None)?;
+            let salvaged = DeskState::repair(&paths::state_file()?)?;
+            if salvaged.is_empty() {
+                println!("No corrupted state files to repair");
+                return Ok(());
+            }
+            for found in &salvaged {
+                let file = found.backup.display();
+                if found.recovered == 0 {
+                    println!("Nothing could be read from {file}");
+                    continue;
+                }
+                let plural = if found.recovered == 1 { "y" } else { "ies" };
+                let mut line = format!("Recovered {} entr{plural} from {file}", found.recovered);
+                if found.skipped > 0 {
+                    let plural = if found.skipped == 1 { "" } else { "s" };
+                    let _ = write!(line, ", skipping {} damaged one{plural}", found.skipped);
+                }
+                if found.truncated {
+                    line.push_str("; it was cut short, so anything after that is lost");
+                }
+                println!("{line}");
+            }
+            println!(
+                "What desk recorded since was kept. The corrupted files are left where they \
+                 are; delete them once everything looks right."
+            );
+        },
+    }
+    Ok(())
+}
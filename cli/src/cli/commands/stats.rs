@@ -0,0 +1,99 @@
+//! `desk stats`: switches per day, most used workspaces, and busiest
+//! repositories.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{Days, Local, Utc};
+
+use crate::core::config::DeskConfig;
+use crate::core::paths;
+use crate::core::state::DeskState;
+use crate::core::stats::Stats;
+use crate::core::time_log::{local_midnight, TimeLog};
+use crate::utils::duration::format_duration;
+
+/// Widest bar in the switches-per-day chart.
+const CHART_WIDTH: usize = 30;
+
+/// Workspaces and repositories listed in the terminal; `--json` has all.
+const TOP: usize = 10;
+
+/// Handles `desk stats`.
+pub fn handle_stats(config: &DeskConfig, days: u32, json: bool) -> Result<()> {
+    let state = DeskState::load()?;
+    let intervals = TimeLog::new(&paths::data_dir()?).read()?;
+    let now = Utc::now();
+    let first_day = Local::now().date_naive() - Days::new(u64::from(days - 1));
+    let usage = Stats::compute(
+        state.history_for(None),
+        &intervals,
+        local_midnight(first_day),
+        now,
+    );
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&usage)?);
+        return Ok(());
+    }
+    if usage.switches == 0 && usage.workspaces.is_empty() {
+        println!("No switches in the last {days} days");
+        return Ok(());
+    }
+
+    println!(
+        "Switches per day ({} in the last {days} days)",
+        usage.switches
+    );
+    let most = usage
+        .switches_per_day
+        .iter()
+        .map(|day| day.switches)
+        .max()
+        .unwrap_or_default()
+        .max(1);
+    for day in &usage.switches_per_day {
+        let bar = "█".repeat((day.switches * CHART_WIDTH).div_ceil(most));
+        println!("  {}  {bar} {}", day.date.format("%a %m-%d"), day.switches);
+    }
+
+    println!("\nMost used workspaces");
+    for ws in usage.workspaces.iter().take(TOP) {
+        let time = if ws.sessions == 0 {
+            String::new()
+        } else {
+            format!(
+                "  {} total, {} per session",
+                format_duration(Duration::from_secs(ws.total_secs)),
+                format_duration(Duration::from_secs(ws.average_secs))
+            )
+        };
+        println!(
+            "  {:<24} {:>3} opens{time}  {}",
+            ws.workspace, ws.opens, ws.repo
+        );
+    }
+
+    println!("\nBusiest repositories");
+    for repo in usage.repos.iter().take(TOP) {
+        println!(
+            "  {:>3} switches  {:>8}  {}",
+            repo.switches,
+            format_duration(Duration::from_secs(repo.total_secs)),
+            repo.repo
+        );
+    }
+
+    let mut per_repo = HashMap::new();
+    for entry in &state.history {
+        *per_repo.entry(&entry.repo).or_insert(0) += 1;
+    }
+    if per_repo.values().any(|&n| n >= config.state.history_size) {
+        println!(
+            "\nOnly the last {} switches per repository are kept; raise state.history_size to count more",
+            config.state.history_size
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,289 @@
+//! `desk status` and `desk prompt`: the open workspace and how the checkout
+//! compares to it.
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use chrono::Utc;
+use tracing::debug;
+
+use super::todo;
+use crate::cli::GlobalOptions;
+use crate::core::budget::BudgetStatus;
+use crate::core::paths;
+use crate::core::state::DeskState;
+use crate::core::status_cache;
+use crate::core::store::{FileWorkspaceStore, Namespace};
+use crate::core::sync::{sync_key, sync_scope};
+use crate::core::workspace::Workspace;
+use crate::daemon::{self, PromptInfo, Request, Response};
+use crate::integrations::git::{DiffStat, Git2Operations, Upstream};
+use crate::output::timestamp::timestamp;
+use crate::output::Verbosity;
+use crate::utils::platform::FsCapabilities;
+
+/// Handles `desk status`.
+pub fn handle_status(globals: &GlobalOptions) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let Namespace::Repo(repo) = store.namespace() else {
+        bail!("this command needs a git repository; run it inside one");
+    };
+    let state = DeskState::load()?;
+    let current = state
+        .current_workspace(repo)
+        .filter(|name| store.exists(name))
+        .map(|name| store.load(name))
+        .transpose()?;
+
+    // In worktree mode the workspace's checkout is its own directory.
+    let checkout = current
+        .as_ref()
+        .and_then(|w| w.state.worktree.clone())
+        .unwrap_or_else(|| repo.clone());
+    warn_network_filesystem(globals, &checkout);
+    let git = Git2Operations::new(&checkout);
+    let branch = git.current_branch().ok();
+    let commit = git.current_commit().ok();
+    let changes = if globals.no_status {
+        None
+    } else {
+        Some(status_cache::diff_stat(&git)?)
+    };
+
+    println!("Repository: {}", checkout.display());
+    println!(
+        "Branch:     {} @ {}",
+        branch.as_deref().unwrap_or("(detached)"),
+        commit.as_deref().map_or("(no commits)", short)
+    );
+    if branch.is_some() {
+        println!(
+            "Upstream:   {}",
+            describe_upstream(git.upstream()?.as_ref())
+        );
+    }
+    println!(
+        "Changes:    {}",
+        changes
+            .as_ref()
+            .map_or_else(|| "not counted (--no-status)".to_string(), describe_changes)
+    );
+
+    let Some(workspace) = current else {
+        println!();
+        println!("No workspace is open; open one with: desk open <name>");
+        return Ok(());
+    };
+
+    println!();
+    println!("Workspace:  {}", workspace.name);
+    if let Some(opened_at) = state.opened_at(repo) {
+        println!("Opened:     {}", timestamp(opened_at));
+    }
+    println!(
+        "Saved:      {} @ {}, {}",
+        workspace.state.branch,
+        short(&workspace.state.commit),
+        timestamp(workspace.updated_at)
+    );
+    if !git.has_commit(&workspace.state.commit)? {
+        println!(
+            "            warning: commit {} is not in this clone; a force-push may have \
+             rewritten it, or it was made in another clone. Fetch with: git fetch --all",
+            short(&workspace.state.commit)
+        );
+    }
+    println!(
+        "Drift:      {}",
+        describe_drift(
+            &workspace,
+            branch.as_deref(),
+            commit.as_deref(),
+            changes.as_ref()
+        )
+    );
+    println!("Sync:       {}", describe_sync(&store, &state, &workspace));
+    for (i, (number, text)) in todo::pending(&workspace).into_iter().enumerate() {
+        let label = if i == 0 { "To do:" } else { "" };
+        println!("{label:<12}{number}. {text}");
+    }
+    Ok(())
+}
+
+/// Warns on stderr that `repo` is on a network mount, where switching is
+/// slow, unless the command is to print nothing.
+pub fn warn_network_filesystem(globals: &GlobalOptions, repo: &Path) {
+    if globals.verbosity == Verbosity::Quiet {
+        return;
+    }
+    if let Some(advice) = FsCapabilities::detect(repo).advice() {
+        eprintln!("warning: {advice}");
+    }
+}
+
+/// Handles `desk prompt`.
+///
+/// Prints the open workspace, marked with `*` when the checkout drifted from
+/// it, or nothing outside a repository or without one. Asks the daemon if it
+/// runs, which has usually worked it out already. With `--no-status`,
+/// uncommitted changes do not count as drift unless the daemon saw them.
+pub async fn handle_prompt(globals: &GlobalOptions, json: bool) -> Result<()> {
+    let cwd = std::env::current_dir()?;
+    let Namespace::Repo(repo) = Namespace::resolve(&cwd, false) else {
+        return Ok(());
+    };
+    let data_dir = paths::data_dir()?;
+    let request = Request::Prompt { repo: repo.clone() };
+    let count_changes = !globals.no_status;
+    let info = match daemon::request(&data_dir, &request).await {
+        Ok(Some(Response::Prompt { prompt })) => prompt,
+        Ok(_) => prompt_info(&data_dir, &DeskState::load()?, &repo, count_changes)?,
+        Err(e) => {
+            debug!("{e}; working out the prompt without the daemon");
+            prompt_info(&data_dir, &DeskState::load()?, &repo, count_changes)?
+        },
+    };
+
+    match info {
+        Some(info) if json => println!("{}", serde_json::to_string(&info)?),
+        Some(info) => {
+            let drifted = if info.drift.is_empty() { "" } else { "*" };
+            let budget = if info.budget.is_some() { "!" } else { "" };
+            println!("{}{drifted}{budget}", info.workspace);
+        },
+        None if json => println!("null"),
+        None => {},
+    }
+    Ok(())
+}
+
+/// How the checkout has moved on from what the workspace last saved.
+fn describe_drift(
+    workspace: &Workspace,
+    branch: Option<&str>,
+    commit: Option<&str>,
+    changes: Option<&DiffStat>,
+) -> String {
+    let drift = drift(workspace, branch, commit, changes);
+    if drift.is_empty() {
+        "none; the checkout matches the saved workspace".to_string()
+    } else {
+        format!("{} since the last save", drift.join(", "))
+    }
+}
+
+/// The workspace open in `repo` and how its checkout drifted, as `desk
+/// prompt` shows it, leaving out uncommitted changes unless `count_changes`.
+/// `None` if no workspace is open there.
+pub fn prompt_info(
+    data_dir: &Path,
+    state: &DeskState,
+    repo: &Path,
+    count_changes: bool,
+) -> Result<Option<PromptInfo>> {
+    let store = FileWorkspaceStore::new(data_dir, Namespace::Repo(repo.to_path_buf()));
+    let Some(name) = state
+        .current_workspace(repo)
+        .filter(|name| store.exists(name))
+    else {
+        return Ok(None);
+    };
+    let workspace = store.load(name)?;
+    let checkout = workspace
+        .state
+        .worktree
+        .clone()
+        .unwrap_or_else(|| repo.to_path_buf());
+    let git = Git2Operations::new(&checkout);
+    let branch = git.current_branch().ok();
+    let commit = git.current_commit().ok();
+    let changes = if count_changes {
+        Some(status_cache::diff_stat(&git)?)
+    } else {
+        None
+    };
+    Ok(Some(PromptInfo {
+        drift: drift(
+            &workspace,
+            branch.as_deref(),
+            commit.as_deref(),
+            changes.as_ref(),
+        ),
+        budget: BudgetStatus::for_workspace(&workspace, state).and_then(|b| b.alert()),
+        workspace: workspace.name,
+        branch,
+        upstream: git.upstream()?,
+        checked_at: Utc::now(),
+    }))
+}
+
+/// Each way the checkout differs from what `workspace` last saved.
+fn drift(
+    workspace: &Workspace,
+    branch: Option<&str>,
+    commit: Option<&str>,
+    changes: Option<&DiffStat>,
+) -> Vec<String> {
+    let mut drift = Vec::new();
+    if branch != Some(workspace.state.branch.as_str()) {
+        drift.push(format!(
+            "on {} instead of {}",
+            branch.unwrap_or("a detached HEAD"),
+            workspace.state.branch
+        ));
+    } else if commit != Some(workspace.state.commit.as_str()) {
+        drift.push("new commits".to_string());
+    }
+    if changes.is_some_and(|changes| !changes.is_empty()) {
+        drift.push("uncommitted changes".to_string());
+    }
+    drift
+}
+
+/// Whether the workspace has changes the sync backend has not seen, as far
+/// as is known without asking it.
+fn describe_sync(store: &FileWorkspaceStore, state: &DeskState, workspace: &Workspace) -> String {
+    let key = sync_key(&sync_scope(store.namespace()), &workspace.name);
+    let Some(record) = state.sync.get(&key) else {
+        return "never synced; push it with: desk sync push".to_string();
+    };
+    let pending = if workspace.updated_at == record.local_updated_at {
+        "no local changes to push"
+    } else {
+        "local changes not pushed yet"
+    };
+    // Changes made on other machines only show up when asking the backend.
+    format!(
+        "{pending} (last synced {}; `desk sync status` checks for remote changes)",
+        timestamp(record.synced_at)
+    )
+}
+
+/// How the branch compares to the branch it tracks, as of the last fetch.
+fn describe_upstream(upstream: Option<&Upstream>) -> String {
+    let Some(upstream) = upstream else {
+        return "none".to_string();
+    };
+    match (upstream.ahead, upstream.behind) {
+        (0, 0) => format!("{}, up to date", upstream.name),
+        (ahead, 0) => format!("{}, {ahead} ahead", upstream.name),
+        (0, behind) => format!("{}, {behind} behind", upstream.name),
+        (ahead, behind) => format!("{}, {ahead} ahead, {behind} behind", upstream.name),
+    }
+}
+
+fn describe_changes(changes: &DiffStat) -> String {
+    match changes.files.len() {
+        0 => "none".to_string(),
+        1 => format!("1 file, +{} -{}", changes.insertions(), changes.deletions()),
+        files => format!(
+            "{files} files, +{} -{}",
+            changes.insertions(),
+            changes.deletions()
+        ),
+    }
+}
+
+fn short(commit: &str) -> &str {
+    commit.get(..7).unwrap_or(commit)
+}
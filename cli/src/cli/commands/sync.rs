@@ -0,0 +1,1643 @@
+//! `desk sync`: exchange workspaces with the desk API.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use age::secrecy::SecretString;
+use anyhow::{bail, Context, Result};
+use clap::Subcommand;
+use futures::{stream, Stream, StreamExt};
+use glob::Pattern;
+use tracing::warn;
+
+use crate::cli::GlobalOptions;
+use crate::client::api::{ApiError, ApiFeature, DeskApiClient, RemoteWorkspace};
+use crate::client::backend::{BackendError, SyncBackend};
+use crate::client::events::WorkspaceEvent;
+use crate::client::git_backend::GitBackend;
+use crate::core::changes;
+use crate::core::config::{edit_config, DeskConfig, SyncBackendKind, SyncConfig};
+use crate::core::hooks::{self, HookContext, HookStage};
+use crate::core::lock::MutationGuard;
+use crate::core::merge::{merge, remote_copy_name, Merged};
+use crate::core::paths;
+use crate::core::remote_cache::RemoteCache;
+use crate::core::state::DeskState;
+use crate::core::store::{FileWorkspaceStore, Namespace, StoreError};
+use crate::core::sync::{
+    classify, diff_fields, sync_key, sync_scope, ApplyMode, PullOutcome, PullStage, SyncFilter,
+    SyncRecord, SyncStatus,
+};
+use crate::core::trash::Trash;
+use crate::core::workspace::Workspace;
+use crate::integrations::git::{Git2Operations, StashContents};
+use crate::output::timestamp::timestamp;
+use crate::output::{notify, ProgressFormat, Reporter, StepEvent, StepKind, Verbosity};
+
+#[derive(Debug, Subcommand)]
+pub enum SyncCommand {
+    /// Upload local workspace changes
+    Push {
+        /// Overwrite remote workspaces that changed since the last sync
+        #[arg(long)]
+        force: bool,
+
+        /// On conflicts, save the remote copy locally as `<name> (remote)`
+        /// before overwriting it
+        #[arg(long, conflicts_with = "force")]
+        keep_both: bool,
+    },
+    /// Download remote workspace changes
+    Pull {
+        /// Overwrite local workspaces that changed since the last sync
+        #[arg(long)]
+        force: bool,
+
+        /// On conflicts, save the remote copy as `<name> (remote)` next to
+        /// the local one
+        #[arg(long, conflicts_with = "force")]
+        keep_both: bool,
+
+        /// Move workspaces deleted remotely to the trash
+        #[arg(long)]
+        prune: bool,
+
+        /// Apply all pulled workspaces or none: undo partial pulls on Ctrl-C
+        /// or errors
+        #[arg(long)]
+        atomic: bool,
+    },
+    /// Keep pulling remote workspace changes as they happen, until Ctrl-C
+    Watch {
+        /// Don't show desktop notifications for pulled workspaces
+        #[arg(long)]
+        no_notify: bool,
+    },
+    /// Compare local and remote workspaces
+    Status {
+        /// Show at most this many workspaces
+        #[arg(short = 'n', long)]
+        limit: Option<usize>,
+    },
+    /// Show which fields differ between a workspace and its remote copy
+    Diff {
+        /// Workspace in the current repository (or `--global`)
+        name: String,
+    },
+    /// Show the remote copy of a workspace
+    Show {
+        /// Workspace in the current repository (or `--global`)
+        name: String,
+    },
+    /// Delete the remote copy of a workspace, keeping the local one
+    Rm {
+        /// Workspace in the current repository (or `--global`)
+        name: String,
+
+        /// Delete it even if it changed remotely since the last sync
+        #[arg(long)]
+        force: bool,
+    },
+    /// Never sync workspaces whose name matches a pattern (e.g. `tmp-*`)
+    Ignore {
+        /// Glob pattern added to `sync.exclude`
+        pattern: String,
+    },
+}
+
+/// First wait before reopening a dropped event stream in `desk sync watch`;
+/// doubles on each failed attempt up to [`MAX_RECONNECT_DELAY`].
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// Widest the local column of `desk sync diff` gets before values are cut.
+const MAX_DIFF_COLUMN: usize = 40;
+
+/// Environment variable holding the passphrase for synced changes, for
+/// scripted use.
+const PASSPHRASE_ENV: &str = "DESK_SYNC_PASSPHRASE";
+
+/// What to do with a workspace that changed on both sides and cannot be
+/// merged cleanly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resolution {
+    /// Leave it alone.
+    Skip,
+    /// Overwrite the other side (`--force`).
+    Overwrite,
+    /// Save the remote copy locally under a new name (`--keep-both`).
+    KeepBoth,
+}
+
+impl Resolution {
+    const fn new(force: bool, keep_both: bool) -> Self {
+        if force {
+            Self::Overwrite
+        } else if keep_both {
+            Self::KeepBoth
+        } else {
+            Self::Skip
+        }
+    }
+}
+
+/// How `desk sync push` uploads local workspaces.
+#[derive(Debug, Clone, Copy)]
+struct PushOptions {
+    resolution: Resolution,
+    /// Most requests to the backend in flight at once.
+    concurrency: usize,
+}
+
+/// How `desk sync pull` treats the remote workspaces it is given.
+#[derive(Debug, Clone, Copy)]
+struct PullOptions {
+    resolution: Resolution,
+    atomic: bool,
+    /// Whether to move workspaces deleted remotely to the trash; `None` when
+    /// only some remote workspaces are pulled, so missing ones mean nothing.
+    prune: Option<bool>,
+}
+
+/// How `desk sync` exchanges the uncommitted changes stashed for workspaces.
+struct ChangeOptions {
+    /// Whether pushes upload changes; pulls always take them.
+    upload: bool,
+    encrypt: bool,
+    /// Largest compressed changes uploaded, in bytes.
+    limit: u64,
+    /// Encrypts uploads (with `encrypt`) and decrypts downloads.
+    passphrase: Option<SecretString>,
+}
+
+impl ChangeOptions {
+    /// Reads the `[sync]` settings, asking for the passphrase if uploads are
+    /// encrypted and `DESK_SYNC_PASSPHRASE` is not set. Nothing is uploaded
+    /// to a backend that cannot store changes.
+    fn new(config: &SyncConfig, stored: bool) -> Result<Self> {
+        let upload = config.include_changes && stored;
+        let encrypt = upload && config.encrypt_changes;
+        let passphrase = match std::env::var(PASSPHRASE_ENV) {
+            Ok(value) => Some(value.into()),
+            Err(_) if encrypt => {
+                let value = rpassword::prompt_password("Sync passphrase: ").with_context(|| {
+                    format!("failed to read passphrase (or set {PASSPHRASE_ENV})")
+                })?;
+                if value.is_empty() {
+                    bail!("passphrase cannot be empty");
+                }
+                Some(value.into())
+            },
+            Err(_) => None,
+        };
+        Ok(Self {
+            upload,
+            encrypt,
+            limit: config.max_changes_mib.saturating_mul(1024 * 1024),
+            passphrase,
+        })
+    }
+
+    fn upload_passphrase(&self) -> Option<&SecretString> {
+        self.passphrase.as_ref().filter(|_| self.encrypt)
+    }
+}
+
+/// A namespace on this machine together with its remote scope.
+struct LocalNamespace {
+    scope: String,
+    store: FileWorkspaceStore,
+}
+
+/// Handles `desk sync`.
+pub async fn handle_sync(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    command: &SyncCommand,
+) -> Result<()> {
+    if let SyncCommand::Ignore { pattern } = command {
+        return handle_sync_ignore(*globals, pattern);
+    }
+    let action = match command {
+        SyncCommand::Push { .. } => Some("push"),
+        SyncCommand::Pull { .. } => Some("pull"),
+        _ => None,
+    };
+    if let Some(action) = action {
+        let cwd = std::env::current_dir()?;
+        let dir = match Namespace::resolve(&cwd, globals.global) {
+            Namespace::Repo(root) => root,
+            Namespace::Global => cwd,
+        };
+        let context = HookContext {
+            repo: &dir,
+            previous: None,
+            workspace: None,
+            tags: &[],
+            sync: Some(action),
+            echo: globals.verbosity != Verbosity::Quiet,
+        };
+        hooks::run_hooks(
+            &config.hooks,
+            HookStage::PreSync,
+            &context,
+            globals.reporter().as_mut(),
+        )?;
+    }
+    let filter = SyncFilter::new(&config.sync).context("invalid pattern in sync.exclude")?;
+
+    match config.sync.backend {
+        SyncBackendKind::Api => {
+            let mut client = DeskApiClient::new(&config.api)?;
+            client.load_credentials()?;
+            if matches!(
+                command,
+                SyncCommand::Push { .. } | SyncCommand::Pull { .. } | SyncCommand::Watch { .. }
+            ) {
+                // Fail (or log in again) now rather than halfway through the batch.
+                super::auth::ensure_session(globals, config, &mut client).await?;
+            }
+            let result = run_api_sync(globals, config, &mut client, &filter, command).await;
+            // Credentials revoked since the session check: log in and start over.
+            if let Err(e) = &result {
+                if super::auth::login_again(globals, config, &mut client, e).await? {
+                    return run_api_sync(globals, config, &mut client, &filter, command).await;
+                }
+            }
+            result
+        },
+        SyncBackendKind::Git => {
+            if matches!(command, SyncCommand::Watch { .. }) {
+                bail!("`desk sync watch` needs the desk API backend; git remotes do not announce changes");
+            }
+            let backend = GitBackend::open(&config.sync.git, &paths::data_dir()?)?;
+            run_sync(globals, config, &backend, &filter, command).await
+        },
+    }
+}
+
+/// Runs a `desk sync` command against the desk API.
+async fn run_api_sync(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    client: &mut DeskApiClient,
+    filter: &SyncFilter,
+    command: &SyncCommand,
+) -> Result<()> {
+    if let SyncCommand::Watch { no_notify } = command {
+        client.require(ApiFeature::WorkspaceEvents).await?;
+        use_backend(client)?;
+        let stored = client.supports(ApiFeature::WorkspaceChanges).await?;
+        let changes = ChangeOptions::new(&config.sync, stored)?;
+        let notify = !*no_notify && notify::settings().sync_watch;
+        return handle_sync_watch(globals, client, filter, &changes, notify).await;
+    }
+    run_sync(globals, config, client, filter, command).await
+}
+
+/// Runs the `desk sync` commands every backend supports.
+async fn run_sync(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    backend: &impl SyncBackend,
+    filter: &SyncFilter,
+    command: &SyncCommand,
+) -> Result<()> {
+    use_backend(backend)?;
+    match command {
+        SyncCommand::Push { force, keep_both } => {
+            let resolution = Resolution::new(*force, *keep_both);
+            let stored = backend.stores_changes().await?;
+            if config.sync.include_changes && !stored {
+                eprintln!(
+                    "The sync backend ({}) cannot store uncommitted changes; pushing workspaces without them",
+                    backend.location()
+                );
+            }
+            let options = PushOptions {
+                resolution,
+                concurrency: config.sync.concurrency.max(1),
+            };
+            let changes = ChangeOptions::new(&config.sync, stored)?;
+            handle_sync_push(globals, backend, filter, options, &changes).await
+        },
+        SyncCommand::Pull {
+            force,
+            keep_both,
+            prune,
+            atomic,
+        } => {
+            let options = PullOptions {
+                resolution: Resolution::new(*force, *keep_both),
+                atomic: *atomic,
+                prune: Some(*prune),
+            };
+            let changes = ChangeOptions::new(&config.sync, backend.stores_changes().await?)?;
+            handle_sync_pull(globals, backend, filter, options, &changes).await
+        },
+        SyncCommand::Status { limit } => handle_sync_status(globals, backend, filter, *limit).await,
+        SyncCommand::Diff { name } => handle_sync_diff(*globals, backend, name).await,
+        SyncCommand::Show { name } => handle_sync_show(*globals, backend, name).await,
+        SyncCommand::Rm { name, force } => handle_sync_rm(*globals, backend, name, *force).await,
+        SyncCommand::Watch { .. } | SyncCommand::Ignore { .. } => {
+            unreachable!("handled before connecting")
+        },
+    }
+}
+
+/// Lists the remote workspaces, caching them for `desk sync status` to fall
+/// back on when the backend is unreachable.
+async fn list_remotes(backend: &impl SyncBackend) -> Result<Vec<RemoteWorkspace>> {
+    let remotes = backend.list().await?;
+    save_cache(&RemoteCache::new(backend.location(), &remotes));
+    Ok(remotes)
+}
+
+/// Saves `cache`; a stale cache is not worth failing the command over.
+fn save_cache(cache: &RemoteCache) {
+    if let Err(e) = paths::data_dir().and_then(|dir| cache.save(&dir)) {
+        warn!("failed to cache remote workspaces: {e:#}");
+    }
+}
+
+/// Drops sync records left by another backend, against which every
+/// workspace would look deleted remotely.
+fn use_backend(backend: &impl SyncBackend) -> Result<()> {
+    let location = backend.location();
+    let _guard = MutationGuard::acquire(&paths::data_dir()?, None)?;
+    let mut state = DeskState::load()?;
+    if state.sync_location.as_deref() == Some(location.as_str()) {
+        return Ok(());
+    }
+    if state.set_sync_location(&location) {
+        eprintln!(
+            "Switched sync backend to {location}; workspaces are matched up again on the next push or pull"
+        );
+    }
+    state.save()
+}
+
+fn handle_sync_ignore(globals: GlobalOptions, pattern: &str) -> Result<()> {
+    Pattern::new(pattern).with_context(|| format!("invalid pattern '{pattern}'"))?;
+
+    let mut added = false;
+    edit_config(|doc| {
+        let sync = doc
+            .entry("sync")
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut();
+        let Some(sync) = sync else { return };
+        let exclude = sync
+            .entry("exclude")
+            .or_insert_with(|| toml_edit::value(toml_edit::Array::new()))
+            .as_array_mut();
+        let Some(exclude) = exclude else { return };
+        if !exclude.iter().any(|p| p.as_str() == Some(pattern)) {
+            exclude.push(pattern);
+            added = true;
+        }
+    })?;
+
+    let summary = if added {
+        format!("Workspaces matching '{pattern}' will no longer be synced")
+    } else {
+        format!("'{pattern}' is already in sync.exclude")
+    };
+    globals.reporter().finish(&summary);
+    Ok(())
+}
+
+async fn handle_sync_push(
+    globals: &GlobalOptions,
+    backend: &impl SyncBackend,
+    filter: &SyncFilter,
+    options: PushOptions,
+    changes: &ChangeOptions,
+) -> Result<()> {
+    let data_dir = paths::data_dir()?;
+    let _guard = MutationGuard::acquire(&data_dir, None)?;
+    let namespaces = local_namespaces(*globals)?;
+    let listed = backend.list().await?;
+    let mut cache = RemoteCache::new(backend.location(), &listed);
+    let remotes = remote_index(listed);
+    let mut state = DeskState::load()?;
+
+    let locals = local_workspaces(&namespaces, filter)?;
+
+    let mut reporter = globals.reporter();
+    reporter.begin("sync push", Some(locals.len()));
+    let (mut done, mut pushed, mut skipped) = (0, 0, 0);
+
+    let mut uploads = Vec::new();
+    for (namespace, workspace) in &locals {
+        let key = sync_key(&namespace.scope, &workspace.name);
+        let remote = remotes.get(&key);
+        let record = state.sync.get(&key);
+        let (base, merged, keep_remote) =
+            match push_action(workspace, remote, record, options.resolution) {
+                PushAction::Upload {
+                    base,
+                    merged,
+                    keep_remote,
+                } => (base, merged, keep_remote),
+                PushAction::Record => {
+                    done += 1;
+                    reporter.progress(done, Some(locals.len()), &key);
+                    if let (Some(remote), None) = (remote, record) {
+                        state.sync.insert(key, SyncRecord::new(remote, workspace));
+                    }
+                    continue;
+                },
+                PushAction::Skip(reason) => {
+                    done += 1;
+                    reporter.progress(done, Some(locals.len()), &key);
+                    skipped += 1;
+                    reporter.step(StepEvent::skipped(StepKind::Sync, &key, reason));
+                    continue;
+                },
+            };
+
+        if let Some(remote) = remote.filter(|_| keep_remote) {
+            keep_remote_copy(&namespace.store, remote, reporter.as_mut())?;
+        }
+        let upload = merged.as_ref().map_or(workspace, |m| &m.workspace);
+        let sealed = outgoing_changes(&namespace.store, upload, changes, &key, reporter.as_mut());
+        uploads.push(Upload {
+            namespace,
+            key,
+            base,
+            local: workspace,
+            merged,
+            sealed,
+        });
+    }
+
+    let expired = AtomicBool::new(false);
+    let mut results = send_uploads(backend, &uploads, options.concurrency, &expired);
+    let mut reauth = None;
+    while let Some((upload, result)) = results.next().await {
+        done += 1;
+        reporter.progress(done, Some(locals.len()), &upload.key);
+        match result {
+            Some(Ok(remote)) => {
+                pushed += 1;
+                cache.update(&remote);
+                record_upload(upload, &remote, &mut state, reporter.as_mut())?;
+            },
+            Some(Err(e)) if e.requires_reauth() => {
+                reauth.get_or_insert(e);
+            },
+            Some(Err(e)) => {
+                reporter.step(StepEvent::failed(
+                    StepKind::Sync,
+                    &upload.key,
+                    e.to_string(),
+                ));
+            },
+            None => {},
+        }
+    }
+    if let Some(e) = reauth {
+        state.save()?;
+        return Err(e.into());
+    }
+
+    let deleted = push_deletions(
+        backend,
+        &remotes,
+        &mut state,
+        &mut cache,
+        filter,
+        options,
+        reporter.as_mut(),
+    )
+    .await;
+    save_cache(&cache);
+    state.save()?;
+    let (deleted, deletions_skipped) = deleted?;
+    skipped += deletions_skipped;
+    reporter.finish(&summary("Pushed", pushed + deleted, skipped));
+    Ok(())
+}
+
+/// A local workspace `desk sync push` uploads.
+struct Upload<'a> {
+    namespace: &'a LocalNamespace,
+    key: String,
+    /// The remote copy replaced; `None` creates one.
+    base: Option<(String, u64)>,
+    local: &'a Workspace,
+    /// The merge of `local` with the remote copy, uploaded instead of it.
+    merged: Option<Box<Merged>>,
+    sealed: Option<String>,
+}
+
+impl Upload<'_> {
+    fn workspace(&self) -> &Workspace {
+        self.merged.as_ref().map_or(self.local, |m| &m.workspace)
+    }
+}
+
+/// Sends `uploads` to the backend, `concurrency` at a time, yielding the
+/// results as they come in. Once the session has expired, which sets
+/// `expired`, the rest would fail as well and are not sent.
+fn send_uploads<'a, B: SyncBackend>(
+    backend: &'a B,
+    uploads: &'a [Upload<'a>],
+    concurrency: usize,
+    expired: &'a AtomicBool,
+) -> impl Stream<
+    Item = (
+        &'a Upload<'a>,
+        Option<Result<RemoteWorkspace, BackendError>>,
+    ),
+> + 'a {
+    stream::iter(uploads)
+        .map(move |upload| async move {
+            if expired.load(Ordering::Relaxed) {
+                return (upload, None);
+            }
+            let workspace = upload.workspace();
+            let sealed = upload.sealed.as_deref();
+            let result = match &upload.base {
+                None => {
+                    backend
+                        .create(&upload.namespace.scope, workspace, sealed)
+                        .await
+                },
+                Some((id, version)) => backend.update(id, *version, workspace, sealed).await,
+            };
+            if result.as_ref().is_err_and(BackendError::requires_reauth) {
+                expired.store(true, Ordering::Relaxed);
+            }
+            (upload, Some(result))
+        })
+        .buffer_unordered(concurrency)
+}
+
+/// Records that `upload` is now `remote`, saving the merge it uploaded.
+fn record_upload(
+    upload: &Upload<'_>,
+    remote: &RemoteWorkspace,
+    state: &mut DeskState,
+    reporter: &mut dyn Reporter,
+) -> Result<()> {
+    let key = &upload.key;
+    if let Some(merged) = &upload.merged {
+        upload.namespace.store.save(&merged.workspace)?;
+        reporter.step(StepEvent::done(StepKind::Sync, merged_message(key, merged)));
+    }
+    state
+        .sync
+        .insert(key.clone(), SyncRecord::new(remote, upload.workspace()));
+    reporter.step(StepEvent::done(StepKind::Sync, format!("Pushed {key}")));
+    Ok(())
+}
+
+/// Deletes the remote copies of workspaces deleted here, returning how many
+/// were deleted and skipped.
+async fn push_deletions(
+    backend: &impl SyncBackend,
+    remotes: &HashMap<String, RemoteWorkspace>,
+    state: &mut DeskState,
+    cache: &mut RemoteCache,
+    filter: &SyncFilter,
+    options: PushOptions,
+    reporter: &mut dyn Reporter,
+) -> Result<(usize, usize)> {
+    let mut keys: Vec<_> = state
+        .tombstones
+        .iter()
+        .filter(|(_, tombstone)| !filter.excludes(&tombstone.name))
+        .map(|(key, _)| key.clone())
+        .collect();
+    keys.sort();
+    let (mut deleted, mut skipped) = (0, 0);
+
+    let mut deletions = Vec::new();
+    for key in keys {
+        let record = &state.tombstones[&key].record;
+        let Some(remote) = remotes.get(&key).filter(|r| r.id == record.remote_id) else {
+            // Already gone, or replaced by a different workspace.
+            state.tombstones.remove(&key);
+            continue;
+        };
+        if remote.version > record.version && options.resolution != Resolution::Overwrite {
+            skipped += 1;
+            reporter.step(StepEvent::skipped(
+                StepKind::Sync,
+                &key,
+                "changed remotely since it was deleted here; use --force to delete it anyway",
+            ));
+            continue;
+        }
+        deletions.push((key, remote));
+    }
+
+    let mut results = stream::iter(deletions)
+        .map(|(key, remote)| async move {
+            // Deleted from another machine already: just as good.
+            let result = backend
+                .delete(&remote.id, remote.version, &key)
+                .await
+                .or_else(|e| if e.is_not_found() { Ok(()) } else { Err(e) });
+            (key, remote, result)
+        })
+        .buffer_unordered(options.concurrency);
+
+    let mut reauth = None;
+    while let Some((key, remote, result)) = results.next().await {
+        match result {
+            Ok(()) => {
+                deleted += 1;
+                cache.remove(&remote.id);
+                state.tombstones.remove(&key);
+                reporter.step(StepEvent::done(StepKind::Sync, format!("Deleted {key}")));
+            },
+            Err(e) if e.requires_reauth() => {
+                reauth.get_or_insert(e);
+            },
+            Err(e) => reporter.step(StepEvent::failed(StepKind::Sync, &key, e.to_string())),
+        }
+    }
+    reauth.map_or(Ok((deleted, skipped)), |e| Err(e.into()))
+}
+
+/// What `desk sync push` does with one local workspace.
+enum PushAction {
+    /// Upload the workspace, or its merge with the remote copy, over the
+    /// remote copy at `base`; without a base the remote copy is created.
+    Upload {
+        base: Option<(String, u64)>,
+        merged: Option<Box<Merged>>,
+        /// Save the remote copy locally first, as it would otherwise be lost.
+        keep_remote: bool,
+    },
+    /// Nothing to push; make sure the pair is recorded.
+    Record,
+    Skip(&'static str),
+}
+
+fn push_action(
+    local: &Workspace,
+    remote: Option<&RemoteWorkspace>,
+    record: Option<&SyncRecord>,
+    resolution: Resolution,
+) -> PushAction {
+    let force = resolution == Resolution::Overwrite;
+    let upload = |base| PushAction::Upload {
+        base,
+        merged: None,
+        keep_remote: false,
+    };
+    match classify(Some(local), remote, record) {
+        Some(SyncStatus::LocalOnly) => upload(None),
+        Some(SyncStatus::DeletedRemotely) if force => upload(None),
+        Some(SyncStatus::DeletedRemotely) => PushAction::Skip(
+            "deleted remotely; use `desk sync pull --prune` to delete it here or --force to upload it again",
+        ),
+        Some(SyncStatus::LocalChanges) => upload(record.map(|r| (r.remote_id.clone(), r.version))),
+        Some(SyncStatus::RemoteChanges | SyncStatus::Diverged) if force => {
+            upload(remote.map(|r| (r.id.clone(), r.version)))
+        },
+        Some(SyncStatus::RemoteChanges) => PushAction::Skip("remote is newer; pull first"),
+        Some(SyncStatus::Diverged) => {
+            let Some(remote) = remote else {
+                return PushAction::Record;
+            };
+            let merged = three_way(record, local, remote);
+            let keep_remote = resolution == Resolution::KeepBoth
+                && merged.as_ref().map_or(true, |m| !m.conflicts.is_empty());
+            if merged.is_none() && !keep_remote {
+                return PushAction::Skip(
+                    "changed on both sides; use --keep-both to keep the remote copy too or --force to overwrite it",
+                );
+            }
+            PushAction::Upload {
+                base: Some((remote.id.clone(), remote.version)),
+                merged: merged.map(Box::new),
+                keep_remote,
+            }
+        },
+        Some(SyncStatus::InSync | SyncStatus::RemoteOnly | SyncStatus::DeletedLocally) | None => {
+            PushAction::Record
+        },
+    }
+}
+
+async fn handle_sync_pull(
+    globals: &GlobalOptions,
+    backend: &impl SyncBackend,
+    filter: &SyncFilter,
+    options: PullOptions,
+    changes: &ChangeOptions,
+) -> Result<()> {
+    let mut remotes = list_remotes(backend).await?;
+    remotes.retain(|remote| !filter.excludes(&remote.name));
+    pull_remotes(*globals, &remotes, filter, options, changes)?;
+    Ok(())
+}
+
+/// Pulls `remotes`, returning the keys of the workspaces it updated.
+fn pull_remotes(
+    globals: GlobalOptions,
+    remotes: &[RemoteWorkspace],
+    filter: &SyncFilter,
+    options: PullOptions,
+    changes: &ChangeOptions,
+) -> Result<Vec<String>> {
+    if remotes.is_empty() && options.prune.is_none() {
+        return Ok(Vec::new());
+    }
+    let data_dir = paths::data_dir()?;
+    let _guard = MutationGuard::acquire(&data_dir, None)?;
+    let namespaces = local_namespaces(globals)?;
+    let mut state = DeskState::load()?;
+
+    let mut reporter = globals.reporter();
+    reporter.begin("sync pull", Some(remotes.len()));
+    let mut staging = PullStage::create(&data_dir)?;
+    let mut pulled_changes = HashMap::new();
+    let mut skipped = 0;
+
+    for (index, remote) in remotes.iter().enumerate() {
+        let key = sync_key(&remote.scope, &remote.name);
+        reporter.progress(index + 1, Some(remotes.len()), &key);
+        let Some(namespace) = namespaces.iter().find(|ns| ns.scope == remote.scope) else {
+            skipped += 1;
+            reporter.step(StepEvent::skipped(
+                StepKind::Sync,
+                &key,
+                "repository not found on this machine; run the pull from inside a clone",
+            ));
+            continue;
+        };
+        let local = match namespace.store.load(&remote.name) {
+            Ok(workspace) => Some(workspace),
+            Err(StoreError::NotFound(_)) => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        let buried = state
+            .tombstones
+            .get(&key)
+            .is_some_and(|t| t.record.remote_id == remote.id);
+        let record = state.sync.get(&key);
+        let action = pull_action(local.as_ref(), remote, record, buried, options.resolution);
+        let merged = match action {
+            PullAction::Take => None,
+            PullAction::Merge {
+                merged,
+                keep_remote,
+            } => {
+                if keep_remote {
+                    keep_remote_copy(&namespace.store, remote, reporter.as_mut())?;
+                }
+                Some(*merged)
+            },
+            PullAction::KeepBoth => {
+                keep_remote_copy(&namespace.store, remote, reporter.as_mut())?;
+                // Recorded against the remote copy, the local one becomes a
+                // change for the next push to upload.
+                let pulled = localize(remote, &namespace.store, local.as_ref());
+                state.sync.insert(key, SyncRecord::new(remote, &pulled));
+                continue;
+            },
+            PullAction::Record => {
+                if let Some(local) = &local {
+                    state
+                        .sync
+                        .entry(key)
+                        .or_insert_with(|| SyncRecord::new(remote, local));
+                }
+                continue;
+            },
+            PullAction::Skip(reason) => {
+                skipped += 1;
+                reporter.step(StepEvent::skipped(StepKind::Sync, &key, reason));
+                continue;
+            },
+        };
+
+        let pulled = localize(remote, &namespace.store, local.as_ref());
+        // A merge result differs from the remote copy, so it is recorded
+        // against the pulled copy and shows up as a local change to push.
+        let record = SyncRecord::new(remote, &pulled);
+        let workspace = match merged {
+            Some(merged) => {
+                reporter.step(StepEvent::done(
+                    StepKind::Sync,
+                    merged_message(&key, &merged),
+                ));
+                merged.workspace
+            },
+            None => pulled,
+        };
+        staging.add(&namespace.store, &workspace, remote, record)?;
+        let opened = incoming_changes(remote, changes, &key, reporter.as_mut());
+        pulled_changes.insert(key, (&namespace.store, remote.name.clone(), opened));
+    }
+
+    if let Some(prune) = options.prune {
+        let reporter = reporter.as_mut();
+        skipped += prune_deleted(&namespaces, remotes, &mut state, filter, prune, reporter)?;
+    }
+
+    if staging.is_empty() {
+        state.save()?;
+        reporter.finish(&summary("Pulled", 0, skipped));
+        return Ok(Vec::new());
+    }
+
+    let outcome = apply_until_interrupted(staging, options.atomic);
+    keep_changes(&pulled_changes, &outcome.applied, reporter.as_mut());
+    finish_pull(outcome, &mut state, reporter.as_mut(), skipped)
+}
+
+/// Keeps the changes pulled with applied workspaces for `desk open`; a
+/// workspace pulled without changes drops any kept from an earlier pull.
+fn keep_changes(
+    pulled: &HashMap<String, (&FileWorkspaceStore, String, Option<Vec<u8>>)>,
+    applied: &[String],
+    reporter: &mut dyn Reporter,
+) {
+    for key in applied {
+        let Some((store, name, bytes)) = pulled.get(key) else {
+            continue;
+        };
+        let kept = bytes.as_ref().map_or_else(
+            || changes::remove_pulled(store, name),
+            |bytes| changes::save_pulled(store, name, bytes),
+        );
+        if let Err(e) = kept {
+            reporter.step(StepEvent::failed(
+                StepKind::Sync,
+                format!("Keep changes of {key}"),
+                format!("{:#}", anyhow::Error::from(e)),
+            ));
+        }
+    }
+}
+
+/// Records what an applied pull changed and reports it.
+fn finish_pull(
+    outcome: PullOutcome,
+    state: &mut DeskState,
+    reporter: &mut dyn Reporter,
+    skipped: usize,
+) -> Result<Vec<String>> {
+    for key in outcome.records.keys() {
+        state.tombstones.remove(key);
+    }
+    state.sync.extend(outcome.records);
+    state.save()?;
+    for key in &outcome.applied {
+        reporter.step(StepEvent::done(StepKind::Sync, format!("Pulled {key}")));
+    }
+
+    if outcome.interrupted || outcome.error.is_some() {
+        if outcome.rolled_back {
+            eprintln!("No workspaces were changed; the pull was rolled back");
+        } else {
+            eprintln!("Updated: {}", list_or_none(&outcome.applied));
+            eprintln!("Not updated: {}", list_or_none(&outcome.pending));
+        }
+        if let Some(e) = outcome.error {
+            return Err(e.into());
+        }
+        bail!("sync pull interrupted");
+    }
+
+    reporter.finish(&summary("Pulled", outcome.applied.len(), skipped));
+    Ok(outcome.applied)
+}
+
+async fn handle_sync_watch(
+    globals: &GlobalOptions,
+    client: &mut DeskApiClient,
+    filter: &SyncFilter,
+    changes: &ChangeOptions,
+    notify: bool,
+) -> Result<()> {
+    if globals.progress == ProgressFormat::Human {
+        println!("Watching for remote workspace changes (Ctrl-C to stop)");
+    }
+    let mut delay = MIN_RECONNECT_DELAY;
+    loop {
+        let watched = tokio::select! {
+            watched = watch_events(globals, client, filter, changes, notify) => watched,
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        };
+        let wait = match watched {
+            Ok(()) => {
+                delay = MIN_RECONNECT_DELAY;
+                delay
+            },
+            Err(e) if is_retriable(&e) => {
+                warn!("lost the desk API event stream: {e:#}");
+                eprintln!("Connection lost; retrying in {}s", delay.as_secs());
+                let wait = delay;
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                wait
+            },
+            Err(e) => {
+                if notify {
+                    notify::desktop("desk", &format!("desk sync watch stopped: {e:#}"));
+                }
+                return Err(e);
+            },
+        };
+        tokio::select! {
+            () = tokio::time::sleep(wait) => {},
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+    }
+}
+
+fn is_retriable(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<ApiError>()
+        .is_some_and(ApiError::is_retriable)
+}
+
+/// Catches up on remote changes, then applies them as the API announces
+/// them until the event stream ends.
+async fn watch_events(
+    globals: &GlobalOptions,
+    client: &mut DeskApiClient,
+    filter: &SyncFilter,
+    changes: &ChangeOptions,
+    notify: bool,
+) -> Result<()> {
+    let options = PullOptions {
+        resolution: Resolution::Skip,
+        atomic: false,
+        prune: None,
+    };
+    // Streams are reopened for hours on end, long enough for tokens to expire.
+    client.ensure_session().await?;
+    let mut stream = client.watch_workspaces().await?;
+
+    let mut remotes = client.list_workspaces().await?;
+    let state = DeskState::load()?;
+    remotes.retain(|remote| !filter.excludes(&remote.name) && !state.has_seen(remote));
+    announce(
+        &pull_remotes(*globals, &remotes, filter, options, changes)?,
+        notify,
+    );
+
+    while let Some(event) = stream.next().await? {
+        match event {
+            WorkspaceEvent::Updated(remote) => {
+                // Pushes from this machine come back as events too.
+                if filter.excludes(&remote.name) || DeskState::load()?.has_seen(&remote) {
+                    continue;
+                }
+                let remotes = std::slice::from_ref(remote.as_ref());
+                announce(
+                    &pull_remotes(*globals, remotes, filter, options, changes)?,
+                    notify,
+                );
+            },
+            WorkspaceEvent::Deleted(deleted) => {
+                let key = sync_key(&deleted.scope, &deleted.name);
+                let state = DeskState::load()?;
+                if state
+                    .sync
+                    .get(&key)
+                    .is_some_and(|r| r.remote_id == deleted.id)
+                {
+                    let message = format!(
+                        "{key} was deleted remotely; run `desk sync pull --prune` to delete it here"
+                    );
+                    // stdout carries nothing but events with --progress json-lines.
+                    match globals.progress {
+                        ProgressFormat::Human => println!("{message}"),
+                        ProgressFormat::JsonLines => globals.reporter().step(StepEvent::skipped(
+                            StepKind::Sync,
+                            format!("Delete {key}"),
+                            "deleted remotely; run `desk sync pull --prune` to delete it here",
+                        )),
+                    }
+                    if notify {
+                        notify::desktop("desk", &message);
+                    }
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+fn announce(applied: &[String], notify: bool) {
+    if notify && !applied.is_empty() {
+        notify::desktop("desk", &format!("Pulled {}", applied.join(", ")));
+    }
+}
+
+/// Handles local workspaces whose remote copy was deleted: with `prune` they
+/// move to the trash, otherwise they are only reported. Returns how many
+/// were skipped.
+fn prune_deleted(
+    namespaces: &[LocalNamespace],
+    remotes: &[RemoteWorkspace],
+    state: &mut DeskState,
+    filter: &SyncFilter,
+    prune: bool,
+    reporter: &mut dyn Reporter,
+) -> Result<usize> {
+    let remote_keys: HashSet<_> = remotes
+        .iter()
+        .map(|remote| sync_key(&remote.scope, &remote.name))
+        .collect();
+    let trash = Trash::new(&paths::data_dir()?);
+    let mut skipped = 0;
+
+    for namespace in namespaces {
+        for workspace in namespace.store.list()? {
+            let key = sync_key(&namespace.scope, &workspace.name);
+            if filter.excludes(&workspace.name) || remote_keys.contains(&key) {
+                continue;
+            }
+            let Some(record) = state.sync.get(&key) else {
+                continue;
+            };
+
+            let reason = if workspace.updated_at != record.local_updated_at {
+                "deleted remotely but changed here; use `desk sync push --force` to upload it again"
+            } else if workspace.metadata.locked {
+                "deleted remotely but locked here"
+            } else if !prune {
+                "deleted remotely; use --prune to move it to the trash"
+            } else {
+                trash.put(&namespace.store, &workspace.name)?;
+                state.sync.remove(&key);
+                if let Namespace::Repo(root) = namespace.store.namespace() {
+                    if state.current_workspace(root) == Some(workspace.name.as_str()) {
+                        state.clear_current(root);
+                    }
+                }
+                reporter.step(StepEvent::done(
+                    StepKind::Sync,
+                    format!("Moved {key} to the trash; it was deleted remotely"),
+                ));
+                continue;
+            };
+            skipped += 1;
+            reporter.step(StepEvent::skipped(StepKind::Sync, &key, reason));
+        }
+    }
+    Ok(skipped)
+}
+
+async fn handle_sync_status(
+    globals: &GlobalOptions,
+    backend: &impl SyncBackend,
+    filter: &SyncFilter,
+    limit: Option<usize>,
+) -> Result<()> {
+    let namespaces = local_namespaces(*globals)?;
+    let listed = match list_remotes(backend).await {
+        Ok(listed) => listed,
+        Err(e) if e.downcast_ref().is_some_and(BackendError::is_unreachable) => {
+            let location = backend.location();
+            let Some(cache) = RemoteCache::load(&paths::data_dir()?, &location)? else {
+                return Err(e);
+            };
+            eprintln!(
+                "{e}; showing remote workspaces as of {}",
+                timestamp(cache.fetched_at)
+            );
+            cache.workspaces
+        },
+        Err(e) => return Err(e),
+    };
+    let mut remotes = remote_index(listed);
+    remotes.retain(|_, remote| !filter.excludes(&remote.name));
+    let state = DeskState::load()?;
+
+    let mut rows = Vec::new();
+    for namespace in &namespaces {
+        for workspace in namespace.store.list()? {
+            if filter.excludes(&workspace.name) {
+                continue;
+            }
+            let key = sync_key(&namespace.scope, &workspace.name);
+            let remote = remotes.remove(&key);
+            if let Some(status) = classify(Some(&workspace), remote.as_ref(), state.sync.get(&key))
+            {
+                rows.push((status, key));
+            }
+        }
+    }
+    rows.extend(remotes.into_iter().map(|(key, remote)| {
+        let buried = state.tombstones.get(&key);
+        if buried.is_some_and(|t| t.record.remote_id == remote.id) {
+            (SyncStatus::DeletedLocally, key)
+        } else {
+            (SyncStatus::RemoteOnly, key)
+        }
+    }));
+    rows.sort_by(|a, b| a.1.cmp(&b.1));
+
+    if rows.is_empty() {
+        println!("No workspaces to sync");
+        return Ok(());
+    }
+    let total = rows.len();
+    let shown = limit.unwrap_or(total).min(total);
+    for (status, key) in rows.into_iter().take(shown) {
+        println!("{:<16} {key}", status.label());
+    }
+    if shown < total {
+        println!(
+            "... and {} more ({total} workspaces in total)",
+            total - shown
+        );
+    }
+    Ok(())
+}
+
+async fn handle_sync_diff(
+    globals: GlobalOptions,
+    backend: &impl SyncBackend,
+    name: &str,
+) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let local = store.load(name)?;
+    let key = sync_key(&sync_scope(store.namespace()), name);
+    let Some(remote) = remote_index(list_remotes(backend).await?).remove(&key) else {
+        bail!("{key} has no remote copy; push it first");
+    };
+    let state = DeskState::load()?;
+    let record = state.sync.get(&key).filter(|r| r.remote_id == remote.id);
+
+    if let Some(status) = classify(Some(&local), Some(&remote), record) {
+        println!("{key}: {}", status.label());
+    }
+    match record {
+        Some(record) => println!(
+            "Remote version {}, last synced at version {} {}",
+            remote.version,
+            record.version,
+            timestamp(record.synced_at)
+        ),
+        None => println!("Remote version {}, never synced from here", remote.version),
+    }
+
+    let diffs = diff_fields(
+        &local,
+        &remote.workspace,
+        record.and_then(|r| r.base.as_ref()),
+    );
+    if diffs.is_empty() {
+        println!("No differences");
+        return Ok(());
+    }
+
+    let width = diffs
+        .iter()
+        .flat_map(|d| d.local.lines())
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0)
+        .clamp("local".len(), MAX_DIFF_COLUMN);
+    println!();
+    println!("{:<12} {:<width$} | remote", "field", "local");
+    for diff in &diffs {
+        let local: Vec<_> = diff.local.lines().collect();
+        let remote: Vec<_> = diff.remote.lines().collect();
+        for i in 0..local.len().max(remote.len()).max(1) {
+            let field = if i == 0 { diff.field } else { "" };
+            let left = truncate(local.get(i).copied().unwrap_or_default(), width);
+            let right = remote.get(i).copied().unwrap_or_default();
+            let note = match diff.changed_by {
+                Some(changed_by) if i == 0 => format!("  ({})", changed_by.label()),
+                _ => String::new(),
+            };
+            let line = format!("{field:<12} {left:<width$} | {right}{note}");
+            println!("{}", line.trim_end());
+        }
+    }
+    Ok(())
+}
+
+async fn handle_sync_show(
+    globals: GlobalOptions,
+    backend: &impl SyncBackend,
+    name: &str,
+) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let key = sync_key(&sync_scope(store.namespace()), name);
+    let state = DeskState::load()?;
+    let Some(remote) = find_remote(backend, &key, state.sync.get(&key)).await? else {
+        bail!("{key} has no remote copy; push it first");
+    };
+    let record = state.sync.get(&key).filter(|r| r.remote_id == remote.id);
+    let local = store.exists(name).then(|| store.load(name)).transpose()?;
+    let workspace = &remote.workspace;
+
+    println!("Workspace:  {key}");
+    println!("Remote id:  {}", remote.id);
+    match record {
+        Some(record) => println!(
+            "Version:    {} (last synced at version {} {})",
+            remote.version,
+            record.version,
+            timestamp(record.synced_at)
+        ),
+        None => println!("Version:    {} (never synced from here)", remote.version),
+    }
+    println!("Updated:    {}", timestamp(remote.updated_at));
+    println!(
+        "Branch:     {} @ {}",
+        workspace.state.branch,
+        short(&workspace.state.commit)
+    );
+    if let Some(description) = &workspace.description {
+        println!("About:      {description}");
+    }
+    if !workspace.metadata.tags.is_empty() {
+        println!("Tags:       {}", workspace.metadata.tags.join(", "));
+    }
+    let changes = if remote.changes.is_some() {
+        "uncommitted changes included"
+    } else {
+        "none"
+    };
+    println!("Changes:    {changes}");
+    if let Some(status) = classify(local.as_ref(), Some(&remote), record) {
+        println!("Here:       {}", status.label());
+    }
+    Ok(())
+}
+
+/// Handles `desk sync rm`. The local workspace stays, so the next push
+/// uploads it again unless it is excluded.
+async fn handle_sync_rm(
+    globals: GlobalOptions,
+    backend: &impl SyncBackend,
+    name: &str,
+    force: bool,
+) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let key = sync_key(&sync_scope(store.namespace()), name);
+    let data_dir = paths::data_dir()?;
+    let _guard = MutationGuard::acquire(&data_dir, None)?;
+    let mut state = DeskState::load()?;
+    let Some(remote) = find_remote(backend, &key, state.sync.get(&key)).await? else {
+        bail!("{key} has no remote copy");
+    };
+    let record = state.sync.get(&key).filter(|r| r.remote_id == remote.id);
+    if let Some(record) = record.filter(|r| remote.version > r.version && !force) {
+        bail!(
+            "{key} changed remotely since the last sync (version {} is now {}); see `desk sync show {name}`, or pass --force to delete it anyway",
+            record.version,
+            remote.version
+        );
+    }
+
+    match backend.delete(&remote.id, remote.version, &key).await {
+        // Deleted from another machine already: just as good.
+        Err(e) if e.is_not_found() => {},
+        Err(e) if e.is_conflict() => {
+            bail!("{key} changed remotely while deleting it; nothing was deleted, try again")
+        },
+        result => result?,
+    }
+    if state
+        .sync
+        .get(&key)
+        .is_some_and(|r| r.remote_id == remote.id)
+    {
+        state.sync.remove(&key);
+    }
+    if state
+        .tombstones
+        .get(&key)
+        .is_some_and(|t| t.record.remote_id == remote.id)
+    {
+        state.tombstones.remove(&key);
+    }
+    state.save()?;
+    if let Some(mut cache) = RemoteCache::load(&data_dir, &backend.location())? {
+        cache.remove(&remote.id);
+        save_cache(&cache);
+    }
+
+    if store.exists(name) {
+        println!("Deleted the remote copy of {key}; the local workspace is kept (`desk sync ignore {name}` stops it from being pushed again)");
+    } else {
+        println!("Deleted the remote copy of {key}");
+    }
+    Ok(())
+}
+
+/// The remote copy of the workspace at `key`: fetched by the id it was last
+/// synced with, or else looked up among all remote workspaces.
+async fn find_remote(
+    backend: &impl SyncBackend,
+    key: &str,
+    record: Option<&SyncRecord>,
+) -> Result<Option<RemoteWorkspace>> {
+    if let Some(record) = record {
+        match backend.get(&record.remote_id, key).await {
+            Ok(remote) => return Ok(Some(remote)),
+            // Deleted, or replaced by a workspace with a new id.
+            Err(e) if e.is_not_found() => {},
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(remote_index(list_remotes(backend).await?).remove(key))
+}
+
+fn short(commit: &str) -> &str {
+    commit.get(..7).unwrap_or(commit)
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+/// Every namespace on this machine, starting with the current one (even if it
+/// has no workspaces yet) so it wins when two clones share a scope.
+fn local_namespaces(globals: GlobalOptions) -> Result<Vec<LocalNamespace>> {
+    let current = globals.workspace_store()?;
+    let mut stores = FileWorkspaceStore::all(&paths::data_dir()?)?;
+    stores.retain(|s| s.namespace() != current.namespace());
+    stores.insert(0, current);
+    Ok(stores
+        .into_iter()
+        .map(|store| LocalNamespace {
+            scope: sync_scope(store.namespace()),
+            store,
+        })
+        .collect())
+}
+
+/// The workspaces of `namespaces` that `filter` lets through.
+fn local_workspaces<'a>(
+    namespaces: &'a [LocalNamespace],
+    filter: &SyncFilter,
+) -> Result<Vec<(&'a LocalNamespace, Workspace)>> {
+    let mut locals = Vec::new();
+    for namespace in namespaces {
+        for workspace in namespace.store.list()? {
+            if !filter.excludes(&workspace.name) {
+                locals.push((namespace, workspace));
+            }
+        }
+    }
+    Ok(locals)
+}
+
+fn remote_index(remotes: Vec<RemoteWorkspace>) -> HashMap<String, RemoteWorkspace> {
+    remotes
+        .into_iter()
+        .map(|remote| (sync_key(&remote.scope, &remote.name), remote))
+        .collect()
+}
+
+/// Applies staged workspaces; Ctrl-C stops between two workspaces instead
+/// of killing the process halfway through one.
+fn apply_until_interrupted(staging: PullStage, atomic: bool) -> PullOutcome {
+    let mode = if atomic {
+        ApplyMode::Batch
+    } else {
+        ApplyMode::PerWorkspace
+    };
+    let cancel = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&cancel);
+    let listener = tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            flag.store(true, Ordering::SeqCst);
+        }
+    });
+    let outcome = staging.apply(&cancel, mode);
+    listener.abort();
+    outcome
+}
+
+/// What `desk sync pull` does with one remote workspace.
+enum PullAction {
+    /// Replace the local copy with the remote one.
+    Take,
+    /// Install the merge of both copies, saving the remote copy under a new
+    /// name first if the merge dropped some of its changes.
+    Merge {
+        merged: Box<Merged>,
+        keep_remote: bool,
+    },
+    /// Leave the local copy alone and save the remote copy under a new name.
+    KeepBoth,
+    /// Nothing to pull; make sure the pair is recorded.
+    Record,
+    Skip(&'static str),
+}
+
+fn pull_action(
+    local: Option<&Workspace>,
+    remote: &RemoteWorkspace,
+    record: Option<&SyncRecord>,
+    buried: bool,
+    resolution: Resolution,
+) -> PullAction {
+    if buried && resolution != Resolution::Overwrite {
+        return PullAction::Skip("deleted here; run `desk sync push` to delete the remote copy");
+    }
+    let keep_both = resolution == Resolution::KeepBoth;
+    let action = match classify(local, Some(remote), record) {
+        Some(SyncStatus::RemoteOnly | SyncStatus::RemoteChanges) => PullAction::Take,
+        Some(SyncStatus::LocalChanges | SyncStatus::Diverged)
+            if resolution == Resolution::Overwrite =>
+        {
+            PullAction::Take
+        },
+        Some(SyncStatus::LocalChanges) => PullAction::Skip("local copy is newer; push first"),
+        Some(SyncStatus::Diverged) => match local.and_then(|l| three_way(record, l, remote)) {
+            Some(merged) => PullAction::Merge {
+                keep_remote: keep_both && !merged.conflicts.is_empty(),
+                merged: Box::new(merged),
+            },
+            None if keep_both => PullAction::KeepBoth,
+            None => PullAction::Skip(
+                "changed on both sides; use --keep-both to keep the remote copy too or --force to overwrite the local copy",
+            ),
+        },
+        Some(
+            SyncStatus::InSync
+            | SyncStatus::LocalOnly
+            | SyncStatus::DeletedRemotely
+            | SyncStatus::DeletedLocally,
+        )
+        | None => PullAction::Record,
+    };
+
+    let replaces_local = matches!(action, PullAction::Take | PullAction::Merge { .. });
+    if replaces_local && local.is_some_and(|l| l.metadata.locked) {
+        return PullAction::Skip("locked; run `desk unlock <name>` to pull over it");
+    }
+    action
+}
+
+/// Merges a workspace changed on both sides against the copy recorded at the
+/// last sync, if one was recorded.
+fn three_way(
+    record: Option<&SyncRecord>,
+    local: &Workspace,
+    remote: &RemoteWorkspace,
+) -> Option<Merged> {
+    let base = record.filter(|r| r.remote_id == remote.id)?.base.as_ref()?;
+    Some(merge(base, local, &remote.workspace))
+}
+
+fn merged_message(key: &str, merged: &Merged) -> String {
+    if merged.conflicts.is_empty() {
+        return format!("Merged {key}");
+    }
+    let kept: Vec<_> = merged
+        .conflicts
+        .iter()
+        .map(|c| format!("{} from {}", c.field, c.kept.label()))
+        .collect();
+    format!("Merged {key}, keeping the newer {}", kept.join(", "))
+}
+
+/// Seals the stashed changes of `workspace` for upload. Changes that cannot
+/// be read or are too large are reported and left out of the push.
+fn outgoing_changes(
+    store: &FileWorkspaceStore,
+    workspace: &Workspace,
+    options: &ChangeOptions,
+    key: &str,
+    reporter: &mut dyn Reporter,
+) -> Option<String> {
+    if !options.upload {
+        return None;
+    }
+    let sealed = saved_changes(store, workspace).and_then(|contents| {
+        let Some(contents) = contents else {
+            return Ok(None);
+        };
+        let bytes = changes::pack(&contents, options.limit)?;
+        Ok(Some(changes::seal(&bytes, options.upload_passphrase())?))
+    });
+    match sealed {
+        Ok(sealed) => sealed,
+        Err(e) => {
+            reporter.step(StepEvent::skipped(
+                StepKind::Sync,
+                format!("Upload changes of {key}"),
+                format!("{e:#}"),
+            ));
+            None
+        },
+    }
+}
+
+/// The uncommitted changes saved for `workspace`: its stash in this clone,
+/// or else the patch captured when it was closed, or else the ones pulled
+/// for it and not restored yet.
+fn saved_changes(
+    store: &FileWorkspaceStore,
+    workspace: &Workspace,
+) -> Result<Option<StashContents>> {
+    if let (Namespace::Repo(root), Some(stash)) =
+        (store.namespace(), workspace.state.stash_name.as_deref())
+    {
+        if let Some(contents) = Git2Operations::new(root).stash_contents(stash)? {
+            return Ok(Some(contents));
+        }
+    }
+    if let Some(contents) = changes::load_captured(store, &workspace.name)? {
+        return Ok(Some(contents));
+    }
+    Ok(changes::load_pulled(store, &workspace.name)?)
+}
+
+/// Opens and checks the changes pulled with `remote`. Changes that cannot be
+/// opened are reported; the workspace itself is still pulled.
+fn incoming_changes(
+    remote: &RemoteWorkspace,
+    options: &ChangeOptions,
+    key: &str,
+    reporter: &mut dyn Reporter,
+) -> Option<Vec<u8>> {
+    let sealed = remote.changes.as_deref()?;
+    let opened = changes::open(sealed, options.passphrase.as_ref())
+        .and_then(|bytes| changes::unpack(&bytes).map(|_| bytes));
+    match opened {
+        Ok(bytes) => Some(bytes),
+        Err(e) => {
+            reporter.step(StepEvent::skipped(
+                StepKind::Sync,
+                format!("Pull changes of {key}"),
+                format!("{:#}", anyhow::Error::from(e)),
+            ));
+            None
+        },
+    }
+}
+
+/// Saves `remote` next to the local workspace of the same name, as
+/// `<name> (remote)` or the first free `<name> (remote N)`.
+fn keep_remote_copy(
+    store: &FileWorkspaceStore,
+    remote: &RemoteWorkspace,
+    reporter: &mut dyn Reporter,
+) -> Result<()> {
+    let name = remote_copy_name(&remote.name, |name| store.exists(name));
+    let mut copy = localize(remote, store, None);
+    copy.name.clone_from(&name);
+    // The stash named after the original belongs to the local workspace.
+    copy.state.stash_name = None;
+    store.save(&copy)?;
+    reporter.step(StepEvent::done(
+        StepKind::Sync,
+        format!(
+            "Saved the remote copy of {} as '{name}'",
+            sync_key(&remote.scope, &remote.name)
+        ),
+    ));
+    Ok(())
+}
+
+/// Adapts a pulled workspace to this machine: it belongs to the local clone,
+/// and worktree paths from the other machine mean nothing here.
+fn localize(
+    remote: &RemoteWorkspace,
+    store: &FileWorkspaceStore,
+    local: Option<&Workspace>,
+) -> Workspace {
+    let mut workspace = remote.workspace.clone();
+    if let Namespace::Repo(root) = store.namespace() {
+        workspace.repo_path.clone_from(root);
+    }
+    workspace.state.worktree = local.and_then(|l| l.state.worktree.clone());
+    workspace
+}
+
+fn summary(verb: &str, count: usize, skipped: usize) -> String {
+    let plural = if count == 1 { "" } else { "s" };
+    if skipped == 0 {
+        format!("{verb} {count} workspace{plural}")
+    } else {
+        format!("{verb} {count} workspace{plural} ({skipped} skipped)")
+    }
+}
+
+fn list_or_none(keys: &[String]) -> String {
+    if keys.is_empty() {
+        "none".to_string()
+    } else {
+        keys.join(", ")
+    }
+}
@@ -0,0 +1,119 @@
+//! `desk time`: where the time went, from the sessions desk recorded.
+
+use anyhow::Result;
+use chrono::{Datelike, Days, Local, Utc};
+use clap::Subcommand;
+
+use crate::core::config::DeskConfig;
+use crate::core::paths;
+use crate::core::state::DeskState;
+use crate::core::time_log::{self, local_midnight, Grouping, ReportRow, TimeLog};
+use crate::utils::duration::format_duration;
+
+#[derive(Debug, Subcommand)]
+pub enum TimeCommand {
+    /// Show the time spent per workspace or repository, the open session
+    /// included
+    Report {
+        /// Only count time since midnight
+        #[arg(long, conflicts_with = "week")]
+        today: bool,
+
+        /// Only count time since Monday
+        #[arg(long)]
+        week: bool,
+
+        /// Add time up per workspace or per repository
+        #[arg(long, value_enum, default_value_t)]
+        by: Grouping,
+
+        /// Print comma-separated values instead of a table
+        #[arg(long)]
+        csv: bool,
+    },
+}
+
+/// Handles `desk time`.
+pub fn handle_time(config: &DeskConfig, command: &TimeCommand) -> Result<()> {
+    match command {
+        TimeCommand::Report {
+            today,
+            week,
+            by,
+            csv,
+        } => {
+            let now = Utc::now();
+            let (since, period) = if *today {
+                (Some(local_midnight(Local::now().date_naive())), "today")
+            } else if *week {
+                let today = Local::now().date_naive();
+                let monday = today - Days::new(u64::from(today.weekday().num_days_from_monday()));
+                (Some(local_midnight(monday)), "this week")
+            } else {
+                (None, "in total")
+            };
+
+            let mut intervals = TimeLog::new(&paths::data_dir()?).read()?;
+            if config.state.track_time {
+                intervals.extend(time_log::open_intervals(&DeskState::load()?, now));
+            }
+            let rows = time_log::report(&intervals, since, now, *by);
+            if *csv {
+                print_csv(&rows, *by);
+                return Ok(());
+            }
+            if rows.is_empty() {
+                println!("No time recorded {period}");
+                if !config.state.track_time {
+                    println!("Time tracking is off; turn it on with: desk config set state.track_time true");
+                }
+                return Ok(());
+            }
+            print_table(&rows, period);
+        },
+    }
+    Ok(())
+}
+
+fn print_table(rows: &[ReportRow], period: &str) {
+    println!("Time spent {period}:");
+    for row in rows {
+        match &row.workspace {
+            Some(workspace) => println!(
+                "  {:>8}  {workspace:<24} {}",
+                format_duration(row.total),
+                row.repo
+            ),
+            None => println!("  {:>8}  {}", format_duration(row.total), row.repo),
+        }
+    }
+    let total = rows.iter().map(|row| row.total).sum();
+    println!("  {:>8}  total", format_duration(total));
+}
+
+fn print_csv(rows: &[ReportRow], by: Grouping) {
+    match by {
+        Grouping::Workspace => println!("workspace,repo,seconds"),
+        Grouping::Repo => println!("repo,seconds"),
+    }
+    for row in rows {
+        let seconds = row.total.as_secs();
+        match &row.workspace {
+            Some(workspace) => println!(
+                "{},{},{seconds}",
+                csv_field(workspace),
+                csv_field(&row.repo)
+            ),
+            None => println!("{},{seconds}", csv_field(&row.repo)),
+        }
+    }
+}
+
+/// `value` quoted as RFC 4180 asks when it holds a separator or quote.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
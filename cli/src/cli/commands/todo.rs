@@ -0,0 +1,173 @@
+//! `desk todo`: a short list of next actions kept with each workspace, so
+//! that opening it, on this machine or after a sync on another, brings back
+//! what was left to do.
+
+use anyhow::{bail, Result};
+use clap::Subcommand;
+
+use crate::cli::GlobalOptions;
+use crate::core::lock::MutationGuard;
+use crate::core::paths;
+use crate::core::state::DeskState;
+use crate::core::store::{FileWorkspaceStore, Namespace};
+use crate::core::workspace::{TodoItem, Workspace};
+use crate::output::{ProgressFormat, Verbosity};
+
+#[derive(Debug, Subcommand)]
+pub enum TodoCommand {
+    /// Add an item to a workspace's to-do list
+    Add {
+        /// What to do
+        #[arg(required = true)]
+        text: Vec<String>,
+
+        /// Workspace to add it to; the open one when omitted
+        #[arg(short, long)]
+        workspace: Option<String>,
+    },
+
+    /// Tick items off, by the numbers `desk todo list` shows
+    Done {
+        #[arg(required = true)]
+        numbers: Vec<usize>,
+
+        /// Workspace the items are in; the open one when omitted
+        #[arg(short, long)]
+        workspace: Option<String>,
+    },
+
+    /// Show a workspace's to-do list
+    #[command(visible_alias = "ls")]
+    List {
+        /// Workspace to show; the open one when omitted
+        workspace: Option<String>,
+    },
+
+    /// Remove the items that were ticked off
+    Clear {
+        /// Workspace to clear; the open one when omitted
+        #[arg(short, long)]
+        workspace: Option<String>,
+    },
+}
+
+/// Handles `desk todo`.
+pub fn handle_todo(globals: &GlobalOptions, command: &TodoCommand) -> Result<()> {
+    let store = globals.workspace_store()?;
+    match command {
+        TodoCommand::Add { text, workspace } => {
+            let text = text.join(" ").trim().to_string();
+            if text.is_empty() {
+                bail!("nothing to add; pass what to do");
+            }
+            let name = target(&store, workspace.as_deref())?;
+            let _guard = guard(&store)?;
+            let mut ws = store.load(&name)?;
+            if ws.metadata.todos.iter().any(|item| item.text == text) {
+                bail!("'{name}' already has \"{text}\" on its to-do list");
+            }
+            ws.metadata.todos.push(TodoItem { text, done: false });
+            ws.touch();
+            store.save(&ws)?;
+            println!("Added item {} to '{name}'", ws.metadata.todos.len());
+        },
+        TodoCommand::Done { numbers, workspace } => {
+            let name = target(&store, workspace.as_deref())?;
+            let _guard = guard(&store)?;
+            let mut ws = store.load(&name)?;
+            let count = ws.metadata.todos.len();
+            if let Some(number) = numbers.iter().find(|n| **n == 0 || **n > count) {
+                bail!("'{name}' has no item {number}; see desk todo list {name}");
+            }
+            for number in numbers {
+                ws.metadata.todos[number - 1].done = true;
+            }
+            ws.touch();
+            store.save(&ws)?;
+            let left = pending(&ws).len();
+            let plural = if left == 1 { "" } else { "s" };
+            println!("Ticked off; '{name}' has {left} item{plural} left to do");
+        },
+        TodoCommand::List { workspace } => {
+            let name = target(&store, workspace.as_deref())?;
+            let ws = store.load(&name)?;
+            if ws.metadata.todos.is_empty() {
+                println!("Nothing to do in '{name}'; add an item with: desk todo add <text>");
+                return Ok(());
+            }
+            println!("To do in '{name}':");
+            for (i, item) in ws.metadata.todos.iter().enumerate() {
+                let mark = if item.done { "x" } else { " " };
+                println!("  {:>2}. [{mark}] {}", i + 1, item.text);
+            }
+        },
+        TodoCommand::Clear { workspace } => {
+            let name = target(&store, workspace.as_deref())?;
+            let _guard = guard(&store)?;
+            let mut ws = store.load(&name)?;
+            let before = ws.metadata.todos.len();
+            ws.metadata.todos.retain(|item| !item.done);
+            let removed = before - ws.metadata.todos.len();
+            if removed == 0 {
+                println!("Nothing ticked off in '{name}'");
+                return Ok(());
+            }
+            ws.touch();
+            store.save(&ws)?;
+            let plural = if removed == 1 { "" } else { "s" };
+            println!("Removed {removed} ticked-off item{plural} from '{name}'");
+        },
+    }
+    Ok(())
+}
+
+/// The items of `workspace` still to do, with the numbers `desk todo list`
+/// gives them.
+pub fn pending(workspace: &Workspace) -> Vec<(usize, &str)> {
+    workspace
+        .metadata
+        .todos
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| !item.done)
+        .map(|(i, item)| (i + 1, item.text.as_str()))
+        .collect()
+}
+
+/// Prints the items of `workspace`, just opened, still to do, unless the
+/// command is to print nothing or progress goes out as JSON.
+pub fn print_pending(globals: &GlobalOptions, workspace: &Workspace) {
+    if globals.verbosity == Verbosity::Quiet || globals.progress != ProgressFormat::Human {
+        return;
+    }
+    let pending = pending(workspace);
+    if !pending.is_empty() {
+        println!("To do:");
+    }
+    for (number, text) in pending {
+        println!("  {number}. {text}");
+    }
+}
+
+/// Workspace `name`, or else the one open in the current repository.
+fn target(store: &FileWorkspaceStore, name: Option<&str>) -> Result<String> {
+    if let Some(name) = name {
+        return Ok(name.to_string());
+    }
+    let open = match store.namespace() {
+        Namespace::Repo(repo) => DeskState::load()?.current_workspace(repo).map(String::from),
+        Namespace::Global => None,
+    };
+    match open {
+        Some(name) => Ok(name),
+        None => bail!("no workspace is open here; name the workspace to use"),
+    }
+}
+
+fn guard(store: &FileWorkspaceStore) -> Result<MutationGuard> {
+    let repo = match store.namespace() {
+        Namespace::Repo(root) => Some(root.as_path()),
+        Namespace::Global => None,
+    };
+    Ok(MutationGuard::acquire(&paths::data_dir()?, repo)?)
+}
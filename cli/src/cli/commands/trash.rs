@@ -0,0 +1,88 @@
+//! `desk trash`, `desk undelete`, and `desk gc`: deleted workspaces.
+
+use anyhow::Result;
+use chrono::Duration;
+use clap::Subcommand;
+
+use crate::cli::GlobalOptions;
+use crate::core::config::DeskConfig;
+use crate::core::lock::MutationGuard;
+use crate::core::paths;
+use crate::core::state::DeskState;
+use crate::core::trash::Trash;
+use crate::output::timestamp::timestamp;
+
+#[derive(Debug, Subcommand)]
+pub enum TrashCommand {
+    /// Show deleted workspaces that can still be restored
+    List,
+}
+
+/// Handles `desk trash`.
+pub fn handle_trash(config: &DeskConfig, command: &TrashCommand) -> Result<()> {
+    match command {
+        TrashCommand::List => {
+            let entries = Trash::new(&paths::data_dir()?).list()?;
+            if entries.is_empty() {
+                println!("Trash is empty");
+                return Ok(());
+            }
+            let retention = Duration::days(i64::from(config.trash.retention_days));
+            for entry in &entries {
+                println!(
+                    "{:<24} deleted {}  purged {}  {}",
+                    entry.name,
+                    timestamp(entry.deleted_at),
+                    timestamp(entry.deleted_at + retention),
+                    entry.namespace
+                );
+            }
+        },
+    }
+    Ok(())
+}
+
+/// Handles `desk undelete`.
+pub fn handle_undelete(globals: &GlobalOptions, name: &str) -> Result<()> {
+    let data_dir = paths::data_dir()?;
+    let namespace = globals.workspace_store()?.namespace().clone();
+    let _guard = MutationGuard::acquire(&data_dir, None)?;
+
+    let trash = Trash::new(&data_dir);
+    let entry = trash.find(&namespace, name)?;
+    trash.restore(&entry, &data_dir)?;
+
+    let mut state = DeskState::load()?;
+    state.unbury(&namespace, name);
+    state.save()?;
+    println!("Restored '{name}'");
+    Ok(())
+}
+
+/// Handles `desk gc`.
+pub fn handle_gc(config: &DeskConfig, dry_run: bool, all: bool) -> Result<()> {
+    let data_dir = paths::data_dir()?;
+    let _guard = MutationGuard::acquire(&data_dir, None)?;
+
+    let trash = Trash::new(&data_dir);
+    let entries = if all {
+        trash.list()?
+    } else {
+        trash.expired(config.trash.retention_days)?
+    };
+    let mut purged = 0;
+    for entry in entries {
+        if !dry_run {
+            trash.purge(&entry)?;
+        }
+        purged += 1;
+    }
+
+    let plural = if purged == 1 { "" } else { "s" };
+    if dry_run {
+        println!("Would permanently delete {purged} workspace{plural} from the trash");
+    } else {
+        println!("Permanently deleted {purged} workspace{plural} from the trash");
+    }
+    Ok(())
+}
@@ -0,0 +1,89 @@
+//! `desk du`: disk usage of desk data.
+
+use anyhow::Result;
+
+use crate::core::config::DeskConfig;
+use crate::core::paths;
+use crate::core::trash::{Trash, TrashEntry};
+use crate::core::usage::DataUsage;
+use crate::utils::size::format_size;
+
+/// Handles `desk du`.
+pub fn handle_du(config: &DeskConfig) -> Result<()> {
+    let data_dir = paths::data_dir()?;
+    let usage = DataUsage::measure(&data_dir)?;
+
+    if usage.workspaces.is_empty() {
+        println!("No saved workspaces");
+    } else {
+        println!(
+            "{:<24} {:>10} {:>10}  Namespace",
+            "Workspace", "Size", "Notes"
+        );
+        for workspace in &usage.workspaces {
+            println!(
+                "{:<24} {:>10} {:>10}  {}",
+                workspace.name,
+                format_size(workspace.bytes),
+                format_size(workspace.notes_bytes),
+                workspace.namespace
+            );
+        }
+    }
+
+    let trash = Trash::new(&data_dir);
+    let trashed = trash.list()?;
+    println!();
+    println!("Data directory {}", data_dir.display());
+    println!("  Workspaces  {:>10}", format_size(usage.workspaces_bytes));
+    println!(
+        "  Trash       {:>10}  ({} deleted workspace{})",
+        format_size(usage.trash_bytes),
+        trashed.len(),
+        if trashed.len() == 1 { "" } else { "s" }
+    );
+    println!("  State       {:>10}", format_size(usage.state_bytes));
+    if !usage.leftovers.is_empty() {
+        println!("  Leftovers   {:>10}", format_size(usage.leftovers_bytes()));
+    }
+    if usage.other_bytes > 0 {
+        println!("  Other       {:>10}", format_size(usage.other_bytes));
+    }
+    println!("  Total       {:>10}", format_size(usage.total_bytes()));
+
+    let expired = trash.expired(config.trash.retention_days)?;
+    let mut hints = Vec::new();
+    if !expired.is_empty() {
+        let bytes = expired
+            .iter()
+            .map(TrashEntry::size)
+            .sum::<Result<u64, _>>()?;
+        hints.push(format!(
+            "`desk gc` would reclaim {} from {} workspace{} deleted over {} days ago",
+            format_size(bytes),
+            expired.len(),
+            if expired.len() == 1 { "" } else { "s" },
+            config.trash.retention_days
+        ));
+    }
+    if trashed.len() > expired.len() {
+        hints.push(format!(
+            "`desk gc --all` would empty the whole trash ({})",
+            format_size(usage.trash_bytes)
+        ));
+    }
+    for leftover in &usage.leftovers {
+        hints.push(format!(
+            "{} ({}) holds data set aside by `desk restore-backup` or `desk sync pull`; delete it once you no longer need it",
+            leftover.path.display(),
+            format_size(leftover.bytes)
+        ));
+    }
+    if !hints.is_empty() {
+        println!();
+        for hint in hints {
+            println!("hint: {hint}");
+        }
+    }
+    Ok(())
+}
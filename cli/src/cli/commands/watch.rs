@@ -0,0 +1,245 @@
+//! `desk watch` and `desk recover`: checkpoints of the open workspace's
+//! uncommitted changes while files change, for getting back to where you
+//! left off after a crash or a careless `git checkout .`.
+//!
+//! A checkpoint is the same archive `desk sync` sends, kept next to the
+//! workspace file; `desk save` takes one too. Only the latest is kept, and closing the workspace drops
+//! it, since closing saves the changes properly.
+
+use std::path::{Component, Path};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::time::{Instant, MissedTickBehavior};
+
+use super::daemon::shutdown_signal;
+use crate::cli::GlobalOptions;
+use crate::core::changes::{self, ChangesError};
+use crate::core::config::DeskConfig;
+use crate::core::state::DeskState;
+use crate::core::store::{FileWorkspaceStore, Namespace};
+use crate::core::workspace::Workspace;
+use crate::integrations::git::Git2Operations;
+use crate::output::timestamp::timestamp;
+use crate::utils::size::format_size;
+
+/// How often `desk watch` looks for another open workspace and whether a
+/// checkpoint is due.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Handles `desk watch`.
+///
+/// Checkpoints the workspace open in the current repository at most every
+/// `interval` minutes (`watch.interval_mins` by default) while files in its
+/// checkout change, and once more on exit.
+pub async fn handle_watch(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    interval: Option<u64>,
+) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let Namespace::Repo(repo) = store.namespace() else {
+        bail!("desk watch needs a git repository; run it inside one");
+    };
+    let minutes = interval.unwrap_or(config.watch.interval_mins).max(1);
+    let period = Duration::from_secs(minutes * 60);
+    println!(
+        "Checkpointing the open workspace every {minutes} min while files change (Ctrl-C to stop)"
+    );
+
+    let active = Arc::new(AtomicBool::new(false));
+    let mut watched: Option<(Workspace, RecommendedWatcher)> = None;
+    let mut last_checkpoint = Instant::now();
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+    poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+
+    loop {
+        let stopping = tokio::select! {
+            _ = poll.tick() => false,
+            () = &mut shutdown => true,
+        };
+
+        let open = open_workspace(&store, repo)?;
+        let moved = match (&watched, &open) {
+            (Some((old, _)), Some(new)) => old.name != new.name || checkout(old) != checkout(new),
+            (None, None) => false,
+            _ => true,
+        };
+        if moved {
+            // Changes made before the switch belong to the old workspace.
+            if let Some((old, _)) = &watched {
+                if active.swap(false, Ordering::Relaxed) {
+                    report(checkpoint(&store, config, old));
+                }
+            }
+            watched = if let Some(workspace) = open {
+                let events = watch(checkout(&workspace), Arc::clone(&active))?;
+                println!(
+                    "Watching '{}' in {}",
+                    workspace.name,
+                    checkout(&workspace).display()
+                );
+                Some((workspace, events))
+            } else {
+                println!("No workspace is open; waiting for `desk open`");
+                None
+            };
+            last_checkpoint = Instant::now();
+        }
+
+        if let Some((workspace, _)) = &watched {
+            let due = stopping || last_checkpoint.elapsed() >= period;
+            if due && active.swap(false, Ordering::Relaxed) {
+                report(checkpoint(&store, config, workspace));
+                last_checkpoint = Instant::now();
+            }
+        }
+        if stopping {
+            return Ok(());
+        }
+    }
+}
+
+/// Handles `desk recover`: applies the latest checkpoint of workspace
+/// `name`, the open one by default, to its clean checkout.
+pub fn handle_recover(globals: &GlobalOptions, name: Option<&str>) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let Namespace::Repo(repo) = store.namespace() else {
+        bail!("desk recover needs a git repository; run it inside one");
+    };
+    let state = DeskState::load()?;
+    let open = state.current_workspace(repo);
+    let Some(name) = name.or(open) else {
+        bail!("no workspace is open; pass the one to recover: desk recover <name>");
+    };
+    let workspace = store.load(name)?;
+    let Some((contents, taken)) = changes::load_checkpoint(&store, name)? else {
+        bail!("no checkpoint of '{name}'; `desk watch` and `desk save` take them");
+    };
+    if open != Some(name) {
+        bail!("'{name}' is not open; open it first: desk open {name}");
+    }
+
+    let git = Git2Operations::new(checkout(&workspace));
+    if git.has_changes(true)? {
+        bail!(
+            "the checkout has uncommitted changes; commit or stash them before recovering the \
+             checkpoint from {}",
+            timestamp(taken)
+        );
+    }
+    let head = git.current_commit()?;
+    if head != contents.base {
+        eprintln!(
+            "note: the checkpoint was taken on {}, and the checkout is now on {}",
+            short(&contents.base),
+            short(&head)
+        );
+    }
+    let files = contents.file_count()?;
+    git.apply_contents(&contents)
+        .context("failed to apply the checkpoint")?;
+    changes::remove_checkpoint(&store, name)?;
+
+    let mut reporter = globals.reporter();
+    let plural = if files == 1 { "" } else { "s" };
+    reporter.finish(&format!(
+        "Recovered {files} changed file{plural} in '{name}' from the checkpoint {}",
+        timestamp(taken)
+    ));
+    Ok(())
+}
+
+/// The workspace open in `repo`, if any.
+fn open_workspace(store: &FileWorkspaceStore, repo: &Path) -> Result<Option<Workspace>> {
+    let state = DeskState::load()?;
+    let Some(name) = state
+        .current_workspace(repo)
+        .filter(|name| store.exists(name))
+    else {
+        return Ok(None);
+    };
+    Ok(Some(store.load(name)?))
+}
+
+fn checkout(workspace: &Workspace) -> &Path {
+    workspace
+        .state
+        .worktree
+        .as_deref()
+        .unwrap_or(&workspace.repo_path)
+}
+
+/// Watches `dir`, setting `active` when a file outside `.git` changes.
+fn watch(dir: &Path, active: Arc<AtomicBool>) -> Result<RecommendedWatcher> {
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        if event.kind.is_access() {
+            return;
+        }
+        if event.paths.iter().any(|path| !in_git_dir(path)) {
+            active.store(true, Ordering::Relaxed);
+        }
+    })
+    .context("failed to watch for file changes")?;
+    watcher
+        .watch(dir, RecursiveMode::Recursive)
+        .with_context(|| format!("failed to watch {}", dir.display()))?;
+    Ok(watcher)
+}
+
+fn in_git_dir(path: &Path) -> bool {
+    path.components()
+        .any(|component| component == Component::Normal(".git".as_ref()))
+}
+
+/// Saves the uncommitted changes of `workspace` as its checkpoint,
+/// returning how many files they touch; `None` when there are none.
+pub fn checkpoint(
+    store: &FileWorkspaceStore,
+    config: &DeskConfig,
+    workspace: &Workspace,
+) -> Result<Option<(String, usize)>> {
+    let git = Git2Operations::new(checkout(workspace));
+    let Some(contents) = git.working_changes(config.capture.include_untracked)? else {
+        return Ok(None);
+    };
+    let limit = config.capture.max_patch_mib.saturating_mul(1024 * 1024);
+    let bytes = match changes::pack(&contents, limit) {
+        Ok(bytes) => bytes,
+        Err(ChangesError::TooLarge { size, .. }) => {
+            bail!(
+                "changes take {}, more than capture.max_patch_mib",
+                format_size(size)
+            )
+        },
+        Err(e) => return Err(e.into()),
+    };
+    changes::save_checkpoint(store, &workspace.name, &bytes)?;
+    Ok(Some((workspace.name.clone(), contents.file_count()?)))
+}
+
+/// Prints the outcome of a checkpoint, keeping the watch going on failure.
+fn report(outcome: Result<Option<(String, usize)>>) {
+    let time = Local::now().format("%H:%M");
+    match outcome {
+        Ok(Some((name, files))) => {
+            let plural = if files == 1 { "" } else { "s" };
+            println!("{time} Checkpointed {files} changed file{plural} of '{name}'");
+        },
+        Ok(None) => {},
+        Err(e) => eprintln!("{time} warning: checkpoint failed: {e:#}"),
+    }
+}
+
+fn short(commit: &str) -> &str {
+    commit.get(..7).unwrap_or(commit)
+}
@@ -0,0 +1,1115 @@
+//! Workspace commands: listing, opening, and closing contexts.
+
+use std::fmt::Write as _;
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::watch::checkpoint;
+use super::{env, issues, shell, status, todo};
+use crate::cli::GlobalOptions;
+use crate::core::changes::{self, ChangesError};
+use crate::core::config::{DeskConfig, StateConfig};
+use crate::core::context;
+use crate::core::hooks::{self, HookContext, HookStage};
+use crate::core::lock::MutationGuard;
+use crate::core::naming;
+use crate::core::paths;
+use crate::core::state::DeskState;
+use crate::core::store::{FileWorkspaceStore, Namespace};
+use crate::core::suggest::{predict_next, recent, sort_workspaces, WorkspaceOrder};
+use crate::core::switch;
+use crate::core::sync::{classify_local, repo_origin, sync_key, sync_scope};
+use crate::core::time_log::{Interval, TimeLog};
+use crate::core::trash::Trash;
+use crate::core::workspace::{IssueLink, Workspace, WorkspaceState};
+use crate::integrations::git::{Git2Operations, GitError, SwitchOptions};
+use crate::output::table::{terminal_width, Table};
+use crate::output::timestamp::timestamp;
+use crate::output::{Reporter, StepEvent, StepKind, Verbosity};
+use crate::utils::size::format_size;
+
+/// Predictions shown in the picker and `desk recent`.
+const MAX_PREDICTIONS: usize = 3;
+
+/// How `desk list` prints workspaces.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ListFormat {
+    /// Aligned columns with branch, saved changes, tags, age, and sync state
+    #[default]
+    Table,
+    /// One name per line, the open workspace marked with `*`
+    Compact,
+    /// A JSON array with every field
+    Json,
+}
+
+/// What a workspace created by `desk open` starts out with, when it should
+/// not just take the current branch.
+#[derive(Debug, Clone, Default)]
+pub struct NewWorkspace {
+    /// Branch to check out for it, which has to exist.
+    pub branch: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub notes: Option<String>,
+    pub issue: Option<IssueLink>,
+}
+
+/// What `desk open` does with a workspace that already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Existing {
+    /// Restore it, if it was saved in this repository.
+    #[default]
+    Restore,
+    /// Restore it in this repository, wherever it was saved (`--here`).
+    RestoreHere,
+    /// Replace its saved state with the current checkout (`--force`).
+    Overwrite,
+}
+
+impl Existing {
+    /// What `--force` and `--here` ask for; `--force` wins.
+    pub const fn asked(force: bool, here: bool) -> Self {
+        match (force, here) {
+            (true, _) => Self::Overwrite,
+            (false, true) => Self::RestoreHere,
+            (false, false) => Self::Restore,
+        }
+    }
+}
+
+/// One workspace as `desk list` shows it.
+#[derive(Debug, Serialize)]
+struct ListEntry<'a> {
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    branch: &'a str,
+    open: bool,
+    locked: bool,
+    /// Where its uncommitted changes are kept until it is opened, if it has
+    /// any: `stash`, `ref`, `patch`, or `pulled`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changes: Option<&'static str>,
+    tags: &'a [String],
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    /// Sync state as far as is known without asking the backend.
+    sync: &'static str,
+}
+
+/// Handles `desk list`.
+pub fn handle_list(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    order: WorkspaceOrder,
+    format: ListFormat,
+) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let mut workspaces = store.list()?;
+    let state = DeskState::load()?;
+    let (current, history) = match store.namespace() {
+        Namespace::Repo(root) => (
+            state.current_workspace(root),
+            history_scope(root, &config.state),
+        ),
+        Namespace::Global => (None, None),
+    };
+    if order != WorkspaceOrder::Name {
+        sort_workspaces(
+            &mut workspaces,
+            order,
+            state.history_for(history),
+            Utc::now(),
+        );
+    }
+
+    let scope = sync_scope(store.namespace());
+    let mut entries = Vec::new();
+    for workspace in &workspaces {
+        let stash = workspace.state.stash_name.as_deref().map(|stash| {
+            if stash.starts_with("refs/") {
+                "ref"
+            } else {
+                "stash"
+            }
+        });
+        entries.push(ListEntry {
+            name: &workspace.name,
+            description: workspace.description.as_deref(),
+            branch: &workspace.state.branch,
+            open: current == Some(workspace.name.as_str()),
+            locked: workspace.metadata.locked,
+            changes: stash.map_or_else(
+                || changes::waiting(&store, &workspace.name),
+                |s| Ok(Some(s)),
+            )?,
+            tags: &workspace.metadata.tags,
+            created_at: workspace.created_at,
+            updated_at: workspace.updated_at,
+            sync: classify_local(
+                workspace,
+                state.sync.get(&sync_key(&scope, &workspace.name)),
+            )
+            .label(),
+        });
+    }
+
+    if format == ListFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("No workspaces for {}", store.namespace());
+        println!("Create one with: desk open <name>");
+        return Ok(());
+    }
+    if format == ListFormat::Compact {
+        for entry in &entries {
+            let marker = if entry.open { "*" } else { " " };
+            println!("{marker} {}", entry.name);
+        }
+        return Ok(());
+    }
+
+    println!("Workspaces for {}:", store.namespace());
+    println!();
+    let mut table = Table::new(&["", "NAME", "BRANCH", "CHANGES", "TAGS", "UPDATED", "SYNC"]);
+    for entry in &entries {
+        table.row(vec![
+            if entry.open { "*" } else { "" }.to_string(),
+            if entry.locked {
+                format!("{} (locked)", entry.name)
+            } else {
+                entry.name.to_string()
+            },
+            entry.branch.to_string(),
+            entry.changes.unwrap_or_default().to_string(),
+            entry.tags.join(", "),
+            timestamp(entry.updated_at),
+            entry.sync.to_string(),
+        ]);
+    }
+    for line in table.render(terminal_width()) {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+/// Handles `desk delete`: moves the workspace to the trash.
+pub fn handle_delete(globals: &GlobalOptions, config: &DeskConfig, name: &str) -> Result<()> {
+    let data_dir = paths::data_dir()?;
+    let store = globals.workspace_store()?;
+    let repo = match store.namespace() {
+        Namespace::Repo(root) => Some(root.as_path()),
+        Namespace::Global => None,
+    };
+    let _guard = MutationGuard::acquire(&data_dir, repo)?;
+
+    let tags = store
+        .load(name)
+        .map(|w| w.metadata.tags)
+        .unwrap_or_default();
+    Trash::new(&data_dir).put(&store, name)?;
+
+    let mut state = DeskState::load()?;
+    state.bury(store.namespace(), name);
+    if let Some(repo) = repo {
+        if state.current_workspace(repo) == Some(name) {
+            state.clear_current(repo);
+        }
+    }
+    state.save()?;
+
+    let cwd = std::env::current_dir()?;
+    let mut reporter = globals.reporter();
+    hooks::run_hooks(
+        &config.hooks,
+        HookStage::OnDelete,
+        &HookContext::for_workspace(
+            repo.unwrap_or(&cwd),
+            name,
+            &tags,
+            globals.verbosity != Verbosity::Quiet,
+        ),
+        reporter.as_mut(),
+    )?;
+    println!("Moved '{name}' to the trash (restore it with: desk undelete {name})");
+    Ok(())
+}
+
+/// Handles `desk lock` and `desk unlock`.
+pub fn handle_lock(globals: &GlobalOptions, name: &str, locked: bool) -> Result<()> {
+    let data_dir = paths::data_dir()?;
+    let store = globals.workspace_store()?;
+    let repo = match store.namespace() {
+        Namespace::Repo(root) => Some(root.as_path()),
+        Namespace::Global => None,
+    };
+    let _guard = MutationGuard::acquire(&data_dir, repo)?;
+
+    let mut workspace = store.load(name)?;
+    let verb = if locked { "locked" } else { "unlocked" };
+    if workspace.metadata.locked == locked {
+        println!("'{name}' is already {verb}");
+        return Ok(());
+    }
+    workspace.metadata.locked = locked;
+    store.save(&workspace)?;
+    println!("'{name}' {verb}");
+    Ok(())
+}
+
+/// Handles `desk open`: saves the current workspace and switches to `name`.
+///
+/// A missing workspace is created from the current branch, or as `new`
+/// describes; what happens to an existing one, `existing` says. Restoring
+/// shows what it will do and asks first, unless `yes`.
+pub fn handle_open(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    name: Option<&str>,
+    new: Option<&NewWorkspace>,
+    existing: Existing,
+    yes: bool,
+) -> Result<()> {
+    let data_dir = paths::data_dir()?;
+    let store = globals.workspace_store()?;
+    let repo = repo_root(&store)?;
+
+    let name = match name {
+        Some(name) => name.to_string(),
+        None => pick_workspace(&store, repo, &config.state)?,
+    };
+
+    let _guard = MutationGuard::acquire(&data_dir, Some(repo))?;
+    let mut state = DeskState::load()?;
+    let current = state.current_workspace(repo).map(String::from);
+    if current.as_deref() == Some(name.as_str()) {
+        if let Ok(workspace) = store.load(&name) {
+            shell::enter(&workspace);
+        }
+        println!("Already working on '{name}'");
+        return Ok(());
+    }
+
+    let overwrite = check_open(&store, &name, repo, existing)?;
+    status::warn_network_filesystem(globals, repo);
+
+    let git = Git2Operations::new(repo);
+    if !overwrite && !yes && store.exists(&name) {
+        confirm_restore(&store, &git, &name, current.is_some(), config)?;
+    }
+    let mut reporter = globals.reporter();
+    let tags = store
+        .load(&name)
+        .map(|w| w.metadata.tags)
+        .unwrap_or_default();
+    let switch = HookContext {
+        repo,
+        previous: current.as_deref(),
+        workspace: Some(&name),
+        tags: &tags,
+        sync: None,
+        echo: globals.verbosity != Verbosity::Quiet,
+    };
+    hooks::run_hooks(
+        &config.hooks,
+        HookStage::PreSwitch,
+        &switch,
+        reporter.as_mut(),
+    )?;
+
+    if let Some(current) = &current {
+        save_current(
+            &store,
+            &state,
+            &git,
+            current,
+            config,
+            switch.echo,
+            reporter.as_mut(),
+        )?;
+    }
+
+    let created = !overwrite && !store.exists(&name);
+    let workspace = if overwrite {
+        overwrite_workspace(&store, &git, &name, config, switch.echo, reporter.as_mut())?
+    } else if store.exists(&name) {
+        let mut workspace = store.load(&name)?;
+        adopt(&mut workspace, repo);
+        let pulled = changes::load_pulled(&store, &name)?;
+        let captured = changes::load_captured(&store, &name)?;
+        switch::restore(
+            &git,
+            &mut workspace,
+            pulled.as_ref(),
+            captured.as_ref(),
+            reporter.as_mut(),
+        )?;
+        context::restore(config, &workspace, repo, switch.echo, reporter.as_mut());
+        workspace
+    } else {
+        create_workspace(&git, repo, &name, new, reporter.as_mut())?
+    };
+    store.save(&workspace)?;
+    changes::remove_pulled(&store, &name)?;
+    changes::remove_captured(&store, &name)?;
+
+    state.set_current(repo, &name);
+    state.record_switch(repo, current.as_deref(), &name, config.state.history_size);
+    state.save()?;
+    if created {
+        hooks::run_hooks(
+            &config.hooks,
+            HookStage::OnCreate,
+            &switch,
+            reporter.as_mut(),
+        )?;
+    }
+    hooks::run_hooks(
+        &config.hooks,
+        HookStage::PostSwitch,
+        &switch,
+        reporter.as_mut(),
+    )?;
+
+    env::write_envrc(config, &workspace, reporter.as_mut())?;
+    shell::enter(&workspace);
+    reporter.finish(&format!("Ready to work on: {name}"));
+    todo::print_pending(globals, &workspace);
+    Ok(())
+}
+
+/// The name `desk open --auto` gives the workspace on the current branch.
+/// One of that name saved on another branch is not restored in its stead.
+pub fn auto_name(globals: &GlobalOptions, config: &DeskConfig) -> Result<String> {
+    let store = globals.workspace_store()?;
+    let repo = repo_root(&store)?;
+    let branch = Git2Operations::new(repo)
+        .current_branch()
+        .context("desk open --auto names the workspace after the branch; check one out first")?;
+    let name = naming::workspace_name(&config.open.name_template, &branch)?;
+    if store.exists(&name) {
+        let saved = store.load(&name)?.state.branch;
+        if saved != branch {
+            bail!(
+                "workspace '{name}' already exists, on branch {saved}; pass a name for the \
+                 workspace on {branch}"
+            );
+        }
+    }
+    Ok(name)
+}
+
+/// Handles `desk open <name> --branch <branch>`.
+///
+/// Creates `branch` at `from`, or at the current commit, and a workspace on
+/// it. A `from` on a remote, such as `origin/main`, is fetched first if the
+/// remote answers.
+pub fn handle_open_branch(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    name: &str,
+    branch: &str,
+    from: Option<&str>,
+    yes: bool,
+) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let repo = repo_root(&store)?;
+    store.check_name(name)?;
+    if store.exists(name) {
+        bail!("workspace '{name}' already exists; open it without --branch");
+    }
+
+    let git = Git2Operations::new(repo);
+    let base = match from {
+        Some(from) => {
+            fetch_base(&git, from)?;
+            git.resolve_commit(from)?
+        },
+        None => git.current_commit()?,
+    };
+    if !git.create_branch(branch, &base)? && from.is_some() {
+        bail!("branch '{branch}' already exists; leave out --from to open a workspace on it");
+    }
+    let new = NewWorkspace {
+        branch: branch.to_string(),
+        ..NewWorkspace::default()
+    };
+    handle_open(
+        globals,
+        config,
+        Some(name),
+        Some(&new),
+        Existing::Restore,
+        yes,
+    )
+}
+
+/// Fetches `rev` when it names a branch on a remote, such as `origin/main`.
+/// Without a connection the remote-tracking branch is used as it is.
+fn fetch_base(git: &Git2Operations, rev: &str) -> Result<()> {
+    let Some((remote, branch)) = rev.split_once('/') else {
+        return Ok(());
+    };
+    if git.remote_url(remote)?.is_none() {
+        return Ok(());
+    }
+    if let Err(e) = git.fetch_branch(remote, branch) {
+        eprintln!("warning: could not fetch {rev}, starting from the copy in this clone: {e}");
+    }
+    Ok(())
+}
+
+/// Checks, before anything changes, that `existing` can be done to
+/// workspace `name` in `repo`. Returns whether it is to be overwritten.
+fn check_open(
+    store: &FileWorkspaceStore,
+    name: &str,
+    repo: &Path,
+    existing: Existing,
+) -> Result<bool> {
+    store.check_name(name)?;
+    if !store.exists(name) {
+        return Ok(false);
+    }
+    let workspace = store.load(name)?;
+    match existing {
+        Existing::Overwrite if workspace.metadata.locked => {
+            bail!("workspace '{name}' is locked; run `desk unlock {name}` before overwriting it")
+        },
+        Existing::Overwrite => Ok(true),
+        Existing::Restore => check_repo(&workspace, repo).map(|()| false),
+        Existing::RestoreHere => Ok(false),
+    }
+}
+
+/// Fails unless `workspace` was saved in `repo`: at its path, and in a clone
+/// of the same `origin` when both have one.
+fn check_repo(workspace: &Workspace, repo: &Path) -> Result<()> {
+    let name = &workspace.name;
+    let saved = workspace
+        .repo_path
+        .canonicalize()
+        .unwrap_or_else(|_| workspace.repo_path.clone());
+    if saved != repo {
+        bail!(
+            "workspace '{name}' was saved in {}, not in {}; open it there, or pass --here to \
+             restore it in this repository",
+            saved.display(),
+            repo.display()
+        );
+    }
+    if let (Some(saved), Some(here)) = (&workspace.origin, repo_origin(repo)) {
+        if *saved != here {
+            bail!(
+                "workspace '{name}' was saved in a clone of {saved}, but this repository is a \
+                 clone of {here}; pass --here to restore it here anyway"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Makes `workspace` belong to `repo`, where it is being restored.
+fn adopt(workspace: &mut Workspace, repo: &Path) {
+    workspace.repo_path = repo.to_path_buf();
+    if let Some(origin) = repo_origin(repo) {
+        workspace.origin = Some(origin);
+    }
+}
+
+/// Shows what opening workspace `name` will do to the working copy and asks
+/// to go ahead. Without a terminal to ask on, `--yes` has to say so.
+fn confirm_restore(
+    store: &FileWorkspaceStore,
+    git: &Git2Operations,
+    name: &str,
+    saving: bool,
+    config: &DeskConfig,
+) -> Result<()> {
+    let preview = switch::preview_restore(
+        git,
+        &store.load(name)?,
+        changes::load_pulled(store, name)?.as_ref(),
+        changes::load_captured(store, name)?.as_ref(),
+        saving.then_some(&config.capture),
+    )?;
+    if preview.is_noop() {
+        return Ok(());
+    }
+
+    let steps = preview.steps();
+    if !steps.is_empty() {
+        eprintln!("Opening '{name}' will:");
+    }
+    for step in steps {
+        eprintln!("  {step}");
+    }
+    if let Some(commit) = &preview.missing_commit {
+        eprintln!(
+            "warning: '{name}' was saved on commit {}, which is not in this clone; a force-push \
+             may have rewritten it, or it was made in another clone",
+            short(commit)
+        );
+    }
+    if !std::io::stdin().is_terminal() {
+        bail!("not opening '{name}' without confirmation; pass --yes to go ahead");
+    }
+    if preview.missing_commit.is_some() && ask("Fetch from all remotes first? [Y/n] ", true)? {
+        git.fetch_all()?;
+        eprintln!("Fetched");
+    }
+    if !ask("Continue? [y/N] ", false)? {
+        bail!("'{name}' was not opened");
+    }
+    Ok(())
+}
+
+/// Asks `question` on the terminal; `default` when answered with nothing.
+fn ask(question: &str, default: bool) -> Result<bool> {
+    eprint!("{question}");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(match answer.trim().to_ascii_lowercase().as_str() {
+        "" => default,
+        answer => matches!(answer, "y" | "yes"),
+    })
+}
+
+/// Replaces the saved state of workspace `name` with the current checkout.
+fn overwrite_workspace(
+    store: &FileWorkspaceStore,
+    git: &Git2Operations,
+    name: &str,
+    config: &DeskConfig,
+    echo: bool,
+    reporter: &mut dyn Reporter,
+) -> Result<Workspace> {
+    let mut workspace = store.load(name)?;
+    if let Some(stash) = workspace.state.stash_name.take() {
+        reporter.step(StepEvent::skipped(
+            StepKind::Git,
+            format!("Restore stash '{stash}'"),
+            "overwritten; the stash is kept in `git stash list`",
+        ));
+    }
+    workspace.state.branch = git.current_branch()?;
+    workspace.state.commit = git.current_commit()?;
+    workspace.touch();
+    reporter.step(StepEvent::done(
+        StepKind::State,
+        format!("Overwrote saved state with {}", workspace.state.branch),
+    ));
+    context::capture(config, &mut workspace, git.repo_path(), echo, reporter);
+    Ok(workspace)
+}
+
+/// A new workspace `name` on the current checkout, or on the branch `new`
+/// names.
+fn create_workspace(
+    git: &Git2Operations,
+    repo: &Path,
+    name: &str,
+    new: Option<&NewWorkspace>,
+    reporter: &mut dyn Reporter,
+) -> Result<Workspace> {
+    if let Some(new) = new {
+        git.switch_branch(&new.branch, &SwitchOptions::default())?;
+        reporter.step(StepEvent::done(
+            StepKind::Git,
+            format!("Checked out {}", new.branch),
+        ));
+    }
+    let mut workspace = Workspace::new(
+        name,
+        repo.to_path_buf(),
+        WorkspaceState {
+            branch: git.current_branch()?,
+            commit: git.current_commit()?,
+            ..WorkspaceState::default()
+        },
+    );
+    workspace.origin = repo_origin(repo);
+    if let Some(new) = new {
+        workspace.description.clone_from(&new.description);
+        workspace.metadata.tags.clone_from(&new.tags);
+        workspace.metadata.notes.clone_from(&new.notes);
+        workspace.metadata.issue.clone_from(&new.issue);
+    }
+    reporter.step(StepEvent::done(
+        StepKind::State,
+        format!("Created workspace on {}", workspace.state.branch),
+    ));
+    Ok(workspace)
+}
+
+/// Handles `desk close`: saves the current workspace and leaves it.
+pub async fn handle_close(globals: &GlobalOptions, config: &DeskConfig) -> Result<()> {
+    let data_dir = paths::data_dir()?;
+    let store = globals.workspace_store()?;
+    let repo = repo_root(&store)?;
+    let _guard = MutationGuard::acquire(&data_dir, Some(repo))?;
+
+    let mut state = DeskState::load()?;
+    let Some(current) = state.current_workspace(repo).map(String::from) else {
+        println!("No workspace is open in {}", repo.display());
+        return Ok(());
+    };
+
+    let mut reporter = globals.reporter();
+    let switch = HookContext {
+        repo,
+        previous: Some(&current),
+        workspace: None,
+        tags: &[],
+        sync: None,
+        echo: globals.verbosity != Verbosity::Quiet,
+    };
+    hooks::run_hooks(
+        &config.hooks,
+        HookStage::PreSwitch,
+        &switch,
+        reporter.as_mut(),
+    )?;
+    let git = Git2Operations::new(repo);
+    save_current(
+        &store,
+        &state,
+        &git,
+        &current,
+        config,
+        switch.echo,
+        reporter.as_mut(),
+    )?;
+    state.clear_current(repo);
+    state.save()?;
+    let workspace = store.load(&current).ok();
+    // A workspace in a worktree of its own leaves the main checkout alone.
+    let in_worktree = workspace
+        .as_ref()
+        .is_some_and(|w| w.state.worktree.is_some());
+    if let (Some(branch), false) = (&config.close.branch, in_worktree) {
+        leave_on(&git, branch, reporter.as_mut())?;
+    }
+    if let Some(workspace) = &workspace {
+        env::remove_envrc(config, workspace)?;
+    }
+    shell::leave();
+    hooks::run_hooks(
+        &config.hooks,
+        HookStage::PostSwitch,
+        &switch,
+        reporter.as_mut(),
+    )?;
+
+    if let Some(workspace) = &workspace {
+        issues::move_on_close(config, workspace, reporter.as_mut()).await;
+    }
+
+    reporter.finish(&format!("Closed '{current}'"));
+    Ok(())
+}
+
+/// Checks out `branch` once the workspace being closed is saved, as
+/// `close.branch` asks, unless the repository has no such branch.
+fn leave_on(git: &Git2Operations, branch: &str, reporter: &mut dyn Reporter) -> Result<()> {
+    if git.current_branch().ok().as_deref() == Some(branch) {
+        return Ok(());
+    }
+    match git.switch_branch(branch, &SwitchOptions::default()) {
+        Ok(()) => reporter.step(StepEvent::done(
+            StepKind::Git,
+            format!("Checked out {branch}"),
+        )),
+        Err(GitError::BranchNotFound(_)) => reporter.step(StepEvent::skipped(
+            StepKind::Git,
+            format!("Check out {branch}"),
+            "no such branch in this repository",
+        )),
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+/// Handles `desk save`: records the checkout as the open workspace's saved
+/// state, keeping its uncommitted changes as a checkpoint, without touching
+/// the working tree.
+pub fn handle_save(globals: &GlobalOptions, config: &DeskConfig, name: Option<&str>) -> Result<()> {
+    let data_dir = paths::data_dir()?;
+    let store = globals.workspace_store()?;
+    let repo = repo_root(&store)?;
+    let _guard = MutationGuard::acquire(&data_dir, Some(repo))?;
+
+    let state = DeskState::load()?;
+    let open = state.current_workspace(repo);
+    let Some(name) = name.or(open) else {
+        bail!("no workspace is open; open one first: desk open <name>");
+    };
+    if open != Some(name) {
+        bail!("'{name}' is not open; desk save only saves the open workspace");
+    }
+    let mut workspace = store.load(name)?;
+    if workspace.metadata.locked {
+        bail!("'{name}' is locked; run `desk unlock {name}` before saving over it");
+    }
+    let checkout = workspace
+        .state
+        .worktree
+        .clone()
+        .unwrap_or_else(|| repo.to_path_buf());
+    let git = Git2Operations::new(&checkout);
+    let mut reporter = globals.reporter();
+    let echo = globals.verbosity != Verbosity::Quiet;
+
+    workspace.state.branch = git.current_branch()?;
+    workspace.state.commit = git.current_commit()?;
+    if let Some(origin) = repo_origin(repo) {
+        workspace.origin = Some(origin);
+    }
+    reporter.step(StepEvent::done(
+        StepKind::Git,
+        format!(
+            "Saved branch {} at {}",
+            workspace.state.branch,
+            short(&workspace.state.commit)
+        ),
+    ));
+    match checkpoint(&store, config, &workspace) {
+        Ok(Some((_, files))) => {
+            let plural = if files == 1 { "" } else { "s" };
+            reporter.step(StepEvent::done(
+                StepKind::Git,
+                format!("Checkpointed {files} changed file{plural}; they stay in the checkout"),
+            ));
+        },
+        Ok(None) => {
+            changes::remove_checkpoint(&store, name)?;
+            reporter.step(StepEvent::skipped(
+                StepKind::Git,
+                "Checkpoint",
+                "no uncommitted changes",
+            ));
+        },
+        // The branch and commit are still worth saving.
+        Err(e) => reporter.step(StepEvent::skipped(
+            StepKind::Git,
+            "Checkpoint uncommitted changes",
+            format!("{e:#}"),
+        )),
+    }
+    context::capture(config, &mut workspace, &checkout, echo, reporter.as_mut());
+    workspace.touch();
+    store.save(&workspace)?;
+    hooks::run_hooks(
+        &config.hooks,
+        HookStage::OnSave,
+        &HookContext::for_workspace(repo, name, &workspace.metadata.tags, echo),
+        reporter.as_mut(),
+    )?;
+    reporter.finish(&format!("Saved '{name}'"));
+    Ok(())
+}
+
+/// Handles `desk clone`: copies workspace `source` as `target`, on a new
+/// `branch` at the same commit if given.
+///
+/// The copy takes the uncommitted changes `source` would restore, or those
+/// in the checkout if it is open, as a patch applied when it is opened.
+pub fn handle_clone(
+    globals: &GlobalOptions,
+    config: &DeskConfig,
+    source: &str,
+    target: &str,
+    branch: Option<&str>,
+) -> Result<()> {
+    let data_dir = paths::data_dir()?;
+    let store = globals.workspace_store()?;
+    let repo = repo_root(&store)?;
+    let _guard = MutationGuard::acquire(&data_dir, Some(repo))?;
+    store.check_name(target)?;
+    if store.exists(target) {
+        bail!("workspace '{target}' already exists; pick another name for the copy");
+    }
+
+    let original = store.load(source)?;
+    let open = DeskState::load()?.current_workspace(repo) == Some(source);
+    let checkout = original
+        .state
+        .worktree
+        .clone()
+        .unwrap_or_else(|| repo.to_path_buf());
+    let git = Git2Operations::new(&checkout);
+
+    let mut copy = original.clone();
+    copy.name = target.to_string();
+    copy.state.stash_name = None;
+    copy.state.worktree = None;
+    copy.metadata.locked = false;
+    copy.metadata.time_spent_secs = 0;
+    copy.created_at = Utc::now();
+    copy.updated_at = copy.created_at;
+    if open {
+        copy.state.branch = git.current_branch()?;
+        copy.state.commit = git.current_commit()?;
+    }
+    let contents = if open {
+        git.working_changes(config.capture.include_untracked)?
+    } else if let Some(pulled) = changes::load_pulled(&store, source)? {
+        Some(pulled)
+    } else if let Some(captured) = changes::load_captured(&store, source)? {
+        Some(captured)
+    } else if let Some(stash) = &original.state.stash_name {
+        git.stash_contents(stash)?
+    } else {
+        None
+    };
+    let packed = if let Some(contents) = &contents {
+        let limit = config.capture.max_patch_mib.saturating_mul(1024 * 1024);
+        let bytes = match changes::pack(contents, limit) {
+            Ok(bytes) => bytes,
+            Err(ChangesError::TooLarge { size, .. }) => bail!(
+                "'{source}' has {} of uncommitted changes, more than capture.max_patch_mib",
+                format_size(size)
+            ),
+            Err(e) => return Err(e.into()),
+        };
+        Some((bytes, contents.file_count()?))
+    } else {
+        None
+    };
+
+    // Created only once the changes are packed, so that a copy refused for
+    // them leaves no branch behind.
+    if let Some(branch) = branch {
+        if !git.create_branch(branch, &copy.state.commit)? {
+            bail!("branch '{branch}' already exists; pick another name for the copy's branch");
+        }
+        copy.state.branch = branch.to_string();
+    }
+    let files = match packed {
+        Some((bytes, files)) => {
+            changes::save_captured(&store, target, &bytes)?;
+            files
+        },
+        None => 0,
+    };
+    store.save(&copy)?;
+
+    let mut done = format!("Copied '{source}' to '{target}' on {}", copy.state.branch);
+    if files > 0 {
+        let plural = if files == 1 { "" } else { "s" };
+        let _ = write!(done, ", with {files} changed file{plural}");
+    }
+    globals.reporter().finish(&done);
+    Ok(())
+}
+
+/// Handles `desk recent`: recently opened workspaces and the ones likely to
+/// follow the current one.
+pub fn handle_recent(globals: &GlobalOptions, config: &DeskConfig, limit: usize) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let repo = repo_root(&store)?;
+    let state = DeskState::load()?;
+    let current = state.current_workspace(repo);
+
+    let scope = history_scope(repo, &config.state);
+    let recent: Vec<_> = recent(state.history_for(scope))
+        .into_iter()
+        .filter(|(name, _)| store.exists(name))
+        .take(limit)
+        .collect();
+    if recent.is_empty() {
+        println!("No workspaces opened yet in {}", repo.display());
+        return Ok(());
+    }
+
+    println!("Recent workspaces in {}:", repo.display());
+    for (name, at) in &recent {
+        let marker = if current == Some(name.as_str()) {
+            "*"
+        } else {
+            " "
+        };
+        println!("{marker} {name:<24} {}", timestamp(*at));
+    }
+
+    if let Some(current) = current {
+        let predicted: Vec<_> = predict_next(state.history_for(scope), current)
+            .into_iter()
+            .filter(|p| store.exists(&p.name))
+            .take(MAX_PREDICTIONS)
+            .map(|p| format!("{} ({}x)", p.name, p.count))
+            .collect();
+        if !predicted.is_empty() {
+            println!();
+            println!(
+                "After '{current}' you usually switch to: {}",
+                predicted.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The repository whose history counts, or `None` for all of them.
+fn history_scope<'a>(repo: &'a Path, config: &StateConfig) -> Option<&'a Path> {
+    config.per_repo_history.then_some(repo)
+}
+
+fn repo_root(store: &FileWorkspaceStore) -> Result<&Path> {
+    match store.namespace() {
+        Namespace::Repo(root) => Ok(root),
+        Namespace::Global => bail!("this command needs a git repository; run it inside one"),
+    }
+}
+
+/// Captures the repository into workspace `name` and adds the session since
+/// it was opened to its time spent, then runs the on-save hooks. A workspace
+/// deleted while open is skipped, and a locked one only gets its time spent
+/// added, so long as nothing in the checkout would be lost.
+fn save_current(
+    store: &FileWorkspaceStore,
+    state: &DeskState,
+    git: &Git2Operations,
+    name: &str,
+    config: &DeskConfig,
+    echo: bool,
+    reporter: &mut dyn Reporter,
+) -> Result<()> {
+    if !store.exists(name) {
+        reporter.step(StepEvent::skipped(
+            StepKind::State,
+            format!("Save '{name}'"),
+            "workspace no longer exists",
+        ));
+        return Ok(());
+    }
+
+    let mut workspace = store.load(name)?;
+    let locked = workspace.metadata.locked;
+    if locked {
+        if !Git2Operations::is_clean(git.repo_path())? {
+            bail!(
+                "'{name}' is locked, so its uncommitted changes cannot be saved; commit or \
+                 discard them, or run `desk unlock {name}` first"
+            );
+        }
+        reporter.step(StepEvent::skipped(
+            StepKind::State,
+            format!("Save '{name}'"),
+            "locked",
+        ));
+    } else {
+        switch::capture(git, store, &mut workspace, &config.capture, reporter)?;
+        context::capture(config, &mut workspace, git.repo_path(), echo, reporter);
+        // What was just captured supersedes changes pulled while it was
+        // open, and checkpoints taken of it.
+        changes::remove_pulled(store, name)?;
+        changes::remove_checkpoint(store, name)?;
+    }
+    if let Some(opened_at) = state
+        .opened_at(&workspace.repo_path)
+        .filter(|_| config.state.track_time)
+    {
+        let now = Utc::now();
+        workspace.record_time_spent((now - opened_at).to_std().unwrap_or_default());
+        TimeLog::new(&paths::data_dir()?).record(&Interval {
+            repo: DeskState::repo_key(&workspace.repo_path),
+            workspace: name.to_string(),
+            start: opened_at,
+            end: now,
+        })?;
+    }
+    store.save(&workspace)?;
+    if locked {
+        return Ok(());
+    }
+    hooks::run_hooks(
+        &config.hooks,
+        HookStage::OnSave,
+        &HookContext::for_workspace(&workspace.repo_path, name, &workspace.metadata.tags, echo),
+        reporter,
+    )?;
+    Ok(())
+}
+
+/// Asks which workspace to open: predicted next workspaces first, then the
+/// rest by frecency.
+fn pick_workspace(
+    store: &FileWorkspaceStore,
+    repo: &Path,
+    state_config: &StateConfig,
+) -> Result<String> {
+    if !std::io::stdin().is_terminal() {
+        bail!("no workspace given; pass a name: desk open <name>");
+    }
+
+    let state = DeskState::load()?;
+    let current = state.current_workspace(repo);
+    let scope = history_scope(repo, state_config);
+    let mut choices: Vec<(String, Option<String>)> = Vec::new();
+    let mut add = |name: &str, hint: Option<String>| {
+        if current != Some(name) && !choices.iter().any(|(n, _)| n == name) {
+            choices.push((name.to_string(), hint));
+        }
+    };
+
+    if let Some(current) = current {
+        for prediction in predict_next(state.history_for(scope), current)
+            .into_iter()
+            .filter(|p| store.exists(&p.name))
+            .take(MAX_PREDICTIONS)
+        {
+            add(&prediction.name, Some(format!("usually follows {current}")));
+        }
+    }
+    let mut workspaces = store.list()?;
+    sort_workspaces(
+        &mut workspaces,
+        WorkspaceOrder::Frecency,
+        state.history_for(scope),
+        Utc::now(),
+    );
+    for workspace in &workspaces {
+        add(&workspace.name, None);
+    }
+
+    if choices.is_empty() {
+        bail!(
+            "no other workspaces for {}; create one with: desk open <name>",
+            store.namespace()
+        );
+    }
+
+    eprintln!("Open which workspace?");
+    for (i, (name, hint)) in choices.iter().enumerate() {
+        match hint {
+            Some(hint) => eprintln!("  {:>2}) {name:<24} {hint}", i + 1),
+            None => eprintln!("  {:>2}) {name}", i + 1),
+        }
+    }
+    eprint!("Number or name: ");
+    std::io::stderr().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    let answer = answer.trim();
+    if answer.is_empty() {
+        bail!("no workspace selected");
+    }
+    match answer.parse::<usize>() {
+        Ok(n) if (1..=choices.len()).contains(&n) => Ok(choices.swap_remove(n - 1).0),
+        _ => Ok(answer.to_string()),
+    }
+}
+
+fn short(commit: &str) -> &str {
+    commit.get(..7).unwrap_or(commit)
+}
@@ -0,0 +1,209 @@
+//! `desk worktree`: keep workspaces and git worktrees consistent.
+
+use anyhow::{bail, Result};
+use clap::Subcommand;
+
+use crate::cli::GlobalOptions;
+use crate::core::lock::MutationGuard;
+use crate::core::paths;
+use crate::core::store::{FileWorkspaceStore, Namespace};
+use crate::core::worktree::{correlate, WorktreeEntry, WorktreeStatus};
+use crate::integrations::git::Git2Operations;
+use crate::output::{ProgressFormat, Reporter, StepEvent, StepKind};
+
+#[derive(Debug, Subcommand)]
+pub enum WorktreeCommand {
+    /// Show worktrees and the workspaces using them
+    List,
+    /// Remove orphaned worktrees and repair workspaces whose worktree is gone
+    Prune {
+        /// Show what would change without touching anything
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Also remove orphaned worktrees that have uncommitted changes
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// Handles `desk worktree`.
+pub fn handle_worktree(globals: &GlobalOptions, command: &WorktreeCommand) -> Result<()> {
+    let store = globals.workspace_store()?;
+    let Namespace::Repo(repo) = store.namespace() else {
+        bail!("worktrees belong to a repository; run this inside one without --global");
+    };
+    let git = Git2Operations::new(repo.clone());
+
+    let mut reporter = globals.reporter();
+    let out = Output {
+        progress: globals.progress,
+        reporter: reporter.as_mut(),
+    };
+    match command {
+        WorktreeCommand::List => list(out, &store, &git),
+        WorktreeCommand::Prune { dry_run, force } => {
+            let _guard = if *dry_run {
+                None
+            } else {
+                Some(MutationGuard::acquire(&paths::data_dir()?, Some(repo))?)
+            };
+            prune(out, &store, &git, *dry_run, *force)
+        },
+    }
+}
+
+/// Where the lines `desk worktree` prints go: to the terminal as they are,
+/// or as step events with `--progress json-lines`, whose stdout carries
+/// nothing else.
+struct Output<'a> {
+    progress: ProgressFormat,
+    reporter: &'a mut dyn Reporter,
+}
+
+impl Output<'_> {
+    fn line(&mut self, line: &str, event: StepEvent) {
+        match self.progress {
+            ProgressFormat::Human => println!("{line}"),
+            ProgressFormat::JsonLines => self.reporter.step(event),
+        }
+    }
+
+    fn finish(&mut self, line: &str) {
+        match self.progress {
+            ProgressFormat::Human => println!("{line}"),
+            ProgressFormat::JsonLines => self.reporter.finish(line),
+        }
+    }
+}
+
+fn list(mut out: Output<'_>, store: &FileWorkspaceStore, git: &Git2Operations) -> Result<()> {
+    let entries = correlate(&store.list()?, &git.worktrees()?);
+    for entry in &entries {
+        let status = entry.status.label();
+        let workspace = entry.workspace.as_deref().unwrap_or("-");
+        let path = entry.path.display();
+        let locked = if entry.locked { " (locked)" } else { "" };
+        out.line(
+            &format!("{status:<10} {workspace:<24} {path}{locked}"),
+            StepEvent::done(
+                StepKind::Git,
+                format!("{status} {workspace} {path}{locked}"),
+            ),
+        );
+    }
+    if entries.is_empty() {
+        out.finish(&format!("No worktrees for {}", store.namespace()));
+    } else if out.progress == ProgressFormat::JsonLines {
+        let plural = if entries.len() == 1 { "" } else { "s" };
+        out.finish(&format!("{} worktree{plural}", entries.len()));
+    }
+    Ok(())
+}
+
+fn prune(
+    mut out: Output<'_>,
+    store: &FileWorkspaceStore,
+    git: &Git2Operations,
+    dry_run: bool,
+    force: bool,
+) -> Result<()> {
+    let entries = correlate(&store.list()?, &git.worktrees()?);
+    let mut changed = 0;
+    for entry in &entries {
+        if prune_entry(&mut out, git, store, entry, dry_run, force)? {
+            changed += 1;
+        }
+    }
+    if changed == 0 {
+        out.finish("Worktrees and workspaces are consistent");
+    } else if out.progress == ProgressFormat::JsonLines {
+        let plural = if changed == 1 { "" } else { "s" };
+        let verb = if dry_run { "Would fix" } else { "Fixed" };
+        out.finish(&format!("{verb} {changed} worktree{plural}"));
+    }
+    Ok(())
+}
+
+/// Applies the fix for one entry and reports whether anything was (or, in a
+/// dry run, would be) changed.
+fn prune_entry(
+    out: &mut Output<'_>,
+    git: &Git2Operations,
+    store: &FileWorkspaceStore,
+    entry: &WorktreeEntry,
+    dry_run: bool,
+    force: bool,
+) -> Result<bool> {
+    let tense = |done: &'static str, planned: &'static str| if dry_run { planned } else { done };
+    let path = entry.path.display();
+    match entry.status {
+        WorktreeStatus::Linked | WorktreeStatus::Unmanaged => Ok(false),
+        WorktreeStatus::Orphaned => {
+            let Some(name) = &entry.name else {
+                return Ok(false);
+            };
+            let remove = format!("Remove orphaned worktree {path}");
+            if entry.locked {
+                out.line(
+                    &format!("Skipping locked worktree {path}"),
+                    StepEvent::skipped(StepKind::Git, remove, "locked"),
+                );
+                return Ok(false);
+            }
+            if !force && !Git2Operations::is_clean(&entry.path)? {
+                out.line(
+                    &format!(
+                        "Skipping {path}: it has uncommitted changes (use --force to remove anyway)"
+                    ),
+                    StepEvent::skipped(
+                        StepKind::Git,
+                        remove,
+                        "uncommitted changes; use --force to remove anyway",
+                    ),
+                );
+                return Ok(false);
+            }
+            if !dry_run {
+                git.remove_worktree(name)?;
+            }
+            let line = format!(
+                "{} orphaned worktree {path}",
+                tense("Removed", "Would remove")
+            );
+            out.line(&line, StepEvent::done(StepKind::Git, &line));
+            Ok(true)
+        },
+        WorktreeStatus::Stale => {
+            let Some(name) = &entry.name else {
+                return Ok(false);
+            };
+            if !dry_run {
+                git.prune_worktree(name)?;
+            }
+            let line = format!("{} stale worktree {name}", tense("Pruned", "Would prune"));
+            out.line(&line, StepEvent::done(StepKind::Git, &line));
+            Ok(true)
+        },
+        WorktreeStatus::Missing => {
+            let Some(workspace) = &entry.workspace else {
+                return Ok(false);
+            };
+            if !dry_run {
+                if let Some(name) = &entry.name {
+                    git.prune_worktree(name)?;
+                }
+                let mut ws = store.load(workspace)?;
+                ws.state.worktree = None;
+                ws.touch();
+                store.save(&ws)?;
+            }
+            let line = format!(
+                "{} '{workspace}': its worktree {path} was deleted",
+                tense("Repaired", "Would repair"),
+            );
+            out.line(&line, StepEvent::done(StepKind::Git, &line));
+            Ok(true)
+        },
+    }
+}
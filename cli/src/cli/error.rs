@@ -0,0 +1,288 @@
+//! How a failed command is reported: an exit code for each class of error,
+//! and the message on stderr, either as text or as JSON for tools.
+
+use std::error::Error;
+
+use clap::builder::BoolishValueParser;
+use clap::Args;
+use serde::Serialize;
+
+use super::Exit;
+use crate::auth::browser_flow::BrowserFlowError;
+use crate::auth::credentials::CredentialError;
+use crate::auth::device_flow::DeviceFlowError;
+use crate::client::api::ApiError;
+use crate::client::backend::BackendError;
+use crate::client::git_backend::GitBackendError;
+use crate::client::http::HttpSetupError;
+use crate::core::changes::ChangesError;
+use crate::core::config::ConfigError;
+use crate::core::lock::LockError;
+use crate::core::naming::NamingError;
+use crate::core::repo_config::RepoConfigError;
+use crate::core::store::StoreError;
+use crate::core::switch::CaptureError;
+use crate::core::sync::SyncError;
+use crate::core::trash::TrashError;
+use crate::integrations::git::GitError;
+use crate::integrations::github::GitHubError;
+use crate::integrations::issues::IssueError;
+use crate::integrations::paste::ShareError;
+
+/// Exit code for a command line desk cannot make sense of.
+pub const USAGE_EXIT_CODE: i32 = 64;
+
+/// What went wrong, as far as a script calling desk needs to know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Anything not covered below.
+    General,
+    /// Not signed in, or the credentials were rejected or are unreadable.
+    Auth,
+    /// Changes that clash with others: on the desk API, in the sync
+    /// repository, or in the checkout.
+    Conflict,
+    /// The desk API or another service could not be reached or is failing.
+    Network,
+    /// A workspace, branch, or remote object that does not exist.
+    NotFound,
+    /// Missing or broken settings.
+    Config,
+    /// A workspace or file locked by another desk.
+    Locked,
+    /// A command line desk cannot make sense of.
+    Usage,
+}
+
+impl ErrorKind {
+    /// The exit code desk ends with for errors of this kind.
+    pub const fn exit_code(self) -> i32 {
+        match self {
+            Self::General => 1,
+            Self::Auth => 2,
+            Self::Conflict => 3,
+            Self::Network => 4,
+            Self::NotFound => 5,
+            Self::Config => 6,
+            Self::Locked => 7,
+            Self::Usage => USAGE_EXIT_CODE,
+        }
+    }
+
+    /// The kind of `error`, from the first error in its chain desk knows.
+    pub fn of(error: &anyhow::Error) -> Self {
+        error.chain().find_map(classify).unwrap_or(Self::General)
+    }
+}
+
+/// How a failed command is reported.
+#[derive(Debug, Clone, Copy, Args)]
+pub struct ErrorOptions {
+    /// When a command fails, print every error that led to it on a line of
+    /// its own
+    #[arg(short, long, global = true)]
+    pub verbose: bool,
+
+    /// When a command fails, print the error to stderr as one line of JSON
+    #[arg(
+        long,
+        global = true,
+        env = "DESK_JSON_ERRORS",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub json_errors: bool,
+}
+
+/// A failed command as printed by `--json-errors`.
+#[derive(Debug, Serialize)]
+struct JsonError {
+    kind: ErrorKind,
+    exit_code: i32,
+    message: String,
+    /// What led to `message`, outermost first.
+    causes: Vec<String>,
+}
+
+/// Prints `error` to stderr as `options` say and returns the exit code to end
+/// with. An [`Exit`] prints nothing.
+pub fn report(error: &anyhow::Error, options: ErrorOptions) -> i32 {
+    if let Some(Exit(code)) = error.downcast_ref() {
+        return *code;
+    }
+    let kind = ErrorKind::of(error);
+    if options.json_errors {
+        let report = JsonError {
+            kind,
+            exit_code: kind.exit_code(),
+            message: error.to_string(),
+            causes: error.chain().skip(1).map(ToString::to_string).collect(),
+        };
+        match serde_json::to_string(&report) {
+            Ok(line) => eprintln!("{line}"),
+            Err(_) => eprintln!("Error: {error:#}"),
+        }
+    } else if options.verbose {
+        eprintln!("Error: {error}");
+        for cause in error.chain().skip(1) {
+            eprintln!("  caused by: {cause}");
+        }
+    } else {
+        eprintln!("Error: {error:#}");
+    }
+    kind.exit_code()
+}
+
+/// The kind of a single error in a chain, or `None` for one desk does not
+/// know, so that the errors it wraps decide.
+fn classify(error: &(dyn Error + 'static)) -> Option<ErrorKind> {
+    if let Some(e) = error.downcast_ref::<BackendError>() {
+        return match e {
+            BackendError::Api(e) => Some(api(e)),
+            BackendError::Git(e) => git_backend(e),
+        };
+    }
+    if let Some(e) = error.downcast_ref::<ApiError>() {
+        return Some(api(e));
+    }
+    if let Some(e) = error.downcast_ref::<GitBackendError>() {
+        return git_backend(e);
+    }
+    if let Some(e) = error.downcast_ref::<GitError>() {
+        return git(e);
+    }
+    if let Some(e) = error.downcast_ref::<StoreError>() {
+        return store(e);
+    }
+    if let Some(e) = error.downcast_ref::<SyncError>() {
+        return match e {
+            SyncError::Store(e) => store(e),
+            SyncError::Io { .. } => None,
+        };
+    }
+    if let Some(e) = error.downcast_ref::<ChangesError>() {
+        return changes(e);
+    }
+    if let Some(e) = error.downcast_ref::<CaptureError>() {
+        return match e {
+            CaptureError::Uncommitted(_) => Some(ErrorKind::Conflict),
+            CaptureError::Git(e) => git(e),
+            CaptureError::Changes(e) => changes(e),
+        };
+    }
+    if let Some(e) = error.downcast_ref::<TrashError>() {
+        return match e {
+            TrashError::NotInTrash(_) => Some(ErrorKind::NotFound),
+            TrashError::Locked(_) => Some(ErrorKind::Locked),
+            TrashError::AlreadyExists(_) => Some(ErrorKind::Conflict),
+            TrashError::Store(e) => store(e),
+        };
+    }
+    if let Some(e) = error.downcast_ref::<LockError>() {
+        return match e {
+            LockError::Held { .. } | LockError::Stale { .. } => Some(ErrorKind::Locked),
+            LockError::Io { .. } => None,
+        };
+    }
+    if let Some(e) = error.downcast_ref::<DeviceFlowError>() {
+        return Some(match e {
+            DeviceFlowError::Http(_) => ErrorKind::Network,
+            _ => ErrorKind::Auth,
+        });
+    }
+    if let Some(e) = error.downcast_ref::<BrowserFlowError>() {
+        return Some(match e {
+            BrowserFlowError::Http(_) => ErrorKind::Network,
+            _ => ErrorKind::Auth,
+        });
+    }
+    if let Some(e) = error.downcast_ref::<GitHubError>() {
+        return match e {
+            GitHubError::NotFound { .. } => Some(ErrorKind::NotFound),
+            GitHubError::Fetch { .. } | GitHubError::Http(_) => Some(ErrorKind::Network),
+            _ => None,
+        };
+    }
+    if let Some(e) = error.downcast_ref::<IssueError>() {
+        return match e {
+            IssueError::NotConfigured | IssueError::MissingSetting { .. } => {
+                Some(ErrorKind::Config)
+            },
+            IssueError::NotFound { .. } => Some(ErrorKind::NotFound),
+            IssueError::Http(_) => Some(ErrorKind::Network),
+            _ => None,
+        };
+    }
+    if let Some(e) = error.downcast_ref::<ShareError>() {
+        return match e {
+            ShareError::MissingToken | ShareError::MissingPasteUrl => Some(ErrorKind::Config),
+            ShareError::Http(_) => Some(ErrorKind::Network),
+            _ => None,
+        };
+    }
+    if error.is::<CredentialError>() {
+        return Some(ErrorKind::Auth);
+    }
+    if error.is::<ConfigError>()
+        || error.is::<RepoConfigError>()
+        || error.is::<HttpSetupError>()
+        || error.is::<NamingError>()
+    {
+        return Some(ErrorKind::Config);
+    }
+    if error.is::<reqwest::Error>() {
+        return Some(ErrorKind::Network);
+    }
+    None
+}
+
+fn api(error: &ApiError) -> ErrorKind {
+    match error {
+        ApiError::NotAuthenticated | ApiError::Unauthorized | ApiError::Credentials(_) => {
+            ErrorKind::Auth
+        },
+        ApiError::NotFound(_) => ErrorKind::NotFound,
+        ApiError::Conflict(_) => ErrorKind::Conflict,
+        ApiError::RateLimited { .. }
+        | ApiError::Unavailable { .. }
+        | ApiError::ApiUnavailable { .. } => ErrorKind::Network,
+        ApiError::Server { status, .. } if status.is_server_error() => ErrorKind::Network,
+        ApiError::HttpSetup(_) => ErrorKind::Config,
+        _ => ErrorKind::General,
+    }
+}
+
+const fn git_backend(error: &GitBackendError) -> Option<ErrorKind> {
+    match error {
+        GitBackendError::NoRemote => Some(ErrorKind::Config),
+        GitBackendError::Fetch { .. } => Some(ErrorKind::Network),
+        GitBackendError::NotFound(_) => Some(ErrorKind::NotFound),
+        GitBackendError::Conflict(_) | GitBackendError::Contended => Some(ErrorKind::Conflict),
+        _ => None,
+    }
+}
+
+const fn git(error: &GitError) -> Option<ErrorKind> {
+    match error {
+        GitError::BranchNotFound(_)
+        | GitError::RevisionNotFound(_)
+        | GitError::StashNotFound(_) => Some(ErrorKind::NotFound),
+        GitError::ChangesDoNotApply(_) | GitError::WouldOverwrite(_) => Some(ErrorKind::Conflict),
+        _ => None,
+    }
+}
+
+const fn store(error: &StoreError) -> Option<ErrorKind> {
+    match error {
+        StoreError::NotFound(_) => Some(ErrorKind::NotFound),
+        StoreError::Collision { .. } => Some(ErrorKind::Conflict),
+        _ => None,
+    }
+}
+
+const fn changes(error: &ChangesError) -> Option<ErrorKind> {
+    match error {
+        ChangesError::Store(e) => store(e),
+        _ => None,
+    }
+}
@@ -0,0 +1,671 @@
+//! Command-line argument parsing and dispatch.
+
+pub mod commands;
+pub mod error;
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::builder::BoolishValueParser;
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::auth::credentials;
+use crate::cli::commands::github::Link;
+use crate::cli::commands::workspace::Existing;
+use crate::cli::error::ErrorOptions;
+use crate::core::config::{load_config, DeskConfig, PROFILE_ENV};
+use crate::core::logs;
+use crate::core::paths;
+use crate::core::status_cache;
+use crate::core::store::{FileWorkspaceStore, Namespace};
+use crate::core::suggest::WorkspaceOrder;
+use crate::output::{notify, timestamp, ProgressFormat, Reporter, TimestampStyle, Verbosity};
+
+/// Developer context switching tool - save, switch, and restore your complete
+/// development environment.
+#[derive(Debug, Parser)]
+#[command(name = "desk", version, about, long_about = None)]
+pub struct Cli {
+    /// How much to print while switching or restoring workspaces
+    #[arg(long, global = true, value_enum, env = "DESK_VERBOSITY")]
+    pub verbosity: Option<Verbosity>,
+
+    /// Print times relative to now or as absolute timestamps
+    #[arg(long, global = true, value_enum)]
+    pub timestamps: Option<TimestampStyle>,
+
+    /// Print nothing on success (same as `--verbosity quiet`)
+    #[arg(short, long, global = true, conflicts_with = "verbosity")]
+    pub quiet: bool,
+
+    /// How to report progress of long operations
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t,
+        env = "DESK_PROGRESS"
+    )]
+    pub progress: ProgressFormat,
+
+    /// Use workspaces that are not tied to the current repository
+    #[arg(long, global = true)]
+    pub global: bool,
+
+    /// Use the settings, data, and sign-in of a profile from `[profiles]`
+    #[arg(long, global = true, env = PROFILE_ENV)]
+    pub profile: Option<String>,
+
+    /// Don't count uncommitted changes where they are only shown, which is
+    /// slow in very large repositories
+    #[arg(
+        long,
+        global = true,
+        env = "DESK_NO_STATUS",
+        value_parser = BoolishValueParser::new()
+    )]
+    pub no_status: bool,
+
+    #[command(flatten)]
+    pub errors: ErrorOptions,
+
+    #[command(subcommand)]
+    pub command: Commands,
+}
+
+impl Cli {
+    /// Parses the command line, along with the name of the subcommand it
+    /// runs, such as `sync push`.
+    pub fn try_parse_named() -> Result<(Self, String), clap::Error> {
+        let matches = Self::command().try_get_matches()?;
+        let mut names = Vec::new();
+        let mut current = &matches;
+        while let Some((name, sub)) = current.subcommand() {
+            names.push(name);
+            current = sub;
+        }
+        Ok((Self::from_arg_matches(&matches)?, names.join(" ")))
+    }
+
+    /// The async runtime to run the command on. Only the daemon and the MCP
+    /// server, which answer requests while other work blocks, get worker
+    /// threads; every other command runs on the calling thread, which starts
+    /// quicker.
+    pub fn runtime(&self) -> std::io::Result<tokio::runtime::Runtime> {
+        let mut builder = if matches!(self.command, Commands::Daemon(_) | Commands::Mcp) {
+            tokio::runtime::Builder::new_multi_thread()
+        } else {
+            tokio::runtime::Builder::new_current_thread()
+        };
+        builder.enable_all().build()
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Commands {
+    /// Save the current workspace and switch to another, creating it if needed
+    #[command(visible_alias = "switch")]
+    Open(OpenArgs),
+
+    /// Save the current workspace and leave it
+    Close,
+
+    /// Save the open workspace's branch, commit, and uncommitted changes
+    /// without switching or touching the working tree
+    Save {
+        /// The open workspace, to make sure it is the one saved
+        name: Option<String>,
+    },
+
+    /// List workspaces for the current repository, most often and most
+    /// recently opened first
+    List {
+        /// Order to list them in
+        #[arg(long, value_enum, default_value_t)]
+        sort: WorkspaceOrder,
+
+        /// How to print them
+        #[arg(long, value_enum, default_value_t)]
+        format: commands::workspace::ListFormat,
+    },
+
+    /// Show the open workspace, how the checkout differs from it, and
+    /// whether it has changes to push
+    Status,
+
+    /// Compare the checkout with a saved workspace: branch, commits since
+    /// it was saved, and changed files against its saved changes
+    Drift {
+        /// Workspace to compare with; the open one when omitted
+        name: Option<String>,
+    },
+
+    /// Print the open workspace for shell prompts, with `*` when the
+    /// checkout drifted from it
+    Prompt {
+        /// Print the workspace, branch, and drift as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Show recently opened workspaces and the ones that usually come next
+    Recent {
+        /// Maximum number of workspaces to show
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+    },
+
+    /// Show switches per day, most used workspaces, and busiest repositories
+    Stats {
+        /// Days to look back over, today included
+        #[arg(short = 'd', long, default_value_t = 14, value_parser = clap::value_parser!(u32).range(1..))]
+        days: u32,
+
+        /// Print the statistics as JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Summarize what was worked on yesterday and today as markdown, for a
+    /// standup
+    Standup {
+        /// Copy the summary to the clipboard too
+        #[arg(long)]
+        copy: bool,
+    },
+
+    /// List workspaces no one has opened or saved in a while, to finish or
+    /// delete
+    Reminders {
+        /// Days without use that count, in place of
+        /// `reminders.stale_after_days`
+        #[arg(short = 'd', long)]
+        days: Option<u64>,
+    },
+
+    /// Search workspaces by name, branch, tags, description, and notes
+    Search {
+        /// Words to look for; every word has to match
+        #[arg(required = true)]
+        query: Vec<String>,
+
+        /// Maximum number of results to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Copy a workspace under a new name, with its notes, tags, and
+    /// uncommitted changes
+    Clone {
+        /// Workspace to copy
+        source: String,
+
+        /// Name of the copy
+        target: String,
+
+        /// Put the copy on a new branch of this name, at the same commit
+        #[arg(long)]
+        branch: Option<String>,
+    },
+
+    /// Open several workspaces together, each in its own repository
+    #[command(subcommand)]
+    Group(commands::group::GroupCommand),
+
+    /// Move a workspace to the trash
+    #[command(visible_alias = "rm")]
+    Delete {
+        /// Workspace to delete
+        name: String,
+    },
+
+    /// Keep a workspace as it is: it is not saved over, overwritten, or deleted
+    Lock {
+        /// Workspace to lock
+        name: String,
+    },
+
+    /// Allow changes to a locked workspace again
+    Unlock {
+        /// Workspace to unlock
+        name: String,
+    },
+
+    /// Restore a workspace from the trash
+    Undelete {
+        /// Workspace to restore
+        name: String,
+    },
+
+    /// Inspect deleted workspaces
+    #[command(subcommand)]
+    Trash(commands::trash::TrashCommand),
+
+    /// Permanently remove workspaces that have been in the trash too long
+    Gc {
+        /// Show what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Empty the whole trash, ignoring `trash.retention_days`
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Show how much disk space workspaces, the trash, and state take
+    Du,
+
+    /// Share a read-only view of a workspace with teammates
+    #[command(subcommand)]
+    Share(commands::share::ShareCommand),
+
+    /// Archive config, state, and all workspaces to a file
+    Backup {
+        /// File or directory to write the backup to (default: current directory)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Encrypt the backup with a passphrase (or `DESK_BACKUP_PASSPHRASE`)
+        #[arg(long)]
+        encrypt: bool,
+    },
+
+    /// Replace local data with the contents of a backup
+    RestoreBackup {
+        /// Backup archive written by `desk backup`
+        archive: PathBuf,
+    },
+
+    /// Read and change settings in the configuration file
+    #[command(subcommand)]
+    Config(commands::config::ConfigCommand),
+
+    /// Inspect the commands run around workspace switches
+    #[command(subcommand)]
+    Hook(commands::hook::HookCommand),
+
+    /// Look after desk's record of open workspaces, switches, and syncs
+    #[command(subcommand)]
+    State(commands::state::StateCommand),
+
+    /// Show desk's log of commands, hooks, and background syncs
+    Logs {
+        /// Lines to show from the end of the log
+        #[arg(short = 'n', long, default_value_t = 50)]
+        lines: usize,
+
+        /// Keep printing lines as they are written, until stopped
+        #[arg(short, long)]
+        follow: bool,
+    },
+
+    /// Sign in to the desk API
+    #[command(subcommand)]
+    Auth(commands::auth::AuthCommand),
+
+    /// Sync workspaces between machines through the desk API
+    #[command(subcommand)]
+    Sync(commands::sync::SyncCommand),
+
+    /// Print a `desk` shell function that changes into the workspace `desk
+    /// open` opens; add `eval "$(desk shell-init bash)"` to your shell's rc
+    /// file
+    ShellInit {
+        #[arg(value_enum)]
+        shell: commands::shell::Shell,
+
+        /// Leave out the hint about a repository's workspace on changing
+        /// into it
+        #[arg(long)]
+        no_hints: bool,
+    },
+
+    /// Print a hint about the workspace of the repository the shell just
+    /// changed into; run by the `desk shell-init` function
+    #[command(hide = true)]
+    ShellHint {
+        /// Directory the shell changed from
+        #[arg(long)]
+        from: Option<PathBuf>,
+    },
+
+    /// Serve workspaces to coding assistants over the Model Context Protocol
+    /// on stdin and stdout
+    Mcp,
+
+    /// Run desk's background work: session refresh, scheduled sync and gc,
+    /// and fast answers for `desk prompt`
+    #[command(subcommand)]
+    Daemon(commands::daemon::DaemonCommand),
+
+    /// Run a command in another workspace, then switch back to the one open
+    /// before
+    Exec {
+        workspace: String,
+
+        /// Restore the workspace without showing what it will do first
+        #[arg(long)]
+        yes: bool,
+
+        /// Command to run, after `--`
+        #[arg(last = true, required = true, value_name = "COMMAND")]
+        command: Vec<String>,
+    },
+
+    /// Checkpoint the open workspace's uncommitted changes while files
+    /// change, until stopped
+    Watch {
+        /// Minutes between checkpoints, in place of `[watch] interval_mins`
+        #[arg(long, value_name = "MINS")]
+        interval: Option<u64>,
+    },
+
+    /// Restore the latest checkpoint `desk watch` took of a workspace into
+    /// its clean checkout
+    Recover {
+        /// Workspace to recover; the open one when omitted
+        name: Option<String>,
+    },
+
+    /// Manage the environment variables a workspace hands to direnv
+    #[command(subcommand)]
+    Env(commands::env::EnvCommand),
+
+    /// Keep a to-do list of next actions with a workspace
+    #[command(subcommand)]
+    Todo(commands::todo::TodoCommand),
+
+    /// Manage per-workspace time budgets
+    #[command(subcommand)]
+    Budget(commands::budget::BudgetCommand),
+
+    /// Report the time spent in workspaces
+    #[command(subcommand)]
+    Time(commands::time::TimeCommand),
+
+    /// Inspect and clean up worktrees used by workspaces
+    #[command(subcommand)]
+    Worktree(commands::worktree::WorktreeCommand),
+
+    /// Run `desk-<name>` from PATH for a command desk does not have
+    #[command(external_subcommand)]
+    External(Vec<String>),
+}
+
+/// Ends the process with an exit code and no error message, for commands
+/// whose output already says what is wrong.
+#[derive(Debug, thiserror::Error)]
+#[error("exit status {0}")]
+pub struct Exit(pub i32);
+
+/// Options shared by every command handler.
+#[derive(Debug, Clone, Copy)]
+pub struct GlobalOptions {
+    pub verbosity: Verbosity,
+    pub progress: ProgressFormat,
+    pub global: bool,
+    /// Skip counting uncommitted changes for `desk status`, `desk prompt`,
+    /// and `desk share`.
+    pub no_status: bool,
+}
+
+impl GlobalOptions {
+    /// Creates the reporter for step and progress events.
+    pub fn reporter(&self) -> Box<dyn Reporter> {
+        crate::output::reporter(self.progress, self.verbosity)
+    }
+
+    /// Opens the workspace store for the namespace these options select.
+    pub fn workspace_store(&self) -> Result<FileWorkspaceStore> {
+        let cwd = std::env::current_dir()?;
+        Ok(FileWorkspaceStore::new(
+            &paths::data_dir()?,
+            Namespace::resolve(&cwd, self.global),
+        ))
+    }
+}
+
+/// Resolves global options and runs the requested command, logged under
+/// `command`, its name.
+pub async fn run(cli: &Cli, command: &str) -> Result<()> {
+    if let Commands::Config(command) = &cli.command {
+        return commands::config::handle_config(command, cli.profile.as_deref()).await;
+    }
+    let config = load_config(cli.profile.as_deref())?;
+    paths::configure(&config.storage, cli.profile.as_deref());
+    credentials::configure(&config.auth);
+    notify::configure(&config.notifications);
+    status_cache::configure(&config.status);
+    logs::configure(&config.logs);
+    timestamp::configure(cli.timestamps.unwrap_or(config.output.timestamps));
+    let globals = GlobalOptions {
+        verbosity: Verbosity::resolve(cli.verbosity, cli.quiet, config.output.verbosity),
+        progress: cli.progress,
+        global: cli.global,
+        no_status: cli.no_status,
+    };
+
+    // Prompts are redrawn all the time, shell-init runs in every new shell,
+    // and the daemon and MCP server have no one to tell.
+    let background = matches!(
+        cli.command,
+        Commands::Prompt { .. }
+            | Commands::ShellInit { .. }
+            | Commands::ShellHint { .. }
+            | Commands::Daemon(_)
+            | Commands::Mcp
+    );
+    // Prompts and hints come with every redraw and change of directory, too
+    // often for a line each in the log, and reading the log is not news.
+    let logged = !matches!(
+        cli.command,
+        Commands::Prompt { .. }
+            | Commands::ShellInit { .. }
+            | Commands::ShellHint { .. }
+            | Commands::Logs { .. }
+    );
+    let span = info_span!("desk", pid = std::process::id(), command);
+    async {
+        if logged {
+            let cwd = std::env::current_dir().unwrap_or_default();
+            info!("started in {}", cwd.display());
+        }
+        let result = async {
+            if !background
+                && globals.verbosity != Verbosity::Quiet
+                && globals.progress == ProgressFormat::Human
+            {
+                // A warning is no reason for the command itself to fail.
+                if let Err(e) = commands::budget::warn_if_over_budget(&globals) {
+                    debug!("cannot check the time budget: {e:#}");
+                }
+            }
+            dispatch(cli, globals, &config).await
+        }
+        .await;
+        if logged {
+            match &result {
+                Ok(()) => info!("finished"),
+                Err(e) => warn!("failed: {e:#}"),
+            }
+        }
+        result
+    }
+    .instrument(span)
+    .await
+}
+
+/// Arguments of `desk open`.
+#[derive(Debug, Args)]
+pub struct OpenArgs {
+    /// Workspace to open; pick from a list when omitted, or `pr-N`,
+    /// `issue-N`, and the ticket key with --from-pr, --from-issue, and
+    /// --issue
+    pub name: Option<String>,
+
+    #[command(flatten)]
+    pub existing: ExistingArgs,
+
+    /// Create the workspace for GitHub pull request N, on its head branch
+    #[arg(long, value_name = "N", conflicts_with_all = ["force", "from_issue"])]
+    pub from_pr: Option<u64>,
+
+    /// Create the workspace for GitHub issue N, on a new branch named
+    /// after it
+    #[arg(long, value_name = "N", conflicts_with = "force")]
+    pub from_issue: Option<u64>,
+
+    /// Create the workspace for a Jira or Linear ticket, such as
+    /// PROJ-123, on a new branch named after it
+    #[arg(long, value_name = "KEY", conflicts_with_all = ["force", "from_pr", "from_issue"])]
+    pub issue: Option<String>,
+
+    /// Create the workspace on a new branch of this name, started from
+    /// --from or the current commit
+    #[arg(
+        long,
+        requires = "name",
+        conflicts_with_all = ["force", "here", "from_pr", "from_issue", "issue"]
+    )]
+    pub branch: Option<String>,
+
+    /// Branch, tag, or commit to start --branch from, such as
+    /// origin/main, which is fetched first
+    #[arg(long, value_name = "REV", requires = "branch")]
+    pub from: Option<String>,
+
+    /// Name the workspace after the current branch, following the name
+    /// template in the configuration
+    #[arg(
+        long,
+        conflicts_with_all = ["name", "force", "here", "from_pr", "from_issue", "issue", "branch"]
+    )]
+    pub auto: bool,
+
+    /// Restore without showing what will change and asking first
+    #[arg(short = 'y', long)]
+    pub yes: bool,
+}
+
+/// What `desk open` does with a workspace that already exists.
+#[derive(Debug, Clone, Copy, Args)]
+pub struct ExistingArgs {
+    /// Overwrite the saved state of an existing workspace with the current
+    /// checkout instead of restoring it
+    #[arg(long, requires = "name")]
+    pub force: bool,
+
+    /// Restore a workspace saved in another repository, or in another
+    /// clone at this path, in this one, which it then belongs to
+    #[arg(long, requires = "name", conflicts_with_all = ["force", "from_pr", "from_issue", "issue"])]
+    pub here: bool,
+}
+
+/// Runs the handler for `cli.command`.
+async fn dispatch(cli: &Cli, globals: GlobalOptions, config: &DeskConfig) -> Result<()> {
+    match &cli.command {
+        Commands::Open(args) => open(&globals, config, args).await,
+        Commands::Close => commands::workspace::handle_close(&globals, config).await,
+        Commands::Save { name } => {
+            commands::workspace::handle_save(&globals, config, name.as_deref())
+        },
+        Commands::List { sort, format } => {
+            commands::workspace::handle_list(&globals, config, *sort, *format)
+        },
+        Commands::Status => commands::status::handle_status(&globals),
+        Commands::Drift { name } => {
+            commands::drift::handle_drift(&globals, config, name.as_deref())
+        },
+        Commands::Prompt { json } => commands::status::handle_prompt(&globals, *json).await,
+        Commands::Recent { limit } => commands::workspace::handle_recent(&globals, config, *limit),
+        Commands::Stats { days, json } => commands::stats::handle_stats(config, *days, *json),
+        Commands::Standup { copy } => commands::standup::handle_standup(config, *copy),
+        Commands::Reminders { days } => commands::reminders::handle_reminders(config, *days),
+        Commands::Search { query, limit } => {
+            commands::search::handle_search(&globals, query, *limit)
+        },
+        Commands::Clone {
+            source,
+            target,
+            branch,
+        } => commands::workspace::handle_clone(&globals, config, source, target, branch.as_deref()),
+        Commands::Group(command) => commands::group::handle_group(&globals, config, command),
+        Commands::Delete { name } => commands::workspace::handle_delete(&globals, config, name),
+        Commands::Lock { name } => commands::workspace::handle_lock(&globals, name, true),
+        Commands::Unlock { name } => commands::workspace::handle_lock(&globals, name, false),
+        Commands::Undelete { name } => commands::trash::handle_undelete(&globals, name),
+        Commands::Trash(command) => commands::trash::handle_trash(config, command),
+        Commands::Gc { dry_run, all } => commands::trash::handle_gc(config, *dry_run, *all),
+        Commands::Du => commands::usage::handle_du(config),
+        Commands::Share(command) => commands::share::handle_share(&globals, config, command).await,
+        Commands::Backup { output, encrypt } => {
+            commands::backup::handle_backup(output.as_deref(), *encrypt)
+        },
+        Commands::RestoreBackup { archive } => commands::backup::handle_restore_backup(archive),
+        Commands::Config(_) => unreachable!("handled before loading the configuration"),
+        Commands::Hook(command) => commands::hook::handle_hook(config, command),
+        Commands::State(command) => commands::state::handle_state(command),
+        Commands::Logs { lines, follow } => commands::logs::handle_logs(*lines, *follow).await,
+        Commands::Auth(command) => commands::auth::handle_auth(&globals, config, command).await,
+        Commands::Sync(command) => commands::sync::handle_sync(&globals, config, command).await,
+        Commands::ShellInit { shell, no_hints } => {
+            commands::shell::handle_shell_init(*shell, !no_hints)
+        },
+        Commands::ShellHint { from } => commands::shell::handle_shell_hint(from.as_deref()),
+        Commands::Mcp => commands::mcp::handle_mcp(),
+        Commands::Daemon(command) => commands::daemon::handle_daemon(config, command).await,
+        Commands::Exec {
+            workspace,
+            yes,
+            command,
+        } => commands::exec::handle_exec(&globals, config, workspace, command, *yes).await,
+        Commands::Watch { interval } => {
+            commands::watch::handle_watch(&globals, config, *interval).await
+        },
+        Commands::Recover { name } => commands::watch::handle_recover(&globals, name.as_deref()),
+        Commands::External(args) => commands::plugin::handle_external(&globals, args),
+        Commands::Env(command) => commands::env::handle_env(&globals, config, command),
+        Commands::Todo(command) => commands::todo::handle_todo(&globals, command),
+        Commands::Budget(command) => commands::budget::handle_budget(&globals, command),
+        Commands::Time(command) => commands::time::handle_time(config, command),
+        Commands::Worktree(command) => commands::worktree::handle_worktree(&globals, command),
+    }
+}
+
+/// Handles `desk open`, which `args` may point at a pull request, an issue,
+/// or a new branch.
+async fn open(globals: &GlobalOptions, config: &DeskConfig, args: &OpenArgs) -> Result<()> {
+    let auto_name = args
+        .auto
+        .then(|| commands::workspace::auto_name(globals, config))
+        .transpose()?;
+    let name = auto_name.as_deref().or(args.name.as_deref());
+    let yes = args.yes;
+    if let (Some(name), Some(branch)) = (name, &args.branch) {
+        return commands::workspace::handle_open_branch(
+            globals,
+            config,
+            name,
+            branch,
+            args.from.as_deref(),
+            yes,
+        );
+    }
+    let link = args
+        .from_pr
+        .map(Link::PullRequest)
+        .or_else(|| args.from_issue.map(Link::Issue));
+    match (link, &args.issue) {
+        (Some(link), _) => {
+            commands::github::handle_open_linked(globals, config, name, link, yes).await
+        },
+        (None, Some(key)) => {
+            commands::issues::handle_open_issue(globals, config, name, key, yes).await
+        },
+        (None, None) => {
+            let existing = Existing::asked(args.existing.force, args.existing.here);
+            commands::workspace::handle_open(globals, config, name, None, existing, yes)
+        },
+    }
+}
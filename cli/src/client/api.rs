@@ -0,0 +1,986 @@
+//! Typed access to the desk API.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderValue, ETAG, IF_NONE_MATCH};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use super::events::{EventStream, STREAM_TIMEOUT};
+use super::http::{self, HttpSetupError};
+use super::http_cache::{CachedResponse, HttpCache};
+use crate::auth::credentials::{
+    self, CredentialError, CredentialSource, CredentialStore, Credentials,
+};
+use crate::auth::providers::AuthProvider;
+use crate::core::config::ApiConfig;
+use crate::core::paths;
+use crate::core::workspace::Workspace;
+use crate::utils::duration::format_duration;
+
+/// Errors raised by [`DeskApiClient`].
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("not logged in; run `desk auth login` or set DESK_API_TOKEN")]
+    NotAuthenticated,
+
+    #[error("the desk API rejected the stored credentials; run `desk auth login`")]
+    Unauthorized,
+
+    #[error("{0} not found on the desk API")]
+    NotFound(String),
+
+    #[error("{0} was changed by another client")]
+    Conflict(String),
+
+    #[error("rate limited by the desk API{}", retry_hint(*.retry_after))]
+    RateLimited { retry_after: Option<Duration> },
+
+    #[error("the desk API is temporarily unavailable{} (request ID {})", retry_hint(*.retry_after), http::request_id())]
+    Unavailable { retry_after: Option<Duration> },
+
+    #[error(
+        "desk API error ({status}): {message} (request ID {})",
+        http::request_id()
+    )]
+    Server { status: StatusCode, message: String },
+
+    #[error("cannot reach the desk API at {url}")]
+    ApiUnavailable {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("the desk API at {url} does not speak API {API_VERSION} (it offers {}); {}", .offered.join(", "), version_advice(.offered))]
+    UnsupportedVersion { url: String, offered: Vec<String> },
+
+    #[error("the desk API at {url} speaks API {version}, newer than the API {API_VERSION} this desk understands; upgrade desk")]
+    NewerVersion { url: String, version: String },
+
+    #[error("the desk API at {url} does not support {feature}")]
+    Unsupported { url: String, feature: ApiFeature },
+
+    #[error(
+        "unexpected response from the desk API (request ID {})",
+        http::request_id()
+    )]
+    Decode(#[source] reqwest::Error),
+
+    #[error(
+        "unexpected response from the desk API (request ID {})",
+        http::request_id()
+    )]
+    Malformed(#[source] serde_json::Error),
+
+    #[error(transparent)]
+    Credentials(#[from] CredentialError),
+
+    #[error(transparent)]
+    HttpSetup(#[from] HttpSetupError),
+}
+
+impl ApiError {
+    /// Whether repeating the request may succeed.
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            Self::RateLimited { .. } | Self::Unavailable { .. } | Self::ApiUnavailable { .. } => {
+                true
+            },
+            Self::Server { status, .. } => status.is_server_error(),
+            _ => false,
+        }
+    }
+
+    /// How long the API asked to wait before trying again.
+    pub const fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimited { retry_after } | Self::Unavailable { retry_after } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Whether the API certainly did not act on the request, so that even a
+    /// non-idempotent request can be sent again.
+    fn unprocessed(&self) -> bool {
+        match self {
+            Self::RateLimited { .. } | Self::Unavailable { .. } => true,
+            Self::ApiUnavailable { source, .. } => source.is_connect(),
+            _ => false,
+        }
+    }
+
+    /// Whether the user has to log in again before retrying.
+    pub const fn requires_reauth(&self) -> bool {
+        matches!(self, Self::NotAuthenticated | Self::Unauthorized)
+    }
+}
+
+/// API version this build speaks.
+pub const API_VERSION: &str = "v1";
+
+/// Header naming the API version of a request, and of the response to it.
+const API_VERSION_HEADER: &str = "x-desk-api-version";
+
+/// Sessions expiring within this many seconds are refreshed before long
+/// operations rather than risking expiry halfway through.
+const REFRESH_MARGIN_SECS: i64 = 300;
+
+/// Workspaces requested per page when listing.
+const PAGE_SIZE: u32 = 100;
+
+/// First wait between retries of a failed request; doubles with every attempt.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest wait before a retry. A `Retry-After` beyond it fails the request
+/// instead of stalling the command.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Optional parts of the desk API, which self-hosted servers may leave out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiFeature {
+    /// Paging through `GET /v1/workspaces` with `limit` and `cursor`.
+    WorkspacePages,
+    /// Storing stashed changes along with workspaces.
+    WorkspaceChanges,
+    /// The `GET /v1/workspaces/events` stream.
+    WorkspaceEvents,
+    /// Renewing sessions with `POST /v1/auth/refresh`.
+    SessionRefresh,
+    /// Account details from `GET /v1/users/me`.
+    UserProfile,
+    /// Listing and revoking sessions under `/v1/auth/sessions`.
+    Sessions,
+}
+
+impl ApiFeature {
+    const ALL: [Self; 6] = [
+        Self::WorkspacePages,
+        Self::WorkspaceChanges,
+        Self::WorkspaceEvents,
+        Self::SessionRefresh,
+        Self::UserProfile,
+        Self::Sessions,
+    ];
+
+    /// Name of the feature in `GET /v1/meta`.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::WorkspacePages => "workspace_pages",
+            Self::WorkspaceChanges => "workspace_changes",
+            Self::WorkspaceEvents => "workspace_events",
+            Self::SessionRefresh => "session_refresh",
+            Self::UserProfile => "user_profile",
+            Self::Sessions => "sessions",
+        }
+    }
+}
+
+impl fmt::Display for ApiFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::WorkspacePages => "paged workspace listings",
+            Self::WorkspaceChanges => "syncing uncommitted changes",
+            Self::WorkspaceEvents => "workspace event streams",
+            Self::SessionRefresh => "session refresh",
+            Self::UserProfile => "account details",
+            Self::Sessions => "session management",
+        })
+    }
+}
+
+/// What a desk API server supports, as reported by `GET /v1/meta`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ApiCapabilities {
+    /// API versions the server speaks, such as `v1`.
+    pub versions: Vec<String>,
+    /// Names of the optional features it implements; see [`ApiFeature`].
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Server name and version, for diagnostics.
+    #[serde(default)]
+    pub server: Option<String>,
+}
+
+impl ApiCapabilities {
+    /// Servers without `/v1/meta` predate it and are assumed to behave like
+    /// the hosted service did then, with every feature.
+    fn legacy() -> Self {
+        Self {
+            versions: vec![API_VERSION.to_string()],
+            features: ApiFeature::ALL
+                .iter()
+                .map(|f| f.name().to_string())
+                .collect(),
+            server: None,
+        }
+    }
+
+    pub fn supports(&self, feature: ApiFeature) -> bool {
+        self.features.iter().any(|f| f == feature.name())
+    }
+
+    /// The newest API version the server speaks, if it is newer than
+    /// [`API_VERSION`].
+    fn newer_version(&self) -> Option<&str> {
+        self.versions
+            .iter()
+            .filter(|v| is_newer(v))
+            .max_by_key(|v| version_number(v))
+            .map(String::as_str)
+    }
+}
+
+/// Subscription of a desk account.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "String")]
+pub enum Plan {
+    Free,
+    Pro,
+    /// A plan this version of desk does not know about, by its API name.
+    Other(String),
+}
+
+impl From<String> for Plan {
+    fn from(name: String) -> Self {
+        match name.as_str() {
+            "free" => Self::Free,
+            "pro" => Self::Pro,
+            _ => Self::Other(name),
+        }
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Free => "Free",
+            Self::Pro => "Pro",
+            Self::Other(name) => name,
+        })
+    }
+}
+
+/// The signed-in account, from `GET /v1/users/me`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CurrentUser {
+    pub id: String,
+    pub email: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    pub plan: Plan,
+    /// Workspaces stored for the account.
+    #[serde(default)]
+    pub workspace_count: Option<u64>,
+    /// Most workspaces the plan allows; `None` when unlimited.
+    #[serde(default)]
+    pub workspace_quota: Option<u64>,
+}
+
+/// A device signed in to the account, from `GET /v1/auth/sessions`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Session {
+    pub id: String,
+    /// How the API identifies the device, such as its client and OS.
+    #[serde(default)]
+    pub device: Option<String>,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// Whether the request listing the sessions was made with this one.
+    #[serde(default)]
+    pub current: bool,
+}
+
+/// A workspace as stored by the desk API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteWorkspace {
+    pub id: String,
+    /// Repository (or `global`) the workspace belongs to; see
+    /// [`crate::core::sync::sync_scope`].
+    pub scope: String,
+    pub name: String,
+    /// Incremented by the server on every update.
+    pub version: u64,
+    pub updated_at: DateTime<Utc>,
+    pub workspace: Workspace,
+    /// The workspace's stashed changes, sealed by
+    /// [`crate::core::changes::seal`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changes: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CreateWorkspace<'a> {
+    scope: &'a str,
+    name: &'a str,
+    workspace: &'a Workspace,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changes: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct UpdateWorkspace<'a> {
+    /// Version the update is based on; the server answers 409 if it moved on.
+    version: u64,
+    workspace: &'a Workspace,
+    /// Replaces the stored changes; omitting it clears them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    changes: Option<&'a str>,
+}
+
+/// One page of `GET /v1/workspaces`.
+#[derive(Deserialize)]
+struct WorkspaceList {
+    workspaces: Vec<RemoteWorkspace>,
+    /// Where the next page starts; absent on the last page.
+    #[serde(default)]
+    next_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SessionList {
+    sessions: Vec<Session>,
+}
+
+#[derive(Serialize)]
+struct TokenExchange<'a> {
+    provider: AuthProvider,
+    access_token: &'a str,
+}
+
+#[derive(Serialize)]
+struct TokenRefresh<'a> {
+    refresh_token: &'a str,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    #[serde(default)]
+    expires_in: Option<i64>,
+    #[serde(default)]
+    user_id: Option<String>,
+}
+
+impl TokenResponse {
+    fn into_credentials(self, provider: AuthProvider) -> Credentials {
+        Credentials {
+            access_token: self.access_token,
+            refresh_token: self.refresh_token,
+            expires_at: self
+                .expires_in
+                .map(|secs| Utc::now() + chrono::Duration::seconds(secs)),
+            provider,
+            user_id: self.user_id,
+            source: CredentialSource::Login,
+        }
+    }
+}
+
+/// Client for the desk API.
+#[derive(Debug, Clone)]
+pub struct DeskApiClient {
+    http: Client,
+    config: ApiConfig,
+    credentials: Option<Credentials>,
+    /// Fetched on first contact; see [`Self::capabilities`].
+    capabilities: OnceLock<ApiCapabilities>,
+    /// Responses to revalidate instead of downloading again; see
+    /// [`Self::send_cached`].
+    cache: Option<HttpCache>,
+}
+
+impl DeskApiClient {
+    pub fn new(config: &ApiConfig) -> Result<Self, ApiError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            http::REQUEST_ID_HEADER,
+            HeaderValue::from_static(http::request_id()),
+        );
+        headers.insert(API_VERSION_HEADER, HeaderValue::from_static(API_VERSION));
+        let http = http::client_builder(config)?
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .default_headers(headers)
+            .build()
+            .map_err(|source| ApiError::ApiUnavailable {
+                url: config.base_url.clone(),
+                source,
+            })?;
+        Ok(Self {
+            http,
+            config: config.clone(),
+            credentials: None,
+            capabilities: OnceLock::new(),
+            cache: paths::cache_dir()
+                .ok()
+                .map(|dir| HttpCache::new(dir.join("api"))),
+        })
+    }
+
+    /// Loads the credentials to use; see [`credentials::load`].
+    pub fn load_credentials(&mut self) -> Result<(), ApiError> {
+        self.credentials = credentials::load()?;
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    pub const fn credentials(&self) -> Option<&Credentials> {
+        self.credentials.as_ref()
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.config.base_url
+    }
+
+    /// What the server supports, asked once per client.
+    ///
+    /// Fails if the server does not speak [`API_VERSION`].
+    pub async fn capabilities(&self) -> Result<&ApiCapabilities, ApiError> {
+        if let Some(capabilities) = self.capabilities.get() {
+            return Ok(capabilities);
+        }
+        let mut request = self.http.get(self.url("/v1/meta"));
+        // Private servers may hide even this behind a login.
+        if let Some(credentials) = &self.credentials {
+            request = request.bearer_auth(&credentials.access_token);
+        }
+        let fetched = match self.send_json::<ApiCapabilities>(request, "/v1/meta").await {
+            Ok(capabilities) => capabilities,
+            Err(ApiError::NotFound(_)) => {
+                debug!("desk API has no /v1/meta; assuming every feature");
+                ApiCapabilities::legacy()
+            },
+            Err(e) => return Err(e),
+        };
+        if !fetched.versions.iter().any(|v| v == API_VERSION) {
+            return Err(ApiError::UnsupportedVersion {
+                url: self.config.base_url.clone(),
+                offered: fetched.versions,
+            });
+        }
+        debug!(
+            "desk API {} supports: {}",
+            fetched.server.as_deref().unwrap_or("(unnamed)"),
+            fetched.features.join(", ")
+        );
+        if let Some(newer) = fetched.newer_version() {
+            warn!(
+                "the desk API at {} also speaks API {newer}; upgrade desk to use it",
+                self.config.base_url
+            );
+        }
+        Ok(self.capabilities.get_or_init(|| fetched))
+    }
+
+    /// Whether the server implements `feature`.
+    pub async fn supports(&self, feature: ApiFeature) -> Result<bool, ApiError> {
+        Ok(self.capabilities().await?.supports(feature))
+    }
+
+    /// Trades a provider access token for desk API credentials.
+    pub async fn exchange_token(
+        &self,
+        provider: AuthProvider,
+        provider_token: &str,
+    ) -> Result<Credentials, ApiError> {
+        self.capabilities().await?;
+        let request = self
+            .http
+            .post(self.url("/v1/auth/token"))
+            .json(&TokenExchange {
+                provider,
+                access_token: provider_token,
+            });
+        let token: TokenResponse = self.send_json(request, "login").await?;
+        Ok(token.into_credentials(provider))
+    }
+
+    /// Makes sure the credentials still work before a long operation.
+    ///
+    /// Credentials close to expiry, or rejected by a cheap authenticated
+    /// request, are refreshed and saved when a refresh token is available.
+    /// Returns whether they were refreshed.
+    pub async fn ensure_session(&mut self) -> Result<bool, ApiError> {
+        let credentials = self
+            .credentials
+            .as_ref()
+            .ok_or(ApiError::NotAuthenticated)?;
+        let has_refresh_token = credentials.refresh_token.is_some();
+        let can_refresh = has_refresh_token && self.supports(ApiFeature::SessionRefresh).await?;
+
+        let stale = credentials.expires_within(chrono::Duration::seconds(REFRESH_MARGIN_SECS));
+        let refreshed = can_refresh && stale;
+        if refreshed {
+            self.refresh_session().await?;
+        }
+
+        match self.ping().await {
+            Ok(()) => Ok(refreshed),
+            Err(ApiError::Unauthorized) if can_refresh && !refreshed => {
+                self.refresh_session().await?;
+                self.ping().await?;
+                Ok(true)
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Checks that the API accepts the current credentials.
+    pub async fn ping(&self) -> Result<(), ApiError> {
+        let request = self.authorized(self.http.get(self.url("/v1/auth/session")))?;
+        self.send(request, "session").await?;
+        Ok(())
+    }
+
+    /// Trades the refresh token for new credentials and saves them.
+    async fn refresh_session(&mut self) -> Result<(), ApiError> {
+        let current = self
+            .credentials
+            .as_ref()
+            .ok_or(ApiError::NotAuthenticated)?;
+        let refresh_token = current
+            .refresh_token
+            .as_deref()
+            .ok_or(ApiError::Unauthorized)?;
+        let request = self
+            .http
+            .post(self.url("/v1/auth/refresh"))
+            .json(&TokenRefresh { refresh_token });
+        let token: TokenResponse = self.send_json(request, "session").await?;
+
+        let mut credentials = token.into_credentials(current.provider);
+        if credentials.refresh_token.is_none() {
+            credentials.refresh_token.clone_from(&current.refresh_token);
+        }
+        if credentials.user_id.is_none() {
+            credentials.user_id.clone_from(&current.user_id);
+        }
+        CredentialStore::new()?.save(&credentials)?;
+        self.credentials = Some(credentials);
+        Ok(())
+    }
+
+    /// The account the credentials belong to.
+    pub async fn get_current_user(&self) -> Result<CurrentUser, ApiError> {
+        self.require(ApiFeature::UserProfile).await?;
+        let request = self.authorized(self.http.get(self.url("/v1/users/me")))?;
+        self.send_cached(request, "account").await
+    }
+
+    /// Lists the devices signed in to the account.
+    pub async fn list_sessions(&self) -> Result<Vec<Session>, ApiError> {
+        self.require(ApiFeature::Sessions).await?;
+        let request = self.authorized(self.http.get(self.url("/v1/auth/sessions")))?;
+        let list: SessionList = self.send_cached(request, "sessions").await?;
+        Ok(list.sessions)
+    }
+
+    /// Signs session `id` out; its refresh token stops working too.
+    pub async fn revoke_session(&self, id: &str) -> Result<(), ApiError> {
+        self.require(ApiFeature::Sessions).await?;
+        let request = self.authorized(
+            self.http
+                .delete(self.url(&format!("/v1/auth/sessions/{id}"))),
+        )?;
+        self.send(request, &format!("session {id}")).await?;
+        Ok(())
+    }
+
+    /// Lists every workspace stored for the account, walking all pages.
+    pub async fn list_workspaces(&self) -> Result<Vec<RemoteWorkspace>, ApiError> {
+        let paged = self.supports(ApiFeature::WorkspacePages).await?;
+        let mut workspaces = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut request = self.authorized(self.http.get(self.url("/v1/workspaces")))?;
+            if paged {
+                request = request.query(&[("limit", PAGE_SIZE)]);
+            }
+            if let Some(cursor) = &cursor {
+                request = request.query(&[("cursor", cursor)]);
+            }
+            let page: WorkspaceList = self.send_cached(request, "workspaces").await?;
+            workspaces.extend(page.workspaces);
+
+            match page.next_cursor {
+                // A cursor that does not move would loop forever.
+                Some(next) if cursor.as_ref() == Some(&next) => {
+                    warn!("desk API returned the same page cursor twice; stopping");
+                    break;
+                },
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        Ok(workspaces)
+    }
+
+    /// Fetches remote workspace `id`. `name` is what not-found errors call
+    /// it.
+    pub async fn get_workspace(&self, id: &str, name: &str) -> Result<RemoteWorkspace, ApiError> {
+        let request = self.authorized(self.http.get(self.url(&format!("/v1/workspaces/{id}"))))?;
+        self.send_cached(request, name).await
+    }
+
+    /// Uploads a workspace the server does not have yet, with its sealed
+    /// `changes`.
+    pub async fn create_workspace(
+        &self,
+        scope: &str,
+        workspace: &Workspace,
+        changes: Option<&str>,
+    ) -> Result<RemoteWorkspace, ApiError> {
+        let changes = self.storable(changes).await?;
+        let request = self
+            .authorized(self.http.post(self.url("/v1/workspaces")))?
+            .json(&CreateWorkspace {
+                scope,
+                name: &workspace.name,
+                workspace,
+                changes,
+            });
+        self.send_json(request, &workspace.name).await
+    }
+
+    /// Replaces remote workspace `id` and its changes, provided it is still at
+    /// `version`.
+    pub async fn update_workspace(
+        &self,
+        id: &str,
+        version: u64,
+        workspace: &Workspace,
+        changes: Option<&str>,
+    ) -> Result<RemoteWorkspace, ApiError> {
+        let changes = self.storable(changes).await?;
+        let request = self
+            .authorized(self.http.put(self.url(&format!("/v1/workspaces/{id}"))))?
+            .json(&UpdateWorkspace {
+                version,
+                workspace,
+                changes,
+            });
+        self.send_json(request, &workspace.name).await
+    }
+
+    /// Deletes remote workspace `id`, provided it is still at `version`;
+    /// fails with [`ApiError::Conflict`] if another client changed it since.
+    pub async fn delete_workspace(
+        &self,
+        id: &str,
+        version: u64,
+        name: &str,
+    ) -> Result<(), ApiError> {
+        let request = self
+            .authorized(self.http.delete(self.url(&format!("/v1/workspaces/{id}"))))?
+            .query(&[("version", version)]);
+        self.send(request, name).await?;
+        Ok(())
+    }
+
+    /// Opens the stream of workspace changes; see [`super::events`].
+    pub async fn watch_workspaces(&self) -> Result<EventStream, ApiError> {
+        self.require(ApiFeature::WorkspaceEvents).await?;
+        let request = self
+            .authorized(self.http.get(self.url("/v1/workspaces/events")))?
+            .header(reqwest::header::ACCEPT, "text/event-stream")
+            .timeout(STREAM_TIMEOUT);
+        let response = self.send(request, "workspace events").await?;
+        Ok(EventStream::new(response, self.config.base_url.clone()))
+    }
+
+    /// Fails with [`ApiError::Unsupported`] unless the server implements
+    /// `feature`.
+    pub async fn require(&self, feature: ApiFeature) -> Result<(), ApiError> {
+        if self.supports(feature).await? {
+            Ok(())
+        } else {
+            Err(ApiError::Unsupported {
+                url: self.config.base_url.clone(),
+                feature,
+            })
+        }
+    }
+
+    /// Drops `changes` the server has nowhere to keep.
+    async fn storable<'a>(&self, changes: Option<&'a str>) -> Result<Option<&'a str>, ApiError> {
+        if changes.is_some() && !self.supports(ApiFeature::WorkspaceChanges).await? {
+            debug!("desk API does not store changes; leaving them out");
+            return Ok(None);
+        }
+        Ok(changes)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.config.base_url.trim_end_matches('/'))
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> Result<RequestBuilder, ApiError> {
+        let credentials = self
+            .credentials
+            .as_ref()
+            .ok_or(ApiError::NotAuthenticated)?;
+        Ok(request.bearer_auth(&credentials.access_token))
+    }
+
+    /// Sends `GET` `request` like [`Self::send_json`], but with the `ETag` of
+    /// the response cached for it last time, which the API can answer with
+    /// `304 Not Modified` instead of the same body again.
+    async fn send_cached<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+        subject: &str,
+    ) -> Result<T, ApiError> {
+        let built = request.try_clone().and_then(|r| r.build().ok());
+        let (Some(cache), Some(built), Some(mut conditional)) =
+            (&self.cache, built, request.try_clone())
+        else {
+            return self.send_json(request, subject).await;
+        };
+        // Another account's copy would be no use, nor anyone's business.
+        let account = self.credentials.as_ref().map_or("", |c| {
+            c.user_id.as_deref().unwrap_or(c.access_token.as_str())
+        });
+        let key = format!("{account} {}", built.url());
+        let cached = cache.load(&key);
+
+        if let Some(cached) = &cached {
+            conditional = conditional.header(IF_NONE_MATCH, &cached.etag);
+        }
+        let response = self.send(conditional, subject).await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(value) = cached.and_then(|c| serde_json::from_str(&c.body).ok()) {
+                debug!("{} not modified; using the cached response", built.url());
+                return Ok(value);
+            }
+            // Not something this version of desk can read: fetch it afresh.
+            cache.remove(&key);
+            return self.send_json(request, subject).await;
+        }
+
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        let body = response.text().await.map_err(ApiError::Decode)?;
+        let value =
+            serde_json::from_str(&body).map_err(|e| self.undecodable(ApiError::Malformed(e)))?;
+        match etag {
+            Some(etag) => cache.store(&key, &CachedResponse { etag, body }),
+            None => cache.remove(&key),
+        }
+        Ok(value)
+    }
+
+    /// Sends `request` and decodes a successful JSON response. `subject`
+    /// names what the request was about in not-found and conflict errors.
+    async fn send_json<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+        subject: &str,
+    ) -> Result<T, ApiError> {
+        self.send(request, subject)
+            .await?
+            .json()
+            .await
+            .map_err(|e| self.undecodable(ApiError::Decode(e)))
+    }
+
+    /// `error` about a response desk could not read, unless the server
+    /// speaks a newer API version, which explains it better.
+    fn undecodable(&self, error: ApiError) -> ApiError {
+        self.capabilities
+            .get()
+            .and_then(ApiCapabilities::newer_version)
+            .map_or(error, |version| ApiError::NewerVersion {
+                url: self.config.base_url.clone(),
+                version: version.to_string(),
+            })
+    }
+
+    /// Sends `request`, retrying retriable failures up to `max_retries`
+    /// times, and turns error statuses into [`ApiError`]s.
+    ///
+    /// Requests that are not idempotent, such as creating a workspace, are
+    /// only retried when the API certainly did not process them.
+    async fn send(&self, request: RequestBuilder, subject: &str) -> Result<Response, ApiError> {
+        let built = request.try_clone().and_then(|r| r.build().ok());
+        if let Some(built) = &built {
+            debug!(
+                "{} {} (request ID {})",
+                built.method(),
+                built.url(),
+                http::request_id()
+            );
+        }
+        let idempotent = built.is_some_and(|r| r.method().is_idempotent());
+        let mut attempt = 0;
+        loop {
+            // Streaming bodies cannot be cloned; those requests get one try.
+            let retry = (attempt < self.config.max_retries)
+                .then(|| request.try_clone())
+                .flatten();
+            let Some(retry) = retry else {
+                return self.send_once(request, subject).await;
+            };
+            let error = match self.send_once(retry, subject).await {
+                Err(e) if e.is_retriable() && (idempotent || e.unprocessed()) => e,
+                result => return result,
+            };
+            let Some(delay) = retry_delay(attempt, error.retry_after()) else {
+                return Err(error);
+            };
+            attempt += 1;
+            warn!(
+                "{error}; retrying in {}ms ({attempt}/{})",
+                delay.as_millis(),
+                self.config.max_retries
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn send_once(
+        &self,
+        request: RequestBuilder,
+        subject: &str,
+    ) -> Result<Response, ApiError> {
+        let response = request
+            .send()
+            .await
+            .map_err(|source| ApiError::ApiUnavailable {
+                url: self.config.base_url.clone(),
+                source,
+            })?;
+        let response = check_status(response, subject).await?;
+        self.check_version(&response)?;
+        Ok(response)
+    }
+
+    /// Fails if the server answered in a newer API version than the one
+    /// asked for, whose responses desk would misread.
+    fn check_version(&self, response: &Response) -> Result<(), ApiError> {
+        let Some(version) = response
+            .headers()
+            .get(API_VERSION_HEADER)
+            .and_then(|v| v.to_str().ok())
+        else {
+            return Ok(());
+        };
+        if is_newer(version) {
+            return Err(ApiError::NewerVersion {
+                url: self.config.base_url.clone(),
+                version: version.to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+async fn check_status(response: Response, subject: &str) -> Result<Response, ApiError> {
+    let status = response.status();
+    // Only requests revalidating a cached response get 304s.
+    if status.is_success() || status == StatusCode::NOT_MODIFIED {
+        return Ok(response);
+    }
+    Err(match status {
+        StatusCode::UNAUTHORIZED => ApiError::Unauthorized,
+        StatusCode::NOT_FOUND => ApiError::NotFound(subject.to_string()),
+        StatusCode::CONFLICT | StatusCode::PRECONDITION_FAILED => {
+            ApiError::Conflict(subject.to_string())
+        },
+        StatusCode::TOO_MANY_REQUESTS => ApiError::RateLimited {
+            retry_after: retry_after(&response),
+        },
+        StatusCode::SERVICE_UNAVAILABLE => ApiError::Unavailable {
+            retry_after: retry_after(&response),
+        },
+        _ => ApiError::Server {
+            status,
+            message: response.text().await.unwrap_or_default(),
+        },
+    })
+}
+
+/// The number in an API version such as `v1`.
+fn version_number(version: &str) -> Option<u32> {
+    version.trim().strip_prefix('v')?.parse().ok()
+}
+
+/// Whether `version` is newer than [`API_VERSION`].
+fn is_newer(version: &str) -> bool {
+    version_number(version) > version_number(API_VERSION)
+}
+
+/// What to do about a server that does not speak [`API_VERSION`].
+fn version_advice(offered: &[String]) -> &'static str {
+    if offered.iter().any(|v| is_newer(v)) {
+        "upgrade desk"
+    } else {
+        "check api.base_url, or ask whoever runs the server to upgrade it"
+    }
+}
+
+fn retry_hint(retry_after: Option<Duration>) -> String {
+    match retry_after.map(|wait| wait.as_secs()) {
+        None | Some(0) => String::new(),
+        Some(secs @ 1..=59) => format!("; try again in {secs}s"),
+        Some(secs) => format!(
+            "; try again in {}",
+            format_duration(Duration::from_secs(secs))
+        ),
+    }
+}
+
+/// Reads `Retry-After`, given either in seconds or as an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (at.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// How long to wait before retry number `attempt + 1`: what the API asked
+/// for, or else exponential backoff with jitter so that clients failing
+/// together do not retry together. `None` if the API asked for a longer wait
+/// than [`MAX_RETRY_DELAY`].
+fn retry_delay(attempt: u32, retry_after: Option<Duration>) -> Option<Duration> {
+    if let Some(wait) = retry_after {
+        return (wait <= MAX_RETRY_DELAY).then_some(wait);
+    }
+    let backoff = RETRY_BASE_DELAY
+        .saturating_mul(2_u32.saturating_pow(attempt))
+        .min(MAX_RETRY_DELAY);
+    // Half of the backoff is fixed, the other half random.
+    let half = backoff / 2;
+    let spread = u64::try_from(half.as_millis()).unwrap_or(u64::MAX);
+    let random = RandomState::new().build_hasher().finish();
+    Some(half + Duration::from_millis(random % spread.saturating_add(1)))
+}
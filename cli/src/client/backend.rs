@@ -0,0 +1,148 @@
+//! Storage behind `desk sync`: the desk API or a git remote of your own.
+
+use std::future::Future;
+
+use thiserror::Error;
+
+use super::api::{ApiError, ApiFeature, DeskApiClient, RemoteWorkspace};
+use super::git_backend::GitBackendError;
+use crate::core::workspace::Workspace;
+
+/// Errors raised by a [`SyncBackend`].
+#[derive(Debug, Error)]
+pub enum BackendError {
+    #[error(transparent)]
+    Api(#[from] ApiError),
+
+    #[error(transparent)]
+    Git(#[from] GitBackendError),
+}
+
+impl BackendError {
+    /// Whether the user has to log in again before retrying.
+    pub const fn requires_reauth(&self) -> bool {
+        match self {
+            Self::Api(e) => e.requires_reauth(),
+            Self::Git(_) => false,
+        }
+    }
+
+    /// Whether the backend could not be reached at all.
+    pub const fn is_unreachable(&self) -> bool {
+        matches!(
+            self,
+            Self::Api(ApiError::ApiUnavailable { .. } | ApiError::Unavailable { .. })
+                | Self::Git(GitBackendError::Fetch { .. })
+        )
+    }
+
+    /// Whether the workspace changed since the version the request was based
+    /// on.
+    pub const fn is_conflict(&self) -> bool {
+        matches!(
+            self,
+            Self::Api(ApiError::Conflict(_)) | Self::Git(GitBackendError::Conflict(_))
+        )
+    }
+
+    /// Whether the workspace the request was about does not exist.
+    pub const fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            Self::Api(ApiError::NotFound(_)) | Self::Git(GitBackendError::NotFound(_))
+        )
+    }
+}
+
+/// Where `desk sync` keeps the shared copies of workspaces.
+///
+/// Every change is made against the version it was based on, so two machines
+/// syncing at once cannot silently overwrite each other.
+pub trait SyncBackend: Sync {
+    /// Identifies the storage, so records of one backend are never matched
+    /// against another.
+    fn location(&self) -> String;
+
+    /// Whether workspaces can carry their stashed changes.
+    fn stores_changes(&self) -> impl Future<Output = Result<bool, BackendError>> + Send;
+
+    /// Every workspace stored in the backend.
+    fn list(&self) -> impl Future<Output = Result<Vec<RemoteWorkspace>, BackendError>> + Send;
+
+    /// Workspace `id`; `name` is what not-found errors call it.
+    fn get(
+        &self,
+        id: &str,
+        name: &str,
+    ) -> impl Future<Output = Result<RemoteWorkspace, BackendError>> + Send;
+
+    /// Stores a workspace the backend does not have yet, with its sealed
+    /// `changes`.
+    fn create(
+        &self,
+        scope: &str,
+        workspace: &Workspace,
+        changes: Option<&str>,
+    ) -> impl Future<Output = Result<RemoteWorkspace, BackendError>> + Send;
+
+    /// Replaces workspace `id` and its changes, provided it is still at
+    /// `version`.
+    fn update(
+        &self,
+        id: &str,
+        version: u64,
+        workspace: &Workspace,
+        changes: Option<&str>,
+    ) -> impl Future<Output = Result<RemoteWorkspace, BackendError>> + Send;
+
+    /// Deletes workspace `id`, provided it is still at `version`.
+    fn delete(
+        &self,
+        id: &str,
+        version: u64,
+        name: &str,
+    ) -> impl Future<Output = Result<(), BackendError>> + Send;
+}
+
+impl SyncBackend for DeskApiClient {
+    fn location(&self) -> String {
+        format!("api {}", self.base_url())
+    }
+
+    async fn stores_changes(&self) -> Result<bool, BackendError> {
+        Ok(self.supports(ApiFeature::WorkspaceChanges).await?)
+    }
+
+    async fn list(&self) -> Result<Vec<RemoteWorkspace>, BackendError> {
+        Ok(self.list_workspaces().await?)
+    }
+
+    async fn get(&self, id: &str, name: &str) -> Result<RemoteWorkspace, BackendError> {
+        Ok(self.get_workspace(id, name).await?)
+    }
+
+    async fn create(
+        &self,
+        scope: &str,
+        workspace: &Workspace,
+        changes: Option<&str>,
+    ) -> Result<RemoteWorkspace, BackendError> {
+        Ok(self.create_workspace(scope, workspace, changes).await?)
+    }
+
+    async fn update(
+        &self,
+        id: &str,
+        version: u64,
+        workspace: &Workspace,
+        changes: Option<&str>,
+    ) -> Result<RemoteWorkspace, BackendError> {
+        Ok(self
+            .update_workspace(id, version, workspace, changes)
+            .await?)
+    }
+
+    async fn delete(&self, id: &str, version: u64, name: &str) -> Result<(), BackendError> {
+        Ok(self.delete_workspace(id, version, name).await?)
+    }
+}
@@ -0,0 +1,140 @@
+//! Server-sent events announcing workspace changes on the desk API.
+//!
+//! `GET /v1/workspaces/events` answers with a `text/event-stream` body:
+//!
+//! ```text
+//! event: workspace.updated
+//! data: {"id": "...", "scope": "...", "name": "...", "version": 3, ...}
+//!
+//! event: workspace.deleted
+//! data: {"id": "...", "scope": "...", "name": "..."}
+//! ```
+//!
+//! Comment lines (`: keep-alive`) and unknown event types are ignored.
+
+use std::time::Duration;
+
+use reqwest::Response;
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use super::api::{ApiError, RemoteWorkspace};
+
+/// How long one event stream stays open. reqwest's timeout covers the whole
+/// response body, so streams are reopened periodically instead of being left
+/// open forever.
+pub const STREAM_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// A change announced by the desk API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorkspaceEvent {
+    /// A workspace was created or updated.
+    Updated(Box<RemoteWorkspace>),
+    /// A workspace was deleted.
+    Deleted(DeletedWorkspace),
+}
+
+/// Identifies a workspace deleted on the desk API.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct DeletedWorkspace {
+    pub id: String,
+    pub scope: String,
+    pub name: String,
+}
+
+/// An open `text/event-stream` response.
+#[derive(Debug)]
+pub struct EventStream {
+    response: Response,
+    url: String,
+    buffer: Vec<u8>,
+    event: String,
+    data: String,
+}
+
+impl EventStream {
+    pub(crate) const fn new(response: Response, url: String) -> Self {
+        Self {
+            response,
+            url,
+            buffer: Vec::new(),
+            event: String::new(),
+            data: String::new(),
+        }
+    }
+
+    /// Waits for the next workspace event; `None` once the server closes the
+    /// stream.
+    pub async fn next(&mut self) -> Result<Option<WorkspaceEvent>, ApiError> {
+        loop {
+            while let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                if let Some(event) = self.feed(line.trim_end_matches(['\n', '\r'])) {
+                    return Ok(Some(event));
+                }
+            }
+
+            let chunk = self
+                .response
+                .chunk()
+                .await
+                .map_err(|source| ApiError::ApiUnavailable {
+                    url: self.url.clone(),
+                    source,
+                })?;
+            match chunk {
+                Some(chunk) => self.buffer.extend_from_slice(&chunk),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Processes one line, returning the event a blank line completes.
+    fn feed(&mut self, line: &str) -> Option<WorkspaceEvent> {
+        if line.is_empty() {
+            let event = std::mem::take(&mut self.event);
+            let data = std::mem::take(&mut self.data);
+            return decode(&event, &data);
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => value.clone_into(&mut self.event),
+            "data" => {
+                if !self.data.is_empty() {
+                    self.data.push('\n');
+                }
+                self.data.push_str(value);
+            },
+            _ => {},
+        }
+        None
+    }
+}
+
+fn decode(event: &str, data: &str) -> Option<WorkspaceEvent> {
+    let decoded = match event {
+        "workspace.updated" => {
+            serde_json::from_str(data).map(|w| WorkspaceEvent::Updated(Box::new(w)))
+        },
+        "workspace.deleted" => serde_json::from_str(data).map(WorkspaceEvent::Deleted),
+        _ => {
+            if !event.is_empty() {
+                debug!("ignoring desk API event '{event}'");
+            }
+            return None;
+        },
+    };
+    match decoded {
+        Ok(event) => Some(event),
+        Err(e) => {
+            warn!("ignoring malformed '{event}' event: {e}");
+            None
+        },
+    }
+}
@@ -0,0 +1,408 @@
+//! A git repository as the storage of `desk sync`.
+//!
+//! The repository holds one file per workspace, under the workspace's scope:
+//!
+//! ```text
+//! github.com/acme/api/feature-x.json      the workspace and its version
+//! github.com/acme/api/feature-x.changes   its sealed changes, if any
+//! global/notes.json
+//! ```
+//!
+//! desk keeps a clone in its data directory and runs the `git` executable
+//! against it, so the user's SSH keys and credential helpers apply. Every
+//! change is one commit; when another machine pushed first, the change is
+//! checked again and retried on top of what it pushed.
+
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Output};
+
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::debug;
+
+use super::api::RemoteWorkspace;
+use super::backend::{BackendError, SyncBackend};
+use crate::core::config::GitSyncConfig;
+use crate::core::sync::sync_key;
+use crate::core::workspace::Workspace;
+
+/// Directory of the clone inside the data directory.
+const CLONE_DIR: &str = "sync-repo";
+
+/// How often a change is tried while other machines keep pushing first.
+const MAX_PUSH_ATTEMPTS: u32 = 5;
+
+const WORKSPACE_EXTENSION: &str = "json";
+const CHANGES_EXTENSION: &str = "changes";
+
+/// Errors raised by [`GitBackend`].
+#[derive(Debug, Error)]
+pub enum GitBackendError {
+    #[error("no repository configured for the git sync backend; set sync.git.remote")]
+    NoRemote,
+
+    #[error("failed to run git; is it installed?")]
+    Spawn(#[source] std::io::Error),
+
+    #[error("`git {command}` failed: {stderr}")]
+    Command { command: String, stderr: String },
+
+    #[error("cannot fetch from {remote}: {stderr}")]
+    Fetch { remote: String, stderr: String },
+
+    #[error("{0} not found in the sync repository")]
+    NotFound(String),
+
+    #[error("{0} was changed by another machine")]
+    Conflict(String),
+
+    #[error("scope '{0}' cannot be stored in the sync repository")]
+    InvalidScope(String),
+
+    #[error("other machines kept pushing to the sync repository; gave up after {MAX_PUSH_ATTEMPTS} attempts")]
+    Contended,
+
+    #[error("failed to access {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("{path} in the sync repository is not a valid workspace")]
+    Invalid {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+/// Syncs workspaces through a branch of a git repository.
+#[derive(Debug, Clone)]
+pub struct GitBackend {
+    remote: String,
+    branch: String,
+    dir: PathBuf,
+}
+
+impl GitBackend {
+    /// Uses desk's clone of `config.remote` under `data_dir`, creating it on
+    /// first use.
+    pub fn open(config: &GitSyncConfig, data_dir: &Path) -> Result<Self, GitBackendError> {
+        let remote = config.remote.as_deref().ok_or(GitBackendError::NoRemote)?;
+        let backend = Self {
+            remote: remote.to_string(),
+            branch: config.branch.clone(),
+            dir: data_dir.join(CLONE_DIR),
+        };
+
+        if backend.dir.join(".git").is_dir() {
+            // Follows edits of sync.git.remote.
+            backend.git(&["remote", "set-url", "origin", remote])?;
+        } else {
+            std::fs::create_dir_all(&backend.dir)
+                .map_err(|source| io_error(&backend.dir, source))?;
+            backend.git(&["init", "--quiet"])?;
+            backend.git(&["remote", "add", "origin", remote])?;
+        }
+        if !backend.run(&["config", "user.email"])?.status.success() {
+            backend.git(&["config", "user.name", "desk"])?;
+            backend.git(&["config", "user.email", "desk@localhost"])?;
+        }
+        Ok(backend)
+    }
+
+    /// Makes the clone match the remote branch.
+    fn refresh(&self) -> Result<(), GitBackendError> {
+        self.git(&["fetch", "--quiet", "--prune", "origin"])
+            .map_err(|e| match e {
+                GitBackendError::Command { stderr, .. } => GitBackendError::Fetch {
+                    remote: self.remote.clone(),
+                    stderr,
+                },
+                e => e,
+            })?;
+        self.reset()
+    }
+
+    /// Drops everything in the clone that is not on the remote branch as of
+    /// the last fetch.
+    fn reset(&self) -> Result<(), GitBackendError> {
+        let head = format!("refs/heads/{}", self.branch);
+        let tracking = format!("refs/remotes/origin/{}", self.branch);
+        self.git(&["symbolic-ref", "HEAD", &head])?;
+        if self
+            .run(&["rev-parse", "--verify", "--quiet", &tracking])?
+            .status
+            .success()
+        {
+            self.git(&["reset", "--hard", "--quiet", &tracking])?;
+        } else {
+            // Nothing was pushed to the branch yet.
+            self.run(&["update-ref", "-d", &head])?;
+            self.git(&["read-tree", "--empty"])?;
+        }
+        self.git(&["clean", "-d", "--force", "--quiet"])?;
+        Ok(())
+    }
+
+    /// Applies `change` to the clone, commits it, and pushes. When another
+    /// machine pushed first, `change` runs again on top of its push.
+    fn commit<T>(
+        &self,
+        message: &str,
+        change: impl Fn(&Self) -> Result<T, GitBackendError>,
+    ) -> Result<T, GitBackendError> {
+        let refspec = format!("HEAD:refs/heads/{}", self.branch);
+        for _ in 0..MAX_PUSH_ATTEMPTS {
+            let pushed = change(self).and_then(|value| {
+                self.git(&["add", "--all"])?;
+                self.git(&["commit", "--quiet", "--message", message])?;
+                self.git(&["push", "--quiet", "origin", &refspec])?;
+                Ok(value)
+            });
+            match pushed {
+                Ok(value) => return Ok(value),
+                Err(GitBackendError::Command { stderr, .. }) if stderr.contains("[rejected]") => {
+                    debug!("push to the sync repository was rejected; fetching and retrying");
+                    self.refresh()?;
+                },
+                Err(e) => {
+                    self.reset()?;
+                    return Err(e);
+                },
+            }
+        }
+        Err(GitBackendError::Contended)
+    }
+
+    /// Every workspace in the clone, with the path of its file.
+    fn read_all(&self) -> Result<Vec<(PathBuf, RemoteWorkspace)>, GitBackendError> {
+        let mut files = Vec::new();
+        collect_files(&self.dir, &mut files)?;
+        let mut workspaces = Vec::new();
+        for path in files {
+            if path
+                .extension()
+                .map_or(true, |ext| ext != WORKSPACE_EXTENSION)
+            {
+                continue;
+            }
+            let contents = std::fs::read(&path).map_err(|source| io_error(&path, source))?;
+            let mut workspace: RemoteWorkspace =
+                serde_json::from_slice(&contents).map_err(|source| GitBackendError::Invalid {
+                    path: path.clone(),
+                    source,
+                })?;
+            let changes = path.with_extension(CHANGES_EXTENSION);
+            workspace.changes = match std::fs::read_to_string(&changes) {
+                Ok(sealed) => Some(sealed.trim_end().to_string()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(source) => return Err(io_error(&changes, source)),
+            };
+            workspaces.push((path, workspace));
+        }
+        Ok(workspaces)
+    }
+
+    fn find(&self, id: &str, name: &str) -> Result<(PathBuf, RemoteWorkspace), GitBackendError> {
+        self.read_all()?
+            .into_iter()
+            .find(|(_, workspace)| workspace.id == id)
+            .ok_or_else(|| GitBackendError::NotFound(name.to_string()))
+    }
+
+    fn path_for(&self, scope: &str, name: &str) -> Result<PathBuf, GitBackendError> {
+        let mut path = self.dir.clone();
+        for part in scope.split('/') {
+            let mut components = Path::new(part).components();
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(part)), None) if part != ".git" => path.push(part),
+                _ => return Err(GitBackendError::InvalidScope(scope.to_string())),
+            }
+        }
+        Ok(path.join(format!("{name}.{WORKSPACE_EXTENSION}")))
+    }
+
+    /// Runs git in the clone, failing unless it succeeds.
+    fn git(&self, args: &[&str]) -> Result<String, GitBackendError> {
+        let output = self.run(args)?;
+        if !output.status.success() {
+            return Err(GitBackendError::Command {
+                command: args.join(" "),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn run(&self, args: &[&str]) -> Result<Output, GitBackendError> {
+        Command::new("git")
+            .arg("-C")
+            .arg(&self.dir)
+            .args(args)
+            // Messages are matched, so they must not be translated.
+            .env("LC_ALL", "C")
+            .output()
+            .map_err(GitBackendError::Spawn)
+    }
+}
+
+impl SyncBackend for GitBackend {
+    fn location(&self) -> String {
+        format!("git {} {}", self.remote, self.branch)
+    }
+
+    async fn stores_changes(&self) -> Result<bool, BackendError> {
+        Ok(true)
+    }
+
+    async fn list(&self) -> Result<Vec<RemoteWorkspace>, BackendError> {
+        self.refresh()?;
+        let workspaces = self.read_all()?;
+        Ok(workspaces.into_iter().map(|(_, w)| w).collect())
+    }
+
+    async fn get(&self, id: &str, name: &str) -> Result<RemoteWorkspace, BackendError> {
+        self.refresh()?;
+        let (_, workspace) = self.find(id, name)?;
+        Ok(workspace)
+    }
+
+    async fn create(
+        &self,
+        scope: &str,
+        workspace: &Workspace,
+        changes: Option<&str>,
+    ) -> Result<RemoteWorkspace, BackendError> {
+        let key = sync_key(scope, &workspace.name);
+        let path = self.path_for(scope, &workspace.name)?;
+        let created = self.commit(&format!("Add {key}"), |_| {
+            if path.exists() {
+                return Err(GitBackendError::Conflict(workspace.name.clone()));
+            }
+            let created = RemoteWorkspace {
+                id: new_id(&key),
+                scope: scope.to_string(),
+                name: workspace.name.clone(),
+                version: 1,
+                updated_at: Utc::now(),
+                workspace: workspace.clone(),
+                changes: changes.map(String::from),
+            };
+            write_workspace(&path, &created)?;
+            Ok(created)
+        })?;
+        Ok(created)
+    }
+
+    async fn update(
+        &self,
+        id: &str,
+        version: u64,
+        workspace: &Workspace,
+        changes: Option<&str>,
+    ) -> Result<RemoteWorkspace, BackendError> {
+        let name = &workspace.name;
+        let (_, current) = self.find(id, name)?;
+        let key = sync_key(&current.scope, name);
+        let updated = self.commit(&format!("Update {key}"), |backend| {
+            let (path, current) = backend.find(id, name)?;
+            if current.version != version {
+                return Err(GitBackendError::Conflict(name.clone()));
+            }
+            let updated = RemoteWorkspace {
+                version: version + 1,
+                updated_at: Utc::now(),
+                workspace: workspace.clone(),
+                changes: changes.map(String::from),
+                ..current
+            };
+            write_workspace(&path, &updated)?;
+            Ok(updated)
+        })?;
+        Ok(updated)
+    }
+
+    async fn delete(&self, id: &str, version: u64, name: &str) -> Result<(), BackendError> {
+        self.commit(&format!("Delete {name}"), |backend| {
+            let (path, current) = backend.find(id, name)?;
+            if current.version != version {
+                return Err(GitBackendError::Conflict(name.to_string()));
+            }
+            remove_if_exists(&path)?;
+            remove_if_exists(&path.with_extension(CHANGES_EXTENSION))
+        })?;
+        Ok(())
+    }
+}
+
+/// A fresh id for the workspace at `key`; a workspace deleted and created
+/// again gets a new one, as with the desk API.
+fn new_id(key: &str) -> String {
+    let seed = format!(
+        "{key}\n{}\n{}",
+        Utc::now().timestamp_nanos_opt().unwrap_or_default(),
+        std::process::id()
+    );
+    hex::encode(&Sha256::digest(seed.as_bytes())[..8])
+}
+
+/// Writes `workspace` to `path` and its changes next to it.
+fn write_workspace(path: &Path, workspace: &RemoteWorkspace) -> Result<(), GitBackendError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|source| io_error(parent, source))?;
+    }
+    let changes = path.with_extension(CHANGES_EXTENSION);
+    let stored = RemoteWorkspace {
+        changes: None,
+        ..workspace.clone()
+    };
+    let mut json =
+        serde_json::to_string_pretty(&stored).map_err(|source| GitBackendError::Invalid {
+            path: path.to_path_buf(),
+            source,
+        })?;
+    json.push('\n');
+    std::fs::write(path, json).map_err(|source| io_error(path, source))?;
+    workspace.changes.as_ref().map_or_else(
+        || remove_if_exists(&changes),
+        |sealed| std::fs::write(&changes, sealed).map_err(|source| io_error(&changes, source)),
+    )
+}
+
+/// Adds every file under `dir` to `files`, skipping git's own directory.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), GitBackendError> {
+    let entries = std::fs::read_dir(dir).map_err(|source| io_error(dir, source))?;
+    for entry in entries {
+        let entry = entry.map_err(|source| io_error(dir, source))?;
+        let path = entry.path();
+        let kind = entry
+            .file_type()
+            .map_err(|source| io_error(&path, source))?;
+        if kind.is_dir() {
+            if entry.file_name() != ".git" {
+                collect_files(&path, files)?;
+            }
+        } else if kind.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), GitBackendError> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(source) => Err(io_error(path, source)),
+    }
+}
+
+fn io_error(path: &Path, source: std::io::Error) -> GitBackendError {
+    GitBackendError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
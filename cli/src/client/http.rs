@@ -0,0 +1,93 @@
+//! The HTTP client desk reaches the desk API and sign-in providers with, set
+//! up for the network it runs on by `api.proxy`, `api.no_proxy`, and
+//! `api.ca_bundle`.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use reqwest::{Certificate, ClientBuilder, NoProxy, Proxy};
+use thiserror::Error;
+
+use crate::core::config::ApiConfig;
+use crate::utils::expand::{expand_path, ExpandError};
+
+/// Errors raised while setting up the HTTP client.
+#[derive(Debug, Error)]
+pub enum HttpSetupError {
+    #[error("invalid api.proxy '{url}'")]
+    Proxy {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+
+    #[error("invalid api.ca_bundle")]
+    CaBundlePath(#[source] ExpandError),
+
+    #[error("failed to read api.ca_bundle {path}")]
+    ReadCaBundle {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("api.ca_bundle {path} is not a PEM certificate bundle")]
+    CaBundle {
+        path: PathBuf,
+        #[source]
+        source: Option<reqwest::Error>,
+    },
+}
+
+/// Header carrying [`request_id`] on desk API requests.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+static REQUEST_ID: OnceLock<String> = OnceLock::new();
+
+/// Identifies this run of desk to the desk API, so that a failure someone
+/// reports can be found in the server's logs. Every request the process
+/// makes carries the same one.
+pub fn request_id() -> &'static str {
+    REQUEST_ID.get_or_init(|| format!("{:016x}", RandomState::new().build_hasher().finish()))
+}
+
+/// A client builder with desk's user agent and the network settings from
+/// `config`.
+pub fn client_builder(config: &ApiConfig) -> Result<ClientBuilder, HttpSetupError> {
+    let mut builder =
+        reqwest::Client::builder().user_agent(concat!("desk-cli/", env!("CARGO_PKG_VERSION")));
+    if let Some(url) = &config.proxy {
+        let proxy = Proxy::all(url).map_err(|source| HttpSetupError::Proxy {
+            url: url.clone(),
+            source,
+        })?;
+        let no_proxy = config.no_proxy.as_deref().and_then(NoProxy::from_string);
+        builder = builder.proxy(proxy.no_proxy(no_proxy));
+    }
+    if let Some(bundle) = &config.ca_bundle {
+        for certificate in ca_certificates(bundle)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    Ok(builder)
+}
+
+/// The certificates in the PEM file at `bundle`, trusted on top of the
+/// built-in ones.
+pub fn ca_certificates(bundle: &str) -> Result<Vec<Certificate>, HttpSetupError> {
+    let path = expand_path(bundle).map_err(HttpSetupError::CaBundlePath)?;
+    let pem = std::fs::read(&path).map_err(|source| HttpSetupError::ReadCaBundle {
+        path: path.clone(),
+        source,
+    })?;
+    match Certificate::from_pem_bundle(&pem) {
+        Ok(certificates) if !certificates.is_empty() => Ok(certificates),
+        Ok(_) => Err(HttpSetupError::CaBundle { path, source: None }),
+        Err(source) => Err(HttpSetupError::CaBundle {
+            path,
+            source: Some(source),
+        }),
+    }
+}
@@ -0,0 +1,93 @@
+//! Desk API responses kept on disk with their `ETag`, so asking for the same
+//! thing again costs the server a `304 Not Modified` rather than the whole
+//! body.
+
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+/// Most responses kept; the ones stored longest ago are dropped first.
+const MAX_ENTRIES: usize = 200;
+
+/// A response body and the `ETag` it was served with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub etag: String,
+    pub body: String,
+}
+
+/// Cached responses, one file per request under `dir`.
+///
+/// The cache only ever saves requests; failing to read or write it is
+/// logged and otherwise ignored.
+#[derive(Debug, Clone)]
+pub struct HttpCache {
+    dir: PathBuf,
+}
+
+impl HttpCache {
+    pub const fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    /// The response stored for `key`, if there is a readable one.
+    pub fn load(&self, key: &str) -> Option<CachedResponse> {
+        let path = self.path(key);
+        let contents = std::fs::read_to_string(&path).ok()?;
+        serde_json::from_str(&contents)
+            .map_err(|e| {
+                debug!(
+                    "ignoring unreadable cached response {}: {e}",
+                    path.display()
+                );
+            })
+            .ok()
+    }
+
+    /// Stores `response` for `key`, replacing what was there.
+    pub fn store(&self, key: &str, response: &CachedResponse) {
+        let path = self.path(key);
+        let tmp = path.with_extension("json.tmp");
+        let written = std::fs::create_dir_all(&self.dir)
+            .and_then(|()| std::fs::write(&tmp, serde_json::to_vec(response)?))
+            .and_then(|()| std::fs::rename(&tmp, &path));
+        match written {
+            Ok(()) => self.prune(),
+            Err(e) => debug!("failed to cache response in {}: {e}", path.display()),
+        }
+    }
+
+    pub fn remove(&self, key: &str) {
+        let _ = std::fs::remove_file(self.path(key));
+    }
+
+    /// Drops the oldest responses beyond [`MAX_ENTRIES`].
+    fn prune(&self) {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut files: Vec<(SystemTime, PathBuf)> = entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let modified = entry.metadata().and_then(|m| m.modified()).ok()?;
+                Some((modified, entry.path()))
+            })
+            .collect();
+        if files.len() <= MAX_ENTRIES {
+            return;
+        }
+        files.sort();
+        for (_, path) in &files[..files.len() - MAX_ENTRIES] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    /// Keys can hold credentials, so only their hash makes it to disk.
+    fn path(&self, key: &str) -> PathBuf {
+        let digest = Sha256::digest(key.as_bytes());
+        self.dir.join(format!("{}.json", hex::encode(digest)))
+    }
+}
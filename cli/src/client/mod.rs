@@ -0,0 +1,9 @@
+//! Clients of the services `desk sync` stores workspaces in: the desk API
+//! over HTTP, or a git remote.
+
+pub mod api;
+pub mod backend;
+pub mod events;
+pub mod git_backend;
+pub mod http;
+pub mod http_cache;
@@ -0,0 +1,386 @@
+//! Backups of all local desk data.
+//!
+//! A backup is a gzipped tar archive, optionally encrypted with an age
+//! passphrase:
+//!
+//! ```text
+//! manifest.json
+//! config/config.toml
+//! data/state.json
+//! data/repo-state/...
+//! data/workspaces/...
+//! ```
+//!
+//! Restoring unpacks into a staging directory, checks that every file loads,
+//! and only then swaps the data in. Whatever was there before is kept in a
+//! `.pre-restore-<timestamp>` directory inside the data directory.
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use age::secrecy::SecretString;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::config::load_config_from;
+use super::lock::LOCK_FILE;
+use super::state::DeskState;
+use super::store::FileWorkspaceStore;
+
+/// Version of the archive layout written by this build.
+pub const BACKUP_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const CONFIG_FILE: &str = "config.toml";
+const STAGING_DIR: &str = ".restore-staging";
+const AGE_MAGIC: &[u8] = b"age-encryption.org/";
+
+/// Errors raised while creating or restoring a backup.
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("failed to access {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("failed to encrypt backup")]
+    Encrypt(#[from] age::EncryptError),
+
+    #[error("failed to decrypt backup; check the passphrase")]
+    Decrypt(#[from] age::DecryptError),
+
+    #[error("backup is encrypted; a passphrase is required")]
+    PassphraseRequired,
+
+    #[error("{0} is not a desk backup")]
+    NotABackup(PathBuf),
+
+    #[error("backup format {found} is newer than this version of desk supports ({supported}); upgrade desk to restore it")]
+    TooNew { found: u32, supported: u32 },
+
+    #[error("backup entry {entry} is not valid")]
+    Invalid {
+        entry: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+}
+
+/// Describes the contents of a backup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    /// Version of desk that wrote the backup.
+    pub desk_version: String,
+    pub created_at: DateTime<Utc>,
+    pub workspaces: usize,
+    pub has_config: bool,
+}
+
+/// Directories a backup is taken from and restored to.
+#[derive(Debug, Clone)]
+pub struct BackupLocations {
+    pub config_dir: PathBuf,
+    pub data_dir: PathBuf,
+}
+
+/// Outcome of a successful restore.
+#[derive(Debug, Clone)]
+pub struct RestoreSummary {
+    pub manifest: BackupManifest,
+    /// Where the data that was replaced has been moved, if there was any.
+    pub previous: Option<PathBuf>,
+}
+
+/// File name for a backup taken at `now`.
+pub fn default_file_name(now: DateTime<Utc>, encrypted: bool) -> String {
+    let suffix = if encrypted { ".age" } else { "" };
+    format!("desk-backup-{}.tar.gz{suffix}", now.format("%Y%m%d-%H%M%S"))
+}
+
+/// Writes a backup of `locations` to `dest`.
+pub fn create_backup(
+    locations: &BackupLocations,
+    dest: &Path,
+    passphrase: Option<SecretString>,
+) -> Result<BackupManifest, BackupError> {
+    let config_file = locations.config_dir.join(CONFIG_FILE);
+    let mut data_files = Vec::new();
+    collect_data_files(&locations.data_dir, Path::new(""), &mut data_files)?;
+    data_files.sort();
+
+    let manifest = BackupManifest {
+        format_version: BACKUP_FORMAT_VERSION,
+        desk_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: Utc::now(),
+        workspaces: data_files
+            .iter()
+            .filter(|p| p.starts_with("workspaces") && p.extension().is_some_and(|e| e == "json"))
+            .count(),
+        has_config: config_file.is_file(),
+    };
+
+    let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let manifest_json = serde_json::to_vec_pretty(&manifest).expect("manifest serializes");
+    append_bytes(&mut archive, MANIFEST, &manifest_json, dest)?;
+    if manifest.has_config {
+        append_file(
+            &mut archive,
+            &config_file,
+            &Path::new("config").join(CONFIG_FILE),
+        )?;
+    }
+    for relative in &data_files {
+        append_file(
+            &mut archive,
+            &locations.data_dir.join(relative),
+            &Path::new("data").join(relative),
+        )?;
+    }
+    let bytes = archive
+        .into_inner()
+        .and_then(GzEncoder::finish)
+        .map_err(|source| io_error(dest, source))?;
+
+    let bytes = match passphrase {
+        Some(passphrase) => age::encrypt(&age::scrypt::Recipient::new(passphrase), &bytes)?,
+        None => bytes,
+    };
+
+    let tmp = dest.with_extension("tmp");
+    std::fs::write(&tmp, &bytes)
+        .and_then(|()| std::fs::rename(&tmp, dest))
+        .map_err(|source| io_error(dest, source))?;
+    Ok(manifest)
+}
+
+/// Whether the backup at `path` is encrypted and needs a passphrase.
+pub fn is_encrypted(path: &Path) -> Result<bool, BackupError> {
+    let mut header = [0; AGE_MAGIC.len()];
+    let mut file = std::fs::File::open(path).map_err(|source| io_error(path, source))?;
+    match file.read_exact(&mut header) {
+        Ok(()) => Ok(header == AGE_MAGIC),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(source) => Err(io_error(path, source)),
+    }
+}
+
+/// Validates the backup at `archive` and replaces the data in `locations`
+/// with it.
+pub fn restore_backup(
+    archive: &Path,
+    locations: &BackupLocations,
+    passphrase: Option<SecretString>,
+) -> Result<RestoreSummary, BackupError> {
+    let bytes = std::fs::read(archive).map_err(|source| io_error(archive, source))?;
+    let bytes = if bytes.starts_with(AGE_MAGIC) {
+        let passphrase = passphrase.ok_or(BackupError::PassphraseRequired)?;
+        age::decrypt(&age::scrypt::Identity::new(passphrase), &bytes)?
+    } else {
+        bytes
+    };
+
+    let data_dir = &locations.data_dir;
+    std::fs::create_dir_all(data_dir).map_err(|source| io_error(data_dir, source))?;
+    let staging = data_dir.join(STAGING_DIR);
+    remove_dir_if_exists(&staging)?;
+
+    let result = unpack(&bytes, archive, &staging)
+        .and_then(|manifest| validate(&staging).map(|()| manifest))
+        .and_then(|manifest| replay(&staging, locations, manifest));
+    remove_dir_if_exists(&staging)?;
+    result
+}
+
+fn unpack(bytes: &[u8], archive: &Path, staging: &Path) -> Result<BackupManifest, BackupError> {
+    tar::Archive::new(GzDecoder::new(bytes))
+        .unpack(staging)
+        .map_err(|_| BackupError::NotABackup(archive.to_path_buf()))?;
+
+    let manifest = std::fs::read(staging.join(MANIFEST))
+        .ok()
+        .and_then(|contents| serde_json::from_slice::<BackupManifest>(&contents).ok())
+        .ok_or_else(|| BackupError::NotABackup(archive.to_path_buf()))?;
+    if manifest.format_version > BACKUP_FORMAT_VERSION {
+        return Err(BackupError::TooNew {
+            found: manifest.format_version,
+            supported: BACKUP_FORMAT_VERSION,
+        });
+    }
+    Ok(manifest)
+}
+
+/// Loads everything in the staged backup the same way desk would at runtime.
+fn validate(staging: &Path) -> Result<(), BackupError> {
+    let data = staging.join("data");
+    let invalid =
+        |entry: &str, source: Box<dyn std::error::Error + Send + Sync>| BackupError::Invalid {
+            entry: entry.to_string(),
+            source,
+        };
+
+    load_config_from(&staging.join("config").join(CONFIG_FILE), None)
+        .map_err(|e| invalid("config/config.toml", e.into()))?;
+    DeskState::load_from(&data.join("state.json"))
+        .map_err(|e| invalid("data/state.json", e.into()))?;
+    let stores =
+        FileWorkspaceStore::all(&data).map_err(|e| invalid("data/workspaces", e.into()))?;
+    for store in stores {
+        store
+            .list()
+            .map_err(|e| invalid(&store.dir().display().to_string(), e.into()))?;
+    }
+    Ok(())
+}
+
+/// Moves the current data aside and the staged data into place.
+fn replay(
+    staging: &Path,
+    locations: &BackupLocations,
+    manifest: BackupManifest,
+) -> Result<RestoreSummary, BackupError> {
+    let stamp = Utc::now().format("%Y%m%d-%H%M%S").to_string();
+    let data_dir = &locations.data_dir;
+    let previous = data_dir.join(format!(".pre-restore-{stamp}"));
+
+    // Move entry by entry rather than the whole directory so the lock file
+    // held by this process stays where it is.
+    let mut moved_any = false;
+    for name in top_level_entries(data_dir)? {
+        create_dir(&previous)?;
+        rename(&data_dir.join(&name), &previous.join(&name))?;
+        moved_any = true;
+    }
+    let staged_data = staging.join("data");
+    if staged_data.is_dir() {
+        for name in top_level_entries(&staged_data)? {
+            rename(&staged_data.join(&name), &data_dir.join(&name))?;
+        }
+    }
+
+    let staged_config = staging.join("config").join(CONFIG_FILE);
+    if staged_config.is_file() {
+        let config_file = locations.config_dir.join(CONFIG_FILE);
+        create_dir(&locations.config_dir)?;
+        if config_file.is_file() {
+            create_dir(&previous)?;
+            copy(&config_file, &previous.join(CONFIG_FILE))?;
+            moved_any = true;
+        }
+        // The config directory may live on another filesystem, so copy and
+        // rename within it instead of renaming across.
+        let tmp = config_file.with_extension("tmp");
+        copy(&staged_config, &tmp)?;
+        rename(&tmp, &config_file)?;
+    }
+
+    Ok(RestoreSummary {
+        manifest,
+        previous: moved_any.then_some(previous),
+    })
+}
+
+/// Files under `relative` that belong in a backup, relative to the data directory.
+fn collect_data_files(
+    root: &Path,
+    relative: &Path,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), BackupError> {
+    let dir = root.join(relative);
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(source) => return Err(io_error(&dir, source)),
+    };
+    for entry in entries {
+        let entry = entry.map_err(|source| io_error(&dir, source))?;
+        let name = entry.file_name();
+        let name_str = name.to_string_lossy();
+        // Hidden entries hold restore staging and previous data, not live state.
+        if name_str.starts_with('.') || name_str == LOCK_FILE || name_str.ends_with(".tmp") {
+            continue;
+        }
+        let path = relative.join(&name);
+        let file_type = entry
+            .file_type()
+            .map_err(|source| io_error(&entry.path(), source))?;
+        if file_type.is_dir() {
+            collect_data_files(root, &path, files)?;
+        } else if file_type.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn top_level_entries(dir: &Path) -> Result<Vec<std::ffi::OsString>, BackupError> {
+    let entries = std::fs::read_dir(dir).map_err(|source| io_error(dir, source))?;
+    let mut names = Vec::new();
+    for entry in entries {
+        let name = entry.map_err(|source| io_error(dir, source))?.file_name();
+        if !name.to_string_lossy().starts_with('.') && name != LOCK_FILE {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+fn append_bytes<W: Write>(
+    archive: &mut tar::Builder<W>,
+    name: &str,
+    contents: &[u8],
+    dest: &Path,
+) -> Result<(), BackupError> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(u64::try_from(Utc::now().timestamp()).unwrap_or_default());
+    header.set_cksum();
+    archive
+        .append_data(&mut header, name, contents)
+        .map_err(|source| io_error(dest, source))
+}
+
+fn append_file<W: Write>(
+    archive: &mut tar::Builder<W>,
+    path: &Path,
+    name: &Path,
+) -> Result<(), BackupError> {
+    archive
+        .append_path_with_name(path, name)
+        .map_err(|source| io_error(path, source))
+}
+
+fn remove_dir_if_exists(dir: &Path) -> Result<(), BackupError> {
+    match std::fs::remove_dir_all(dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(io_error(dir, e)),
+        _ => Ok(()),
+    }
+}
+
+fn create_dir(dir: &Path) -> Result<(), BackupError> {
+    std::fs::create_dir_all(dir).map_err(|source| io_error(dir, source))
+}
+
+fn rename(from: &Path, to: &Path) -> Result<(), BackupError> {
+    std::fs::rename(from, to).map_err(|source| io_error(from, source))
+}
+
+fn copy(from: &Path, to: &Path) -> Result<(), BackupError> {
+    std::fs::copy(from, to)
+        .map(|_| ())
+        .map_err(|source| io_error(from, source))
+}
+
+fn io_error(path: &Path, source: std::io::Error) -> BackupError {
+    BackupError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
@@ -0,0 +1,89 @@
+//! Per-workspace time budgets.
+//!
+//! A budget caps the time spent in a workspace, counting closed sessions
+//! recorded on the workspace plus the session currently open, if any.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use super::state::DeskState;
+use super::workspace::Workspace;
+use crate::utils::duration::format_duration;
+
+/// Share of the budget after which a warning is shown, in percent.
+const WARN_THRESHOLD_PERCENT: u64 = 90;
+
+/// How close a workspace is to its budget, once it is worth a warning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetAlert {
+    /// Most of the budget is used up.
+    Nearly,
+    /// The budget is used up.
+    Over,
+}
+
+/// Time spent against a workspace's budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BudgetStatus {
+    pub spent: Duration,
+    pub limit: Duration,
+}
+
+impl BudgetStatus {
+    /// Computes the budget status of `workspace`, or `None` if it has no budget.
+    pub fn for_workspace(workspace: &Workspace, state: &DeskState) -> Option<Self> {
+        let limit = Duration::from_secs(workspace.metadata.time_budget_secs?);
+        let mut spent = Duration::from_secs(workspace.metadata.time_spent_secs);
+
+        if state.current_workspace(&workspace.repo_path) == Some(workspace.name.as_str()) {
+            if let Some(opened_at) = state.opened_at(&workspace.repo_path) {
+                spent += (Utc::now() - opened_at).to_std().unwrap_or_default();
+            }
+        }
+
+        Some(Self { spent, limit })
+    }
+
+    pub fn is_exceeded(&self) -> bool {
+        self.spent > self.limit
+    }
+
+    pub const fn remaining(&self) -> Duration {
+        self.limit.saturating_sub(self.spent)
+    }
+
+    /// How close the budget is to used up, once it is worth a warning.
+    pub fn alert(&self) -> Option<BudgetAlert> {
+        if self.is_exceeded() {
+            Some(BudgetAlert::Over)
+        } else if self.spent.as_secs().saturating_mul(100)
+            >= self.limit.as_secs().saturating_mul(WARN_THRESHOLD_PERCENT)
+        {
+            Some(BudgetAlert::Nearly)
+        } else {
+            None
+        }
+    }
+
+    /// A warning for `name` once the budget is nearly used up or exceeded.
+    pub fn warning(&self, name: &str) -> Option<String> {
+        let limit = format_duration(self.limit);
+        match self.alert()? {
+            BudgetAlert::Over => {
+                let over = format_duration(self.spent.saturating_sub(self.limit));
+                Some(format!(
+                    "workspace '{name}' is {over} over its {limit} budget"
+                ))
+            },
+            BudgetAlert::Nearly => {
+                let left = format_duration(self.remaining());
+                Some(format!(
+                    "workspace '{name}' has {left} left of its {limit} budget"
+                ))
+            },
+        }
+    }
+}
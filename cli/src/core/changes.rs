@@ -0,0 +1,347 @@
+//! Uncommitted changes exchanged by `desk sync`.
+//!
+//! A workspace's stash only exists in the clone it was saved in, so pushes
+//! carry its contents along as a bundle: a gzipped tar archive, optionally
+//! encrypted with an age passphrase, sent base64-encoded:
+//!
+//! ```text
+//! changes.json      format version and base commit
+//! changes.patch     staged and unstaged changes to tracked files
+//! untracked/...     untracked files
+//! ```
+//!
+//! Pulled bundles are kept, decrypted, next to the workspace file until
+//! `desk open` restores them. So are the changes of a workspace closed with
+//! `capture.backend = "patch"`, and the checkpoints `desk watch` takes of
+//! the open one.
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use age::secrecy::SecretString;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::store::{FileWorkspaceStore, StoreError};
+use crate::integrations::git::{StashContents, UntrackedFile};
+use crate::utils::size::format_size;
+
+/// Version of the bundle layout written by this build.
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+const MANIFEST: &str = "changes.json";
+const PATCH: &str = "changes.patch";
+const UNTRACKED_DIR: &str = "untracked";
+const PULLED_EXTENSION: &str = "changes";
+const CAPTURED_EXTENSION: &str = "captured";
+const CHECKPOINT_EXTENSION: &str = "checkpoint";
+const AGE_MAGIC: &[u8] = b"age-encryption.org/";
+
+/// Most a bundle may unpack to, whatever limit its sender used.
+const MAX_UNPACKED_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Errors raised while packing, sealing, or restoring changes.
+#[derive(Debug, Error)]
+pub enum ChangesError {
+    #[error("changes take {}, more than the {} limit (sync.max_changes_mib)", format_size(*size), format_size(*limit))]
+    TooLarge { size: u64, limit: u64 },
+
+    #[error("failed to encrypt changes")]
+    Encrypt(#[from] age::EncryptError),
+
+    #[error("failed to decrypt changes; check the passphrase")]
+    Decrypt(#[from] age::DecryptError),
+
+    #[error(
+        "changes are encrypted; set DESK_SYNC_PASSPHRASE or sync.encrypt_changes to restore them"
+    )]
+    PassphraseRequired,
+
+    #[error("changes bundle format {found} is newer than this version of desk supports ({supported}); upgrade desk to restore it")]
+    TooNew { found: u32, supported: u32 },
+
+    #[error("changes bundle is not valid")]
+    Invalid(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    #[error("failed to access {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleManifest {
+    format_version: u32,
+    base: String,
+}
+
+/// Archives `contents`, failing if the archive exceeds `limit` bytes.
+pub fn pack(contents: &StashContents, limit: u64) -> Result<Vec<u8>, ChangesError> {
+    let manifest = serde_json::to_vec_pretty(&BundleManifest {
+        format_version: BUNDLE_FORMAT_VERSION,
+        base: contents.base.clone(),
+    })
+    .map_err(|e| ChangesError::Invalid(e.into()))?;
+
+    let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let mut append = |path: &Path, data: &[u8], mode: u32| {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(mode);
+        archive.append_data(&mut header, path, data)
+    };
+    append(Path::new(MANIFEST), &manifest, 0o644).map_err(invalid)?;
+    append(Path::new(PATCH), &contents.patch, 0o644).map_err(invalid)?;
+    for file in &contents.untracked {
+        let mode = if file.executable { 0o755 } else { 0o644 };
+        append(
+            &Path::new(UNTRACKED_DIR).join(&file.path),
+            &file.contents,
+            mode,
+        )
+        .map_err(invalid)?;
+    }
+    let bytes = archive
+        .into_inner()
+        .and_then(GzEncoder::finish)
+        .map_err(invalid)?;
+
+    let size = bytes.len() as u64;
+    if size > limit {
+        return Err(ChangesError::TooLarge { size, limit });
+    }
+    Ok(bytes)
+}
+
+/// Reads an archive written by [`pack`].
+pub fn unpack(bytes: &[u8]) -> Result<StashContents, ChangesError> {
+    let mut manifest = None;
+    let mut contents = StashContents::default();
+    let decoder = GzDecoder::new(bytes).take(MAX_UNPACKED_SIZE);
+    let mut archive = tar::Archive::new(decoder);
+    for entry in archive.entries().map_err(invalid)? {
+        let mut entry = entry.map_err(invalid)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path().map_err(invalid)?.into_owned();
+        let executable = entry.header().mode().map_err(invalid)? & 0o111 != 0;
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).map_err(invalid)?;
+
+        if path == Path::new(MANIFEST) {
+            let parsed: BundleManifest =
+                serde_json::from_slice(&data).map_err(|e| ChangesError::Invalid(e.into()))?;
+            manifest = Some(parsed);
+        } else if path == Path::new(PATCH) {
+            contents.patch = data;
+        } else if let Ok(relative) = path.strip_prefix(UNTRACKED_DIR) {
+            contents.untracked.push(UntrackedFile {
+                path: relative.to_path_buf(),
+                contents: data,
+                executable,
+            });
+        }
+    }
+
+    let manifest =
+        manifest.ok_or_else(|| ChangesError::Invalid(format!("{MANIFEST} is missing").into()))?;
+    if manifest.format_version > BUNDLE_FORMAT_VERSION {
+        return Err(ChangesError::TooNew {
+            found: manifest.format_version,
+            supported: BUNDLE_FORMAT_VERSION,
+        });
+    }
+    contents.base = manifest.base;
+    Ok(contents)
+}
+
+/// Encrypts an archive if given a passphrase and encodes it for the desk API.
+pub fn seal(bytes: &[u8], passphrase: Option<&SecretString>) -> Result<String, ChangesError> {
+    let sealed = match passphrase {
+        Some(passphrase) => age::encrypt(&age::scrypt::Recipient::new(passphrase.clone()), bytes)?,
+        None => bytes.to_vec(),
+    };
+    Ok(STANDARD.encode(sealed))
+}
+
+/// Reverses [`seal`], returning the archive.
+pub fn open(encoded: &str, passphrase: Option<&SecretString>) -> Result<Vec<u8>, ChangesError> {
+    let bytes = STANDARD
+        .decode(encoded)
+        .map_err(|e| ChangesError::Invalid(e.into()))?;
+    if !bytes.starts_with(AGE_MAGIC) {
+        return Ok(bytes);
+    }
+    let passphrase = passphrase.ok_or(ChangesError::PassphraseRequired)?;
+    Ok(age::decrypt(
+        &age::scrypt::Identity::new(passphrase.clone()),
+        &bytes,
+    )?)
+}
+
+/// Keeps the archive pulled for workspace `name` until it is opened.
+pub fn save_pulled(
+    store: &FileWorkspaceStore,
+    name: &str,
+    bytes: &[u8],
+) -> Result<(), ChangesError> {
+    save_bundle(store, name, PULLED_EXTENSION, bytes)
+}
+
+/// The changes pulled for workspace `name`, if any are waiting.
+pub fn load_pulled(
+    store: &FileWorkspaceStore,
+    name: &str,
+) -> Result<Option<StashContents>, ChangesError> {
+    load_bundle(store, name, PULLED_EXTENSION)
+}
+
+/// Forgets the changes pulled for workspace `name`.
+pub fn remove_pulled(store: &FileWorkspaceStore, name: &str) -> Result<(), ChangesError> {
+    remove_bundle(store, name, PULLED_EXTENSION)
+}
+
+/// Keeps the archive of changes captured from workspace `name` until it is
+/// opened again.
+pub fn save_captured(
+    store: &FileWorkspaceStore,
+    name: &str,
+    bytes: &[u8],
+) -> Result<(), ChangesError> {
+    save_bundle(store, name, CAPTURED_EXTENSION, bytes)
+}
+
+/// The changes captured from workspace `name`, if it was closed with some.
+pub fn load_captured(
+    store: &FileWorkspaceStore,
+    name: &str,
+) -> Result<Option<StashContents>, ChangesError> {
+    load_bundle(store, name, CAPTURED_EXTENSION)
+}
+
+/// Forgets the changes captured from workspace `name`.
+pub fn remove_captured(store: &FileWorkspaceStore, name: &str) -> Result<(), ChangesError> {
+    remove_bundle(store, name, CAPTURED_EXTENSION)
+}
+
+/// Keeps the archive of the latest checkpoint of workspace `name`, in place
+/// of the one before.
+pub fn save_checkpoint(
+    store: &FileWorkspaceStore,
+    name: &str,
+    bytes: &[u8],
+) -> Result<(), ChangesError> {
+    save_bundle(store, name, CHECKPOINT_EXTENSION, bytes)
+}
+
+/// The latest checkpoint of workspace `name` and when it was taken, if
+/// there is one.
+pub fn load_checkpoint(
+    store: &FileWorkspaceStore,
+    name: &str,
+) -> Result<Option<(StashContents, DateTime<Utc>)>, ChangesError> {
+    let Some(taken) = checkpoint_time(store, name)? else {
+        return Ok(None);
+    };
+    Ok(load_bundle(store, name, CHECKPOINT_EXTENSION)?.map(|contents| (contents, taken)))
+}
+
+/// When the latest checkpoint of workspace `name` was taken, if there is
+/// one.
+pub fn checkpoint_time(
+    store: &FileWorkspaceStore,
+    name: &str,
+) -> Result<Option<DateTime<Utc>>, ChangesError> {
+    let path = bundle_path(store, name, CHECKPOINT_EXTENSION)?;
+    match std::fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+        Ok(modified) => Ok(Some(modified.into())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(io_error(&path, source)),
+    }
+}
+
+/// Forgets the checkpoint of workspace `name`.
+pub fn remove_checkpoint(store: &FileWorkspaceStore, name: &str) -> Result<(), ChangesError> {
+    remove_bundle(store, name, CHECKPOINT_EXTENSION)
+}
+
+/// Where the uncommitted changes of workspace `name` wait to be restored
+/// when they are not in a stash: `pulled` or `patch`, if anywhere.
+pub fn waiting(store: &FileWorkspaceStore, name: &str) -> Result<Option<&'static str>, StoreError> {
+    for (extension, label) in [(PULLED_EXTENSION, "pulled"), (CAPTURED_EXTENSION, "patch")] {
+        if bundle_path(store, name, extension)?.exists() {
+            return Ok(Some(label));
+        }
+    }
+    Ok(None)
+}
+
+fn save_bundle(
+    store: &FileWorkspaceStore,
+    name: &str,
+    extension: &str,
+    bytes: &[u8],
+) -> Result<(), ChangesError> {
+    let path = bundle_path(store, name, extension)?;
+    let tmp = path.with_extension(format!("{extension}.tmp"));
+    std::fs::write(&tmp, bytes)
+        .and_then(|()| std::fs::rename(&tmp, &path))
+        .map_err(|source| io_error(&path, source))
+}
+
+fn load_bundle(
+    store: &FileWorkspaceStore,
+    name: &str,
+    extension: &str,
+) -> Result<Option<StashContents>, ChangesError> {
+    let path = bundle_path(store, name, extension)?;
+    match std::fs::read(&path) {
+        Ok(bytes) => unpack(&bytes).map(Some),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(io_error(&path, source)),
+    }
+}
+
+fn remove_bundle(
+    store: &FileWorkspaceStore,
+    name: &str,
+    extension: &str,
+) -> Result<(), ChangesError> {
+    let path = bundle_path(store, name, extension)?;
+    match std::fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(source) => Err(io_error(&path, source)),
+    }
+}
+
+fn bundle_path(
+    store: &FileWorkspaceStore,
+    name: &str,
+    extension: &str,
+) -> Result<PathBuf, StoreError> {
+    Ok(store.path_for(name)?.with_extension(extension))
+}
+
+fn invalid(source: std::io::Error) -> ChangesError {
+    ChangesError::Invalid(source.into())
+}
+
+fn io_error(path: &Path, source: std::io::Error) -> ChangesError {
+    ChangesError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
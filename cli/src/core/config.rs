@@ -0,0 +1,1130 @@
+//! User configuration loaded from `config.toml`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use toml_edit::DocumentMut;
+use tracing::warn;
+
+use super::workspace::IssueTracker;
+use crate::auth::providers::AuthProvider;
+use crate::output::{TimestampStyle, Verbosity};
+
+/// Base URL of the hosted desk API.
+pub const DEFAULT_API_URL: &str = "https://api.getdesk.dev";
+
+/// Environment variable selecting a profile, like `--profile`.
+pub const PROFILE_ENV: &str = "DESK_PROFILE";
+
+/// Table holding the profiles, each overriding the settings around it.
+pub const PROFILES_KEY: &str = "profiles";
+
+/// Environment variables overriding settings, with the setting each one
+/// replaces. Lists take comma-separated values.
+pub const ENV_OVERRIDES: &[(&str, &str)] = &[
+    ("DESK_API_URL", "api.base_url"),
+    ("DESK_API_TIMEOUT", "api.timeout_secs"),
+    ("DESK_API_MAX_RETRIES", "api.max_retries"),
+    ("DESK_API_PROXY", "api.proxy"),
+    ("DESK_API_NO_PROXY", "api.no_proxy"),
+    ("DESK_API_CA_BUNDLE", "api.ca_bundle"),
+    ("DESK_AUTH_PROVIDER", "auth.default_provider"),
+    ("DESK_AUTH_STORAGE", "auth.storage"),
+    ("DESK_GITHUB_CLIENT_ID", "auth.providers.github.client_id"),
+    ("DESK_GITHUB_BASE_URL", "auth.providers.github.base_url"),
+    (
+        "DESK_MICROSOFT_CLIENT_ID",
+        "auth.providers.microsoft.client_id",
+    ),
+    ("DESK_MICROSOFT_TENANT", "auth.providers.microsoft.tenant"),
+    ("DESK_SHARE_TARGET", "share.target"),
+    ("DESK_SHARE_PASTE_URL", "share.paste_url"),
+    ("DESK_SHARE_PUBLIC", "share.public"),
+    ("DESK_SYNC_BACKEND", "sync.backend"),
+    ("DESK_SYNC_EXCLUDE", "sync.exclude"),
+    ("DESK_SYNC_INCLUDE_CHANGES", "sync.include_changes"),
+    ("DESK_SYNC_ENCRYPT_CHANGES", "sync.encrypt_changes"),
+    ("DESK_SYNC_MAX_CHANGES_MIB", "sync.max_changes_mib"),
+    ("DESK_SYNC_CONCURRENCY", "sync.concurrency"),
+    ("DESK_SYNC_GIT_REMOTE", "sync.git.remote"),
+    ("DESK_SYNC_GIT_BRANCH", "sync.git.branch"),
+    ("DESK_TRASH_RETENTION_DAYS", "trash.retention_days"),
+    (
+        "DESK_CAPTURE_INCLUDE_UNTRACKED",
+        "capture.include_untracked",
+    ),
+    ("DESK_CAPTURE_BACKEND", "capture.backend"),
+    ("DESK_CAPTURE_AUTO_STASH", "capture.auto_stash"),
+    ("DESK_CAPTURE_MAX_PATCH_MIB", "capture.max_patch_mib"),
+    ("DESK_CAPTURE_COMPOSE", "capture.compose"),
+    ("DESK_OPEN_NAME_TEMPLATE", "open.name_template"),
+    ("DESK_CLOSE_BRANCH", "close.branch"),
+    ("DESK_STATE_HISTORY_SIZE", "state.history_size"),
+    ("DESK_STATE_TRACK_TIME", "state.track_time"),
+    ("DESK_STATE_PER_REPO_HISTORY", "state.per_repo_history"),
+    ("DESK_OUTPUT_TIMESTAMPS", "output.timestamps"),
+    ("DESK_HOOKS_TIMEOUT", "hooks.timeout_secs"),
+    ("DESK_HOOKS_ON_FAILURE", "hooks.on_failure"),
+    ("DESK_HOOKS_SHELL", "hooks.shell"),
+    ("DESK_NOTIFICATIONS_ENABLED", "notifications.enabled"),
+    ("DESK_DIRENV_ENABLED", "direnv.enabled"),
+    ("DESK_WATCH_INTERVAL_MINS", "watch.interval_mins"),
+    (
+        "DESK_REMINDERS_STALE_AFTER_DAYS",
+        "reminders.stale_after_days",
+    ),
+    ("DESK_STATUS_CACHE_SECS", "status.cache_secs"),
+    ("DESK_LOGS_LEVEL", "logs.level"),
+    ("DESK_LOGS_MAX_SIZE_MIB", "logs.max_size_mib"),
+    ("DESK_LOGS_KEEP", "logs.keep"),
+    ("DESK_ISSUES_TRACKER", "issues.tracker"),
+    ("DESK_JIRA_BASE_URL", "issues.jira.base_url"),
+    ("DESK_JIRA_EMAIL", "issues.jira.email"),
+    ("DESK_JIRA_TOKEN", "issues.jira.token"),
+    ("DESK_LINEAR_TOKEN", "issues.linear.token"),
+    ("DESK_NOTIFICATIONS_SYNC_WATCH", "notifications.sync_watch"),
+    (
+        "DESK_NOTIFICATIONS_HOOK_AFTER_SECS",
+        "notifications.hook_after_secs",
+    ),
+    (
+        "DESK_DAEMON_SYNC_INTERVAL_MINS",
+        "daemon.sync_interval_mins",
+    ),
+    ("DESK_DAEMON_GC_INTERVAL_HOURS", "daemon.gc_interval_hours"),
+    (
+        "DESK_DAEMON_WATCH_INTERVAL_SECS",
+        "daemon.watch_interval_secs",
+    ),
+    (
+        "DESK_DAEMON_REMINDER_INTERVAL_HOURS",
+        "daemon.reminder_interval_hours",
+    ),
+];
+
+/// Errors raised while reading the configuration file.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read config file {path}")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("invalid config file {path}")]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    #[error("invalid config file {path}")]
+    Edit {
+        path: PathBuf,
+        #[source]
+        source: toml_edit::TomlError,
+    },
+
+    #[error("failed to write config file {path}")]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("invalid profile {name} in config file {path}")]
+    Profile {
+        path: PathBuf,
+        name: String,
+        #[source]
+        source: Box<toml::de::Error>,
+    },
+
+    #[error(
+        "invalid profile name '{name}' in config file {path}; use letters, digits, '-', and '_'"
+    )]
+    ProfileName { path: PathBuf, name: String },
+
+    #[error("no profile {name} in config file {path}; add a [profiles.{name}] table")]
+    UnknownProfile { path: PathBuf, name: String },
+}
+
+/// Top-level configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeskConfig {
+    /// Terminal output preferences.
+    pub output: OutputConfig,
+    /// Where `desk share` publishes snapshots.
+    pub share: ShareConfig,
+    /// Connection to the desk API used by `desk sync`.
+    pub api: ApiConfig,
+    /// Sign-in providers for `desk auth login`.
+    pub auth: AuthConfig,
+    /// How long deleted workspaces are kept.
+    pub trash: TrashConfig,
+    /// Where workspaces and state live.
+    pub storage: StorageConfig,
+    /// Where `desk sync` stores workspaces and which it leaves alone.
+    pub sync: SyncConfig,
+    /// How `desk open` and `desk close` save uncommitted changes.
+    pub capture: CaptureConfig,
+    /// How `desk open --auto` names workspaces.
+    pub open: OpenConfig,
+    /// What `desk close` leaves checked out.
+    pub close: CloseConfig,
+    /// What desk remembers about switches between workspaces.
+    pub state: StateConfig,
+    /// Commands run around `desk open` and `desk close`.
+    pub hooks: HooksConfig,
+    /// Desktop notifications when work in the background finishes.
+    pub notifications: NotificationsConfig,
+    /// What `desk daemon` does in the background.
+    pub daemon: DaemonConfig,
+    /// Handing workspace environment variables to direnv.
+    pub direnv: DirenvConfig,
+    /// Jira and Linear, for `desk open --issue`.
+    pub issues: IssuesConfig,
+    /// Checkpoints taken by `desk watch`.
+    pub watch: WatchConfig,
+    /// When `desk reminders` counts a workspace as left behind.
+    pub reminders: RemindersConfig,
+    /// Counting uncommitted changes for `desk status` and `desk prompt`.
+    pub status: StatusConfig,
+    /// The log file `desk logs` reads.
+    pub logs: LogsConfig,
+    /// Plugins saving and restoring context desk does not know about, by
+    /// name; see [`super::context`].
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub context: BTreeMap<String, ContextProvider>,
+}
+
+impl DeskConfig {
+    /// Applies the [`ENV_OVERRIDES`] that are set on top of the file.
+    /// Variables with values the setting cannot take are ignored with a
+    /// warning.
+    #[must_use]
+    pub fn with_env_overrides(self) -> Self {
+        let mut config = self;
+        for (var, key) in ENV_OVERRIDES {
+            let Some(raw) = std::env::var(var).ok().filter(|v| !v.is_empty()) else {
+                continue;
+            };
+            match config.overridden(key, &raw) {
+                Ok(overridden) => config = overridden,
+                Err(e) => warn!("ignoring {var}={raw}: {e}"),
+            }
+        }
+        config
+    }
+
+    /// A copy with setting `key` (dotted, like `api.timeout_secs`) read from
+    /// `raw`, which is taken as a number or boolean when it looks like one.
+    fn overridden(&self, key: &str, raw: &str) -> Result<Self, String> {
+        let table = toml::Table::try_from(self).map_err(|e| e.to_string())?;
+        let segments: Vec<_> = key.split('.').collect();
+        let typed = if setting(&table, &segments).is_some_and(toml::Value::is_array) {
+            toml::Value::Array(
+                raw.split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| toml::Value::String(item.to_string()))
+                    .collect(),
+            )
+        } else if let Ok(number) = raw.parse::<i64>() {
+            toml::Value::Integer(number)
+        } else if let Ok(flag) = raw.parse::<bool>() {
+            toml::Value::Boolean(flag)
+        } else {
+            toml::Value::String(raw.to_string())
+        };
+
+        match with_setting(table.clone(), &segments, typed).try_into() {
+            Ok(config) => Ok(config),
+            // Strings that look like numbers, such as a branch named 2024.
+            Err(e) => with_setting(table, &segments, toml::Value::String(raw.to_string()))
+                .try_into()
+                .map_err(|_| e.message().to_string()),
+        }
+    }
+}
+
+/// The value at `segments` in `table`.
+fn setting<'a>(table: &'a toml::Table, segments: &[&str]) -> Option<&'a toml::Value> {
+    let (name, parents) = segments.split_last()?;
+    let mut section = table;
+    for parent in parents {
+        section = section.get(*parent)?.as_table()?;
+    }
+    section.get(*name)
+}
+
+/// `table` with the value at `segments` replaced, adding missing tables on
+/// the way.
+fn with_setting(mut table: toml::Table, segments: &[&str], value: toml::Value) -> toml::Value {
+    if let Some((name, parents)) = segments.split_last() {
+        let mut section = Some(&mut table);
+        for parent in parents {
+            section = section.and_then(|s| {
+                s.entry((*parent).to_string())
+                    .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                    .as_table_mut()
+            });
+        }
+        if let Some(section) = section {
+            section.insert((*name).to_string(), value);
+        }
+    }
+    toml::Value::Table(table)
+}
+
+/// The `[output]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    /// How much detail to print while switching or restoring workspaces.
+    pub verbosity: Verbosity,
+    /// Whether times are shown relative to now or as absolute timestamps.
+    pub timestamps: TimestampStyle,
+}
+
+/// Service that receives shared snapshots.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ShareTarget {
+    /// A secret GitHub gist, authenticated with `GITHUB_TOKEN`/`GH_TOKEN`.
+    #[default]
+    Gist,
+    /// A paste service at `share.paste_url`.
+    Paste,
+}
+
+/// The `[share]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShareConfig {
+    pub target: ShareTarget,
+    /// Endpoint that accepts the snapshot as a POST body and answers with its URL.
+    pub paste_url: Option<String>,
+    /// Create public gists instead of secret ones.
+    pub public: bool,
+}
+
+/// The `[api]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    pub base_url: String,
+    /// Per-request timeout.
+    pub timeout_secs: u64,
+    /// How often a failed request may be retried.
+    pub max_retries: u32,
+    /// Proxy for every request, such as `http://proxy.example.com:3128`.
+    /// Without it, `HTTPS_PROXY` and the like are honored.
+    pub proxy: Option<String>,
+    /// Comma-separated hosts and domains reached without `proxy`.
+    pub no_proxy: Option<String>,
+    /// PEM file of extra certificate authorities to trust, for networks
+    /// that inspect TLS.
+    pub ca_bundle: Option<String>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_API_URL.to_string(),
+            timeout_secs: 30,
+            max_retries: 3,
+            proxy: None,
+            no_proxy: None,
+            ca_bundle: None,
+        }
+    }
+}
+
+/// The `[auth]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Provider used when `desk auth login` is run without `--provider`.
+    pub default_provider: AuthProvider,
+    /// Where `desk auth login` keeps credentials.
+    pub storage: CredentialStorage,
+    pub providers: ProvidersConfig,
+}
+
+/// Where credentials are stored; see [`crate::auth::credentials`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CredentialStorage {
+    /// The system keyring, or the encrypted file where the keyring fails.
+    #[default]
+    Auto,
+    Keyring,
+    /// An encrypted file in the config directory.
+    File,
+}
+
+/// The `[auth.providers]` table.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProvidersConfig {
+    pub github: ProviderSettings,
+    pub microsoft: ProviderSettings,
+}
+
+/// Settings for one sign-in provider.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProviderSettings {
+    /// OAuth client ID, overriding the one desk ships with.
+    pub client_id: Option<String>,
+    /// Microsoft Entra tenant (directory ID or domain) to sign in to;
+    /// defaults to any organization. Ignored by other providers.
+    pub tenant: Option<String>,
+    /// GitHub Enterprise Server to sign in to, such as
+    /// `https://github.example.com`; defaults to github.com. Ignored by other
+    /// providers.
+    pub base_url: Option<String>,
+}
+
+/// The `[trash]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrashConfig {
+    /// Days a deleted workspace stays restorable before `desk gc` removes it.
+    pub retention_days: u32,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self { retention_days: 30 }
+    }
+}
+
+/// The `[storage]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Data directory, e.g. a synced folder; `~` and `$VARS` are expanded.
+    pub dir: Option<String>,
+}
+
+/// Where `desk sync` keeps the shared copies of workspaces.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncBackendKind {
+    /// The hosted desk API (`[api]`).
+    #[default]
+    Api,
+    /// A git remote of your own (`[sync.git]`).
+    Git,
+}
+
+/// The `[sync]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    pub backend: SyncBackendKind,
+    /// Repository used by the git backend.
+    pub git: GitSyncConfig,
+    /// Glob patterns (`tmp-*`, `scratch`) of workspace names never synced.
+    pub exclude: Vec<String>,
+    /// Upload the uncommitted changes stashed for a workspace along with it.
+    pub include_changes: bool,
+    /// Encrypt uploaded changes with a passphrase (or `DESK_SYNC_PASSPHRASE`).
+    pub encrypt_changes: bool,
+    /// Largest compressed changes uploaded, in MiB; bigger ones stay local.
+    pub max_changes_mib: u64,
+    /// Most workspaces `desk sync push` uploads at once.
+    pub concurrency: usize,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            backend: SyncBackendKind::default(),
+            git: GitSyncConfig::default(),
+            exclude: Vec::new(),
+            include_changes: true,
+            encrypt_changes: false,
+            max_changes_mib: 10,
+            concurrency: 4,
+        }
+    }
+}
+
+/// The `[sync.git]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GitSyncConfig {
+    /// URL of the repository, e.g. `git@github.com:me/desk-sync.git`.
+    pub remote: Option<String>,
+    pub branch: String,
+}
+
+impl Default for GitSyncConfig {
+    fn default() -> Self {
+        Self {
+            remote: None,
+            branch: "main".to_string(),
+        }
+    }
+}
+
+/// Where a workspace's uncommitted changes are kept while it is closed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptureBackend {
+    /// A `git stash` entry, listed by `git stash list`.
+    #[default]
+    Stash,
+    /// A bundle in desk's data directory, outside the repository.
+    Patch,
+    /// A stash commit under `refs/desk/`, out of `git stash list`.
+    Ref,
+}
+
+/// The `[capture]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureConfig {
+    /// Save untracked files along with changes to tracked ones.
+    pub include_untracked: bool,
+    pub backend: CaptureBackend,
+    /// Put uncommitted changes away when switching; without it, switching
+    /// away from a workspace with changes fails.
+    pub auto_stash: bool,
+    /// Largest compressed changes the patch backend keeps, in MiB; bigger
+    /// ones stay in a stash.
+    pub max_patch_mib: u64,
+    /// Save and restore which Docker Compose override is active; see
+    /// [`super::context`].
+    pub compose: bool,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            include_untracked: true,
+            backend: CaptureBackend::default(),
+            auto_stash: true,
+            max_patch_mib: 50,
+            compose: true,
+        }
+    }
+}
+
+/// The `[open]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OpenConfig {
+    /// Name `desk open --auto` gives a workspace, from `{branch}`,
+    /// `{ticket}`, and `{slug}`; see [`super::naming`].
+    pub name_template: String,
+}
+
+impl Default for OpenConfig {
+    fn default() -> Self {
+        Self {
+            name_template: "{branch}".to_string(),
+        }
+    }
+}
+
+/// The `[close]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CloseConfig {
+    /// Branch to check out once the workspace is saved, such as `main`;
+    /// unset leaves the workspace's branch checked out.
+    pub branch: Option<String>,
+}
+
+/// The `[state]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StateConfig {
+    /// Workspace switches remembered per repository for `desk recent` and
+    /// predictions.
+    pub history_size: usize,
+    /// Add the time a workspace was open to its time spent when it is
+    /// closed, and log the session for `desk time`.
+    pub track_time: bool,
+    /// Learn from the switches in the current repository only; when off,
+    /// `desk recent` and predictions draw on every repository.
+    pub per_repo_history: bool,
+}
+
+impl Default for StateConfig {
+    fn default() -> Self {
+        Self {
+            history_size: 50,
+            track_time: true,
+            per_repo_history: true,
+        }
+    }
+}
+
+/// The `[notifications]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    /// Show desktop notifications at all.
+    pub enabled: bool,
+    /// Notify when `desk sync watch` pulls or misses a change, or stops.
+    pub sync_watch: bool,
+    /// Notify when a hook that ran at least this many seconds finishes or
+    /// fails; 0 never does.
+    pub hook_after_secs: u64,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sync_watch: true,
+            hook_after_secs: 30,
+        }
+    }
+}
+
+/// The `[daemon]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// Minutes between automatic `desk sync push` and `pull` runs; 0 never
+    /// syncs automatically.
+    pub sync_interval_mins: u64,
+    /// Hours between automatic `desk gc` runs; 0 never collects garbage.
+    pub gc_interval_hours: u64,
+    /// Seconds between checks of how checkouts drifted from their open
+    /// workspaces, as shown by `desk prompt`.
+    pub watch_interval_secs: u64,
+    /// Hours between desktop notifications listing the workspaces `desk
+    /// reminders` would; 0 never notifies.
+    pub reminder_interval_hours: u64,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            sync_interval_mins: 0,
+            gc_interval_hours: 24,
+            watch_interval_secs: 30,
+            reminder_interval_hours: 24,
+        }
+    }
+}
+
+/// The `[direnv]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DirenvConfig {
+    /// Write the open workspace's environment variables to `.envrc.desk` in
+    /// the checkout, for direnv to load.
+    pub enabled: bool,
+}
+
+/// The `[watch]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchConfig {
+    /// Minutes between checkpoints of the open workspace while files change.
+    pub interval_mins: u64,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self { interval_mins: 5 }
+    }
+}
+
+/// The `[reminders]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemindersConfig {
+    /// Days a workspace goes without being opened or saved before `desk
+    /// reminders` lists it; 0 never lists any.
+    pub stale_after_days: u64,
+}
+
+impl Default for RemindersConfig {
+    fn default() -> Self {
+        Self {
+            stale_after_days: 14,
+        }
+    }
+}
+
+/// The `[status]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatusConfig {
+    /// Seconds the uncommitted changes counted in a checkout are reused for
+    /// while `HEAD` and the index stay the same; 0 counts them every time.
+    pub cache_secs: u64,
+}
+
+/// The `[logs]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogsConfig {
+    /// Least severe events written to the log file.
+    pub level: LogLevel,
+    /// Size in MiB past which the log file is rotated.
+    pub max_size_mib: u64,
+    /// Rotated log files kept besides the current one.
+    pub keep: u32,
+}
+
+impl Default for LogsConfig {
+    fn default() -> Self {
+        Self {
+            level: LogLevel::Info,
+            max_size_mib: 5,
+            keep: 3,
+        }
+    }
+}
+
+/// How much goes into the log file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    /// Write no log file.
+    Off,
+    Error,
+    Warn,
+    /// Commands, hooks, and what went wrong.
+    Info,
+    Debug,
+    Trace,
+}
+
+/// A `[context.<name>]` section: a command speaking the context provider
+/// protocol.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContextProvider {
+    /// Command run through `hooks.shell` for each capture and restore.
+    pub run: String,
+    /// Seconds the command may run before it is stopped; 0 for no limit.
+    #[serde(default = "default_context_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+const fn default_context_timeout_secs() -> u64 {
+    30
+}
+
+impl ContextProvider {
+    /// How long the command may run, if there is a limit.
+    pub fn timeout(&self) -> Option<Duration> {
+        (self.timeout_secs > 0).then(|| Duration::from_secs(self.timeout_secs))
+    }
+}
+
+/// The `[issues]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IssuesConfig {
+    /// Tracker `desk open --issue` looks keys up in; without it, the only
+    /// one set up.
+    pub tracker: Option<IssueTracker>,
+    pub jira: JiraConfig,
+    pub linear: LinearConfig,
+}
+
+/// The `[issues.jira]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JiraConfig {
+    /// Site, such as `https://example.atlassian.net`.
+    pub base_url: Option<String>,
+    /// Account the API token belongs to. Without it the token is sent as a
+    /// personal access token, as Jira Data Center expects.
+    pub email: Option<String>,
+    pub token: Option<String>,
+    /// Transition applied to the ticket on `desk close`, such as
+    /// "In Review".
+    pub close_transition: Option<String>,
+}
+
+/// The `[issues.linear]` section.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinearConfig {
+    /// Personal API key.
+    pub token: Option<String>,
+    /// Workflow state the ticket moves to on `desk close`, such as
+    /// "In Review".
+    pub close_state: Option<String>,
+}
+
+/// The `[hooks]` section.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// Commands run in the repository before leaving a workspace.
+    pub pre_switch: Vec<HookEntry>,
+    /// Commands run in the repository once the switch is done.
+    pub post_switch: Vec<HookEntry>,
+    /// Commands run once `desk open` has created a workspace.
+    pub on_create: Vec<HookEntry>,
+    /// Commands run once a workspace has been moved to the trash.
+    pub on_delete: Vec<HookEntry>,
+    /// Commands run once the open workspace has been saved by a switch or
+    /// `desk save`.
+    pub on_save: Vec<HookEntry>,
+    /// Commands run before `desk sync push` and `pull`.
+    pub pre_sync: Vec<HookEntry>,
+    /// Seconds a hook may run before it is stopped; 0 for no limit.
+    pub timeout_secs: u64,
+    /// What a failing pre-switch or pre-sync hook does to the command.
+    pub on_failure: HookFailure,
+    /// Shell hooks run in; sh, or PowerShell on Windows, when not set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shell: Option<ShellSetting>,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            pre_switch: Vec::new(),
+            post_switch: Vec::new(),
+            on_create: Vec::new(),
+            on_delete: Vec::new(),
+            on_save: Vec::new(),
+            pre_sync: Vec::new(),
+            timeout_secs: 300,
+            on_failure: HookFailure::default(),
+            shell: None,
+        }
+    }
+}
+
+/// A hook: a shell command, or a table with the command and settings of its
+/// own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum HookEntry {
+    Command(String),
+    Detailed {
+        run: String,
+        /// Overrides `hooks.timeout_secs`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout_secs: Option<u64>,
+        /// Overrides `hooks.on_failure`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        on_failure: Option<HookFailure>,
+        /// Overrides `hooks.shell`.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        shell: Option<ShellSetting>,
+    },
+}
+
+impl HookEntry {
+    /// The shell command.
+    pub fn command(&self) -> &str {
+        match self {
+            Self::Command(run) | Self::Detailed { run, .. } => run,
+        }
+    }
+
+    /// How long the hook may run under `config`, if there is a limit.
+    pub fn timeout(&self, config: &HooksConfig) -> Option<Duration> {
+        let secs = match self {
+            Self::Detailed {
+                timeout_secs: Some(secs),
+                ..
+            } => *secs,
+            _ => config.timeout_secs,
+        };
+        (secs > 0).then(|| Duration::from_secs(secs))
+    }
+
+    /// What the hook failing does to a switch or sync under `config`.
+    pub const fn on_failure(&self, config: &HooksConfig) -> HookFailure {
+        match self {
+            Self::Detailed {
+                on_failure: Some(policy),
+                ..
+            } => *policy,
+            _ => config.on_failure,
+        }
+    }
+
+    /// The shell the hook runs in under `config` on this platform.
+    pub fn shell(&self, config: &HooksConfig) -> HookShell {
+        let own = match self {
+            Self::Detailed { shell, .. } => shell.and_then(ShellSetting::for_this_platform),
+            Self::Command(_) => None,
+        };
+        own.or_else(|| config.shell.and_then(ShellSetting::for_this_platform))
+            .unwrap_or_else(HookShell::platform_default)
+    }
+}
+
+/// A shell hook commands can run in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookShell {
+    Sh,
+    Bash,
+    /// Windows PowerShell.
+    Powershell,
+    /// PowerShell 7 and later.
+    Pwsh,
+    Cmd,
+}
+
+impl HookShell {
+    /// The shell hooks run in unless configured otherwise.
+    pub const fn platform_default() -> Self {
+        if cfg!(windows) {
+            Self::Powershell
+        } else {
+            Self::Sh
+        }
+    }
+}
+
+impl fmt::Display for HookShell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Sh => "sh",
+            Self::Bash => "bash",
+            Self::Powershell => "powershell",
+            Self::Pwsh => "pwsh",
+            Self::Cmd => "cmd",
+        })
+    }
+}
+
+/// `hooks.shell`: one shell everywhere, or one for each platform, as in
+/// `{ unix = "bash", windows = "pwsh" }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ShellSetting {
+    Everywhere(HookShell),
+    PerPlatform {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        unix: Option<HookShell>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        windows: Option<HookShell>,
+    },
+}
+
+impl ShellSetting {
+    /// The shell chosen for this platform, if any.
+    pub const fn for_this_platform(self) -> Option<HookShell> {
+        match self {
+            Self::Everywhere(shell) => Some(shell),
+            Self::PerPlatform { windows, .. } if cfg!(windows) => windows,
+            Self::PerPlatform { unix, .. } => unix,
+        }
+    }
+}
+
+/// What a failing pre-switch or pre-sync hook does to the command.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookFailure {
+    /// Stop the command.
+    #[default]
+    Abort,
+    /// Report the failure and go ahead.
+    Continue,
+    /// Ask whether to go ahead; stop when there is no terminal to ask on.
+    Prompt,
+}
+
+impl fmt::Display for HookFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Abort => "abort",
+            Self::Continue => "continue",
+            Self::Prompt => "prompt",
+        })
+    }
+}
+
+/// Loads the configuration at `path` with `profile` applied, falling back to
+/// defaults when the file does not exist.
+pub fn load_config_from(path: &Path, profile: Option<&str>) -> Result<DeskConfig, ConfigError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(source) => {
+            return Err(ConfigError::Read {
+                path: path.to_path_buf(),
+                source,
+            })
+        },
+    };
+    parse_config(path, &contents, profile)
+}
+
+/// Parses the configuration file `contents`, checking every profile in it,
+/// and returns the settings of `profile`, or the top-level ones.
+fn parse_config(
+    path: &Path,
+    contents: &str,
+    profile: Option<&str>,
+) -> Result<DeskConfig, ConfigError> {
+    #[derive(Deserialize)]
+    struct Profiles {
+        #[serde(default)]
+        profiles: BTreeMap<String, toml::Table>,
+    }
+
+    if let Some(name) = profile.filter(|name| !is_profile_name(name)) {
+        return Err(ConfigError::ProfileName {
+            path: path.to_path_buf(),
+            name: name.to_string(),
+        });
+    }
+    let parse_err = |source| ConfigError::Parse {
+        path: path.to_path_buf(),
+        source,
+    };
+    let config: DeskConfig = toml::from_str(contents).map_err(parse_err)?;
+    let Profiles { profiles } = toml::from_str(contents).map_err(parse_err)?;
+    let mut base: toml::Table = toml::from_str(contents).map_err(parse_err)?;
+    base.remove(PROFILES_KEY);
+
+    let mut selected = None;
+    for (name, overrides) in profiles {
+        if !is_profile_name(&name) {
+            return Err(ConfigError::ProfileName {
+                path: path.to_path_buf(),
+                name,
+            });
+        }
+        let mut merged = base.clone();
+        merge(&mut merged, overrides);
+        let config =
+            toml::Value::Table(merged)
+                .try_into()
+                .map_err(|source| ConfigError::Profile {
+                    path: path.to_path_buf(),
+                    name: name.clone(),
+                    source: Box::new(source),
+                })?;
+        if profile == Some(name.as_str()) {
+            selected = Some(config);
+        }
+    }
+
+    let Some(name) = profile else {
+        return Ok(config);
+    };
+    selected.ok_or_else(|| ConfigError::UnknownProfile {
+        path: path.to_path_buf(),
+        name: name.to_string(),
+    })
+}
+
+/// Profile names end up in paths, so they are kept simple.
+fn is_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Lays `overrides` over `base`: tables are merged key by key, anything else
+/// is replaced.
+fn merge(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overrides)) => {
+                merge(base, overrides);
+            },
+            (_, value) => {
+                base.insert(key, value);
+            },
+        }
+    }
+}
+
+/// Applies `edit` to the configuration file at `path`, keeping its comments
+/// and layout, and returns the resulting configuration.
+///
+/// The file is only written if the edited document is still a valid
+/// configuration.
+pub fn edit_config_at(
+    path: &Path,
+    edit: impl FnOnce(&mut DocumentMut),
+) -> Result<DeskConfig, ConfigError> {
+    let (contents, config) = apply_edit(path, None, edit)?;
+
+    let write_err = |source| ConfigError::Write {
+        path: path.to_path_buf(),
+        source,
+    };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(write_err)?;
+    }
+    let tmp = path.with_extension("toml.tmp");
+    std::fs::write(&tmp, contents).map_err(write_err)?;
+    std::fs::rename(&tmp, path).map_err(write_err)?;
+    Ok(config)
+}
+
+/// Returns the configuration [`edit_config_at`] would produce, with
+/// `profile` applied, without writing it.
+pub fn preview_config_edit_at(
+    path: &Path,
+    profile: Option<&str>,
+    edit: impl FnOnce(&mut DocumentMut),
+) -> Result<DeskConfig, ConfigError> {
+    apply_edit(path, profile, edit).map(|(_, config)| config)
+}
+
+/// Edits the file at `path` in memory, returning the new contents and the
+/// configuration they hold for `profile`.
+fn apply_edit(
+    path: &Path,
+    profile: Option<&str>,
+    edit: impl FnOnce(&mut DocumentMut),
+) -> Result<(String, DeskConfig), ConfigError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(source) => {
+            return Err(ConfigError::Read {
+                path: path.to_path_buf(),
+                source,
+            })
+        },
+    };
+    let mut doc: DocumentMut = contents.parse().map_err(|source| ConfigError::Edit {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    edit(&mut doc);
+    let contents = doc.to_string();
+    let config = parse_config(path, &contents, profile)?;
+    Ok((contents, config))
+}
+
+/// Edits the configuration file at the default location; see
+/// [`edit_config_at`].
+pub fn edit_config(edit: impl FnOnce(&mut DocumentMut)) -> anyhow::Result<DeskConfig> {
+    let path = super::paths::config_file()?;
+    Ok(edit_config_at(&path, edit)?)
+}
+
+/// Loads the configuration from the default location, with `profile`
+/// applied.
+pub fn load_config(profile: Option<&str>) -> anyhow::Result<DeskConfig> {
+    let path = super::paths::config_file()?;
+    Ok(load_config_from(&path, profile)?.with_env_overrides())
+}
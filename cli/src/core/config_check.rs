@@ -0,0 +1,434 @@
+//! Problems in the configuration that loading it lets through: settings desk
+//! does not know, malformed URLs, and options that contradict each other.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::ops::Range;
+use std::path::Path;
+
+use reqwest::Url;
+use toml_edit::{ImDocument, TableLike};
+
+use super::config::{
+    load_config_from, ApiConfig, ConfigError, DeskConfig, ShareTarget, SyncBackendKind,
+    ENV_OVERRIDES, PROFILES_KEY,
+};
+use super::hooks::{unknown_placeholders, HookStage};
+use super::naming::{self, NamingError};
+use super::sync::SyncFilter;
+use crate::client::http::ca_certificates;
+use crate::utils::expand::expand_path;
+
+/// How serious a [`Finding`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// desk runs, but probably not as intended.
+    Warning,
+    /// desk refuses to run, or the setting cannot work.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// A problem with the configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    /// Line of the file at fault, counted from 1, when the problem is in it.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// What [`check_config`] found.
+#[derive(Debug, Default)]
+pub struct ConfigReport {
+    pub findings: Vec<Finding>,
+    /// The settings in effect, environment variables included, unless the
+    /// file could not be loaded.
+    pub config: Option<DeskConfig>,
+}
+
+impl ConfigReport {
+    pub fn errors(&self) -> usize {
+        self.count(Severity::Error)
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.count(Severity::Warning)
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.findings
+            .iter()
+            .filter(|f| f.severity == severity)
+            .count()
+    }
+
+    fn push(&mut self, severity: Severity, line: Option<usize>, message: impl Into<String>) {
+        self.findings.push(Finding {
+            severity,
+            line,
+            message: message.into(),
+        });
+    }
+}
+
+/// Checks the configuration file at `path` with `profile` applied.
+///
+/// Only failing to read the file is an error; everything wrong with its
+/// contents ends up in the report.
+pub fn check_config(path: &Path, profile: Option<&str>) -> Result<ConfigReport, ConfigError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(source) => {
+            return Err(ConfigError::Read {
+                path: path.to_path_buf(),
+                source,
+            })
+        },
+    };
+    let mut report = ConfigReport::default();
+    let doc = match ImDocument::parse(contents.as_str()) {
+        Ok(doc) => doc,
+        Err(e) => {
+            report.push(
+                Severity::Error,
+                e.span().map(|span| line_at(&contents, &span)),
+                one_line(e.message()),
+            );
+            return Ok(report);
+        },
+    };
+    let file = SourceFile {
+        contents: &contents,
+        doc,
+        profile,
+    };
+
+    let config = match load_config_from(path, profile) {
+        Ok(config) => config,
+        Err(ConfigError::Parse { source, .. }) => {
+            report.push(
+                Severity::Error,
+                source.span().map(|span| line_at(&contents, &span)),
+                one_line(source.message()),
+            );
+            return Ok(report);
+        },
+        Err(ConfigError::Profile { name, source, .. }) => {
+            report.push(
+                Severity::Error,
+                file.line_of(&[PROFILES_KEY, &name]),
+                format!("profile {name}: {}", one_line(source.message())),
+            );
+            return Ok(report);
+        },
+        Err(ConfigError::ProfileName { name, .. }) => {
+            report.push(
+                Severity::Error,
+                file.line_of(&[PROFILES_KEY, &name]),
+                format!("invalid profile name '{name}'; use letters, digits, '-', and '_'"),
+            );
+            return Ok(report);
+        },
+        Err(ConfigError::UnknownProfile { name, .. }) => {
+            report.push(
+                Severity::Error,
+                None,
+                format!("no profile {name}; add a [profiles.{name}] table"),
+            );
+            return Ok(report);
+        },
+        Err(e) => return Err(e),
+    };
+
+    for key in unknown_keys(&contents) {
+        let segments: Vec<_> = key.split('.').collect();
+        report.push(
+            Severity::Warning,
+            file.line_of(&segments),
+            format!("unknown setting {key} is ignored"),
+        );
+    }
+
+    let config = config.with_env_overrides();
+    for (severity, key, message) in check_settings(&config) {
+        match ENV_OVERRIDES
+            .iter()
+            .find(|(var, setting)| *setting == key && std::env::var_os(var).is_some())
+        {
+            Some((var, _)) => report.push(severity, None, format!("{message} (set by {var})")),
+            None => report.push(severity, file.setting_line(key), message),
+        }
+    }
+    report.config = Some(config);
+    Ok(report)
+}
+
+/// The configuration file as written, for pointing at lines in it.
+struct SourceFile<'a> {
+    contents: &'a str,
+    doc: ImDocument<&'a str>,
+    profile: Option<&'a str>,
+}
+
+impl SourceFile<'_> {
+    /// Line of the setting `key` in effect: the selected profile's, or else
+    /// the top-level one.
+    fn setting_line(&self, key: &str) -> Option<usize> {
+        let segments: Vec<_> = key.split('.').collect();
+        self.profile
+            .and_then(|profile| {
+                let mut path = vec![PROFILES_KEY, profile];
+                path.extend(&segments);
+                self.line_of(&path)
+            })
+            .or_else(|| self.line_of(&segments))
+    }
+
+    /// Line of the key at `segments`, if the file has it.
+    fn line_of(&self, segments: &[&str]) -> Option<usize> {
+        let mut table: &dyn TableLike = self.doc.as_table();
+        let mut span = None;
+        for (i, segment) in segments.iter().enumerate() {
+            let (key, item) = table.get_key_value(segment)?;
+            span = key.span();
+            if i + 1 < segments.len() {
+                table = item.as_table_like()?;
+            }
+        }
+        span.map(|span| line_at(self.contents, &span))
+    }
+}
+
+/// Parser messages sometimes continue with hints on further lines.
+fn one_line(message: &str) -> String {
+    message.trim().lines().collect::<Vec<_>>().join("; ")
+}
+
+fn line_at(contents: &str, span: &Range<usize>) -> usize {
+    contents
+        .get(..span.start)
+        .map_or(0, |before| before.matches('\n').count())
+        + 1
+}
+
+/// Dotted names of the settings in `contents` that desk ignores, profiles
+/// included.
+fn unknown_keys(contents: &str) -> Vec<String> {
+    let mut unknown = Vec::new();
+    let _ =
+        serde_ignored::deserialize::<_, _, DeskConfig>(toml::Deserializer::new(contents), |path| {
+            let key = path.to_string();
+            if key != PROFILES_KEY {
+                unknown.push(key);
+            }
+        });
+    let profiles = toml::from_str::<toml::Table>(contents)
+        .ok()
+        .and_then(|mut table| table.remove(PROFILES_KEY));
+    if let Some(toml::Value::Table(profiles)) = profiles {
+        for (name, overrides) in profiles {
+            let _ = serde_ignored::deserialize::<_, _, DeskConfig>(overrides, |path| {
+                unknown.push(format!("{PROFILES_KEY}.{name}.{path}"));
+            });
+        }
+    }
+    unknown
+}
+
+/// Problems with the settings themselves, with the setting at fault.
+fn check_settings(config: &DeskConfig) -> Vec<(Severity, &'static str, String)> {
+    let mut findings = Vec::new();
+    let mut check_url = |key, url: &str| {
+        if let Err(message) = web_url(url) {
+            findings.push((Severity::Error, key, format!("{key} {message}")));
+        }
+    };
+    check_url("api.base_url", &config.api.base_url);
+    if let Some(url) = &config.share.paste_url {
+        check_url("share.paste_url", url);
+    }
+    if let Some(url) = &config.auth.providers.github.base_url {
+        check_url("auth.providers.github.base_url", url);
+    }
+    if let Some(url) = &config.issues.jira.base_url {
+        check_url("issues.jira.base_url", url);
+    }
+
+    findings.extend(check_api(&config.api));
+
+    if config.share.target == ShareTarget::Paste && config.share.paste_url.is_none() {
+        findings.push((
+            Severity::Error,
+            "share.target",
+            "share.target is \"paste\" but share.paste_url is not set".to_string(),
+        ));
+    }
+
+    let github = &config.auth.providers.github;
+    if github.base_url.is_some() && github.client_id.is_none() {
+        findings.push((
+            Severity::Warning,
+            "auth.providers.github.base_url",
+            "auth.providers.github.base_url is set without a client_id; desk's own OAuth app \
+             only exists on github.com"
+                .to_string(),
+        ));
+    }
+
+    if config.sync.backend == SyncBackendKind::Git && config.sync.git.remote.is_none() {
+        findings.push((
+            Severity::Error,
+            "sync.backend",
+            "sync.backend is \"git\" but sync.git.remote is not set".to_string(),
+        ));
+    }
+    if config.sync.encrypt_changes && !config.sync.include_changes {
+        findings.push((
+            Severity::Warning,
+            "sync.encrypt_changes",
+            "sync.encrypt_changes has no effect while sync.include_changes is off".to_string(),
+        ));
+    }
+    if let Err(e) = SyncFilter::new(&config.sync) {
+        findings.push((
+            Severity::Error,
+            "sync.exclude",
+            format!("sync.exclude has an invalid pattern: {e}"),
+        ));
+    }
+
+    findings.extend(check_placeholders(config));
+
+    for (name, provider) in &config.context {
+        if provider.run.trim().is_empty() {
+            findings.push((
+                Severity::Error,
+                "context",
+                format!("context.{name}.run is empty; give the provider's command"),
+            ));
+        }
+    }
+
+    if let Some(dir) = &config.storage.dir {
+        match expand_path(dir) {
+            Ok(path) if path.is_absolute() => {},
+            Ok(_) => findings.push((
+                Severity::Error,
+                "storage.dir",
+                format!("storage.dir '{dir}' is not an absolute path"),
+            )),
+            Err(e) => findings.push((Severity::Error, "storage.dir", format!("storage.dir: {e}"))),
+        }
+    }
+    findings
+}
+
+/// Placeholders in hooks and `open.name_template` that desk does not know.
+fn check_placeholders(config: &DeskConfig) -> Vec<(Severity, &'static str, String)> {
+    let mut findings = Vec::new();
+    for stage in HookStage::ALL {
+        for hook in stage.hooks(&config.hooks) {
+            for name in unknown_placeholders(hook.command()) {
+                findings.push((
+                    Severity::Warning,
+                    stage.setting(),
+                    format!(
+                        "{} uses unknown placeholder {{{{{name}}}}}, which is left as written; \
+                         use {{{{workspace}}}}, {{{{branch}}}}, {{{{repo}}}}, or {{{{tags}}}}",
+                        stage.setting()
+                    ),
+                ));
+            }
+        }
+    }
+
+    for name in naming::unknown_placeholders(&config.open.name_template) {
+        findings.push((
+            Severity::Error,
+            "open.name_template",
+            NamingError::UnknownPlaceholder(name.to_string()).to_string(),
+        ));
+    }
+    findings
+}
+
+/// Problems with the `[api]` settings on their own.
+fn check_api(api: &ApiConfig) -> Vec<(Severity, &'static str, String)> {
+    let mut findings = Vec::new();
+    if let Ok(url) = Url::parse(&api.base_url) {
+        let local = url.host_str().is_some_and(|host| {
+            host == "localhost"
+                || host
+                    .trim_matches(['[', ']'])
+                    .parse::<IpAddr>()
+                    .is_ok_and(|ip| ip.is_loopback())
+        });
+        if url.scheme() == "http" && !local {
+            findings.push((
+                Severity::Warning,
+                "api.base_url",
+                "api.base_url uses plain http, so credentials are sent unencrypted".to_string(),
+            ));
+        }
+    }
+    if let Some(url) = &api.proxy {
+        if let Err(e) = reqwest::Proxy::all(url) {
+            findings.push((
+                Severity::Error,
+                "api.proxy",
+                format!("api.proxy '{url}' is not a valid proxy URL: {e}"),
+            ));
+        }
+    } else if api.no_proxy.is_some() {
+        findings.push((
+            Severity::Warning,
+            "api.no_proxy",
+            "api.no_proxy has no effect without api.proxy; NO_PROXY governs HTTPS_PROXY"
+                .to_string(),
+        ));
+    }
+    if let Some(bundle) = &api.ca_bundle {
+        if let Err(e) = ca_certificates(bundle) {
+            findings.push((Severity::Error, "api.ca_bundle", error_chain(&e)));
+        }
+    }
+    if api.timeout_secs == 0 {
+        findings.push((
+            Severity::Error,
+            "api.timeout_secs",
+            "api.timeout_secs is 0, so every request times out at once".to_string(),
+        ));
+    }
+    findings
+}
+
+/// `error` and its causes on one line.
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+/// Why `url` is no use as an http(s) URL, if it is not.
+fn web_url(url: &str) -> Result<(), String> {
+    let parsed = Url::parse(url).map_err(|e| format!("'{url}' is not a valid URL: {e}"))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("'{url}' is not an http or https URL"));
+    }
+    Ok(())
+}
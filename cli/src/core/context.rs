@@ -0,0 +1,367 @@
+//! Context providers: plugins that save and restore state desk knows
+//! nothing about, such as what a service manager runs, along with each
+//! workspace.
+//!
+//! A provider is a command in a `[context.<name>]` section. desk runs it
+//! through `hooks.shell` in the checkout, writes one JSON request to its
+//! stdin, and reads one JSON response from its stdout:
+//!
+//! ```text
+//! → {"version":1,"action":"capture","workspace":"fix-login","repo":"/src/app"}
+//! ← {"state":{"services":["db","cache"]}}
+//! → {"version":1,"action":"restore","workspace":"fix-login","repo":"/src/app","state":{"services":["db","cache"]}}
+//! ← {"message":"started db and cache"}
+//! ```
+//!
+//! Whatever a provider captures is kept in the workspace, synced with it,
+//! and handed back as it was on restore; `{"state":null}` saves nothing.
+//! Providers with nothing saved are not asked to restore.
+//!
+//! One provider is built in: with `capture.compose` on, desk saves which
+//! Docker Compose override the repository's override symlink points at, and
+//! `COMPOSE_FILE` and `COMPOSE_PROFILES`, under [`COMPOSE`], re-points the
+//! symlink on restore, and hands the variables to direnv with the
+//! workspace's own. A `[context.compose]` provider takes its place.
+//!
+//! A response of `{"error":"..."}`, an unsuccessful exit, or running past
+//! `timeout_secs` counts as a failure, which is reported but never stops a
+//! switch. Providers get nothing from desk but the request: it is the only
+//! input, the response is capped at [`MAX_RESPONSE`], and the desk API token
+//! is left out of their environment.
+
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Child, ExitStatus, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use super::config::{ContextProvider, DeskConfig, HookShell, ShellSetting};
+use super::hooks::shell_process;
+use super::workspace::Workspace;
+use crate::auth::credentials::TOKEN_ENV;
+use crate::integrations::docker::{self, ComposeState};
+use crate::output::{Reporter, StepEvent, StepKind};
+
+/// Version of the request and response format.
+const PROTOCOL_VERSION: u32 = 1;
+
+/// Largest response read from a provider.
+const MAX_RESPONSE: u64 = 1024 * 1024;
+
+/// Name the built-in Docker Compose provider saves its state under.
+pub const COMPOSE: &str = "compose";
+
+/// How often a running provider is checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Errors raised by a context provider.
+#[derive(Debug, Error)]
+pub enum ContextError {
+    #[error("could not run `{command}`: {source}")]
+    Spawn {
+        command: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("exited with status {0}")]
+    Failed(i32),
+
+    #[error("was killed by a signal")]
+    Killed,
+
+    #[error("timed out after {0}s")]
+    TimedOut(u64),
+
+    #[error("answered with more than {} KiB", MAX_RESPONSE / 1024)]
+    TooLarge,
+
+    #[error("answered with something other than a JSON response: {0}")]
+    Response(#[source] serde_json::Error),
+
+    #[error("{0}")]
+    Reported(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// What a provider is asked to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Capture,
+    Restore,
+}
+
+#[derive(Debug, Serialize)]
+struct Request<'a> {
+    version: u32,
+    action: Action,
+    workspace: &'a str,
+    repo: &'a Path,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state: Option<&'a Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Response {
+    state: Option<Value>,
+    message: Option<String>,
+    error: Option<String>,
+}
+
+/// Asks every provider in `config` for its state and saves it in
+/// `workspace`, run in `checkout`. A provider that fails keeps what it saved
+/// before.
+pub fn capture(
+    config: &DeskConfig,
+    workspace: &mut Workspace,
+    checkout: &Path,
+    echo: bool,
+    reporter: &mut dyn Reporter,
+) {
+    if compose_enabled(config) {
+        capture_compose(workspace, checkout, reporter);
+    }
+    for (name, provider) in &config.context {
+        let request = Request {
+            version: PROTOCOL_VERSION,
+            action: Action::Capture,
+            workspace: &workspace.name,
+            repo: checkout,
+            state: None,
+        };
+        match call(provider, shell(config), &request, checkout, echo) {
+            Ok(response) => {
+                let message = response
+                    .message
+                    .unwrap_or_else(|| format!("Saved {name} context"));
+                match response.state.filter(|state| !state.is_null()) {
+                    Some(state) => workspace.state.context.insert(name.clone(), state),
+                    None => workspace.state.context.remove(name),
+                };
+                reporter.step(StepEvent::done(StepKind::Context, message));
+            },
+            Err(e) => reporter.step(StepEvent::failed(
+                StepKind::Context,
+                format!("Save {name} context"),
+                e.to_string(),
+            )),
+        }
+    }
+}
+
+/// Hands every provider in `config` the state it saved in `workspace`, run
+/// in `checkout`.
+pub fn restore(
+    config: &DeskConfig,
+    workspace: &Workspace,
+    checkout: &Path,
+    echo: bool,
+    reporter: &mut dyn Reporter,
+) {
+    if compose_enabled(config) {
+        restore_compose(workspace, checkout, reporter);
+    }
+    for (name, provider) in &config.context {
+        let Some(state) = workspace.state.context.get(name) else {
+            continue;
+        };
+        let request = Request {
+            version: PROTOCOL_VERSION,
+            action: Action::Restore,
+            workspace: &workspace.name,
+            repo: checkout,
+            state: Some(state),
+        };
+        match call(provider, shell(config), &request, checkout, echo) {
+            Ok(response) => reporter.step(StepEvent::done(
+                StepKind::Context,
+                response
+                    .message
+                    .unwrap_or_else(|| format!("Restored {name} context")),
+            )),
+            Err(e) => reporter.step(StepEvent::failed(
+                StepKind::Context,
+                format!("Restore {name} context"),
+                e.to_string(),
+            )),
+        }
+    }
+}
+
+/// `COMPOSE_FILE` and `COMPOSE_PROFILES` as they were when `workspace` was
+/// saved, for `.envrc.desk`.
+pub fn compose_env(workspace: &Workspace) -> Vec<(&'static str, String)> {
+    saved_compose(workspace)
+        .and_then(Result::ok)
+        .map(|state| state.env_vars())
+        .unwrap_or_default()
+}
+
+/// Whether the built-in Compose provider runs under `config`.
+fn compose_enabled(config: &DeskConfig) -> bool {
+    config.capture.compose && !config.context.contains_key(COMPOSE)
+}
+
+/// Saves the Compose setup of `checkout` in `workspace`, for repositories
+/// that use Compose at all.
+fn capture_compose(workspace: &mut Workspace, checkout: &Path, reporter: &mut dyn Reporter) {
+    let state = match ComposeState::capture(checkout) {
+        Ok(state) => state,
+        Err(e) => {
+            reporter.step(StepEvent::failed(
+                StepKind::Service,
+                "Save Compose override",
+                e.to_string(),
+            ));
+            return;
+        },
+    };
+    let used = state != ComposeState::default();
+    if !used && !docker::uses_compose(checkout) {
+        workspace.state.context.remove(COMPOSE);
+        return;
+    }
+    match serde_json::to_value(&state) {
+        Ok(value) => {
+            workspace.state.context.insert(COMPOSE.to_string(), value);
+            if let Some(active) = &state.active_override {
+                reporter.step(StepEvent::done(
+                    StepKind::Service,
+                    format!("Saved Compose override {}", active.target.display()),
+                ));
+            }
+        },
+        Err(e) => reporter.step(StepEvent::failed(
+            StepKind::Service,
+            "Save Compose override",
+            e.to_string(),
+        )),
+    }
+}
+
+/// Points the Compose override of `checkout` back at the variant saved in
+/// `workspace`, if one was saved.
+fn restore_compose(workspace: &Workspace, checkout: &Path, reporter: &mut dyn Reporter) {
+    let restored = match saved_compose(workspace) {
+        None => return,
+        Some(Ok(state)) => state.restore(checkout, reporter).map_err(|e| e.to_string()),
+        Some(Err(e)) => Err(e.to_string()),
+    };
+    if let Err(e) = restored {
+        reporter.step(StepEvent::failed(
+            StepKind::Service,
+            "Restore Compose override",
+            e,
+        ));
+    }
+}
+
+fn saved_compose(workspace: &Workspace) -> Option<Result<ComposeState, serde_json::Error>> {
+    let value = workspace.state.context.get(COMPOSE)?;
+    Some(serde_json::from_value(value.clone()))
+}
+
+/// The shell providers run in under `config`.
+fn shell(config: &DeskConfig) -> HookShell {
+    config
+        .hooks
+        .shell
+        .and_then(ShellSetting::for_this_platform)
+        .unwrap_or_else(HookShell::platform_default)
+}
+
+/// Runs `provider` in `dir` with `request` and reads its response. Its
+/// stderr is passed through when `echo`.
+fn call(
+    provider: &ContextProvider,
+    shell: HookShell,
+    request: &Request<'_>,
+    dir: &Path,
+    echo: bool,
+) -> Result<Response, ContextError> {
+    let mut input = serde_json::to_vec(request).map_err(std::io::Error::from)?;
+    input.push(b'\n');
+    let mut child = shell_process(shell, &provider.run)
+        .current_dir(dir)
+        .env_remove(TOKEN_ENV)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(if echo {
+            Stdio::inherit()
+        } else {
+            Stdio::null()
+        })
+        .spawn()
+        .map_err(|source| ContextError::Spawn {
+            command: provider.run.clone(),
+            source,
+        })?;
+
+    // Written and read on threads of their own, so that a provider busy
+    // with one end cannot block desk on the other.
+    let stdin = child.stdin.take();
+    let writer = std::thread::spawn(move || {
+        if let Some(mut stdin) = stdin {
+            // A provider may exit without reading its request.
+            let _ = stdin.write_all(&input);
+        }
+    });
+    let stdout = child.stdout.take();
+    let reader: JoinHandle<std::io::Result<Vec<u8>>> = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(stdout) = stdout {
+            stdout.take(MAX_RESPONSE + 1).read_to_end(&mut output)?;
+        }
+        Ok(output)
+    });
+
+    let status = wait(&mut child, provider.timeout())?;
+    let _ = writer.join();
+    let output = reader
+        .join()
+        .map_err(|_| std::io::Error::other("failed to read the response"))??;
+    match status.code() {
+        Some(0) => {},
+        Some(code) => return Err(ContextError::Failed(code)),
+        None => return Err(ContextError::Killed),
+    }
+    if output.len() as u64 > MAX_RESPONSE {
+        return Err(ContextError::TooLarge);
+    }
+    let response = if output.iter().all(u8::is_ascii_whitespace) {
+        Response::default()
+    } else {
+        serde_json::from_slice::<Response>(&output).map_err(ContextError::Response)?
+    };
+    match response.error {
+        Some(error) => Err(ContextError::Reported(error)),
+        None => Ok(response),
+    }
+}
+
+/// Waits for `child` to exit, killing it once `timeout` has passed.
+fn wait(child: &mut Child, timeout: Option<Duration>) -> Result<ExitStatus, ContextError> {
+    let Some(timeout) = timeout else {
+        return Ok(child.wait()?);
+    };
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(ContextError::TimedOut(timeout.as_secs()));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
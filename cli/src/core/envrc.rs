@@ -0,0 +1,151 @@
+//! The `.envrc.desk` file that hands a workspace's environment variables to
+//! direnv.
+//!
+//! desk writes the file into the checkout when a workspace is opened and
+//! removes it when it is closed. The repository's `.envrc` loads it with
+//! `source_env_if_exists`; desk creates a `.envrc` that does so if there is
+//! none. Both files are listed in `.git/info/exclude` so they never count as
+//! changes to the workspace.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use super::repo;
+
+/// File written into the checkout.
+pub const ENVRC_FILE: &str = ".envrc.desk";
+
+/// The file direnv reads, which has to load [`ENVRC_FILE`].
+const DIRENV_FILE: &str = ".envrc";
+
+/// The line in `.envrc` that loads [`ENVRC_FILE`].
+pub const SOURCE_LINE: &str = "source_env_if_exists .envrc.desk";
+
+/// Errors raised while writing the direnv files.
+#[derive(Debug, Error)]
+pub enum EnvrcError {
+    #[error("failed to write {}", path.display())]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("invalid environment variable name '{0}'")]
+    InvalidName(String),
+}
+
+/// What writing the environment did to `.envrc`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirenvHook {
+    /// `.envrc` already loads `.envrc.desk`.
+    Present,
+    /// desk created `.envrc`; direnv wants `direnv allow` before using it.
+    Created,
+    /// `.envrc` exists without loading `.envrc.desk`, and desk leaves it be.
+    Missing,
+}
+
+/// Whether `name` can be exported by a shell.
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Writes `env` for `workspace` into `checkout`, or removes the file when
+/// there is nothing to export. `None` when nothing was written.
+pub fn write(
+    checkout: &Path,
+    workspace: &str,
+    env: &BTreeMap<String, String>,
+) -> Result<Option<DirenvHook>, EnvrcError> {
+    if env.is_empty() {
+        remove(checkout)?;
+        return Ok(None);
+    }
+    // The name ends up in a comment; a line break in it would start a line
+    // that direnv runs.
+    let workspace: String = workspace
+        .chars()
+        .map(|c| if c.is_control() { '?' } else { c })
+        .collect();
+    let mut script = format!(
+        "# Environment of desk workspace '{workspace}'; desk rewrites this file,\n\
+         # change it with `desk env set {workspace} NAME=VALUE`.\n"
+    );
+    for (name, value) in env {
+        if !is_valid_name(name) {
+            return Err(EnvrcError::InvalidName(name.clone()));
+        }
+        let _ = writeln!(script, "export {name}='{}'", value.replace('\'', r"'\''"));
+    }
+    exclude(checkout, ENVRC_FILE)?;
+    write_file(&checkout.join(ENVRC_FILE), &script)?;
+    hook(checkout).map(Some)
+}
+
+/// Removes `.envrc.desk` from `checkout`, if it is there.
+pub fn remove(checkout: &Path) -> Result<(), EnvrcError> {
+    let path = checkout.join(ENVRC_FILE);
+    match std::fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(EnvrcError::Write { path, source: e })
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Makes sure `.envrc` loads `.envrc.desk`, creating it if there is none.
+fn hook(checkout: &Path) -> Result<DirenvHook, EnvrcError> {
+    let path = checkout.join(DIRENV_FILE);
+    match std::fs::read_to_string(&path) {
+        Ok(contents) if contents.contains(ENVRC_FILE) => Ok(DirenvHook::Present),
+        Ok(_) => Ok(DirenvHook::Missing),
+        Err(_) if path.exists() => Ok(DirenvHook::Missing),
+        Err(_) => {
+            exclude(checkout, DIRENV_FILE)?;
+            write_file(&path, &format!("{SOURCE_LINE}\n"))?;
+            Ok(DirenvHook::Created)
+        },
+    }
+}
+
+/// Adds `/file` to the repository's `info/exclude`, unless it is listed.
+fn exclude(checkout: &Path, file: &str) -> Result<(), EnvrcError> {
+    let git_dir = repo::git_dir(checkout);
+    // Linked worktrees share the exclude file of the main repository.
+    let common = std::fs::read_to_string(git_dir.join("commondir"))
+        .map_or_else(|_| git_dir.clone(), |dir| git_dir.join(dir.trim()));
+    let path = common.join("info").join("exclude");
+    let pattern = format!("/{file}");
+    let mut contents = std::fs::read_to_string(&path).unwrap_or_default();
+    if contents.lines().any(|line| line.trim() == pattern) {
+        return Ok(());
+    }
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(&pattern);
+    contents.push('\n');
+    let write = || {
+        std::fs::create_dir_all(common.join("info"))?;
+        std::fs::write(&path, &contents)
+    };
+    write().map_err(|source| EnvrcError::Write {
+        path: path.clone(),
+        source,
+    })
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), EnvrcError> {
+    std::fs::write(path, contents).map_err(|source| EnvrcError::Write {
+        path: path.to_path_buf(),
+        source,
+    })
+}
@@ -0,0 +1,722 @@
+//! Commands run around workspace switches, saves, creation, deletion, and
+//! sync, from `[hooks]`.
+//!
+//! Each command runs through `hooks.shell` (sh, or PowerShell on Windows, by
+//! default) in the repository root, or the current directory for workspaces
+//! outside a repository. Its output is passed through
+//! to stderr line by line, prefixed with the hook that printed it. The
+//! environment describes the event, so scripts need not ask desk:
+//!
+//! ```text
+//! DESK_EVENT               pre-open, post-open, pre-close, post-close, create,
+//!                          delete, save, pre-push, or pre-pull
+//! DESK_WORKSPACE           workspace being opened, created, deleted, or saved
+//!                          (unset when closing and syncing)
+//! DESK_PREVIOUS_WORKSPACE  workspace being left (unset if none was open)
+//! DESK_BRANCH              branch checked out as the hook runs
+//! DESK_REPO_PATH           repository root, or the directory the hook runs in
+//! DESK_TAGS                tags of DESK_WORKSPACE, comma-separated
+//! ```
+//!
+//! Commands can also use `{{workspace}}`, `{{branch}}`, `{{repo}}`, and
+//! `{{tags}}`. They are replaced with references to the variables above rather
+//! than with the values themselves, so a branch named `x;rm -rf ~` stays data:
+//! `notify-send "switched to {{workspace}}"` runs as
+//! `notify-send "switched to ${DESK_WORKSPACE}"` in sh, with `${env:...}` in
+//! PowerShell and `%...%` in cmd.
+//!
+//! A repository can also share hooks in its `.desk.toml`; they run before the
+//! user's own, once the user has trusted the file (see
+//! [`repo_config`](super::repo_config)).
+//!
+//! A hook that runs past its timeout is killed and counts as failed. Every
+//! run is appended to `hooks.log` in the data directory, output included, and
+//! the last run of each hook is kept in `hook-runs.json` for `desk hook list`.
+
+use std::fmt::{self, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, IsTerminal, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{info, warn};
+
+use super::config::{HookEntry, HookFailure, HookShell, HooksConfig};
+use super::paths;
+use super::repo_config::{RepoConfigFile, TrustedConfigs, REPO_CONFIG_FILE};
+use crate::integrations::git::Git2Operations;
+use crate::output::{notify, Reporter, StepEvent, StepKind};
+
+/// Placeholders hook commands may use, with the variable each stands for.
+pub const PLACEHOLDERS: &[(&str, &str)] = &[
+    ("workspace", "DESK_WORKSPACE"),
+    ("branch", "DESK_BRANCH"),
+    ("repo", "DESK_REPO_PATH"),
+    ("tags", "DESK_TAGS"),
+];
+
+const LOG_FILE: &str = "hooks.log";
+const RUNS_FILE: &str = "hook-runs.json";
+
+/// Size past which `hooks.log` moves to `hooks.log.1` and starts afresh.
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+/// How often a hook with a timeout is checked on.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// When a hook runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HookStage {
+    /// Before anything is saved or changed; a failure can stop the switch.
+    PreSwitch,
+    /// Once the switch is done.
+    PostSwitch,
+    /// Once `desk open` has created a new workspace.
+    OnCreate,
+    /// Once a workspace has been moved to the trash.
+    OnDelete,
+    /// Once the open workspace has been saved on the way to another.
+    OnSave,
+    /// Before `desk sync push` or `pull` contacts the backend; a failure can
+    /// stop the sync.
+    PreSync,
+}
+
+impl HookStage {
+    /// Every stage, in the order `[hooks]` lists them.
+    pub const ALL: [Self; 6] = [
+        Self::PreSwitch,
+        Self::PostSwitch,
+        Self::OnCreate,
+        Self::OnDelete,
+        Self::OnSave,
+        Self::PreSync,
+    ];
+
+    /// Whether a failing hook at this stage can stop what it runs before.
+    pub const fn can_stop(self) -> bool {
+        matches!(self, Self::PreSwitch | Self::PreSync)
+    }
+
+    /// The `[hooks]` setting listing this stage's hooks.
+    pub const fn setting(self) -> &'static str {
+        match self {
+            Self::PreSwitch => "hooks.pre_switch",
+            Self::PostSwitch => "hooks.post_switch",
+            Self::OnCreate => "hooks.on_create",
+            Self::OnDelete => "hooks.on_delete",
+            Self::OnSave => "hooks.on_save",
+            Self::PreSync => "hooks.pre_sync",
+        }
+    }
+
+    /// The hooks `config` runs at this stage.
+    pub fn hooks(self, config: &HooksConfig) -> &[HookEntry] {
+        match self {
+            Self::PreSwitch => &config.pre_switch,
+            Self::PostSwitch => &config.post_switch,
+            Self::OnCreate => &config.on_create,
+            Self::OnDelete => &config.on_delete,
+            Self::OnSave => &config.on_save,
+            Self::PreSync => &config.pre_sync,
+        }
+    }
+
+    /// `DESK_EVENT` for this stage of `context`.
+    fn event(self, context: &HookContext<'_>) -> String {
+        let action = if context.workspace.is_some() {
+            "open"
+        } else {
+            "close"
+        };
+        match self {
+            Self::PreSwitch => format!("pre-{action}"),
+            Self::PostSwitch => format!("post-{action}"),
+            Self::OnCreate => "create".to_string(),
+            Self::OnDelete => "delete".to_string(),
+            Self::OnSave => "save".to_string(),
+            Self::PreSync => format!("pre-{}", context.sync.unwrap_or("sync")),
+        }
+    }
+}
+
+impl fmt::Display for HookStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::PreSwitch => "pre-switch",
+            Self::PostSwitch => "post-switch",
+            Self::OnCreate => "on-create",
+            Self::OnDelete => "on-delete",
+            Self::OnSave => "on-save",
+            Self::PreSync => "pre-sync",
+        })
+    }
+}
+
+/// How a hook run ended.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum HookOutcome {
+    Succeeded,
+    /// Exited unsuccessfully; no code when a signal ended it.
+    Failed {
+        code: Option<i32>,
+    },
+    TimedOut {
+        after_secs: u64,
+    },
+    /// Could not be started or waited for.
+    Error {
+        message: String,
+    },
+}
+
+impl fmt::Display for HookOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Succeeded => f.write_str("succeeded"),
+            Self::Failed { code: Some(code) } => write!(f, "exited with status {code}"),
+            Self::Failed { code: None } => f.write_str("was killed by a signal"),
+            Self::TimedOut { after_secs } => write!(f, "timed out after {after_secs}s"),
+            Self::Error { message } => write!(f, "could not run: {message}"),
+        }
+    }
+}
+
+/// Errors raised by a pre-switch or pre-sync hook, which stop the command.
+#[derive(Debug, Error)]
+pub enum HookError {
+    #[error(
+        "{stage} hook `{command}` {outcome}; set hooks.on_failure = \"continue\" to go ahead anyway"
+    )]
+    Failed {
+        stage: HookStage,
+        command: String,
+        outcome: HookOutcome,
+    },
+}
+
+/// What hooks run for.
+#[derive(Debug, Clone, Copy)]
+pub struct HookContext<'a> {
+    /// Repository root, or the current directory outside a repository.
+    pub repo: &'a Path,
+    /// Workspace being left, if one is open.
+    pub previous: Option<&'a str>,
+    /// Workspace being opened, created, deleted, or saved; `None` for
+    /// `desk close` and sync.
+    pub workspace: Option<&'a str>,
+    /// Tags of `workspace`.
+    pub tags: &'a [String],
+    /// `push` or `pull` for pre-sync hooks.
+    pub sync: Option<&'a str>,
+    /// Pass the hooks' output through.
+    pub echo: bool,
+}
+
+impl<'a> HookContext<'a> {
+    /// Context for hooks about `workspace` alone.
+    pub const fn for_workspace(
+        repo: &'a Path,
+        workspace: &'a str,
+        tags: &'a [String],
+        echo: bool,
+    ) -> Self {
+        Self {
+            repo,
+            previous: None,
+            workspace: Some(workspace),
+            tags,
+            sync: None,
+            echo,
+        }
+    }
+}
+
+/// A hook's most recent run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HookRun {
+    pub stage: HookStage,
+    pub command: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub outcome: HookOutcome,
+}
+
+/// The last run of each hook, stored in `hook-runs.json`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HookRuns {
+    pub runs: Vec<HookRun>,
+}
+
+impl HookRuns {
+    /// Loads the runs recorded under `data_dir`; none if the file is missing
+    /// or unreadable.
+    pub fn load(data_dir: &Path) -> anyhow::Result<Self> {
+        let path = data_dir.join(RUNS_FILE);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        Ok(serde_json::from_str(&contents).unwrap_or_else(|e| {
+            warn!("ignoring unreadable hook runs {}: {e}", path.display());
+            Self::default()
+        }))
+    }
+
+    pub fn save(&self, data_dir: &Path) -> anyhow::Result<()> {
+        let path = data_dir.join(RUNS_FILE);
+        let tmp = path.with_extension("json.tmp");
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(&tmp, json)
+            .and_then(|()| std::fs::rename(&tmp, &path))
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// The last run of `command` at `stage`, if it has run.
+    pub fn last(&self, stage: HookStage, command: &str) -> Option<&HookRun> {
+        self.runs
+            .iter()
+            .find(|run| run.stage == stage && run.command == command)
+    }
+
+    fn record(&mut self, run: HookRun) {
+        self.runs
+            .retain(|r| r.stage != run.stage || r.command != run.command);
+        self.runs.push(run);
+    }
+}
+
+/// Where hook runs and their output are logged.
+pub fn log_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(LOG_FILE)
+}
+
+/// `hooks.log`, shared with the threads reading a hook's output.
+type SharedLog = Option<Arc<Mutex<File>>>;
+
+/// Runs the `stage` hooks in order.
+///
+/// A failing pre-switch or pre-sync hook stops the rest and fails the
+/// command, unless its `on_failure` policy lets it go on; failures at other
+/// stages are only reported, as what they run after has already happened.
+pub fn run_hooks(
+    config: &HooksConfig,
+    stage: HookStage,
+    context: &HookContext<'_>,
+    reporter: &mut dyn Reporter,
+) -> Result<(), HookError> {
+    // Keeping records is a convenience; it never gets in the way of a switch.
+    let data_dir = paths::data_dir()
+        .map_err(|e| warn!("not recording hook runs: {e:#}"))
+        .ok();
+    let shared = shared_hooks(stage, context, data_dir.as_deref(), reporter);
+    let hooks: Vec<_> = shared
+        .iter()
+        .flat_map(|shared| stage.hooks(shared).iter().map(move |hook| (shared, hook)))
+        .chain(stage.hooks(config).iter().map(|hook| (config, hook)))
+        .collect();
+    if hooks.is_empty() {
+        return Ok(());
+    }
+    let log = data_dir.as_deref().and_then(open_log);
+    let mut runs = data_dir
+        .as_deref()
+        .and_then(|dir| HookRuns::load(dir).map_err(|e| warn!("{e:#}")).ok())
+        .unwrap_or_default();
+
+    let mut result = Ok(());
+    for (config, hook) in hooks {
+        let command = hook.command();
+        let started_at = Utc::now();
+        let started = Instant::now();
+        let outcome = run_hook(hook, config, stage, context, &log);
+        info!(
+            "{stage} hook `{command}` {outcome} in {}ms",
+            started.elapsed().as_millis()
+        );
+        notify_if_slow(stage, command, &outcome, started.elapsed());
+        runs.record(HookRun {
+            stage,
+            command: command.to_string(),
+            started_at,
+            duration_ms: u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+            outcome: outcome.clone(),
+        });
+        if outcome == HookOutcome::Succeeded {
+            reporter.step(StepEvent::done(
+                StepKind::Hook,
+                format!("Ran {stage} hook `{command}`"),
+            ));
+            continue;
+        }
+        let go_ahead = !stage.can_stop()
+            || match hook.on_failure(config) {
+                HookFailure::Abort => false,
+                HookFailure::Continue => true,
+                HookFailure::Prompt => confirm(stage, command, &outcome),
+            };
+        if !go_ahead {
+            result = Err(HookError::Failed {
+                stage,
+                command: command.to_string(),
+                outcome,
+            });
+            break;
+        }
+        reporter.step(StepEvent::failed(
+            StepKind::Hook,
+            format!("Run {stage} hook `{command}`"),
+            outcome.to_string(),
+        ));
+    }
+
+    if let Some(dir) = &data_dir {
+        if let Err(e) = runs.save(dir) {
+            warn!("{e:#}");
+        }
+    }
+    result
+}
+
+/// Tells whoever started a hook that ran for `hook_after_secs` or more how it
+/// went, as they have likely turned to something else meanwhile.
+fn notify_if_slow(stage: HookStage, command: &str, outcome: &HookOutcome, elapsed: Duration) {
+    let after = notify::settings().hook_after_secs;
+    if after == 0 || elapsed < Duration::from_secs(after) {
+        return;
+    }
+    let body = match outcome {
+        HookOutcome::Succeeded => format!(
+            "{stage} hook `{command}` finished after {}s",
+            elapsed.as_secs()
+        ),
+        outcome => format!("{stage} hook `{command}` {outcome}"),
+    };
+    notify::desktop("desk", &body);
+}
+
+/// The `stage` hooks of the repository's `.desk.toml`, if it has any and they
+/// are trusted. The first time, or once the file has changed, the user is
+/// asked whether to trust it.
+fn shared_hooks(
+    stage: HookStage,
+    context: &HookContext<'_>,
+    data_dir: Option<&Path>,
+    reporter: &mut dyn Reporter,
+) -> Option<HooksConfig> {
+    let file = match RepoConfigFile::load(context.repo) {
+        Ok(file) => file?,
+        Err(e) => {
+            reporter.step(StepEvent::failed(
+                StepKind::Hook,
+                format!("Read {REPO_CONFIG_FILE}"),
+                format!("{:#}", anyhow::Error::new(e)),
+            ));
+            return None;
+        },
+    };
+    if stage.hooks(&file.config.hooks).is_empty() {
+        return None;
+    }
+    let data_dir = data_dir?;
+    let mut trusted = TrustedConfigs::load(data_dir)
+        .map_err(|e| warn!("{e:#}"))
+        .unwrap_or_default();
+    if !trusted.trusts(&file) {
+        if !confirm_trust(&file) {
+            reporter.step(StepEvent::skipped(
+                StepKind::Hook,
+                format!("Run {stage} hooks from {}", file.path.display()),
+                "not trusted; review them and run `desk hook trust`",
+            ));
+            return None;
+        }
+        trusted.trust(&file);
+        if let Err(e) = trusted.save(data_dir) {
+            warn!("{e:#}");
+        }
+    }
+    Some(file.config.hooks)
+}
+
+/// Shows the hooks of `file` and asks whether to trust them; no when nobody
+/// can answer.
+fn confirm_trust(file: &RepoConfigFile) -> bool {
+    if !std::io::stdin().is_terminal() {
+        return false;
+    }
+    eprintln!("{} has hooks that run commands:", file.path.display());
+    for stage in HookStage::ALL {
+        for hook in stage.hooks(&file.config.hooks) {
+            eprintln!("  {stage}: {}", hook.command());
+        }
+    }
+    ask("Trust them until the file changes?")
+}
+
+/// Asks whether to go ahead despite a failed hook; no when nobody can answer.
+fn confirm(stage: HookStage, command: &str, outcome: &HookOutcome) -> bool {
+    std::io::stdin().is_terminal()
+        && ask(&format!(
+            "{stage} hook `{command}` {outcome}. Continue anyway?"
+        ))
+}
+
+/// Asks a yes-or-no `question` on the terminal, taking anything but yes as no.
+fn ask(question: &str) -> bool {
+    eprint!("{question} [y/N] ");
+    let _ = std::io::stderr().flush();
+    let mut answer = String::new();
+    if std::io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+fn run_hook(
+    hook: &HookEntry,
+    config: &HooksConfig,
+    stage: HookStage,
+    context: &HookContext<'_>,
+    log: &SharedLog,
+) -> HookOutcome {
+    let command = hook.command();
+    let shell = hook.shell(config);
+    let event = stage.event(context);
+    log_line(
+        log,
+        &format!("{event} `{command}` started in {}", context.repo.display()),
+    );
+    let started = Instant::now();
+    let outcome = match shell_command(shell, command, &event, context).spawn() {
+        Ok(mut child) => {
+            let forwarders: Vec<_> = [
+                child
+                    .stdout
+                    .take()
+                    .map(|s| forward(s, stage, context.echo, log.clone())),
+                child
+                    .stderr
+                    .take()
+                    .map(|s| forward(s, stage, context.echo, log.clone())),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            wait(&mut child, hook.timeout(config), forwarders)
+        },
+        Err(e) => HookOutcome::Error {
+            message: format!("{shell}: {e}"),
+        },
+    };
+    log_line(
+        log,
+        &format!(
+            "{event} `{command}` {outcome} ({:.1}s)",
+            started.elapsed().as_secs_f64()
+        ),
+    );
+    outcome
+}
+
+/// `shell` running `command` for `event`, with the environment describing
+/// the switch.
+fn shell_command(
+    shell: HookShell,
+    command: &str,
+    event: &str,
+    context: &HookContext<'_>,
+) -> Command {
+    let mut process = shell_process(shell, &expand_placeholders(shell, command));
+    process
+        .current_dir(context.repo)
+        .env("DESK_EVENT", event)
+        .env("DESK_REPO_PATH", context.repo)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    // Unset rather than inherited from a desk run that started this one.
+    let optional = [
+        ("DESK_WORKSPACE", context.workspace.map(String::from)),
+        (
+            "DESK_PREVIOUS_WORKSPACE",
+            context.previous.map(String::from),
+        ),
+        (
+            "DESK_BRANCH",
+            Git2Operations::new(context.repo).current_branch().ok(),
+        ),
+        (
+            "DESK_TAGS",
+            (!context.tags.is_empty()).then(|| context.tags.join(",")),
+        ),
+    ];
+    for (var, value) in optional {
+        match value {
+            Some(value) => process.env(var, value),
+            None => process.env_remove(var),
+        };
+    }
+    process
+}
+
+/// `shell` set up to run `command`.
+pub fn shell_process(shell: HookShell, command: &str) -> Command {
+    let mut process = Command::new(shell.to_string());
+    match shell {
+        HookShell::Sh | HookShell::Bash => process.arg("-c"),
+        HookShell::Powershell | HookShell::Pwsh => {
+            process.args(["-NoProfile", "-NonInteractive", "-Command"])
+        },
+        HookShell::Cmd => process.arg("/C"),
+    };
+    process.arg(command);
+    process
+}
+
+/// `command` with its known placeholders replaced by references, in the
+/// syntax of `shell`, to the variables holding their values.
+fn expand_placeholders(shell: HookShell, command: &str) -> String {
+    let mut expanded = String::with_capacity(command.len());
+    let mut end = 0;
+    for (range, name) in placeholders(command) {
+        let Some((_, var)) = PLACEHOLDERS.iter().find(|(known, _)| *known == name) else {
+            continue;
+        };
+        expanded.push_str(&command[end..range.start]);
+        let _ = match shell {
+            HookShell::Sh | HookShell::Bash => write!(expanded, "${{{var}}}"),
+            HookShell::Powershell | HookShell::Pwsh => write!(expanded, "${{env:{var}}}"),
+            HookShell::Cmd => write!(expanded, "%{var}%"),
+        };
+        end = range.end;
+    }
+    expanded.push_str(&command[end..]);
+    expanded
+}
+
+/// Placeholders in `command` that are not in [`PLACEHOLDERS`], which are
+/// left as written.
+pub fn unknown_placeholders(command: &str) -> Vec<&str> {
+    placeholders(command)
+        .into_iter()
+        .map(|(_, name)| name)
+        .filter(|name| !PLACEHOLDERS.iter().any(|(known, _)| known == name))
+        .collect()
+}
+
+/// The `{{name}}` placeholders in `command`, with where each is.
+fn placeholders(command: &str) -> Vec<(Range<usize>, &str)> {
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(open) = command[from..].find("{{").map(|i| from + i) {
+        let Some(close) = command[open + 2..].find("}}").map(|i| open + 2 + i) else {
+            break;
+        };
+        found.push((open..close + 2, command[open + 2..close].trim()));
+        from = close + 2;
+    }
+    found
+}
+
+/// Waits for `child` to exit, killing it once `timeout` has passed.
+fn wait(
+    child: &mut Child,
+    timeout: Option<Duration>,
+    forwarders: Vec<JoinHandle<()>>,
+) -> HookOutcome {
+    let started = Instant::now();
+    let status: std::io::Result<ExitStatus> = loop {
+        let Some(timeout) = timeout else {
+            break child.wait();
+        };
+        match child.try_wait() {
+            Ok(Some(status)) => break Ok(status),
+            Ok(None) if started.elapsed() >= timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                // Anything the hook started may still hold its output open, so
+                // the forwarders are left to finish on their own.
+                return HookOutcome::TimedOut {
+                    after_secs: timeout.as_secs(),
+                };
+            },
+            Ok(None) => std::thread::sleep(POLL_INTERVAL),
+            Err(e) => break Err(e),
+        }
+    };
+    for forwarder in forwarders {
+        let _ = forwarder.join();
+    }
+    match status {
+        Ok(status) if status.success() => HookOutcome::Succeeded,
+        Ok(status) => HookOutcome::Failed {
+            code: status.code(),
+        },
+        Err(e) => HookOutcome::Error {
+            message: e.to_string(),
+        },
+    }
+}
+
+/// Copies `output` to stderr a line at a time, prefixed with `stage`, and to
+/// the log. Output still has to be read when it is not shown, or the hook
+/// could block on a full pipe.
+fn forward(
+    output: impl Read + Send + 'static,
+    stage: HookStage,
+    echo: bool,
+    log: SharedLog,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(output);
+        let mut line = Vec::new();
+        while matches!(reader.read_until(b'\n', &mut line), Ok(read) if read > 0) {
+            let text = String::from_utf8_lossy(&line);
+            let text = text.trim_end_matches(['\r', '\n']);
+            if echo {
+                eprintln!("{stage} | {text}");
+            }
+            log_line(&log, &format!("{stage} | {text}"));
+            line.clear();
+        }
+    })
+}
+
+/// Opens `hooks.log` for appending, first moving it aside if it has grown
+/// past [`MAX_LOG_SIZE`].
+fn open_log(data_dir: &Path) -> SharedLog {
+    let path = log_path(data_dir);
+    if std::fs::metadata(&path).is_ok_and(|m| m.len() > MAX_LOG_SIZE) {
+        let _ = std::fs::rename(&path, path.with_extension("log.1"));
+    }
+    let opened = std::fs::create_dir_all(data_dir)
+        .and_then(|()| OpenOptions::new().create(true).append(true).open(&path));
+    match opened {
+        Ok(file) => Some(Arc::new(Mutex::new(file))),
+        Err(e) => {
+            warn!("not logging hooks to {}: {e}", path.display());
+            None
+        },
+    }
+}
+
+fn log_line(log: &SharedLog, line: &str) {
+    let Some(log) = log else {
+        return;
+    };
+    if let Ok(mut file) = log.lock() {
+        let _ = writeln!(file, "{} {line}", Utc::now().format("%Y-%m-%dT%H:%M:%SZ"));
+    }
+}
@@ -0,0 +1,212 @@
+//! Advisory locks that serialize mutating desk invocations.
+//!
+//! Two `desk open` runs in different terminals (or a hook that calls desk
+//! recursively) would otherwise interleave stash/switch operations and state
+//! writes. Mutating commands take a lock on the data directory and, when run
+//! inside a repository, on that repository's git directory. Locks are released
+//! when the guard is dropped, and by the OS if the process dies.
+//!
+//! OS advisory locks are unreliable on network filesystems, so there the lock
+//! is an exclusively-created file instead. Such a file survives a crash and has
+//! to be removed by hand, which the error message points out.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use fs4::fs_std::FileExt;
+use thiserror::Error;
+use tracing::debug;
+
+use crate::core::repo::git_dir;
+use crate::utils::platform::FsCapabilities;
+
+/// Name of the lock file inside the data directory and the git directory.
+pub const LOCK_FILE: &str = "desk.lock";
+
+/// Errors raised while acquiring a lock.
+#[derive(Debug, Error)]
+pub enum LockError {
+    #[error(
+        "another desk process{} is running (lock held on {path}); wait for it to finish and retry",
+        pid.map(|p| format!(" (pid {p})")).unwrap_or_default()
+    )]
+    Held { path: PathBuf, pid: Option<u32> },
+
+    #[error(
+        "another desk process{} is running (lock file {path} exists); \
+         if no desk process is running, delete the file and retry",
+        pid.map(|p| format!(" (pid {p})")).unwrap_or_default()
+    )]
+    Stale { path: PathBuf, pid: Option<u32> },
+
+    #[error("failed to open lock file {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// How a [`FileLock`] excludes other processes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockStrategy {
+    /// An OS advisory lock on the file.
+    Advisory,
+    /// Existence of the file itself, created with `O_EXCL` semantics.
+    ExclusiveCreate,
+}
+
+impl LockStrategy {
+    /// Picks the strategy that works on the filesystem holding `dir`.
+    pub fn for_dir(dir: &Path) -> Self {
+        if FsCapabilities::detect(dir).reliable_locking() {
+            Self::Advisory
+        } else {
+            Self::ExclusiveCreate
+        }
+    }
+}
+
+/// An exclusive lock on a single file.
+#[derive(Debug)]
+pub struct FileLock {
+    path: PathBuf,
+    strategy: LockStrategy,
+    _file: File,
+}
+
+impl FileLock {
+    /// Tries to take the lock at `path` without blocking, choosing the
+    /// strategy from the filesystem it lives on.
+    pub fn try_acquire(path: &Path) -> Result<Self, LockError> {
+        let io_err = |source| LockError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+
+        let parent = path.parent().unwrap_or_else(|| Path::new("."));
+        std::fs::create_dir_all(parent).map_err(io_err)?;
+
+        match LockStrategy::for_dir(parent) {
+            LockStrategy::Advisory => Self::advisory(path),
+            LockStrategy::ExclusiveCreate => Self::exclusive_create(path),
+        }
+    }
+
+    fn advisory(path: &Path) -> Result<Self, LockError> {
+        let io_err = |source| LockError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(io_err)?;
+
+        if !file.try_lock_exclusive().map_err(io_err)? {
+            return Err(LockError::Held {
+                path: path.to_path_buf(),
+                pid: read_pid(&mut file),
+            });
+        }
+
+        // Record the owner so a blocked process can say who holds the lock.
+        file.set_len(0).map_err(io_err)?;
+        write_pid(&mut file).map_err(io_err)?;
+
+        debug!("acquired advisory lock {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+            strategy: LockStrategy::Advisory,
+            _file: file,
+        })
+    }
+
+    fn exclusive_create(path: &Path) -> Result<Self, LockError> {
+        let mut file = match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                let pid = File::open(path).ok().and_then(|mut f| read_pid(&mut f));
+                return Err(LockError::Stale {
+                    path: path.to_path_buf(),
+                    pid,
+                });
+            },
+            Err(source) => {
+                return Err(LockError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                })
+            },
+        };
+
+        write_pid(&mut file).map_err(|source| LockError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        debug!("acquired lock file {}", path.display());
+        Ok(Self {
+            path: path.to_path_buf(),
+            strategy: LockStrategy::ExclusiveCreate,
+            _file: file,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub const fn strategy(&self) -> LockStrategy {
+        self.strategy
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        if self.strategy == LockStrategy::ExclusiveCreate {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Locks held for the duration of a mutating command.
+#[derive(Debug)]
+pub struct MutationGuard {
+    _data: FileLock,
+    _repo: Option<FileLock>,
+}
+
+impl MutationGuard {
+    /// Locks the data directory and, if given, the repository at `repo_root`.
+    ///
+    /// The data directory is always locked first so concurrent processes
+    /// acquire locks in the same order.
+    pub fn acquire(data_dir: &Path, repo_root: Option<&Path>) -> Result<Self, LockError> {
+        let data = FileLock::try_acquire(&data_dir.join(LOCK_FILE))?;
+        let repo = repo_root
+            .map(|root| FileLock::try_acquire(&git_dir(root).join(LOCK_FILE)))
+            .transpose()?;
+        Ok(Self {
+            _data: data,
+            _repo: repo,
+        })
+    }
+}
+
+fn write_pid(file: &mut File) -> std::io::Result<()> {
+    write!(file, "{}", std::process::id())?;
+    file.flush()
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    contents.trim().parse().ok()
+}
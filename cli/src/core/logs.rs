@@ -0,0 +1,176 @@
+//! desk's own log: the commands it ran, the hooks they ran, and what went
+//! wrong, written to `logs/desk.log` in the data directory so that `desk
+//! logs` can show what a background sync did yesterday.
+//!
+//! Once the file grows past `logs.max_size_mib` it moves to `desk.log.1`,
+//! pushing older files along and dropping the one past `logs.keep`. Each
+//! event is appended with a single write, so desks running at the same time
+//! do not mix up their lines.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use tracing::level_filters::LevelFilter;
+use tracing::{Metadata, Subscriber};
+use tracing_subscriber::filter::DynFilterFn;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use super::config::{LogLevel, LogsConfig};
+use super::paths;
+
+const LOG_DIR: &str = "logs";
+const LOG_FILE: &str = "desk.log";
+
+/// Events from other crates are kept to warnings and errors, however much
+/// `logs.level` asks for.
+const OTHER_CRATES: LevelFilter = LevelFilter::WARN;
+
+struct Settings {
+    path: PathBuf,
+    level: LevelFilter,
+    max_size: u64,
+    keep: u32,
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Applies the `[logs]` settings, starting the log file. Called once, right
+/// after the configuration is loaded; nothing is logged before.
+pub fn configure(config: &LogsConfig) {
+    let Ok(data_dir) = paths::data_dir() else {
+        return;
+    };
+    let _ = SETTINGS.set(Settings {
+        path: log_path(&data_dir),
+        level: level_filter(config.level),
+        max_size: config.max_size_mib.saturating_mul(1024 * 1024),
+        keep: config.keep,
+    });
+}
+
+/// The log file under `data_dir`.
+pub fn log_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(LOG_DIR).join(LOG_FILE)
+}
+
+/// The log files under `data_dir` that were rotated out, newest first.
+pub fn rotated_paths(data_dir: &Path) -> Vec<PathBuf> {
+    let current = log_path(data_dir);
+    (1..=u32::MAX)
+        .map(|n| rotated(&current, n))
+        .take_while(|path| path.is_file())
+        .collect()
+}
+
+/// The layer writing events to the log file.
+pub fn layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    tracing_subscriber::fmt::layer()
+        .with_ansi(false)
+        .with_writer(LogFile)
+        .with_filter(DynFilterFn::new(|metadata, _| enabled(metadata)))
+}
+
+/// Whether an event or span belongs in the log file; never before
+/// [`configure`].
+fn enabled(metadata: &Metadata<'_>) -> bool {
+    let Some(settings) = SETTINGS.get() else {
+        return false;
+    };
+    let level = if metadata.target().starts_with("desk") {
+        settings.level
+    } else {
+        settings.level.min(OTHER_CRATES)
+    };
+    *metadata.level() <= level
+}
+
+const fn level_filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Off => LevelFilter::OFF,
+        LogLevel::Error => LevelFilter::ERROR,
+        LogLevel::Warn => LevelFilter::WARN,
+        LogLevel::Info => LevelFilter::INFO,
+        LogLevel::Debug => LevelFilter::DEBUG,
+        LogLevel::Trace => LevelFilter::TRACE,
+    }
+}
+
+/// Hands out an [`Event`] writer for each event.
+struct LogFile;
+
+impl<'a> MakeWriter<'a> for LogFile {
+    type Writer = Event;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        Event(Vec::new())
+    }
+}
+
+/// One formatted event, appended to the log file once it is complete.
+struct Event(Vec<u8>);
+
+impl Write for Event {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Event {
+    fn drop(&mut self) {
+        if let Some(settings) = SETTINGS.get() {
+            // Nowhere left to report a log that cannot be written.
+            let _ = append(settings, &self.0);
+        }
+    }
+}
+
+fn append(settings: &Settings, event: &[u8]) -> std::io::Result<()> {
+    if event.is_empty() {
+        return Ok(());
+    }
+    let path = &settings.path;
+    if std::fs::metadata(path).is_ok_and(|m| m.len() >= settings.max_size) {
+        rotate(path, settings.keep)?;
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(event)
+}
+
+/// Moves `path` to `path.1`, `path.1` to `path.2`, and so on, dropping the
+/// file past `keep`.
+fn rotate(path: &Path, keep: u32) -> std::io::Result<()> {
+    if keep == 0 {
+        return std::fs::remove_file(path);
+    }
+    for n in (1..keep).rev() {
+        let from = rotated(path, n);
+        if from.is_file() {
+            std::fs::rename(&from, rotated(path, n + 1))?;
+        }
+    }
+    std::fs::rename(path, rotated(path, 1))
+}
+
+fn rotated(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
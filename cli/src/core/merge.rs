@@ -0,0 +1,370 @@
+//! Field-level three-way merging of workspaces changed on two machines.
+//!
+//! Both copies are compared against the base, the workspace as it was at the
+//! last sync. A field changed on one side only takes that side's value.
+//! Tags, notes, and to-do items are merged item by item, so additions and
+//! removals from both sides survive. Git state (branch, commit, stash) describes one
+//! checkout and cannot be combined, so it comes from whichever copy was
+//! updated last, as do other fields changed differently on both sides.
+
+use chrono::Utc;
+
+use super::workspace::{TodoItem, Workspace};
+
+/// Which copy a conflicting field was taken from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Local,
+    Remote,
+}
+
+impl Side {
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Local => "local",
+            Self::Remote => "remote",
+        }
+    }
+}
+
+/// A field both sides changed to different values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldConflict {
+    pub field: &'static str,
+    /// The side whose value was kept.
+    pub kept: Side,
+}
+
+/// Result of [`merge`].
+#[derive(Debug, Clone)]
+pub struct Merged {
+    pub workspace: Workspace,
+    pub conflicts: Vec<FieldConflict>,
+}
+
+/// Merges `local` and `remote`, both descended from `base`.
+///
+/// The result keeps the local repository path and worktree, and is stamped
+/// with the current time so it counts as a local change.
+pub fn merge(base: &Workspace, local: &Workspace, remote: &Workspace) -> Merged {
+    let newer = if remote.updated_at > local.updated_at {
+        Side::Remote
+    } else {
+        Side::Local
+    };
+    let mut conflicts = Vec::new();
+    let mut workspace = local.clone();
+
+    workspace.description = merge_value(
+        "description",
+        &base.description,
+        &local.description,
+        &remote.description,
+        newer,
+        &mut conflicts,
+    );
+    workspace.metadata.time_budget_secs = merge_value(
+        "time budget",
+        &base.metadata.time_budget_secs,
+        &local.metadata.time_budget_secs,
+        &remote.metadata.time_budget_secs,
+        newer,
+        &mut conflicts,
+    );
+    workspace.metadata.locked = merge_value(
+        "lock",
+        &base.metadata.locked,
+        &local.metadata.locked,
+        &remote.metadata.locked,
+        newer,
+        &mut conflicts,
+    );
+    workspace.metadata.env = merge_value(
+        "environment",
+        &base.metadata.env,
+        &local.metadata.env,
+        &remote.metadata.env,
+        newer,
+        &mut conflicts,
+    );
+    workspace.metadata.issue = merge_value(
+        "issue",
+        &base.metadata.issue,
+        &local.metadata.issue,
+        &remote.metadata.issue,
+        newer,
+        &mut conflicts,
+    );
+    workspace.metadata.tags = merge_lists(
+        &base.metadata.tags,
+        &local.metadata.tags,
+        &remote.metadata.tags,
+    );
+    workspace.metadata.notes = merge_notes(
+        base.metadata.notes.as_deref(),
+        local.metadata.notes.as_deref(),
+        remote.metadata.notes.as_deref(),
+    );
+    workspace.metadata.todos = merge_todos(
+        &base.metadata.todos,
+        &local.metadata.todos,
+        &remote.metadata.todos,
+    );
+    // Each machine adds the sessions spent on it; neither count may shrink.
+    workspace.metadata.time_spent_secs = local
+        .metadata
+        .time_spent_secs
+        .max(remote.metadata.time_spent_secs);
+
+    let (branch, commit, stash_name) = merge_value(
+        "git state",
+        &git_state(base),
+        &git_state(local),
+        &git_state(remote),
+        newer,
+        &mut conflicts,
+    );
+    workspace.state.branch = branch;
+    workspace.state.commit = commit;
+    workspace.state.stash_name = stash_name;
+    workspace.state.context = merge_value(
+        "context",
+        &base.state.context,
+        &local.state.context,
+        &remote.state.context,
+        newer,
+        &mut conflicts,
+    );
+
+    workspace.created_at = local.created_at.min(remote.created_at);
+    workspace.updated_at = Utc::now();
+    Merged {
+        workspace,
+        conflicts,
+    }
+}
+
+/// The name the remote copy of workspace `name` is kept under next to the
+/// local one with `--keep-both`: `name (remote)`, or `name (remote 2)` and
+/// up when `exists` says that is taken.
+pub fn remote_copy_name(name: &str, exists: impl Fn(&str) -> bool) -> String {
+    let mut copy = format!("{name} (remote)");
+    let mut n = 1;
+    while exists(&copy) {
+        n += 1;
+        copy = format!("{name} (remote {n})");
+    }
+    copy
+}
+
+/// Three-way merge of a single value; differing changes on both sides keep
+/// the `newer` side's value.
+fn merge_value<T: Clone + PartialEq>(
+    field: &'static str,
+    base: &T,
+    local: &T,
+    remote: &T,
+    newer: Side,
+    conflicts: &mut Vec<FieldConflict>,
+) -> T {
+    if local == remote || remote == base {
+        return local.clone();
+    }
+    if local == base {
+        return remote.clone();
+    }
+    conflicts.push(FieldConflict { field, kept: newer });
+    match newer {
+        Side::Local => local.clone(),
+        Side::Remote => remote.clone(),
+    }
+}
+
+/// Keeps local items unless the remote removed them, then appends items the
+/// remote added.
+fn merge_lists(base: &[String], local: &[String], remote: &[String]) -> Vec<String> {
+    let mut merged: Vec<String> = local
+        .iter()
+        .filter(|item| remote.contains(item) || !base.contains(item))
+        .cloned()
+        .collect();
+    for item in remote {
+        if !base.contains(item) && !merged.contains(item) {
+            merged.push(item.clone());
+        }
+    }
+    merged
+}
+
+/// Merges to-do lists with [`merge_lists`] on their text; an item ticked
+/// off or reopened on one side only takes that side's state.
+fn merge_todos(base: &[TodoItem], local: &[TodoItem], remote: &[TodoItem]) -> Vec<TodoItem> {
+    let texts = |items: &[TodoItem]| -> Vec<String> {
+        items.iter().map(|item| item.text.clone()).collect()
+    };
+    let done = |items: &[TodoItem], text: &str| {
+        items
+            .iter()
+            .find(|item| item.text == text)
+            .map(|item| item.done)
+    };
+    merge_lists(&texts(base), &texts(local), &texts(remote))
+        .into_iter()
+        .map(|text| {
+            let (base, local, remote) =
+                (done(base, &text), done(local, &text), done(remote, &text));
+            let done = match (local, remote) {
+                (Some(local), Some(remote)) if Some(local) == base => remote,
+                (Some(local), _) => local,
+                (None, remote) => remote.unwrap_or(false),
+            };
+            TodoItem { text, done }
+        })
+        .collect()
+}
+
+/// The checkout a workspace describes; the worktree location is local to
+/// each machine and never merged.
+fn git_state(workspace: &Workspace) -> (String, String, Option<String>) {
+    let state = &workspace.state;
+    (
+        state.branch.clone(),
+        state.commit.clone(),
+        state.stash_name.clone(),
+    )
+}
+
+/// Merges notes line by line with [`merge_lists`] when both sides edited them.
+fn merge_notes(base: Option<&str>, local: Option<&str>, remote: Option<&str>) -> Option<String> {
+    if local == remote || remote == base {
+        return local.map(String::from);
+    }
+    if local == base {
+        return remote.map(String::from);
+    }
+    let lines = |text: Option<&str>| -> Vec<String> {
+        text.map(|t| t.lines().map(String::from).collect())
+            .unwrap_or_default()
+    };
+    let merged = merge_lists(&lines(base), &lines(local), &lines(remote));
+    if merged.is_empty() {
+        None
+    } else {
+        Some(merged.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::Duration;
+
+    use super::{merge, remote_copy_name, FieldConflict, Side};
+    use crate::core::workspace::{TodoItem, Workspace, WorkspaceState};
+
+    fn base() -> Workspace {
+        let mut workspace = Workspace::new(
+            "feat",
+            PathBuf::from("/repo"),
+            WorkspaceState {
+                branch: "feat".to_string(),
+                commit: "abc".to_string(),
+                ..WorkspaceState::default()
+            },
+        );
+        workspace.metadata.tags = vec!["a".to_string(), "b".to_string()];
+        workspace.metadata.todos = vec![TodoItem {
+            text: "test".to_string(),
+            done: false,
+        }];
+        workspace
+    }
+
+    /// A copy of `base` changed by `change`, saved `minutes` after it.
+    fn edited(base: &Workspace, minutes: i64, change: impl FnOnce(&mut Workspace)) -> Workspace {
+        let mut workspace = base.clone();
+        change(&mut workspace);
+        workspace.updated_at = base.updated_at + Duration::minutes(minutes);
+        workspace
+    }
+
+    #[test]
+    fn merges_changes_to_different_fields() {
+        let base = base();
+        let local = edited(&base, 1, |ws| {
+            ws.description = Some("local".to_string());
+            ws.metadata.tags.retain(|tag| tag != "a");
+            ws.metadata.todos[0].done = true;
+        });
+        let remote = edited(&base, 2, |ws| {
+            ws.state.commit = "def".to_string();
+            ws.metadata.tags.push("c".to_string());
+            ws.metadata.notes = Some("from remote".to_string());
+        });
+
+        let merged = merge(&base, &local, &remote);
+        assert_eq!(merged.conflicts, []);
+        let ws = merged.workspace;
+        assert_eq!(ws.description.as_deref(), Some("local"));
+        assert_eq!(ws.state.commit, "def");
+        assert_eq!(ws.metadata.tags, ["b", "c"]);
+        assert_eq!(ws.metadata.notes.as_deref(), Some("from remote"));
+        assert!(ws.metadata.todos[0].done);
+    }
+
+    #[test]
+    fn keeps_the_newer_side_of_a_conflict() {
+        let base = base();
+        let local = edited(&base, 2, |ws| ws.description = Some("local".to_string()));
+        let remote = edited(&base, 1, |ws| {
+            ws.description = Some("remote".to_string());
+            ws.state.branch = "other".to_string();
+        });
+
+        let merged = merge(&base, &local, &remote);
+        assert_eq!(
+            merged.conflicts,
+            [FieldConflict {
+                field: "description",
+                kept: Side::Local,
+            }]
+        );
+        assert_eq!(merged.workspace.description.as_deref(), Some("local"));
+        assert_eq!(merged.workspace.state.branch, "other");
+
+        let remote = edited(&base, 3, |ws| ws.description = Some("remote".to_string()));
+        let merged = merge(&base, &local, &remote);
+        assert_eq!(merged.conflicts[0].kept, Side::Remote);
+        assert_eq!(merged.workspace.description.as_deref(), Some("remote"));
+    }
+
+    #[test]
+    fn merges_notes_edited_on_both_sides_line_by_line() {
+        let mut base = base();
+        base.metadata.notes = Some("one\ntwo".to_string());
+        let local = edited(&base, 1, |ws| {
+            ws.metadata.notes = Some("one\ntwo\nlocal".to_string());
+        });
+        let remote = edited(&base, 2, |ws| {
+            ws.metadata.notes = Some("two\nremote".to_string());
+        });
+
+        let merged = merge(&base, &local, &remote);
+        assert_eq!(merged.conflicts, []);
+        assert_eq!(
+            merged.workspace.metadata.notes.as_deref(),
+            Some("two\nlocal\nremote")
+        );
+    }
+
+    #[test]
+    fn names_remote_copies_after_the_first_free_name() {
+        assert_eq!(remote_copy_name("feat", |_| false), "feat (remote)");
+        let taken = ["feat (remote)", "feat (remote 2)"];
+        assert_eq!(
+            remote_copy_name("feat", |name| taken.contains(&name)),
+            "feat (remote 3)"
+        );
+    }
+}
@@ -0,0 +1,101 @@
+//! Versioned on-disk formats and upgrades between them.
+//!
+//! Workspace files and state files carry a `schema_version`. Loading runs
+//! every migration between the stored version and the current one on the raw
+//! JSON before deserializing, so renamed or restructured fields are carried
+//! over instead of silently dropped. Files written by a newer desk are refused
+//! rather than loaded lossily and then overwritten.
+//!
+//! To change a format, bump its version constant and append a migration that
+//! rewrites documents from the previous version.
+
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+/// Current version of workspace files.
+pub const WORKSPACE_SCHEMA_VERSION: u32 = 1;
+
+/// Current version of `state.json` and the files in `repo-state/`.
+pub const STATE_SCHEMA_VERSION: u32 = 2;
+
+/// Key holding the version in every versioned document.
+const VERSION_KEY: &str = "schema_version";
+
+/// Rewrites a document from version `n` to `n + 1`.
+type Migration = fn(&mut Map<String, Value>);
+
+/// `WORKSPACE_MIGRATIONS[n]` upgrades a workspace from version `n`.
+const WORKSPACE_MIGRATIONS: [Migration; WORKSPACE_SCHEMA_VERSION as usize] = [introduce_version];
+
+/// `STATE_MIGRATIONS[n]` upgrades state from version `n`.
+const STATE_MIGRATIONS: [Migration; STATE_SCHEMA_VERSION as usize] =
+    [introduce_version, split_repos];
+
+/// Errors raised while upgrading a document.
+#[derive(Debug, Error)]
+pub enum MigrationError {
+    #[error(
+        "{kind} was written by a newer version of desk (schema {found}, this desk supports {supported}); \
+         upgrade desk to use it"
+    )]
+    TooNew {
+        kind: &'static str,
+        found: u64,
+        supported: u32,
+    },
+
+    #[error("{0} is not a JSON object")]
+    NotAnObject(&'static str),
+}
+
+/// Upgrades a workspace document to [`WORKSPACE_SCHEMA_VERSION`].
+pub fn migrate_workspace(doc: Value) -> Result<Value, MigrationError> {
+    migrate(doc, "workspace file", &WORKSPACE_MIGRATIONS)
+}
+
+/// Upgrades a state document to [`STATE_SCHEMA_VERSION`].
+pub fn migrate_state(doc: Value) -> Result<Value, MigrationError> {
+    migrate(doc, "state file", &STATE_MIGRATIONS)
+}
+
+fn migrate(
+    doc: Value,
+    kind: &'static str,
+    migrations: &[Migration],
+) -> Result<Value, MigrationError> {
+    let Value::Object(mut map) = doc else {
+        return Err(MigrationError::NotAnObject(kind));
+    };
+
+    // Documents from before versioning have no version key.
+    let found = map.get(VERSION_KEY).and_then(Value::as_u64).unwrap_or(0);
+    let supported = migrations.len();
+    let Ok(start) = usize::try_from(found) else {
+        return Err(too_new(kind, found, supported));
+    };
+    if start > supported {
+        return Err(too_new(kind, found, supported));
+    }
+
+    for (version, migration) in migrations.iter().enumerate().skip(start) {
+        migration(&mut map);
+        map.insert(VERSION_KEY.to_string(), Value::from(version + 1));
+    }
+    Ok(Value::Object(map))
+}
+
+fn too_new(kind: &'static str, found: u64, supported: usize) -> MigrationError {
+    MigrationError::TooNew {
+        kind,
+        found,
+        supported: u32::try_from(supported).unwrap_or(u32::MAX),
+    }
+}
+
+/// Version 1 introduced `schema_version` itself; nothing else changed.
+const fn introduce_version(_doc: &mut Map<String, Value>) {}
+
+/// Version 2 moved each repository's open workspace and history out of
+/// `state.json` into its own file. The old fields are still read, and move
+/// out the next time state is saved.
+const fn split_repos(_doc: &mut Map<String, Value>) {}
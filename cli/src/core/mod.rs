@@ -0,0 +1,35 @@
+//! Core business logic: configuration, on-disk locations, and state.
+
+pub mod backup;
+pub mod budget;
+pub mod changes;
+pub mod config;
+pub mod config_check;
+pub mod context;
+pub mod envrc;
+pub mod hooks;
+pub mod lock;
+pub mod logs;
+pub mod merge;
+pub mod migrations;
+pub mod naming;
+pub mod paths;
+pub mod reminders;
+pub mod remote_cache;
+pub mod repo;
+pub mod repo_config;
+pub mod search;
+pub mod snapshot;
+pub mod standup;
+pub mod state;
+pub mod stats;
+pub mod status_cache;
+pub mod store;
+pub mod suggest;
+pub mod switch;
+pub mod sync;
+pub mod time_log;
+pub mod trash;
+pub mod usage;
+pub mod workspace;
+pub mod worktree;
@@ -0,0 +1,214 @@
+//! Workspace names worked out from the checked-out branch, for `desk open
+//! --auto`, as `open.name_template` lays them out.
+//!
+//! The template may use `{branch}`, the branch with `/` turned into `-`;
+//! `{ticket}`, the ticket key or issue number the branch starts its last
+//! part with, like `PROJ-123` in `feature/proj-123-fix-login` or `42` in
+//! `42-fix-login`; and `{slug}`, what follows it, like `fix-login`. On a
+//! branch without a ticket `{ticket}` and `{slug}` are empty, and the dashes
+//! left around them at either end are dropped. Placeholders may also be
+//! written `{{branch}}`, as in hook commands.
+
+use thiserror::Error;
+
+use crate::integrations::issues::slug;
+
+/// Placeholders `open.name_template` may use.
+pub const PLACEHOLDERS: [&str; 3] = ["branch", "ticket", "slug"];
+
+/// Errors raised while naming a workspace after a branch.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum NamingError {
+    #[error(
+        "open.name_template uses unknown placeholder {{{0}}}; use {{branch}}, {{ticket}}, or \
+         {{slug}}"
+    )]
+    UnknownPlaceholder(String),
+
+    #[error(
+        "branch '{0}' has no ticket for open.name_template's {{ticket}} and {{slug}}; pass a \
+         name instead"
+    )]
+    NoTicket(String),
+
+    #[error("open.name_template makes an empty name of branch '{0}'; pass a name instead")]
+    Empty(String),
+}
+
+/// A piece of a template: text kept as written, or a placeholder's name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Part<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+/// The name `template` gives a workspace on `branch`.
+pub fn workspace_name(template: &str, branch: &str) -> Result<String, NamingError> {
+    let whole = branch.replace(['/', '\\'], "-");
+    let ticket = ticket(branch);
+    let mut name = String::new();
+    let mut wants_ticket = false;
+    for part in parts(template) {
+        match part {
+            Part::Text(text) => name.push_str(text),
+            Part::Placeholder("branch") => name.push_str(&whole),
+            Part::Placeholder(placeholder @ ("ticket" | "slug")) => {
+                wants_ticket = true;
+                if let Some((key, rest)) = &ticket {
+                    name.push_str(&if placeholder == "ticket" {
+                        key.clone()
+                    } else {
+                        slug(rest)
+                    });
+                }
+            },
+            Part::Placeholder(unknown) => {
+                return Err(NamingError::UnknownPlaceholder(unknown.to_string()));
+            },
+        }
+    }
+    let name = name.trim_matches(|c: char| c == '-' || c.is_whitespace());
+    if name.is_empty() {
+        return Err(if wants_ticket && ticket.is_none() {
+            NamingError::NoTicket(branch.to_string())
+        } else {
+            NamingError::Empty(branch.to_string())
+        });
+    }
+    Ok(name.to_string())
+}
+
+/// Placeholders in `template` that are not in [`PLACEHOLDERS`].
+pub fn unknown_placeholders(template: &str) -> Vec<&str> {
+    parts(template)
+        .into_iter()
+        .filter_map(|part| match part {
+            Part::Placeholder(name) if !PLACEHOLDERS.contains(&name) => Some(name),
+            _ => None,
+        })
+        .collect()
+}
+
+/// `template` split into text and placeholders, written `{name}` or
+/// `{{name}}`. A brace that is never closed is kept as text.
+fn parts(template: &str) -> Vec<Part<'_>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let (opening, closing) = if rest[open..].starts_with("{{") {
+            ("{{", "}}")
+        } else {
+            ("{", "}")
+        };
+        let start = open + opening.len();
+        let Some(len) = rest[start..].find(closing) else {
+            break;
+        };
+        if open > 0 {
+            parts.push(Part::Text(&rest[..open]));
+        }
+        parts.push(Part::Placeholder(&rest[start..start + len]));
+        rest = &rest[start + len + closing.len()..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    parts
+}
+
+/// The ticket the last part of `branch` starts with, and the rest of it:
+/// a key like `PROJ-123` (uppercased), or an issue number like `42` or
+/// `issue-42`.
+fn ticket(branch: &str) -> Option<(String, &str)> {
+    let last = branch.rsplit('/').next().unwrap_or(branch);
+    let mut parts = last.splitn(3, ['-', '_']);
+    let first = parts.next()?;
+    if is_number(first) {
+        return Some((first.to_string(), last.get(first.len() + 1..).unwrap_or("")));
+    }
+    let second = parts.next()?;
+    if !is_number(second) || !first.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    if !first.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let rest = parts.next().unwrap_or("");
+    if first.eq_ignore_ascii_case("issue") {
+        return Some((second.to_string(), rest));
+    }
+    Some((format!("{}-{second}", first.to_ascii_uppercase()), rest))
+}
+
+fn is_number(part: &str) -> bool {
+    !part.is_empty() && part.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{slug, ticket, unknown_placeholders, workspace_name, NamingError};
+
+    #[test]
+    fn finds_tickets() {
+        assert_eq!(
+            ticket("feature/proj-123-fix-login"),
+            Some(("PROJ-123".to_string(), "fix-login"))
+        );
+        assert_eq!(
+            ticket("42-fix-login"),
+            Some(("42".to_string(), "fix-login"))
+        );
+        assert_eq!(ticket("issue_42_fix"), Some(("42".to_string(), "fix")));
+        assert_eq!(ticket("ABC-7"), Some(("ABC-7".to_string(), "")));
+        for branch in ["main", "feature/fix-login", "v1.2-3"] {
+            assert_eq!(ticket(branch), None, "{branch}");
+        }
+    }
+
+    #[test]
+    fn slugs_titles() {
+        assert_eq!(slug("Fix login redirect!"), "fix-login-redirect");
+        assert_eq!(slug("fix_login"), "fix-login");
+        assert_eq!(slug("!!"), "");
+    }
+
+    #[test]
+    fn names_branches_with_a_ticket() {
+        let feature = "feature/proj-123-fix-login";
+        assert_eq!(
+            workspace_name("{branch}", feature).unwrap(),
+            "feature-proj-123-fix-login"
+        );
+        assert_eq!(
+            workspace_name("{ticket}-{slug}", feature).unwrap(),
+            "PROJ-123-fix-login"
+        );
+        assert_eq!(workspace_name("{{ticket}}", feature).unwrap(), "PROJ-123");
+    }
+
+    #[test]
+    fn names_branches_without_a_ticket() {
+        assert_eq!(
+            workspace_name("wip-{branch}", "fix/login").unwrap(),
+            "wip-fix-login"
+        );
+        assert_eq!(
+            workspace_name("wip-{ticket}-{slug}", "main").unwrap(),
+            "wip"
+        );
+        assert_eq!(
+            workspace_name("{ticket}-{slug}", "main"),
+            Err(NamingError::NoTicket("main".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders() {
+        assert_eq!(
+            workspace_name("{brnach}", "main"),
+            Err(NamingError::UnknownPlaceholder("brnach".to_string()))
+        );
+        assert_eq!(unknown_placeholders("{a}-{{branch}}-{{b}}"), ["a", "b"]);
+        assert!(unknown_placeholders("{branch} {unclosed").is_empty());
+    }
+}
@@ -0,0 +1,101 @@
+//! Well-known locations for desk configuration, data, and caches.
+//!
+//! The data directory defaults to the platform data directory and can be
+//! moved, in order of precedence, with `DESK_DATA_DIR` or `[storage] dir` in
+//! `config.toml`. Both accept `~` and environment variables.
+//!
+//! Under a profile, the default data directory and the credentials move to a
+//! `profiles/<name>` subdirectory, keeping the profile's environment apart.
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Context, Result};
+
+use super::config::StorageConfig;
+use crate::utils::expand::expand_path;
+
+/// Directory name used under the platform config and data directories.
+const APP_DIR: &str = "desk";
+
+/// Environment variable overriding the data directory.
+pub const DATA_DIR_ENV: &str = "DESK_DATA_DIR";
+
+/// The `[storage] dir` setting, recorded by [`configure`].
+static CONFIGURED_DATA_DIR: OnceLock<Option<String>> = OnceLock::new();
+
+/// The selected profile, recorded by [`configure`].
+static PROFILE: OnceLock<Option<String>> = OnceLock::new();
+
+/// Applies the `[storage]` settings of the selected `profile`. Called once,
+/// right after the configuration is loaded.
+pub fn configure(storage: &StorageConfig, profile: Option<&str>) {
+    // Later calls keep the first setting; the process only ever has one.
+    let _ = CONFIGURED_DATA_DIR.set(storage.dir.clone());
+    let _ = PROFILE.set(profile.map(str::to_string));
+}
+
+/// The profile selected with `--profile` or `DESK_PROFILE`, if any.
+pub fn profile() -> Option<&'static str> {
+    PROFILE.get().and_then(Option::as_deref)
+}
+
+/// Returns the directory holding `config.toml`.
+pub fn config_dir() -> Result<PathBuf> {
+    dirs::config_dir()
+        .map(|dir| dir.join(APP_DIR))
+        .context("could not determine the user configuration directory")
+}
+
+/// Returns the path of the main configuration file.
+pub fn config_file() -> Result<PathBuf> {
+    Ok(config_dir()?.join("config.toml"))
+}
+
+/// Returns the directory holding the credentials of the selected profile.
+pub fn credentials_dir() -> Result<PathBuf> {
+    Ok(for_profile(config_dir()?))
+}
+
+/// Returns the directory holding workspaces and state.
+pub fn data_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var(DATA_DIR_ENV).ok().filter(|d| !d.is_empty()) {
+        return resolve_dir(&dir, DATA_DIR_ENV);
+    }
+    if let Some(Some(dir)) = CONFIGURED_DATA_DIR.get() {
+        return resolve_dir(dir, "storage.dir");
+    }
+    dirs::data_dir()
+        .map(|dir| for_profile(dir.join(APP_DIR)))
+        .context("could not determine the user data directory")
+}
+
+fn for_profile(dir: PathBuf) -> PathBuf {
+    match profile() {
+        Some(profile) => dir.join("profiles").join(profile),
+        None => dir,
+    }
+}
+
+/// Returns the directory holding data desk can fetch again, such as
+/// responses from the desk API.
+pub fn cache_dir() -> Result<PathBuf> {
+    dirs::cache_dir()
+        .map(|dir| for_profile(dir.join(APP_DIR)))
+        .context("could not determine the user cache directory")
+}
+
+/// Returns the path of the global state file.
+pub fn state_file() -> Result<PathBuf> {
+    Ok(data_dir()?.join("state.json"))
+}
+
+/// Expands a configured directory, which must end up absolute so it means the
+/// same thing from every working directory.
+fn resolve_dir(dir: &str, setting: &str) -> Result<PathBuf> {
+    let path = expand_path(dir).with_context(|| format!("invalid {setting}"))?;
+    if !path.is_absolute() {
+        bail!("{setting} must be an absolute path, got '{dir}'");
+    }
+    Ok(path)
+}
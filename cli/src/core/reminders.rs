@@ -0,0 +1,116 @@
+//! Workspaces left alone for a while, which `desk reminders` and the daemon
+//! nudge about finishing or deleting.
+//!
+//! A workspace counts as left alone once neither opening nor saving it has
+//! touched it for `reminders.stale_after_days`. Workspaces open somewhere
+//! are in use, however long ago they were opened.
+
+use std::path::Path;
+
+use chrono::{DateTime, Days, Utc};
+use tracing::debug;
+
+use super::changes;
+use super::state::DeskState;
+use super::store::{FileWorkspaceStore, Namespace, StoreError};
+use super::workspace::Workspace;
+use crate::integrations::git::Git2Operations;
+
+/// A workspace no one has opened or saved in a while.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleWorkspace {
+    pub name: String,
+    pub namespace: Namespace,
+    pub description: Option<String>,
+    pub branch: String,
+    /// When it was last opened or saved.
+    pub last_used: DateTime<Utc>,
+    /// Files changed in the uncommitted changes it keeps; `None` when it
+    /// keeps none, or they could not be read.
+    pub changed_files: Option<usize>,
+}
+
+/// The workspaces under `data_dir` that are not open and were last opened
+/// or saved over `days` days ago, least recently used first.
+pub fn stale_workspaces(
+    data_dir: &Path,
+    state: &DeskState,
+    days: u64,
+) -> Result<Vec<StaleWorkspace>, StoreError> {
+    let cutoff = Utc::now()
+        .checked_sub_days(Days::new(days))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+    let mut found = Vec::new();
+    for store in FileWorkspaceStore::all(data_dir)? {
+        let repo = match store.namespace() {
+            Namespace::Repo(root) => Some(root.as_path()),
+            Namespace::Global => None,
+        };
+        for workspace in store.list()? {
+            let name = workspace.name.as_str();
+            if repo.is_some_and(|repo| state.current_workspace(repo) == Some(name)) {
+                continue;
+            }
+            let last_used = repo
+                .and_then(|repo| last_opened(state, repo, name))
+                .map_or(workspace.updated_at, |at| at.max(workspace.updated_at));
+            if last_used >= cutoff {
+                continue;
+            }
+            found.push(StaleWorkspace {
+                changed_files: changed_files(&store, &workspace),
+                namespace: store.namespace().clone(),
+                description: workspace.description,
+                branch: workspace.state.branch,
+                last_used,
+                name: workspace.name,
+            });
+        }
+    }
+    found.sort_by_key(|workspace| workspace.last_used);
+    Ok(found)
+}
+
+/// When workspace `name` was last switched to in `repo`.
+fn last_opened(state: &DeskState, repo: &Path, name: &str) -> Option<DateTime<Utc>> {
+    let key = DeskState::repo_key(repo);
+    state
+        .history
+        .iter()
+        .rev()
+        .find(|entry| entry.repo == key && entry.to == name)
+        .map(|entry| entry.at)
+}
+
+/// How many files the uncommitted changes kept for `workspace` touch,
+/// wherever they are kept.
+fn changed_files(store: &FileWorkspaceStore, workspace: &Workspace) -> Option<usize> {
+    let name = &workspace.name;
+    let saved = changes::load_pulled(store, name).and_then(|pulled| {
+        pulled.map_or_else(
+            || changes::load_captured(store, name),
+            |pulled| Ok(Some(pulled)),
+        )
+    });
+    let contents = match saved {
+        Ok(Some(contents)) => contents,
+        Ok(None) => {
+            let stash = workspace.state.stash_name.as_deref()?;
+            match Git2Operations::new(&workspace.repo_path).stash_contents(stash) {
+                Ok(contents) => contents?,
+                Err(e) => {
+                    debug!("cannot read stash '{stash}' of '{name}': {e}");
+                    return None;
+                },
+            }
+        },
+        Err(e) => {
+            debug!("cannot read the saved changes of '{name}': {e}");
+            return None;
+        },
+    };
+    contents
+        .file_count()
+        .map_err(|e| debug!("cannot count the saved changes of '{name}': {e}"))
+        .ok()
+}
@@ -0,0 +1,86 @@
+//! The remote workspaces `desk sync` saw last, so `desk sync status` still has
+//! something to show when the backend cannot be reached.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::client::api::RemoteWorkspace;
+
+const CACHE_FILE: &str = "remote-cache.json";
+
+/// Remote workspaces as of `fetched_at`, stored in `remote-cache.json`
+/// without their changes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteCache {
+    /// Backend the workspaces came from, as given by
+    /// [`SyncBackend::location`](crate::client::backend::SyncBackend::location).
+    pub location: String,
+    pub fetched_at: DateTime<Utc>,
+    pub workspaces: Vec<RemoteWorkspace>,
+}
+
+impl RemoteCache {
+    /// Caches a list just fetched from `location`.
+    pub fn new(location: String, workspaces: &[RemoteWorkspace]) -> Self {
+        Self {
+            location,
+            fetched_at: Utc::now(),
+            workspaces: workspaces.iter().map(without_changes).collect(),
+        }
+    }
+
+    /// Loads the cache under `data_dir`, unless it is missing, unreadable, or
+    /// belongs to a backend other than `location`.
+    pub fn load(data_dir: &Path, location: &str) -> Result<Option<Self>> {
+        let path = cache_path(data_dir);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        match serde_json::from_str::<Self>(&contents) {
+            Ok(cache) => Ok(Some(cache).filter(|c| c.location == location)),
+            Err(e) => {
+                warn!("ignoring unreadable remote cache {}: {e}", path.display());
+                Ok(None)
+            },
+        }
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<()> {
+        let path = cache_path(data_dir);
+        let tmp = path.with_extension("json.tmp");
+        let json = serde_json::to_string(self)?;
+        std::fs::write(&tmp, json)
+            .and_then(|()| std::fs::rename(&tmp, &path))
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Takes in a workspace this machine just pushed.
+    pub fn update(&mut self, remote: &RemoteWorkspace) {
+        match self.workspaces.iter_mut().find(|w| w.id == remote.id) {
+            Some(cached) => *cached = without_changes(remote),
+            None => self.workspaces.push(without_changes(remote)),
+        }
+    }
+
+    /// Forgets a workspace this machine just deleted remotely.
+    pub fn remove(&mut self, id: &str) {
+        self.workspaces.retain(|w| w.id != id);
+    }
+}
+
+fn without_changes(remote: &RemoteWorkspace) -> RemoteWorkspace {
+    RemoteWorkspace {
+        changes: None,
+        ..remote.clone()
+    }
+}
+
+fn cache_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(CACHE_FILE)
+}
@@ -0,0 +1,25 @@
+//! Repository discovery without opening git.
+
+use std::path::{Path, PathBuf};
+
+/// Walks up from `start` to the nearest directory containing `.git`.
+pub fn find_repo_root(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Resolves the git directory for a working tree, following the `gitdir:`
+/// pointer that linked worktrees and submodules use.
+pub fn git_dir(repo_root: &Path) -> PathBuf {
+    let dot_git = repo_root.join(".git");
+    if dot_git.is_file() {
+        if let Ok(contents) = std::fs::read_to_string(&dot_git) {
+            if let Some(dir) = contents.trim().strip_prefix("gitdir:") {
+                return repo_root.join(dir.trim());
+            }
+        }
+    }
+    dot_git
+}
@@ -0,0 +1,131 @@
+//! Settings a repository carries in `.desk.toml`, committed and shared with
+//! everyone working on it. For now that is `[hooks]`, laid out like the
+//! section in `config.toml`.
+//!
+//! A repository's hooks run code its authors chose, so they only run once the
+//! user has trusted the file on this machine, as with direnv's `allow`. Trust
+//! is recorded against the file's contents in `trusted-configs.json`; any
+//! change to the file needs trusting again.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::warn;
+
+use super::config::HooksConfig;
+
+/// Name of the file at the repository root.
+pub const REPO_CONFIG_FILE: &str = ".desk.toml";
+
+const TRUST_FILE: &str = "trusted-configs.json";
+
+/// Errors raised reading `.desk.toml`.
+#[derive(Debug, Error)]
+pub enum RepoConfigError {
+    #[error("failed to read {}", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("invalid {}", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+}
+
+/// The settings in `.desk.toml`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepoConfig {
+    /// Hooks run for everyone working on the repository, before their own.
+    pub hooks: HooksConfig,
+}
+
+/// `.desk.toml` as found in a repository.
+#[derive(Debug, Clone)]
+pub struct RepoConfigFile {
+    pub path: PathBuf,
+    pub config: RepoConfig,
+    /// SHA-256 of the contents, which trust is recorded against.
+    digest: String,
+}
+
+impl RepoConfigFile {
+    /// Loads `.desk.toml` from the root of `repo`, if it has one.
+    pub fn load(repo: &Path) -> Result<Option<Self>, RepoConfigError> {
+        let path = repo.join(REPO_CONFIG_FILE);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => return Err(RepoConfigError::Read { path, source }),
+        };
+        let config = match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(source) => return Err(RepoConfigError::Parse { path, source }),
+        };
+        Ok(Some(Self {
+            path,
+            config,
+            digest: hex::encode(Sha256::digest(contents.as_bytes())),
+        }))
+    }
+}
+
+/// The `.desk.toml` files trusted on this machine, stored in
+/// `trusted-configs.json`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustedConfigs {
+    /// Digest of the trusted contents, by path.
+    pub files: BTreeMap<PathBuf, String>,
+}
+
+impl TrustedConfigs {
+    /// Loads the trusted files recorded under `data_dir`; none if the record
+    /// is missing or unreadable.
+    pub fn load(data_dir: &Path) -> anyhow::Result<Self> {
+        let path = data_dir.join(TRUST_FILE);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+        Ok(serde_json::from_str(&contents).unwrap_or_else(|e| {
+            warn!("ignoring unreadable trust record {}: {e}", path.display());
+            Self::default()
+        }))
+    }
+
+    pub fn save(&self, data_dir: &Path) -> anyhow::Result<()> {
+        std::fs::create_dir_all(data_dir)
+            .with_context(|| format!("failed to create {}", data_dir.display()))?;
+        let path = data_dir.join(TRUST_FILE);
+        let tmp = path.with_extension("json.tmp");
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(&tmp, json)
+            .and_then(|()| std::fs::rename(&tmp, &path))
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Whether `file` is trusted as it is now.
+    pub fn trusts(&self, file: &RepoConfigFile) -> bool {
+        self.files.get(&file.path) == Some(&file.digest)
+    }
+
+    /// Trusts `file` as it is now.
+    pub fn trust(&mut self, file: &RepoConfigFile) {
+        self.files.insert(file.path.clone(), file.digest.clone());
+    }
+
+    /// Stops trusting the file at `path`, returning whether it was trusted.
+    pub fn revoke(&mut self, path: &Path) -> bool {
+        self.files.remove(path).is_some()
+    }
+}
@@ -0,0 +1,195 @@
+//! Full-text search over saved workspaces.
+//!
+//! Matching is case-insensitive and every query term has to match at least one
+//! field. Hits are ranked by which fields matched (a name match outranks a
+//! match buried in the notes) and how closely.
+
+use std::ops::Range;
+
+use super::workspace::Workspace;
+
+/// Maximum number of matching note lines reported per workspace.
+const MAX_NOTE_LINES: usize = 3;
+
+/// A searchable workspace field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Tag,
+    Branch,
+    Description,
+    Notes,
+}
+
+impl Field {
+    const fn weight(self) -> u32 {
+        match self {
+            Self::Name => 10,
+            Self::Tag => 6,
+            Self::Branch => 5,
+            Self::Description => 3,
+            Self::Notes => 1,
+        }
+    }
+
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Tag => "tag",
+            Self::Branch => "branch",
+            Self::Description => "description",
+            Self::Notes => "notes",
+        }
+    }
+}
+
+/// Text from one field with the byte ranges that matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldMatch {
+    pub field: Field,
+    pub text: String,
+    pub ranges: Vec<Range<usize>>,
+}
+
+/// A workspace that matched a query.
+#[derive(Debug, Clone)]
+pub struct SearchHit<'a> {
+    pub workspace: &'a Workspace,
+    pub score: u32,
+    pub matches: Vec<FieldMatch>,
+}
+
+/// Searches `workspaces` for `query`, best hits first.
+pub fn search<'a>(
+    workspaces: impl IntoIterator<Item = &'a Workspace>,
+    query: &str,
+) -> Vec<SearchHit<'a>> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(str::to_ascii_lowercase)
+        .collect();
+    if terms.is_empty() {
+        return Vec::new();
+    }
+
+    let mut hits: Vec<SearchHit<'a>> = workspaces
+        .into_iter()
+        .filter_map(|workspace| score_workspace(workspace, &terms))
+        .collect();
+
+    hits.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| b.workspace.updated_at.cmp(&a.workspace.updated_at))
+    });
+    hits
+}
+
+fn score_workspace<'a>(workspace: &'a Workspace, terms: &[String]) -> Option<SearchHit<'a>> {
+    let fields = searchable_fields(workspace);
+    let mut score = 0;
+
+    for term in terms {
+        let best = fields
+            .iter()
+            .filter_map(|(field, text)| term_score(*field, text, term))
+            .max()?;
+        score += best;
+    }
+
+    let matches = fields
+        .into_iter()
+        .filter_map(|(field, text)| {
+            let ranges = find_ranges(&text, terms);
+            (!ranges.is_empty()).then_some(FieldMatch {
+                field,
+                text,
+                ranges,
+            })
+        })
+        .collect();
+
+    Some(SearchHit {
+        workspace,
+        score,
+        matches,
+    })
+}
+
+/// Flattens a workspace into `(field, text)` pairs; tags and note lines are
+/// searched individually so results can show just the relevant part.
+fn searchable_fields(workspace: &Workspace) -> Vec<(Field, String)> {
+    let mut fields = vec![
+        (Field::Name, workspace.name.clone()),
+        (Field::Branch, workspace.state.branch.clone()),
+    ];
+    fields.extend(
+        workspace
+            .metadata
+            .tags
+            .iter()
+            .map(|tag| (Field::Tag, tag.clone())),
+    );
+    if let Some(description) = &workspace.description {
+        fields.push((Field::Description, description.clone()));
+    }
+    if let Some(notes) = &workspace.metadata.notes {
+        fields.extend(
+            notes
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(|line| (Field::Notes, line.to_string())),
+        );
+    }
+    fields
+}
+
+fn term_score(field: Field, text: &str, term: &str) -> Option<u32> {
+    let text = text.to_ascii_lowercase();
+    let closeness = if text == term {
+        3
+    } else if text.starts_with(term) {
+        2
+    } else if text.contains(term) {
+        1
+    } else {
+        return None;
+    };
+    Some(field.weight() * closeness)
+}
+
+/// Byte ranges of every occurrence of any term in `text`, merged and sorted.
+fn find_ranges(text: &str, terms: &[String]) -> Vec<Range<usize>> {
+    let haystack = text.to_ascii_lowercase();
+    let mut ranges: Vec<Range<usize>> = terms
+        .iter()
+        .flat_map(|term| {
+            haystack
+                .match_indices(term.as_str())
+                .map(|(start, m)| start..start + m.len())
+        })
+        .collect();
+    ranges.sort_by_key(|r| r.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// Keeps the first few matching note lines and every other field match.
+pub fn trim_note_matches(matches: &[FieldMatch]) -> impl Iterator<Item = &FieldMatch> {
+    let mut notes = 0;
+    matches.iter().filter(move |m| {
+        if m.field != Field::Notes {
+            return true;
+        }
+        notes += 1;
+        notes <= MAX_NOTE_LINES
+    })
+}
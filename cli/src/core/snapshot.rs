@@ -0,0 +1,197 @@
+//! Human-readable, sanitized workspace snapshots for sharing.
+//!
+//! A snapshot is a Markdown summary of where a workspace stands (branch,
+//! uncommitted changes, checklist, notes) that is safe to paste somewhere a
+//! teammate can read it. Home directory paths are shortened and values that
+//! look like credentials are redacted.
+
+use std::fmt::Write;
+
+use super::workspace::Workspace;
+use crate::integrations::git::DiffStat;
+
+/// Keys whose values are redacted from notes.
+const SECRET_KEYS: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "api_key",
+    "api-key",
+    "apikey",
+    "private_key",
+    "access_key",
+];
+
+/// Maximum number of changed files listed individually.
+const MAX_LISTED_FILES: usize = 20;
+
+/// Renders `workspace` as Markdown, including `diff` when the workspace's
+/// uncommitted changes are available.
+pub fn render(workspace: &Workspace, diff: Option<&DiffStat>) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}", workspace.name);
+    if let Some(description) = &workspace.description {
+        let _ = writeln!(out, "\n> {}", sanitize(description));
+    }
+
+    let repo = workspace
+        .repo_path
+        .file_name()
+        .map_or_else(|| "-".into(), |n| n.to_string_lossy());
+    let short_commit = workspace
+        .state
+        .commit
+        .get(..7)
+        .unwrap_or(&workspace.state.commit);
+    out.push_str("\n| | |\n|---|---|\n");
+    let _ = writeln!(out, "| Repository | `{repo}` |");
+    let _ = writeln!(out, "| Branch | `{}` |", workspace.state.branch);
+    let _ = writeln!(out, "| Commit | `{short_commit}` |");
+    if !workspace.metadata.tags.is_empty() {
+        let _ = writeln!(out, "| Tags | {} |", workspace.metadata.tags.join(", "));
+    }
+    let _ = writeln!(
+        out,
+        "| Updated | {} |",
+        workspace.updated_at.format("%Y-%m-%d %H:%M UTC")
+    );
+
+    if let Some(diff) = diff.filter(|d| !d.is_empty()) {
+        let _ = writeln!(
+            out,
+            "\n## Uncommitted changes\n\n{} files changed, +{} -{}\n",
+            diff.files.len(),
+            diff.insertions(),
+            diff.deletions()
+        );
+        for file in diff.files.iter().take(MAX_LISTED_FILES) {
+            let _ = writeln!(
+                out,
+                "- `{}` (+{} -{})",
+                file.path.display(),
+                file.insertions,
+                file.deletions
+            );
+        }
+        if diff.files.len() > MAX_LISTED_FILES {
+            let _ = writeln!(
+                out,
+                "- ... and {} more",
+                diff.files.len() - MAX_LISTED_FILES
+            );
+        }
+    } else if let Some(stash) = &workspace.state.stash_name {
+        let _ = writeln!(out, "\n## Uncommitted changes\n\nSaved in stash `{stash}`");
+    }
+
+    if let Some(notes) = &workspace.metadata.notes {
+        let (checklist, rest): (Vec<&str>, Vec<&str>) =
+            notes.lines().partition(|line| is_checklist_item(line));
+        if !checklist.is_empty() {
+            out.push_str("\n## Checklist\n\n");
+            for item in checklist {
+                let _ = writeln!(out, "{}", sanitize(item.trim()));
+            }
+        }
+        let rest = rest.join("\n");
+        if !rest.trim().is_empty() {
+            let _ = writeln!(out, "\n## Notes\n\n{}", sanitize(rest.trim()));
+        }
+    }
+
+    out
+}
+
+fn is_checklist_item(line: &str) -> bool {
+    let line = line.trim_start();
+    ["- [ ]", "- [x]", "- [X]", "* [ ]", "* [x]", "* [X]"]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+}
+
+/// Shortens home directory paths and redacts credential-looking values.
+pub fn sanitize(text: &str) -> String {
+    let home = dirs::home_dir().map(|h| h.to_string_lossy().into_owned());
+    text.lines()
+        .map(|line| {
+            let line = match &home {
+                Some(home) if home.len() > 1 => line.replace(home.as_str(), "~"),
+                _ => line.to_string(),
+            };
+            redact_line(&line)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Replaces the value after `key=`/`key:` for any secret-looking key,
+/// including keys that carry it inside a longer name such as
+/// `GITHUB_TOKEN_FOR_CI=` or `client_secret_value:`.
+fn redact_line(line: &str) -> String {
+    let lower = line.to_ascii_lowercase();
+    let value_start = SECRET_KEYS
+        .iter()
+        .flat_map(|key| {
+            lower
+                .match_indices(key)
+                .filter_map(|(pos, key)| secret_value_start(line, pos + key.len()))
+        })
+        .min();
+    value_start.map_or_else(
+        || line.to_string(),
+        |start| format!("{} [redacted]", &line[..start]),
+    )
+}
+
+/// Where the value assigned to the key ending at `key_end` starts, if the
+/// rest of the key name is followed by `=` or `:` and a value.
+fn secret_value_start(line: &str, key_end: usize) -> Option<usize> {
+    let rest = line[key_end..]
+        .trim_start_matches(|c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        .trim_start_matches(['"', '\'', ' ']);
+    let value = rest.strip_prefix(['=', ':'])?;
+    (!value.trim().is_empty()).then(|| line.len() - value.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::redact_line;
+
+    #[test]
+    fn redacts_plain_keys() {
+        assert_eq!(redact_line("password=hunter2"), "password= [redacted]");
+        assert_eq!(redact_line("api_key: abc"), "api_key: [redacted]");
+    }
+
+    #[test]
+    fn redacts_a_later_occurrence_of_a_key() {
+        assert_eq!(
+            redact_line("the tokenizer reads token=ghp_x"),
+            "the tokenizer reads token= [redacted]"
+        );
+    }
+
+    #[test]
+    fn redacts_keys_with_suffixes() {
+        assert_eq!(
+            redact_line("client_secret_value=abc"),
+            "client_secret_value= [redacted]"
+        );
+        assert_eq!(
+            redact_line("export GITHUB_TOKEN_FOR_CI=ghp_x"),
+            "export GITHUB_TOKEN_FOR_CI= [redacted]"
+        );
+        assert_eq!(
+            redact_line(r#""aws-access_key-id": "AKIA""#),
+            r#""aws-access_key-id": [redacted]"#
+        );
+    }
+
+    #[test]
+    fn leaves_lines_without_values_alone() {
+        for line in ["rotate the token tomorrow", "password:", "secret = "] {
+            assert_eq!(redact_line(line), line);
+        }
+    }
+}
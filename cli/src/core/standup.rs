@@ -0,0 +1,131 @@
+//! Standup summaries for `desk standup`: the workspaces worked on over a
+//! day, in the order they were picked up, with the time spent in each and
+//! what each is about, as a short markdown list.
+//!
+//! Switches come from the history in [`DeskState`](super::state::DeskState)
+//! and time from the [time log](super::time_log), so with time tracking off
+//! the summary still names the workspaces, just without times.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use super::state::HistoryEntry;
+use super::time_log::Interval;
+use super::workspace::Workspace;
+use crate::utils::duration::format_duration;
+
+/// A workspace worked on over a day.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Worked {
+    /// Repository path, as used for
+    /// [`DeskState::current_workspaces`](super::state::DeskState::current_workspaces).
+    pub repo: String,
+    pub workspace: String,
+    /// Time spent in it that day; zero without time tracking.
+    pub spent: Duration,
+    /// When it was first worked on that day.
+    pub first: DateTime<Utc>,
+}
+
+/// The workspaces `intervals` and `history` show were worked on between
+/// `start` and `end`, in the order they were first worked on.
+pub fn worked_on(
+    intervals: &[Interval],
+    history: &[HistoryEntry],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Vec<Worked> {
+    let timed = intervals.iter().filter_map(|interval| {
+        let spent = interval.overlap(Some(start), end);
+        (!spent.is_zero()).then(|| {
+            (
+                interval.repo.as_str(),
+                interval.workspace.as_str(),
+                interval.start.max(start),
+                spent,
+            )
+        })
+    });
+    let switched = history
+        .iter()
+        .filter(|entry| entry.at >= start && entry.at < end)
+        .map(|entry| {
+            (
+                entry.repo.as_str(),
+                entry.to.as_str(),
+                entry.at,
+                Duration::ZERO,
+            )
+        });
+    let mut worked: HashMap<(&str, &str), Worked> = HashMap::new();
+    for (repo, workspace, at, spent) in timed.chain(switched) {
+        let entry = worked.entry((repo, workspace)).or_insert_with(|| Worked {
+            repo: repo.to_string(),
+            workspace: workspace.to_string(),
+            spent: Duration::ZERO,
+            first: at,
+        });
+        entry.first = entry.first.min(at);
+        entry.spent += spent;
+    }
+    let mut worked: Vec<_> = worked.into_values().collect();
+    worked.sort_by(|a, b| {
+        a.first
+            .cmp(&b.first)
+            .then_with(|| a.workspace.cmp(&b.workspace))
+    });
+    worked
+}
+
+/// What `workspace` is about in a line: its description, or else the first
+/// line of its notes that is neither a heading nor a checklist item.
+pub fn gloss(workspace: &Workspace) -> Option<String> {
+    if let Some(description) = workspace
+        .description
+        .as_deref()
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+    {
+        return Some(description.to_string());
+    }
+    workspace
+        .metadata
+        .notes
+        .as_deref()?
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with("- ["))
+        .map(String::from)
+}
+
+/// The summary of `days`, each a title and what was worked on, with what
+/// `about` says each workspace is about.
+pub fn markdown(
+    days: &[(String, Vec<Worked>)],
+    about: impl Fn(&Worked) -> Option<String>,
+) -> String {
+    let mut out = String::new();
+    for (title, worked) in days {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        let _ = writeln!(out, "## {title}\n");
+        if worked.is_empty() {
+            out.push_str("- Nothing recorded\n");
+        }
+        for item in worked {
+            let _ = write!(out, "- {}", item.workspace);
+            if item.spent.as_secs() >= 60 {
+                let _ = write!(out, ", {}", format_duration(item.spent));
+            }
+            if let Some(about) = about(item) {
+                let _ = write!(out, ": {about}");
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
@@ -0,0 +1,796 @@
+//! Cross-invocation state: which workspace is active in which repository.
+//!
+//! Sync records, tombstones, and workspace groups live in `state.json`. Everything about one
+//! repository (its open workspace and switch history) lives in its own file
+//! under `repo-state/`, so that work in one repository never rewrites
+//! another's, and saving only touches the files that changed.
+//!
+//! Repositories are keyed by their canonical path, so that `/repo`, `/repo/`,
+//! and a symlink to it share one entry. Keys written before that are
+//! normalized as state is loaded, and saving moves them to their new files.
+//!
+//! A file that does not read is moved aside with a warning rather than
+//! written over, and [`DeskState::repair`] takes back what is left of it.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use super::migrations::{migrate_state, STATE_SCHEMA_VERSION};
+use super::paths;
+use super::store::{write_atomic, Namespace};
+use super::sync::{sync_key, sync_scope, SyncRecord, Tombstone};
+use crate::client::api::RemoteWorkspace;
+
+/// Directory next to `state.json` holding one file per repository.
+const REPO_STATE_DIR: &str = "repo-state";
+
+/// Put between the name of a corrupted state file and when it was set aside.
+const CORRUPT_SUFFIX: &str = ".corrupt-";
+
+/// State shared by every desk invocation, stored in `state.json` and
+/// `repo-state/`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeskState {
+    /// On-disk format version; see [`super::migrations`].
+    pub schema_version: u32,
+    /// Active workspace name, keyed by [`DeskState::repo_key`].
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub current_workspaces: HashMap<String, String>,
+    /// When the active workspace in each repository was opened.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub current_opened_at: HashMap<String, DateTime<Utc>>,
+    /// Last exchange with the sync backend, keyed by
+    /// [`sync_key`](super::sync::sync_key).
+    pub sync: HashMap<String, SyncRecord>,
+    /// Backend the `sync` records and tombstones belong to, as given by
+    /// [`SyncBackend::location`](crate::client::backend::SyncBackend::location).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sync_location: Option<String>,
+    /// Synced workspaces deleted here, keyed like `sync`, until `desk sync
+    /// push` deletes them remotely.
+    pub tombstones: HashMap<String, Tombstone>,
+    /// Recent workspace switches, oldest first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<HistoryEntry>,
+    /// Workspace groups that `desk group open` restores together, by name.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub groups: BTreeMap<String, Vec<GroupMember>>,
+    /// Contents of each file as last read, so saving can skip the ones that
+    /// did not change.
+    #[serde(skip)]
+    read: HashMap<PathBuf, String>,
+}
+
+/// The state of one repository, stored in `repo-state/`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct RepoState {
+    schema_version: u32,
+    repo: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    opened_at: Option<DateTime<Utc>>,
+    history: Vec<HistoryEntry>,
+}
+
+/// What `desk state repair` got back from one corrupted state file.
+#[derive(Debug)]
+pub struct Salvaged {
+    /// The corrupted file, where it was set aside.
+    pub backup: PathBuf,
+    /// Entries that could still be read: open workspaces, switches, sync
+    /// records, tombstones, and groups.
+    pub recovered: usize,
+    /// Entries too damaged to read.
+    pub skipped: usize,
+    /// Whether the file was cut short, or unreadable altogether, losing what
+    /// came after.
+    pub truncated: bool,
+}
+
+/// One switch from a workspace (or none) to another.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Repository path, as used for [`DeskState::current_workspaces`].
+    pub repo: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    pub to: String,
+    pub at: DateTime<Utc>,
+}
+
+/// A workspace in a group, and the repository it is saved in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupMember {
+    /// Repository path, as used for [`DeskState::current_workspaces`].
+    pub repo: String,
+    pub workspace: String,
+}
+
+impl DeskState {
+    /// Loads state from the default location.
+    pub fn load() -> Result<Self> {
+        Self::load_from(&paths::state_file()?)
+    }
+
+    /// Loads state from `path` and the repository files next to it; missing
+    /// files yield empty state.
+    pub fn load_from(path: &Path) -> Result<Self> {
+        let mut state = Self::default();
+        if let Some((contents, doc)) = read_document(path)? {
+            match serde_json::from_value(doc) {
+                Ok(read) => {
+                    state = read;
+                    state.read.insert(path.to_path_buf(), contents);
+                },
+                Err(e) => set_aside(path, &e)?,
+            }
+        }
+
+        let dir = repo_state_dir(path);
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(state),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", dir.display())),
+        };
+        for entry in entries {
+            let file = entry
+                .with_context(|| format!("failed to read {}", dir.display()))?
+                .path();
+            if file.extension().map_or(true, |ext| ext != "json") {
+                continue;
+            }
+            let Some((contents, doc)) = read_document(&file)? else {
+                continue;
+            };
+            match serde_json::from_value::<RepoState>(doc) {
+                Ok(repo) => state.merge(repo),
+                Err(e) => {
+                    set_aside(&file, &e)?;
+                    continue;
+                },
+            }
+            state.read.insert(file, contents);
+        }
+        state.normalize_keys();
+        state.history.sort_by_key(|entry| entry.at);
+        Ok(state)
+    }
+
+    /// Rekeys entries stored under other spellings of a repository path,
+    /// merging those that turn out to be one repository.
+    fn normalize_keys(&mut self) {
+        let mut keys: HashMap<String, String> = HashMap::new();
+        let mut key_for = |repo: &str| {
+            keys.entry(repo.to_string())
+                .or_insert_with(|| Self::repo_key(Path::new(repo)))
+                .clone()
+        };
+        let mut opened = std::mem::take(&mut self.current_opened_at);
+        let mut current: Vec<_> = std::mem::take(&mut self.current_workspaces)
+            .into_iter()
+            .map(|(repo, name)| {
+                let at = opened.remove(&repo);
+                (key_for(&repo), name, at)
+            })
+            .collect();
+        // Of several entries for one repository, the one opened last wins.
+        current.sort_by_key(|(_, _, at)| *at);
+        for (key, name, at) in current {
+            self.current_workspaces.insert(key.clone(), name);
+            match at {
+                Some(at) => self.current_opened_at.insert(key, at),
+                None => self.current_opened_at.remove(&key),
+            };
+        }
+        for entry in &mut self.history {
+            entry.repo = key_for(&entry.repo);
+        }
+        for member in self.groups.values_mut().flatten() {
+            member.repo = key_for(&member.repo);
+        }
+    }
+
+    /// Takes over a repository file, which wins over whatever `state.json`
+    /// still says about the repository from before it was split up.
+    fn merge(&mut self, repo: RepoState) {
+        self.history.retain(|entry| entry.repo != repo.repo);
+        self.history.extend(repo.history);
+        match repo.current {
+            Some(current) => self.current_workspaces.insert(repo.repo.clone(), current),
+            None => self.current_workspaces.remove(&repo.repo),
+        };
+        match repo.opened_at {
+            Some(opened_at) => self.current_opened_at.insert(repo.repo, opened_at),
+            None => self.current_opened_at.remove(&repo.repo),
+        };
+    }
+
+    /// Saves state to the default location.
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&paths::state_file()?)
+    }
+
+    /// Saves state to `path` and the repository files next to it, writing
+    /// only the files whose contents changed since they were loaded.
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        let global = Self {
+            schema_version: STATE_SCHEMA_VERSION,
+            sync: self.sync.clone(),
+            sync_location: self.sync_location.clone(),
+            tombstones: self.tombstones.clone(),
+            groups: self.groups.clone(),
+            ..Self::default()
+        };
+        let mut files = vec![(path.to_path_buf(), serde_json::to_string_pretty(&global)?)];
+        let repos: HashSet<_> = self
+            .current_workspaces
+            .keys()
+            .chain(self.history.iter().map(|entry| &entry.repo))
+            .collect();
+        for repo in repos {
+            let state = RepoState {
+                schema_version: STATE_SCHEMA_VERSION,
+                repo: repo.clone(),
+                current: self.current_workspaces.get(repo).cloned(),
+                opened_at: self.current_opened_at.get(repo).copied(),
+                history: self
+                    .history
+                    .iter()
+                    .filter(|entry| entry.repo == *repo)
+                    .cloned()
+                    .collect(),
+            };
+            files.push((
+                repo_state_file(path, repo),
+                serde_json::to_string_pretty(&state)?,
+            ));
+        }
+
+        for (file, json) in &files {
+            if self.read.get(file) == Some(json) {
+                continue;
+            }
+            if let Some(parent) = file.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("failed to create {}", parent.display()))?;
+            }
+            write_atomic(file, json.as_bytes())?;
+        }
+        // Repositories with nothing left to remember lose their file.
+        let dir = repo_state_dir(path);
+        for file in self.read.keys() {
+            if file.starts_with(&dir) && !files.iter().any(|(kept, _)| kept == file) {
+                match std::fs::remove_file(file) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(e)
+                            .with_context(|| format!("failed to remove {}", file.display()))
+                    },
+                    _ => {},
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Recovers what it can from the corrupted state files set aside next to
+    /// `path` and saves it, keeping whatever state holds now where the two
+    /// differ. The corrupted files stay where they are.
+    pub fn repair(path: &Path) -> Result<Vec<Salvaged>> {
+        let mut state = Self::load_from(path)?;
+        let mut salvaged = Vec::new();
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let name = path.file_name().and_then(|name| name.to_str());
+        for backup in set_aside_files(dir, name)? {
+            let (doc, truncated) = read_salvageable(&backup)?;
+            let mut found = Salvaged {
+                backup,
+                recovered: 0,
+                skipped: 0,
+                truncated,
+            };
+            if let Some(doc) = doc {
+                state.salvage_global(&doc, &mut found);
+            }
+            salvaged.push(found);
+        }
+        for backup in set_aside_files(&repo_state_dir(path), None)? {
+            let (doc, truncated) = read_salvageable(&backup)?;
+            let mut found = Salvaged {
+                backup,
+                recovered: 0,
+                skipped: 0,
+                truncated,
+            };
+            if let Some(doc) = doc {
+                state.salvage_repo(&doc, &mut found);
+            }
+            salvaged.push(found);
+        }
+        if salvaged.is_empty() {
+            return Ok(salvaged);
+        }
+        state.normalize_keys();
+        state.history.sort_by_key(|entry| entry.at);
+        state.history.dedup();
+        state.save_to(path)?;
+        Ok(salvaged)
+    }
+
+    /// Takes over the entries of a `state.json` document that still read,
+    /// one by one.
+    fn salvage_global(&mut self, doc: &Value, found: &mut Salvaged) {
+        for (key, name) in entries::<String>(doc, "current_workspaces", found) {
+            self.current_workspaces.entry(key).or_insert(name);
+        }
+        for (key, at) in entries(doc, "current_opened_at", found) {
+            self.current_opened_at.entry(key).or_insert(at);
+        }
+        for (key, record) in entries(doc, "sync", found) {
+            self.sync.entry(key).or_insert(record);
+        }
+        for (key, tombstone) in entries(doc, "tombstones", found) {
+            self.tombstones.entry(key).or_insert(tombstone);
+        }
+        for (name, members) in entries(doc, "groups", found) {
+            self.groups.entry(name).or_insert(members);
+        }
+        if self.sync_location.is_none() {
+            self.sync_location = field(doc, "sync_location");
+        }
+        self.history.extend(items(doc, "history", found));
+    }
+
+    /// Takes over the entries of a `repo-state/` document that still read,
+    /// one by one.
+    fn salvage_repo(&mut self, doc: &Value, found: &mut Salvaged) {
+        let Some(repo) = field::<String>(doc, "repo") else {
+            return;
+        };
+        if let Some(current) = field::<String>(doc, "current") {
+            found.recovered += 1;
+            if !self.current_workspaces.contains_key(&repo) {
+                self.current_workspaces.insert(repo.clone(), current);
+                if let Some(at) = field(doc, "opened_at") {
+                    self.current_opened_at.insert(repo.clone(), at);
+                }
+            }
+        }
+        self.history.extend(
+            items::<HistoryEntry>(doc, "history", found)
+                .into_iter()
+                .filter(|entry| entry.repo == repo),
+        );
+    }
+
+    /// The workspace currently open in `repo`, if any.
+    pub fn current_workspace(&self, repo: &Path) -> Option<&str> {
+        self.current_workspaces
+            .get(&Self::repo_key(repo))
+            .map(String::as_str)
+    }
+
+    /// When the workspace currently open in `repo` was opened.
+    pub fn opened_at(&self, repo: &Path) -> Option<DateTime<Utc>> {
+        self.current_opened_at.get(&Self::repo_key(repo)).copied()
+    }
+
+    /// Marks `workspace` as open in `repo` from now on.
+    pub fn set_current(&mut self, repo: &Path, workspace: &str) {
+        let key = Self::repo_key(repo);
+        self.current_workspaces
+            .insert(key.clone(), workspace.to_string());
+        self.current_opened_at.insert(key, Utc::now());
+    }
+
+    /// Clears the open workspace for `repo`, returning its name and open time.
+    pub fn clear_current(&mut self, repo: &Path) -> Option<(String, Option<DateTime<Utc>>)> {
+        let key = Self::repo_key(repo);
+        let name = self.current_workspaces.remove(&key)?;
+        Some((name, self.current_opened_at.remove(&key)))
+    }
+
+    /// Whether `remote` is no newer than the copy last synced with it.
+    pub fn has_seen(&self, remote: &RemoteWorkspace) -> bool {
+        self.sync
+            .get(&sync_key(&remote.scope, &remote.name))
+            .filter(|record| record.remote_id == remote.id)
+            .is_some_and(|record| record.version >= remote.version)
+    }
+
+    /// Ties the sync records to the backend at `location`. Records of another
+    /// backend mean nothing there and are dropped; returns whether any were.
+    pub fn set_sync_location(&mut self, location: &str) -> bool {
+        // Records without a location predate the backends other than the
+        // desk API.
+        let switched = self.sync_location.as_deref().map_or_else(
+            || !location.starts_with("api "),
+            |current| current != location,
+        );
+        let dropped = switched && !(self.sync.is_empty() && self.tombstones.is_empty());
+        if switched {
+            self.sync.clear();
+            self.tombstones.clear();
+        }
+        self.sync_location = Some(location.to_string());
+        dropped
+    }
+
+    /// Turns the sync record of workspace `name` in `namespace` into a
+    /// tombstone, so the next push deletes its remote copy. Workspaces that
+    /// were never synced need none.
+    pub fn bury(&mut self, namespace: &Namespace, name: &str) {
+        let key = sync_key(&sync_scope(namespace), name);
+        if let Some(record) = self.sync.remove(&key) {
+            self.tombstones.insert(
+                key,
+                Tombstone {
+                    name: name.to_string(),
+                    record,
+                    deleted_at: Utc::now(),
+                },
+            );
+        }
+    }
+
+    /// Undoes [`bury`](Self::bury) for a restored workspace whose deletion
+    /// was not pushed yet.
+    pub fn unbury(&mut self, namespace: &Namespace, name: &str) {
+        let key = sync_key(&sync_scope(namespace), name);
+        if let Some(tombstone) = self.tombstones.remove(&key) {
+            self.sync.insert(key, tombstone.record);
+        }
+    }
+
+    /// Records a switch in `repo`, dropping its oldest entries beyond `limit`
+    /// (`state.history_size`).
+    pub fn record_switch(&mut self, repo: &Path, from: Option<&str>, to: &str, limit: usize) {
+        let key = Self::repo_key(repo);
+        self.history.push(HistoryEntry {
+            repo: key.clone(),
+            from: from.map(String::from),
+            to: to.to_string(),
+            at: Utc::now(),
+        });
+        let mut excess = self
+            .history
+            .iter()
+            .filter(|entry| entry.repo == key)
+            .count()
+            .saturating_sub(limit);
+        self.history.retain(|entry| {
+            let drop = excess > 0 && entry.repo == key;
+            excess -= usize::from(drop);
+            !drop
+        });
+    }
+
+    /// Switches made in `repo`, or in every repository without one, oldest
+    /// first.
+    pub fn history_for<'a>(
+        &'a self,
+        repo: Option<&Path>,
+    ) -> impl Iterator<Item = &'a HistoryEntry> {
+        let key = repo.map(Self::repo_key);
+        self.history
+            .iter()
+            .filter(move |entry| key.as_ref().map_or(true, |key| entry.repo == *key))
+    }
+
+    /// Key of `repo` in the maps above: its canonical path, or, for a
+    /// repository that is gone, the path without trailing separators and
+    /// `.` components. Windows ignores case in paths, and so do the keys.
+    pub fn repo_key(repo: &Path) -> String {
+        let path = repo
+            .canonicalize()
+            .unwrap_or_else(|_| repo.components().collect());
+        let key = path.to_string_lossy().into_owned();
+        if cfg!(windows) {
+            key.to_lowercase()
+        } else {
+            key
+        }
+    }
+}
+
+/// Reads and upgrades the state document at `path`; a missing file yields
+/// nothing, and so does a corrupted one, which is set aside.
+fn read_document(path: &Path) -> Result<Option<(String, Value)>> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let contents = match String::from_utf8(bytes) {
+        Ok(contents) => contents,
+        Err(e) => {
+            set_aside(path, &e)?;
+            return Ok(None);
+        },
+    };
+    let doc = match serde_json::from_str(&contents) {
+        Ok(doc) => doc,
+        Err(e) => {
+            set_aside(path, &e)?;
+            return Ok(None);
+        },
+    };
+    // A newer format must stop us: loading it lossily and saving would drop data.
+    let doc = migrate_state(doc).with_context(|| format!("cannot load {}", path.display()))?;
+    Ok(Some((contents, doc)))
+}
+
+fn repo_state_dir(state_file: &Path) -> PathBuf {
+    state_file.with_file_name(REPO_STATE_DIR)
+}
+
+/// The file holding the state of `repo`, named after a hash of its path.
+fn repo_state_file(state_file: &Path, repo: &str) -> PathBuf {
+    let digest = hex::encode(Sha256::digest(repo.as_bytes()));
+    repo_state_dir(state_file).join(format!("{}.json", &digest[..16]))
+}
+
+/// Moves the corrupted state file at `path` aside for `desk state repair`,
+/// so that saving state does not write over what is left of it.
+fn set_aside(path: &Path, error: &dyn std::fmt::Display) -> Result<()> {
+    let stamp = Utc::now().format("%Y%m%dT%H%M%S%3f");
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!("{CORRUPT_SUFFIX}{stamp}"));
+    let backup = PathBuf::from(backup);
+    match std::fs::rename(path, &backup) {
+        Ok(()) => {},
+        // Another desk got there first.
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => {
+            return Err(e).with_context(|| {
+                format!(
+                    "{} is corrupted ({error}) and could not be moved aside; move it yourself \
+                     and run `desk state repair`",
+                    path.display()
+                )
+            })
+        },
+    }
+    warn!("set aside corrupted {}: {error}", path.display());
+    eprintln!(
+        "warning: {} is corrupted ({error}); moved it to {} and carried on without it. Run \
+         `desk state repair` to recover what it can.",
+        path.display(),
+        backup.display()
+    );
+    Ok(())
+}
+
+/// The corrupted files set aside in `dir` from files named `original`, or
+/// from any `*.json` file when there is none, oldest first.
+fn set_aside_files(dir: &Path, original: Option<&str>) -> Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", dir.display())),
+    };
+    let prefix = format!("{}{CORRUPT_SUFFIX}", original.unwrap_or(""));
+    let mut files = Vec::new();
+    for entry in entries {
+        let file = entry
+            .with_context(|| format!("failed to read {}", dir.display()))?
+            .path();
+        let Some(name) = file.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let set_aside = match original {
+            Some(_) => name.starts_with(&prefix),
+            None => name.contains(&format!(".json{prefix}")),
+        };
+        if set_aside {
+            files.push(file);
+        }
+    }
+    // Named after when they were set aside, so this is oldest first.
+    files.sort();
+    Ok(files)
+}
+
+/// Reads what is left of a corrupted state file: the document, upgraded
+/// where it can be, and whether it had to be cut short.
+fn read_salvageable(path: &Path) -> Result<(Option<Value>, bool)> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let text = String::from_utf8_lossy(&bytes);
+    let Some((doc, truncated)) = salvage_json(&text) else {
+        return Ok((None, true));
+    };
+    Ok((Some(migrate_state(doc.clone()).unwrap_or(doc)), truncated))
+}
+
+/// Parses `text` as JSON or, failing that, as the longest prefix of it that
+/// closes into JSON, such as a file cut short by a crash; `true` in the
+/// latter case.
+fn salvage_json(text: &str) -> Option<(Value, bool)> {
+    if let Ok(doc) = serde_json::from_str(text) {
+        return Some((doc, false));
+    }
+    // Places a complete value ends, with the brackets still open there.
+    let mut cuts = Vec::new();
+    let mut open = Vec::new();
+    let (mut in_string, mut escaped) = (false, false);
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {},
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => open.push('}'),
+            '[' => open.push(']'),
+            '}' | ']' => {
+                if open.pop() != Some(c) {
+                    break;
+                }
+                cuts.push((i + 1, open.iter().rev().collect::<String>()));
+            },
+            ',' => cuts.push((i, open.iter().rev().collect())),
+            _ => {},
+        }
+    }
+    cuts.iter().rev().find_map(|(end, closing)| {
+        serde_json::from_str(&format!("{}{closing}", &text[..*end]))
+            .ok()
+            .map(|doc| (doc, true))
+    })
+}
+
+/// The entries of the object at `key` in `doc` that read as `T`.
+fn entries<T: DeserializeOwned>(doc: &Value, key: &str, found: &mut Salvaged) -> Vec<(String, T)> {
+    let Some(map) = doc.get(key).and_then(Value::as_object) else {
+        return Vec::new();
+    };
+    map.iter()
+        .filter_map(|(k, v)| read_entry(v, found).map(|v| (k.clone(), v)))
+        .collect()
+}
+
+/// The items of the array at `key` in `doc` that read as `T`.
+fn items<T: DeserializeOwned>(doc: &Value, key: &str, found: &mut Salvaged) -> Vec<T> {
+    let Some(items) = doc.get(key).and_then(Value::as_array) else {
+        return Vec::new();
+    };
+    items.iter().filter_map(|v| read_entry(v, found)).collect()
+}
+
+/// The value at `key` in `doc`, if it reads as `T`.
+fn field<T: DeserializeOwned>(doc: &Value, key: &str) -> Option<T> {
+    doc.get(key).and_then(|v| T::deserialize(v).ok())
+}
+
+fn read_entry<T: DeserializeOwned>(value: &Value, found: &mut Salvaged) -> Option<T> {
+    let entry = T::deserialize(value).ok();
+    if entry.is_some() {
+        found.recovered += 1;
+    } else {
+        found.skipped += 1;
+    }
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use serde_json::json;
+
+    use super::{salvage_json, set_aside_files, DeskState, CORRUPT_SUFFIX};
+
+    /// An empty directory of its own for `test` under the system temp dir.
+    fn scratch(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("desk-state-{test}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn salvages_whole_documents() {
+        assert_eq!(
+            salvage_json(r#"{"a": [1]}"#),
+            Some((json!({"a": [1]}), false))
+        );
+    }
+
+    #[test]
+    fn salvages_the_start_of_truncated_documents() {
+        assert_eq!(
+            salvage_json(r#"{"a": 1, "b": [1, 2"#),
+            Some((json!({"a": 1, "b": [1]}), true))
+        );
+        assert_eq!(
+            salvage_json(r#"{"a": "x,y", "b": "unfinis"#),
+            Some((json!({"a": "x,y"}), true))
+        );
+    }
+
+    #[test]
+    fn salvages_nothing_from_empty_or_garbage_input() {
+        for text in ["", "   ", "not json", "{\"a\": \"x,y"] {
+            assert_eq!(salvage_json(text), None, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn sets_corrupted_state_aside() {
+        let dir = scratch("set-aside");
+        let path = dir.join("state.json");
+        std::fs::write(&path, "{\"current_workspaces\": {").unwrap();
+
+        let state = DeskState::load_from(&path).unwrap();
+        assert!(state.current_workspaces.is_empty());
+        assert!(!path.exists());
+        let backups = set_aside_files(&dir, Some("state.json")).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(
+            std::fs::read_to_string(&backups[0]).unwrap(),
+            "{\"current_workspaces\": {"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn repair_keeps_what_still_reads() {
+        let dir = scratch("repair");
+        let path = dir.join("state.json");
+        let truncated = dir.join(format!("state.json{CORRUPT_SUFFIX}20260101T000000000"));
+        let garbage = dir.join(format!("state.json{CORRUPT_SUFFIX}20260102T000000000"));
+        std::fs::write(
+            &truncated,
+            r#"{"schema_version": 2, "current_workspaces": {"/r": "feat"}, "history": [
+                {"repo": "/r", "to": "feat", "at": "2026-01-01T00:00:00Z"},
+                {"repo": "/r", "to": 5, "at": "yesterday"},
+                {"repo": "/r", "to": "ma"#,
+        )
+        .unwrap();
+        std::fs::write(&garbage, [0xff, 0xfe, 0x00]).unwrap();
+
+        let salvaged = DeskState::repair(&path).unwrap();
+        assert_eq!(salvaged.len(), 2);
+        assert_eq!(salvaged[0].backup, truncated);
+        // The damaged switch, and the one cut short.
+        assert_eq!(
+            (
+                salvaged[0].recovered,
+                salvaged[0].skipped,
+                salvaged[0].truncated
+            ),
+            (2, 2, true)
+        );
+        assert_eq!(
+            (
+                salvaged[1].recovered,
+                salvaged[1].skipped,
+                salvaged[1].truncated
+            ),
+            (0, 0, true)
+        );
+        assert!(truncated.exists() && garbage.exists());
+
+        let state = DeskState::load_from(&path).unwrap();
+        assert_eq!(state.current_workspace(Path::new("/r")), Some("feat"));
+        assert_eq!(state.history.len(), 1);
+        assert_eq!(state.history[0].to, "feat");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+//! Usage statistics for `desk stats`: how often desk switches and where the
+//! time goes, from the switch history in `state.json` and the time log.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Days, Local, NaiveDate, Utc};
+use serde::Serialize;
+
+use super::state::HistoryEntry;
+use super::time_log::Interval;
+
+/// Usage since a point in time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    pub since: DateTime<Utc>,
+    /// Switches in the period.
+    pub switches: usize,
+    /// Switches on each local day of the period, days without any included.
+    pub switches_per_day: Vec<DayCount>,
+    /// Most opened first.
+    pub workspaces: Vec<WorkspaceStats>,
+    /// Most switched in first.
+    pub repos: Vec<RepoStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DayCount {
+    pub date: NaiveDate,
+    pub switches: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct WorkspaceStats {
+    pub repo: String,
+    pub workspace: String,
+    /// Times the workspace was switched to.
+    pub opens: usize,
+    /// Sessions in the time log.
+    pub sessions: usize,
+    pub total_secs: u64,
+    pub average_secs: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RepoStats {
+    pub repo: String,
+    pub switches: usize,
+    pub total_secs: u64,
+}
+
+impl Stats {
+    /// Statistics for the switches in `history` and the sessions in
+    /// `intervals` that started between `since` and `now`.
+    pub fn compute<'a>(
+        history: impl IntoIterator<Item = &'a HistoryEntry>,
+        intervals: &[Interval],
+        since: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let mut per_day: HashMap<NaiveDate, usize> = HashMap::new();
+        let mut workspaces: HashMap<(String, String), WorkspaceStats> = HashMap::new();
+        let mut repos: HashMap<String, RepoStats> = HashMap::new();
+        let mut switches = 0;
+
+        for entry in history.into_iter().filter(|e| e.at >= since && e.at <= now) {
+            switches += 1;
+            *per_day.entry(local_date(entry.at)).or_default() += 1;
+            workspace_entry(&mut workspaces, &entry.repo, &entry.to).opens += 1;
+            repo_entry(&mut repos, &entry.repo).switches += 1;
+        }
+        for interval in intervals
+            .iter()
+            .filter(|i| i.start >= since && i.start <= now)
+        {
+            let secs = (interval.end - interval.start)
+                .to_std()
+                .unwrap_or_default()
+                .as_secs();
+            let stats = workspace_entry(&mut workspaces, &interval.repo, &interval.workspace);
+            stats.sessions += 1;
+            stats.total_secs += secs;
+            repo_entry(&mut repos, &interval.repo).total_secs += secs;
+        }
+
+        let mut switches_per_day = Vec::new();
+        let mut day = local_date(since);
+        while day <= local_date(now) {
+            switches_per_day.push(DayCount {
+                date: day,
+                switches: per_day.get(&day).copied().unwrap_or_default(),
+            });
+            let Some(next) = day.checked_add_days(Days::new(1)) else {
+                break;
+            };
+            day = next;
+        }
+
+        let mut workspaces: Vec<_> = workspaces
+            .into_values()
+            .map(|mut stats| {
+                stats.average_secs = stats
+                    .total_secs
+                    .checked_div(stats.sessions as u64)
+                    .unwrap_or_default();
+                stats
+            })
+            .collect();
+        workspaces.sort_by(|a, b| {
+            (b.opens, b.total_secs, &a.repo, &a.workspace).cmp(&(
+                a.opens,
+                a.total_secs,
+                &b.repo,
+                &b.workspace,
+            ))
+        });
+        let mut repos: Vec<_> = repos.into_values().collect();
+        repos.sort_by(|a, b| {
+            (b.switches, b.total_secs, &a.repo).cmp(&(a.switches, a.total_secs, &b.repo))
+        });
+
+        Self {
+            since,
+            switches,
+            switches_per_day,
+            workspaces,
+            repos,
+        }
+    }
+}
+
+fn local_date(at: DateTime<Utc>) -> NaiveDate {
+    at.with_timezone(&Local).date_naive()
+}
+
+fn workspace_entry<'a>(
+    workspaces: &'a mut HashMap<(String, String), WorkspaceStats>,
+    repo: &str,
+    workspace: &str,
+) -> &'a mut WorkspaceStats {
+    workspaces
+        .entry((repo.to_string(), workspace.to_string()))
+        .or_insert_with(|| WorkspaceStats {
+            repo: repo.to_string(),
+            workspace: workspace.to_string(),
+            ..WorkspaceStats::default()
+        })
+}
+
+fn repo_entry<'a>(repos: &'a mut HashMap<String, RepoStats>, repo: &str) -> &'a mut RepoStats {
+    repos.entry(repo.to_string()).or_insert_with(|| RepoStats {
+        repo: repo.to_string(),
+        ..RepoStats::default()
+    })
+}
@@ -0,0 +1,93 @@
+//! Uncommitted changes counted in a checkout, kept for `status.cache_secs`
+//! so that `desk status` and `desk prompt` stay quick in very large
+//! repositories.
+//!
+//! A count is reused only while `HEAD` and the index stay the same. Edits to
+//! files in between do not touch either, so they show up once the count
+//! runs out.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::core::config::StatusConfig;
+use crate::core::paths;
+use crate::integrations::git::{DiffStat, Git2Operations, GitError};
+
+const CACHE_DIR: &str = "status";
+
+static SETTINGS: OnceLock<StatusConfig> = OnceLock::new();
+
+/// Applies the `[status]` settings. Called once, right after the
+/// configuration is loaded.
+pub fn configure(config: &StatusConfig) {
+    let _ = SETTINGS.set(config.clone());
+}
+
+/// The changes of one checkout as counted at `counted_at`.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedStatus {
+    /// [`Git2Operations::status_key`] when they were counted.
+    key: String,
+    counted_at: DateTime<Utc>,
+    changes: DiffStat,
+}
+
+/// The uncommitted changes in the checkout of `git`, counted again unless a
+/// recent enough count is cached.
+pub fn diff_stat(git: &Git2Operations) -> Result<DiffStat, GitError> {
+    let secs = SETTINGS.get().map_or(0, |settings| settings.cache_secs);
+    if secs == 0 {
+        return git.diff_stat();
+    }
+    let key = git.status_key()?;
+    let path = cache_path(git);
+    let cached = path
+        .as_ref()
+        .and_then(|path| std::fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice::<CachedStatus>(&bytes).ok())
+        .filter(|cached| cached.key == key)
+        .filter(|cached| {
+            (Utc::now() - cached.counted_at)
+                .to_std()
+                .is_ok_and(|age| age.as_secs() < secs)
+        });
+    if let Some(cached) = cached {
+        return Ok(cached.changes);
+    }
+
+    let changes = git.diff_stat()?;
+    if let Some(path) = path {
+        let cached = CachedStatus {
+            key,
+            counted_at: Utc::now(),
+            changes,
+        };
+        if let Err(e) = save(&path, &cached) {
+            debug!("failed to cache the changes in {}: {e}", path.display());
+        }
+        return Ok(cached.changes);
+    }
+    Ok(changes)
+}
+
+/// Where the count for the checkout of `git` is cached, named after a hash
+/// of its path.
+fn cache_path(git: &Git2Operations) -> Option<PathBuf> {
+    let dir = paths::cache_dir().ok()?.join(CACHE_DIR);
+    let hash = Sha256::digest(git.repo_path().to_string_lossy().as_bytes());
+    Some(dir.join(format!("{}.json", &hex::encode(hash)[..16])))
+}
+
+fn save(path: &Path, cached: &CachedStatus) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(cached)?)?;
+    std::fs::rename(&tmp, path)
+}
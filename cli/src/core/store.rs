@@ -0,0 +1,533 @@
+//! On-disk workspace storage.
+//!
+//! Workspaces are namespaced by repository so that `fix-login` in one project
+//! does not collide with `fix-login` in another:
+//!
+//! ```text
+//! <data_dir>/workspaces/
+//! ├── global/<name>.json
+//! └── repos/<repo-name>-<hash>/
+//!     ├── repo.path
+//!     └── <name>.json
+//! ```
+//!
+//! `repo.path` records the repository a namespace belongs to, since the
+//! directory name only carries a hash of it.
+//!
+//! Files are named after [`file_stem`], a form of the workspace name every
+//! filesystem takes; the name itself is kept in the file. Files saved under
+//! the name as it was are renamed the first time the workspace is used.
+
+use std::fmt::{self, Write as _};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tracing::debug;
+
+use super::migrations::{migrate_workspace, MigrationError};
+use super::workspace::Workspace;
+
+/// File inside a repository namespace recording the repository root.
+const REPO_MARKER: &str = "repo.path";
+
+/// Longest file stem, leaving room for extensions within the 255 bytes
+/// filesystems allow.
+const MAX_STEM: usize = 128;
+
+/// Names Windows reserves for devices, with or without an extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Errors raised by [`FileWorkspaceStore`].
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("workspace '{0}' not found")]
+    NotFound(String),
+
+    #[error("invalid workspace name '{0}': names cannot be empty, start with '.', or contain path separators or control characters")]
+    InvalidName(String),
+
+    #[error(
+        "workspace '{name}' would be saved in the same file as '{existing}'; names that differ \
+         only in case cannot both be used, so pick another"
+    )]
+    Collision { name: String, existing: String },
+
+    #[error("failed to access {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("failed to encode workspace '{name}'")]
+    Encode {
+        name: String,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("workspace file {path} is not valid")]
+    Corrupt {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+
+    #[error("cannot load {path}")]
+    Schema {
+        path: PathBuf,
+        #[source]
+        source: MigrationError,
+    },
+}
+
+/// Which set of workspace names an operation applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Namespace {
+    /// Workspaces belonging to the repository rooted at this path.
+    Repo(PathBuf),
+    /// Workspaces not tied to any repository (`--global`).
+    Global,
+}
+
+impl Namespace {
+    /// Selects the namespace for a command run in `cwd`.
+    ///
+    /// Outside a repository, or with `--global`, the global namespace is used.
+    pub fn resolve(cwd: &Path, global: bool) -> Self {
+        if global {
+            return Self::Global;
+        }
+        super::repo::find_repo_root(cwd).map_or(Self::Global, |root| {
+            Self::Repo(root.canonicalize().unwrap_or(root))
+        })
+    }
+
+    /// Directory name of this namespace relative to the workspaces root.
+    fn relative_dir(&self) -> PathBuf {
+        match self {
+            Self::Global => PathBuf::from("global"),
+            Self::Repo(root) => {
+                let digest = Sha256::digest(root.to_string_lossy().as_bytes());
+                let hash = hex::encode(&digest[..6]);
+                let name = root
+                    .file_name()
+                    .map_or_else(|| "repo".into(), |n| n.to_string_lossy());
+                PathBuf::from("repos").join(format!("{name}-{hash}"))
+            },
+        }
+    }
+}
+
+impl fmt::Display for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Global => f.write_str("global"),
+            Self::Repo(root) => write!(f, "{}", root.display()),
+        }
+    }
+}
+
+/// Stores workspaces as JSON files in one namespace.
+#[derive(Debug, Clone)]
+pub struct FileWorkspaceStore {
+    dir: PathBuf,
+    namespace: Namespace,
+}
+
+impl FileWorkspaceStore {
+    /// Opens the store for `namespace` under `data_dir`.
+    pub fn new(data_dir: &Path, namespace: Namespace) -> Self {
+        let dir = data_dir.join("workspaces").join(namespace.relative_dir());
+        Self { dir, namespace }
+    }
+
+    /// Opens a store for every namespace that exists under `data_dir`: the
+    /// global one followed by each repository with saved workspaces.
+    pub fn all(data_dir: &Path) -> Result<Vec<Self>, StoreError> {
+        let mut stores = vec![Self::new(data_dir, Namespace::Global)];
+        let repos_dir = data_dir.join("workspaces").join("repos");
+
+        let entries = match std::fs::read_dir(&repos_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(stores),
+            Err(source) => {
+                return Err(StoreError::Io {
+                    path: repos_dir,
+                    source,
+                })
+            },
+        };
+
+        for entry in entries.flatten() {
+            let dir = entry.path();
+            if let Ok(root) = std::fs::read_to_string(dir.join(REPO_MARKER)) {
+                stores.push(Self {
+                    dir,
+                    namespace: Namespace::Repo(PathBuf::from(root.trim())),
+                });
+            }
+        }
+        Ok(stores)
+    }
+
+    pub const fn namespace(&self) -> &Namespace {
+        &self.namespace
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Writes `workspace`, replacing any existing file atomically. Fails
+    /// when another workspace already has its file.
+    pub fn save(&self, workspace: &Workspace) -> Result<(), StoreError> {
+        let path = self.check_name(&workspace.name)?;
+        self.prepare_dir()?;
+
+        let json = serde_json::to_vec_pretty(workspace).map_err(|source| StoreError::Encode {
+            name: workspace.name.clone(),
+            source,
+        })?;
+        write_atomic(&path, &json)
+    }
+
+    /// Creates the namespace directory and its `repo.path` marker.
+    pub fn prepare_dir(&self) -> Result<(), StoreError> {
+        std::fs::create_dir_all(&self.dir).map_err(|source| StoreError::Io {
+            path: self.dir.clone(),
+            source,
+        })?;
+
+        if let Namespace::Repo(root) = &self.namespace {
+            let marker = self.dir.join(REPO_MARKER);
+            if !marker.exists() {
+                write_atomic(&marker, root.to_string_lossy().as_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that a workspace called `name` can be saved: that the name is
+    /// valid and no other workspace has its file. Returns the file.
+    pub fn check_name(&self, name: &str) -> Result<PathBuf, StoreError> {
+        let path = self.path_for(name)?;
+        match stored_name(&path) {
+            Some(existing) if existing != name => Err(StoreError::Collision {
+                name: name.to_string(),
+                existing,
+            }),
+            _ => Ok(path),
+        }
+    }
+
+    /// Loads the workspace called `name`.
+    pub fn load(&self, name: &str) -> Result<Workspace, StoreError> {
+        let path = self.path_for(name)?;
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(StoreError::NotFound(name.to_string()))
+            },
+            Err(source) => return Err(StoreError::Io { path, source }),
+        };
+        let workspace = decode(path, &contents)?;
+        if workspace.name != name {
+            return Err(StoreError::NotFound(name.to_string()));
+        }
+        Ok(workspace)
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.path_for(name).is_ok_and(|path| {
+            path.exists() && stored_name(&path).map_or(true, |stored| stored == name)
+        })
+    }
+
+    /// Lists every workspace in the namespace, sorted by name.
+    pub fn list(&self) -> Result<Vec<Workspace>, StoreError> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(source) => {
+                return Err(StoreError::Io {
+                    path: self.dir.clone(),
+                    source,
+                })
+            },
+        };
+
+        let mut workspaces = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let contents = std::fs::read(&path).map_err(|source| StoreError::Io {
+                    path: path.clone(),
+                    source,
+                })?;
+                workspaces.push(decode(path, &contents)?);
+            }
+        }
+        workspaces.sort_by(|a: &Workspace, b| a.name.cmp(&b.name));
+        Ok(workspaces)
+    }
+
+    /// Removes the workspace called `name`.
+    pub fn delete(&self, name: &str) -> Result<(), StoreError> {
+        let path = self.path_for(name)?;
+        if stored_name(&path).is_some_and(|stored| stored != name) {
+            return Err(StoreError::NotFound(name.to_string()));
+        }
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(StoreError::NotFound(name.to_string()))
+            },
+            Err(source) => Err(StoreError::Io { path, source }),
+        }
+    }
+
+    /// Path of the file holding the workspace called `name`.
+    pub fn path_for(&self, name: &str) -> Result<PathBuf, StoreError> {
+        validate_name(name)?;
+        let stem = file_stem(name);
+        if stem != name {
+            self.rename_legacy(name, &stem);
+        }
+        Ok(self.dir.join(format!("{stem}.json")))
+    }
+
+    /// Renames the files of workspace `name` saved before names were
+    /// normalized, `<name>.json` and the changes next to it, after `stem`.
+    fn rename_legacy(&self, name: &str, stem: &str) {
+        if !self.dir.join(format!("{name}.json")).is_file() {
+            return;
+        }
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return;
+        };
+        let prefix = format!("{name}.");
+        for entry in entries.flatten() {
+            let file = entry.file_name();
+            let Some(extension) = file.to_str().and_then(|file| file.strip_prefix(&prefix)) else {
+                continue;
+            };
+            if extension.contains('.') {
+                continue;
+            }
+            let target = self.dir.join(format!("{stem}.{extension}"));
+            // On a case-insensitive filesystem the target is the file itself.
+            if target.exists() && !same_file(&entry.path(), &target) {
+                continue;
+            }
+            if let Err(e) = std::fs::rename(entry.path(), &target) {
+                debug!("failed to rename {}: {e}", entry.path().display());
+            }
+        }
+    }
+}
+
+/// The file name, without extension, of the files of workspace `name`.
+///
+/// Names are lowercased, so that two names differing only in case never
+/// get files that a case-insensitive filesystem takes for one, and anything
+/// but ASCII letters, digits, `-`, `_`, and `.` is written as `%XX`. Names
+/// Windows reserves, trailing dots, and overlong names are escaped too.
+pub fn file_stem(name: &str) -> String {
+    let mut stem = String::with_capacity(name.len());
+    for byte in name.to_lowercase().bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.') {
+            stem.push(char::from(byte));
+        } else {
+            let _ = write!(stem, "%{byte:02X}");
+        }
+    }
+    if stem.ends_with('.') {
+        stem.pop();
+        stem.push_str("%2E");
+    }
+    let device = stem.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES.contains(&device) {
+        let first = stem.remove(0);
+        stem.insert_str(0, &format!("%{:02X}", u32::from(first)));
+    }
+    if stem.len() > MAX_STEM {
+        let digest = Sha256::digest(name.as_bytes());
+        stem.truncate(MAX_STEM - 13);
+        stem.push('-');
+        stem.push_str(&hex::encode(&digest[..6]));
+    }
+    stem
+}
+
+/// The name recorded in the workspace file at `path`, if it can be read.
+fn stored_name(path: &Path) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Named {
+        name: String,
+    }
+    let contents = std::fs::read(path).ok()?;
+    serde_json::from_slice::<Named>(&contents)
+        .ok()
+        .map(|named| named.name)
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (a.metadata(), b.metadata()) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+/// Windows filesystems ignore case.
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> bool {
+    a.to_string_lossy().to_lowercase() == b.to_string_lossy().to_lowercase()
+}
+
+/// Parses a workspace file, upgrading it from older schema versions.
+fn decode(path: PathBuf, contents: &[u8]) -> Result<Workspace, StoreError> {
+    let doc = serde_json::from_slice(contents).map_err(|source| StoreError::Corrupt {
+        path: path.clone(),
+        source,
+    })?;
+    let doc = migrate_workspace(doc).map_err(|source| StoreError::Schema {
+        path: path.clone(),
+        source,
+    })?;
+    serde_json::from_value(doc).map_err(|source| StoreError::Corrupt { path, source })
+}
+
+fn validate_name(name: &str) -> Result<(), StoreError> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || name.contains(['/', '\\'])
+        || name.contains(char::is_control)
+    {
+        return Err(StoreError::InvalidName(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Writes `contents` to a sibling temp file and renames it over `path`.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), StoreError> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, contents)
+        .and_then(|()| std::fs::rename(&tmp, path))
+        .map_err(|source| StoreError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{file_stem, FileWorkspaceStore, Namespace, StoreError};
+    use crate::core::workspace::{Workspace, WorkspaceState};
+
+    /// A store of its own for `test` under the system temp dir.
+    fn scratch(test: &str) -> (PathBuf, FileWorkspaceStore) {
+        let dir = std::env::temp_dir().join(format!("desk-store-{test}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = FileWorkspaceStore::new(&dir, Namespace::Global);
+        (dir, store)
+    }
+
+    fn workspace(name: &str) -> Workspace {
+        Workspace::new(name, PathBuf::from("/repo"), WorkspaceState::default())
+    }
+
+    #[test]
+    fn escapes_names_into_stems() {
+        assert_eq!(file_stem("fix-login_2.x"), "fix-login_2.x");
+        assert_eq!(file_stem("Fix Login"), "fix%20login");
+        assert_eq!(file_stem("100%"), "100%25");
+        assert_eq!(file_stem("café"), "caf%C3%A9");
+        assert_eq!(file_stem("wip."), "wip%2E");
+        assert_ne!(file_stem("a b"), file_stem("a%20b"));
+    }
+
+    #[test]
+    fn escapes_reserved_names() {
+        assert_eq!(file_stem("CON"), "%63on");
+        assert_eq!(file_stem("nul"), "%6Eul");
+        assert_eq!(file_stem("com1.txt"), "%63om1.txt");
+        assert_eq!(file_stem("console"), "console");
+    }
+
+    #[test]
+    fn shortens_overlong_names() {
+        let long = "x".repeat(300);
+        let stem = file_stem(&long);
+        assert_eq!(stem.len(), 128);
+        assert_ne!(stem, file_stem(&"x".repeat(301)));
+    }
+
+    #[test]
+    fn escaped_names_round_trip() {
+        let (dir, store) = scratch("round-trip");
+        for name in ["Fix Login", "café", "100%", "CON", "wip."] {
+            store.save(&workspace(name)).unwrap();
+            assert_eq!(store.load(name).unwrap().name, name);
+        }
+        let names: Vec<_> = store
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|ws| ws.name)
+            .collect();
+        assert_eq!(names, ["100%", "CON", "Fix Login", "café", "wip."]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_names_differing_only_in_case() {
+        let (dir, store) = scratch("collision");
+        store.save(&workspace("Feature")).unwrap();
+        assert!(matches!(
+            store.save(&workspace("feature")),
+            Err(StoreError::Collision { existing, .. }) if existing == "Feature"
+        ));
+        assert!(matches!(
+            store.load("FEATURE"),
+            Err(StoreError::NotFound(_))
+        ));
+        assert!(!store.exists("feature"));
+        assert!(matches!(
+            store.delete("feature"),
+            Err(StoreError::NotFound(_))
+        ));
+        assert_eq!(store.load("Feature").unwrap().name, "Feature");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn renames_legacy_files() {
+        let (dir, store) = scratch("legacy");
+        store.prepare_dir().unwrap();
+        let json = serde_json::to_vec(&workspace("My Feature")).unwrap();
+        std::fs::write(store.dir().join("My Feature.json"), json).unwrap();
+        std::fs::write(store.dir().join("My Feature.patch"), "diff").unwrap();
+        std::fs::write(store.dir().join("My Feature.old.patch"), "old").unwrap();
+
+        assert_eq!(store.load("My Feature").unwrap().name, "My Feature");
+        assert!(store.dir().join("my%20feature.json").is_file());
+        assert_eq!(
+            std::fs::read_to_string(store.dir().join("my%20feature.patch")).unwrap(),
+            "diff"
+        );
+        assert!(!store.dir().join("My Feature.json").exists());
+        assert!(store.dir().join("My Feature.old.patch").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
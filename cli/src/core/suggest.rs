@@ -0,0 +1,135 @@
+//! Next-workspace predictions mined from the switch history.
+//!
+//! The model is a table of transition counts: how often a switch away from
+//! workspace `A` landed on workspace `B`. Predictions for `A` are the targets
+//! it most often led to, with ties going to the most recent switch. Nothing
+//! leaves the machine; the history is the one kept in desk's state.
+//!
+//! The same history ranks workspaces by frecency, so the ones opened both
+//! often and lately come first in `desk list` and the picker.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+use super::state::HistoryEntry;
+use super::workspace::Workspace;
+
+/// How workspaces are ordered in `desk list` and the picker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum WorkspaceOrder {
+    /// Alphabetically
+    Name,
+    /// Most recently saved first
+    Updated,
+    /// Most recently created first
+    Created,
+    /// Most often and most recently opened first
+    #[default]
+    Frecency,
+}
+
+/// A workspace likely to be opened next.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prediction {
+    pub name: String,
+    /// How often the history went from the current workspace to this one.
+    pub count: usize,
+    pub last_seen: DateTime<Utc>,
+}
+
+/// Ranks the workspaces that followed `current` in `history`.
+pub fn predict_next<'a>(
+    history: impl IntoIterator<Item = &'a HistoryEntry>,
+    current: &str,
+) -> Vec<Prediction> {
+    let mut counts: HashMap<&str, Prediction> = HashMap::new();
+    for entry in history {
+        if entry.from.as_deref() != Some(current) || entry.to == current {
+            continue;
+        }
+        counts
+            .entry(&entry.to)
+            .and_modify(|p| {
+                p.count += 1;
+                p.last_seen = p.last_seen.max(entry.at);
+            })
+            .or_insert_with(|| Prediction {
+                name: entry.to.clone(),
+                count: 1,
+                last_seen: entry.at,
+            });
+    }
+
+    let mut predictions: Vec<_> = counts.into_values().collect();
+    predictions.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| b.last_seen.cmp(&a.last_seen))
+    });
+    predictions
+}
+
+/// Workspaces opened in `history`, most recently opened first, each with the
+/// time it was last opened.
+pub fn recent<'a>(
+    history: impl IntoIterator<Item = &'a HistoryEntry>,
+) -> Vec<(String, DateTime<Utc>)> {
+    let mut last_opened: HashMap<&str, DateTime<Utc>> = HashMap::new();
+    for entry in history {
+        let at = last_opened.entry(&entry.to).or_insert(entry.at);
+        *at = (*at).max(entry.at);
+    }
+
+    let mut recent: Vec<_> = last_opened
+        .into_iter()
+        .map(|(name, at)| (name.to_string(), at))
+        .collect();
+    recent.sort_by_key(|&(_, at)| Reverse(at));
+    recent
+}
+
+/// Frecency of each workspace opened in `history`: every switch to it
+/// scores points, fewer the longer ago it was.
+pub fn frecency<'a>(
+    history: impl IntoIterator<Item = &'a HistoryEntry>,
+    now: DateTime<Utc>,
+) -> HashMap<String, u64> {
+    let mut scores = HashMap::new();
+    for entry in history {
+        let points = match (now - entry.at).num_days() {
+            ..=3 => 100,
+            4..=14 => 70,
+            15..=31 => 50,
+            32..=90 => 30,
+            _ => 10,
+        };
+        *scores.entry(entry.to.clone()).or_insert(0) += points;
+    }
+    scores
+}
+
+/// Sorts `workspaces` by `order`; frecency comes from `history`, with ties
+/// going to the most recently saved.
+pub fn sort_workspaces<'a>(
+    workspaces: &mut [Workspace],
+    order: WorkspaceOrder,
+    history: impl IntoIterator<Item = &'a HistoryEntry>,
+    now: DateTime<Utc>,
+) {
+    match order {
+        WorkspaceOrder::Name => workspaces.sort_by(|a, b| a.name.cmp(&b.name)),
+        WorkspaceOrder::Updated => workspaces.sort_by_key(|w| Reverse(w.updated_at)),
+        WorkspaceOrder::Created => workspaces.sort_by_key(|w| Reverse(w.created_at)),
+        WorkspaceOrder::Frecency => {
+            let scores = frecency(history, now);
+            workspaces.sort_by_key(|w| {
+                (
+                    Reverse(scores.get(&w.name).copied().unwrap_or(0)),
+                    Reverse(w.updated_at),
+                )
+            });
+        },
+    }
+}
@@ -0,0 +1,315 @@
+//! Moving the working copy in and out of a workspace.
+//!
+//! Capturing records the checked-out branch and commit and stashes
+//! uncommitted changes under a message naming the workspace; restoring checks
+//! the branch out again and pops that stash, or applies the changes pulled
+//! for the workspace from another machine.
+//!
+//! `capture.backend` decides where the stash ends up: in `git stash list`,
+//! under a ref of its own, or packed into desk's data directory.
+
+use std::fmt::Write;
+
+use thiserror::Error;
+
+use super::changes::{self, ChangesError};
+use super::config::{CaptureBackend, CaptureConfig};
+use super::store::FileWorkspaceStore;
+use super::sync::repo_origin;
+use super::workspace::Workspace;
+use crate::integrations::git::{Git2Operations, GitError, StashContents, SwitchOptions};
+use crate::output::{Reporter, StepEvent, StepKind};
+use crate::utils::size::format_size;
+
+/// Errors raised while capturing a workspace.
+#[derive(Debug, Error)]
+pub enum CaptureError {
+    #[error(
+        "'{0}' has uncommitted changes and capture.auto_stash is off; commit or stash them first"
+    )]
+    Uncommitted(String),
+
+    #[error(transparent)]
+    Git(#[from] GitError),
+
+    #[error(transparent)]
+    Changes(#[from] ChangesError),
+}
+
+/// Stash message used for the uncommitted changes of workspace `name`.
+pub fn stash_message(name: &str) -> String {
+    format!("desk: {name}")
+}
+
+/// Ref holding the uncommitted changes of workspace `name` with
+/// `capture.backend = "ref"`.
+pub fn stash_ref(name: &str) -> String {
+    // Workspace names may hold spaces and the like, which refs may not.
+    let mut refname = String::from("refs/desk/changes/");
+    for byte in name.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_') {
+            refname.push(char::from(byte));
+        } else {
+            let _ = write!(refname, "%{byte:02X}");
+        }
+    }
+    refname
+}
+
+/// Records the repository's current git state into `workspace`, putting
+/// any uncommitted changes away as `config` says.
+pub fn capture(
+    git: &Git2Operations,
+    store: &FileWorkspaceStore,
+    workspace: &mut Workspace,
+    config: &CaptureConfig,
+    reporter: &mut dyn Reporter,
+) -> Result<(), CaptureError> {
+    if !config.auto_stash && git.has_changes(config.include_untracked)? {
+        return Err(CaptureError::Uncommitted(workspace.name.clone()));
+    }
+    let branch = git.current_branch()?;
+    let commit = git.current_commit()?;
+    reporter.step(StepEvent::done(
+        StepKind::Git,
+        format!("Saved branch {branch} at {}", short(&commit)),
+    ));
+
+    let message = stash_message(&workspace.name);
+    let stash_name = if git.stash_save(&message, config.include_untracked)? {
+        put_away(git, store, workspace, config, message, reporter)?
+    } else {
+        reporter.step(StepEvent::skipped(
+            StepKind::Git,
+            "Stash",
+            "no uncommitted changes",
+        ));
+        None
+    };
+
+    workspace.state.branch = branch;
+    workspace.state.commit = commit;
+    workspace.state.stash_name = stash_name;
+    if let Some(origin) = repo_origin(git.repo_path()) {
+        workspace.origin = Some(origin);
+    }
+    workspace.touch();
+    Ok(())
+}
+
+/// Moves the changes just stashed under `message` to where `config.backend`
+/// keeps them, returning the stash name to record.
+fn put_away(
+    git: &Git2Operations,
+    store: &FileWorkspaceStore,
+    workspace: &Workspace,
+    config: &CaptureConfig,
+    message: String,
+    reporter: &mut dyn Reporter,
+) -> Result<Option<String>, CaptureError> {
+    match config.backend {
+        CaptureBackend::Stash => {
+            reporter.step(StepEvent::done(
+                StepKind::Git,
+                "Stashed uncommitted changes",
+            ));
+            Ok(Some(message))
+        },
+        CaptureBackend::Ref => {
+            let refname = stash_ref(&workspace.name);
+            git.stash_to_ref(&message, &refname)?;
+            reporter.step(StepEvent::done(
+                StepKind::Git,
+                format!("Saved uncommitted changes to {refname}"),
+            ));
+            Ok(Some(refname))
+        },
+        CaptureBackend::Patch => {
+            let contents = git
+                .stash_contents(&message)?
+                .ok_or_else(|| GitError::StashNotFound(message.clone()))?;
+            let limit = config.max_patch_mib.saturating_mul(1024 * 1024);
+            match changes::pack(&contents, limit) {
+                Ok(bytes) => {
+                    changes::save_captured(store, &workspace.name, &bytes)?;
+                    git.stash_drop(&message)?;
+                    reporter.step(StepEvent::done(
+                        StepKind::Git,
+                        "Saved uncommitted changes as a patch",
+                    ));
+                    Ok(None)
+                },
+                Err(ChangesError::TooLarge { size, .. }) => {
+                    reporter.step(StepEvent::skipped(
+                        StepKind::Git,
+                        "Save changes as a patch",
+                        format!(
+                            "{} is over capture.max_patch_mib; kept in stash '{message}'",
+                            format_size(size)
+                        ),
+                    ));
+                    Ok(Some(message))
+                },
+                Err(e) => Err(e.into()),
+            }
+        },
+    }
+}
+
+/// What opening a workspace is about to do to the working copy, shown
+/// before anything changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestorePreview {
+    /// Changed files that saving the open workspace puts away first.
+    pub put_away: usize,
+    /// Branch checked out now; `None` on a detached `HEAD`.
+    pub from_branch: Option<String>,
+    pub to_branch: String,
+    /// Commits between the two branches, when both are known.
+    pub commits_apart: Option<usize>,
+    /// What gets applied afterwards, and how many files it touches.
+    pub apply: Option<(String, usize)>,
+    /// The commit the workspace was saved on, when it is not in this clone.
+    pub missing_commit: Option<String>,
+}
+
+impl RestorePreview {
+    /// Whether restoring leaves the working copy as it is.
+    pub fn is_noop(&self) -> bool {
+        self.put_away == 0
+            && self.from_branch.as_deref() == Some(self.to_branch.as_str())
+            && self.apply.is_none()
+            && self.missing_commit.is_none()
+    }
+
+    /// One line per step, e.g. `switch main → feature/x (12 commits apart)`.
+    pub fn steps(&self) -> Vec<String> {
+        let mut steps = Vec::new();
+        match self.put_away {
+            0 => {},
+            1 => steps.push("stash 1 modified file".to_string()),
+            files => steps.push(format!("stash {files} modified files")),
+        }
+        let from = self.from_branch.as_deref().unwrap_or("detached HEAD");
+        if from != self.to_branch {
+            let apart = match self.commits_apart {
+                Some(1) => " (1 commit apart)".to_string(),
+                Some(n) => format!(" ({n} commits apart)"),
+                None => String::new(),
+            };
+            steps.push(format!("switch {from} → {}{apart}", self.to_branch));
+        }
+        if let Some((source, files)) = &self.apply {
+            let noun = if *files == 1 { "file" } else { "files" };
+            steps.push(format!("apply {source} with {files} {noun}"));
+        }
+        steps
+    }
+}
+
+/// Works out what [`restore`] will do for `workspace`, after capturing the
+/// open workspace with `saving` if there is one. Changes nothing.
+pub fn preview_restore(
+    git: &Git2Operations,
+    workspace: &Workspace,
+    pulled: Option<&StashContents>,
+    captured: Option<&StashContents>,
+    saving: Option<&CaptureConfig>,
+) -> Result<RestorePreview, GitError> {
+    let put_away = match saving {
+        Some(config) => git.changed_files(config.include_untracked)?,
+        None => 0,
+    };
+    let to_branch = workspace.state.branch.clone();
+    let commits_apart = match git.ahead_behind(&to_branch)? {
+        Some(counts) => Some(counts),
+        // Not checked out in this clone yet; it starts at the saved commit.
+        None => git.ahead_behind(&workspace.state.commit)?,
+    }
+    .map(|(ahead, behind)| ahead + behind);
+
+    let apply = if let Some(pulled) = pulled {
+        Some((
+            "changes pulled from another machine".to_string(),
+            pulled.file_count()?,
+        ))
+    } else if let Some(captured) = captured {
+        Some(("saved patch".to_string(), captured.file_count()?))
+    } else if let Some(stash) = &workspace.state.stash_name {
+        git.stash_contents(stash)?
+            .map(|contents| contents.file_count())
+            .transpose()?
+            .map(|files| ("stash".to_string(), files))
+    } else {
+        None
+    };
+
+    let commit = &workspace.state.commit;
+    Ok(RestorePreview {
+        put_away,
+        from_branch: git.current_branch().ok(),
+        to_branch,
+        commits_apart,
+        apply,
+        missing_commit: (!git.has_commit(commit)?).then(|| commit.clone()),
+    })
+}
+
+/// Checks out the branch saved in `workspace` and restores its uncommitted
+/// changes: the `pulled` ones if given, otherwise the `captured` patch or
+/// its stash.
+///
+/// The stash is forgotten once applied, so `workspace` must be saved
+/// afterwards.
+pub fn restore(
+    git: &Git2Operations,
+    workspace: &mut Workspace,
+    pulled: Option<&StashContents>,
+    captured: Option<&StashContents>,
+    reporter: &mut dyn Reporter,
+) -> Result<(), GitError> {
+    let branch = &workspace.state.branch;
+    git.switch_branch(branch, &SwitchOptions::default())?;
+    reporter.step(StepEvent::done(
+        StepKind::Git,
+        format!("Checked out {branch}"),
+    ));
+
+    if let Some(pulled) = pulled {
+        // Pulled changes are newer than a stash of the same name left in
+        // this clone, which stays in `git stash list`.
+        git.apply_contents(pulled)?;
+        reporter.step(StepEvent::done(
+            StepKind::Git,
+            "Restored uncommitted changes pulled from another machine",
+        ));
+        workspace.state.stash_name = None;
+    } else if let Some(captured) = captured {
+        git.apply_contents(captured)?;
+        reporter.step(StepEvent::done(
+            StepKind::Git,
+            "Restored uncommitted changes",
+        ));
+        workspace.state.stash_name = None;
+    } else if let Some(stash) = &workspace.state.stash_name {
+        match git.stash_pop(stash) {
+            Ok(()) => reporter.step(StepEvent::done(
+                StepKind::Git,
+                "Restored uncommitted changes",
+            )),
+            // Saved in another clone whose changes were not synced.
+            Err(GitError::StashNotFound(_)) => reporter.step(StepEvent::skipped(
+                StepKind::Git,
+                format!("Restore stash '{stash}'"),
+                "not in this clone",
+            )),
+            Err(e) => return Err(e),
+        }
+        workspace.state.stash_name = None;
+    }
+    Ok(())
+}
+
+fn short(commit: &str) -> &str {
+    commit.get(..7).unwrap_or(commit)
+}
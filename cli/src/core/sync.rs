@@ -0,0 +1,545 @@
+//! Matching local workspaces with their copies on the desk API, and applying
+//! pulled workspaces safely.
+//!
+//! Workspaces are identified remotely by a scope and a name. The scope stands
+//! in for the repository so the same project maps onto the right namespace on
+//! every machine, whatever path it is cloned to: it is the normalized `origin`
+//! URL, `local/<dir name>` for repositories without one, or `global`.
+//!
+//! Pulls are staged: every workspace is written under
+//! `<data_dir>/.sync-staging/` first, then moved into place one rename at a
+//! time. The file each rename replaces is kept in the staging area until the
+//! pull finishes, so an interrupted batch can be rolled back.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use glob::{Pattern, PatternError};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::warn;
+
+use super::config::SyncConfig;
+use super::store::{FileWorkspaceStore, Namespace, StoreError};
+use super::workspace::Workspace;
+use crate::client::api::RemoteWorkspace;
+use crate::integrations::git::Git2Operations;
+use crate::utils::duration::format_duration;
+
+const STAGING_DIR: &str = ".sync-staging";
+
+/// Errors raised while staging or applying a pull.
+#[derive(Debug, Error)]
+pub enum SyncError {
+    #[error("failed to access {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+/// What was last exchanged with the API for one workspace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncRecord {
+    pub remote_id: String,
+    /// Remote version the local copy matches: the base version of the next
+    /// change on either side.
+    pub version: u64,
+    /// `updated_at` of the local copy at that point.
+    pub local_updated_at: DateTime<Utc>,
+    pub synced_at: DateTime<Utc>,
+    /// The workspace as exchanged at that point, the common ancestor for
+    /// [three-way merges](super::merge).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<Workspace>,
+}
+
+impl SyncRecord {
+    /// Records that `local` now matches `remote`.
+    pub fn new(remote: &RemoteWorkspace, local: &Workspace) -> Self {
+        Self {
+            remote_id: remote.id.clone(),
+            version: remote.version,
+            local_updated_at: local.updated_at,
+            synced_at: Utc::now(),
+            base: Some(local.clone()),
+        }
+    }
+}
+
+/// A workspace deleted locally after it was synced, kept until the deletion
+/// is pushed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub name: String,
+    /// The sync record the workspace had when it was deleted.
+    pub record: SyncRecord,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// How a local workspace relates to its remote copy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncStatus {
+    InSync,
+    LocalChanges,
+    RemoteChanges,
+    /// Both sides changed since the last sync.
+    Diverged,
+    LocalOnly,
+    RemoteOnly,
+    /// Synced before, but gone from the API since.
+    DeletedRemotely,
+    /// Deleted here; the deletion has not been pushed yet.
+    DeletedLocally,
+}
+
+impl SyncStatus {
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::InSync => "in sync",
+            Self::LocalChanges => "local changes",
+            Self::RemoteChanges => "remote changes",
+            Self::Diverged => "diverged",
+            Self::LocalOnly => "local only",
+            Self::RemoteOnly => "remote only",
+            Self::DeletedRemotely => "deleted remotely",
+            Self::DeletedLocally => "deleted locally",
+        }
+    }
+}
+
+/// Compares the two copies of a workspace against the last sync.
+pub fn classify(
+    local: Option<&Workspace>,
+    remote: Option<&RemoteWorkspace>,
+    record: Option<&SyncRecord>,
+) -> Option<SyncStatus> {
+    let (local, remote) = match (local, remote) {
+        (None, None) => return None,
+        (Some(_), None) if record.is_some() => return Some(SyncStatus::DeletedRemotely),
+        (Some(_), None) => return Some(SyncStatus::LocalOnly),
+        (None, Some(_)) => return Some(SyncStatus::RemoteOnly),
+        (Some(local), Some(remote)) => (local, remote),
+    };
+
+    let Some(record) = record.filter(|r| r.remote_id == remote.id) else {
+        // Never synced from here: only identical copies count as in sync.
+        return Some(if local.updated_at == remote.workspace.updated_at {
+            SyncStatus::InSync
+        } else {
+            SyncStatus::Diverged
+        });
+    };
+
+    let local_changed = local.updated_at != record.local_updated_at;
+    let remote_changed = remote.version > record.version;
+    Some(match (local_changed, remote_changed) {
+        (false, false) => SyncStatus::InSync,
+        (true, false) => SyncStatus::LocalChanges,
+        (false, true) => SyncStatus::RemoteChanges,
+        (true, true) => SyncStatus::Diverged,
+    })
+}
+
+/// What can be told about a workspace's sync state without asking the
+/// backend: whether it was synced, and whether it changed here since.
+pub fn classify_local(local: &Workspace, record: Option<&SyncRecord>) -> SyncStatus {
+    match record {
+        None => SyncStatus::LocalOnly,
+        Some(record) if local.updated_at != record.local_updated_at => SyncStatus::LocalChanges,
+        Some(_) => SyncStatus::InSync,
+    }
+}
+
+/// Which copy changed a field since the last sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangedBy {
+    Local,
+    Remote,
+    Both,
+}
+
+impl ChangedBy {
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Local => "changed locally",
+            Self::Remote => "changed remotely",
+            Self::Both => "changed on both sides",
+        }
+    }
+}
+
+/// A field that differs between a local workspace and its remote copy,
+/// rendered as text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    pub local: String,
+    pub remote: String,
+    /// Known when the copy from the last sync is recorded.
+    pub changed_by: Option<ChangedBy>,
+}
+
+/// Lists the fields that differ between `local` and `remote`.
+///
+/// Each change is attributed to a side when `base`, the copy from the last
+/// sync, is known. Machine-specific fields (repository path, worktree) are
+/// left out.
+pub fn diff_fields(
+    local: &Workspace,
+    remote: &Workspace,
+    base: Option<&Workspace>,
+) -> Vec<FieldDiff> {
+    let base = base.map(field_values);
+    field_values(local)
+        .into_iter()
+        .zip(field_values(remote))
+        .enumerate()
+        .filter(|(_, ((_, l), (_, r)))| l != r)
+        .map(|(i, ((field, local), (_, remote)))| {
+            let changed_by = base.as_ref().map(|base| {
+                let base = &base[i].1;
+                match (&local != base, &remote != base) {
+                    (true, false) => ChangedBy::Local,
+                    (false, true) => ChangedBy::Remote,
+                    _ => ChangedBy::Both,
+                }
+            });
+            FieldDiff {
+                field,
+                local,
+                remote,
+                changed_by,
+            }
+        })
+        .collect()
+}
+
+fn field_values(workspace: &Workspace) -> Vec<(&'static str, String)> {
+    let optional = |value: Option<&str>| value.unwrap_or("-").to_string();
+    let duration = |secs: Option<u64>| {
+        secs.map_or_else(
+            || "-".to_string(),
+            |s| format_duration(Duration::from_secs(s)),
+        )
+    };
+    let time = |at: DateTime<Utc>| at.format("%Y-%m-%d %H:%M:%S").to_string();
+    let meta = &workspace.metadata;
+    vec![
+        ("branch", workspace.state.branch.clone()),
+        ("commit", workspace.state.commit.clone()),
+        ("stash", optional(workspace.state.stash_name.as_deref())),
+        ("description", optional(workspace.description.as_deref())),
+        (
+            "tags",
+            if meta.tags.is_empty() {
+                "-".to_string()
+            } else {
+                meta.tags.join(", ")
+            },
+        ),
+        ("notes", optional(meta.notes.as_deref())),
+        (
+            "env",
+            if meta.env.is_empty() {
+                "-".to_string()
+            } else {
+                meta.env
+                    .iter()
+                    .map(|(name, value)| format!("{name}={value}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            },
+        ),
+        (
+            "todos",
+            if meta.todos.is_empty() {
+                "-".to_string()
+            } else {
+                meta.todos
+                    .iter()
+                    .map(|item| format!("[{}] {}", if item.done { "x" } else { " " }, item.text))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            },
+        ),
+        (
+            "issue",
+            meta.issue
+                .as_ref()
+                .map_or_else(|| "-".to_string(), ToString::to_string),
+        ),
+        (
+            "context",
+            if workspace.state.context.is_empty() {
+                "-".to_string()
+            } else {
+                workspace
+                    .state
+                    .context
+                    .iter()
+                    .map(|(name, saved)| format!("{name}={saved}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            },
+        ),
+        ("budget", duration(meta.time_budget_secs)),
+        ("time spent", duration(Some(meta.time_spent_secs))),
+        ("locked", if meta.locked { "yes" } else { "no" }.to_string()),
+        ("created", time(workspace.created_at)),
+        ("updated", time(workspace.updated_at)),
+    ]
+}
+
+/// The `[sync] exclude` patterns, compiled.
+#[derive(Debug, Clone, Default)]
+pub struct SyncFilter {
+    patterns: Vec<Pattern>,
+}
+
+impl SyncFilter {
+    pub fn new(config: &SyncConfig) -> Result<Self, PatternError> {
+        let patterns = config
+            .exclude
+            .iter()
+            .map(|p| Pattern::new(p))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { patterns })
+    }
+
+    /// Whether workspace `name` is kept out of sync.
+    pub fn excludes(&self, name: &str) -> bool {
+        self.patterns.iter().any(|p| p.matches(name))
+    }
+}
+
+/// Remote scope of the workspaces in `namespace`.
+pub fn sync_scope(namespace: &Namespace) -> String {
+    match namespace {
+        Namespace::Global => "global".to_string(),
+        Namespace::Repo(root) => repo_origin(root).unwrap_or_else(|| {
+            let name = root
+                .file_name()
+                .map_or_else(|| "repo".into(), |n| n.to_string_lossy());
+            format!("local/{name}")
+        }),
+    }
+}
+
+/// The `origin` remote of the repository at `root` as `host/path`, if it
+/// has one.
+pub fn repo_origin(root: &Path) -> Option<String> {
+    Git2Operations::new(root)
+        .remote_url("origin")
+        .ok()
+        .flatten()
+        .map(|url| normalize_remote_url(&url))
+}
+
+/// Key of a workspace in [`DeskState::sync`](super::state::DeskState::sync).
+pub fn sync_key(scope: &str, name: &str) -> String {
+    format!("{scope}/{name}")
+}
+
+/// Reduces the many spellings of a remote URL to `host/path`, so that SSH
+/// and HTTPS clones of one repository share a scope.
+fn normalize_remote_url(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    let (rest, scp_like) = url
+        .split_once("://")
+        .map_or((url, true), |(_, rest)| (rest, false));
+    let rest = match rest.split_once('@') {
+        Some((user, host)) if !user.contains('/') => host,
+        _ => rest,
+    };
+    if scp_like {
+        rest.replacen(':', "/", 1)
+    } else {
+        rest.to_string()
+    }
+}
+
+/// How a staged pull is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyMode {
+    /// Keep whatever was applied when interrupted.
+    PerWorkspace,
+    /// Undo everything unless every workspace was applied.
+    Batch,
+}
+
+/// A workspace waiting in the staging area.
+#[derive(Debug)]
+struct StagedWorkspace {
+    key: String,
+    name: String,
+    record: SyncRecord,
+    store: FileWorkspaceStore,
+    staged: PathBuf,
+    backup: PathBuf,
+}
+
+/// Outcome of [`PullStage::apply`].
+#[derive(Debug, Default)]
+pub struct PullOutcome {
+    /// Keys of the workspaces now on disk in their pulled form.
+    pub applied: Vec<String>,
+    /// Keys of staged workspaces that were not applied.
+    pub pending: Vec<String>,
+    pub interrupted: bool,
+    pub rolled_back: bool,
+    /// The error that stopped the pull early, if any.
+    pub error: Option<SyncError>,
+    /// Sync records for the applied workspaces, by key.
+    pub records: HashMap<String, SyncRecord>,
+}
+
+/// Pulled workspaces written to disk but not yet moved into place.
+#[derive(Debug)]
+pub struct PullStage {
+    root: PathBuf,
+    items: Vec<StagedWorkspace>,
+}
+
+impl PullStage {
+    /// Creates an empty staging area under `data_dir`.
+    ///
+    /// A staging area left behind by a crashed pull still holds the files
+    /// that pull replaced, so it is moved aside rather than deleted.
+    pub fn create(data_dir: &Path) -> Result<Self, SyncError> {
+        let root = data_dir.join(STAGING_DIR);
+        if root.exists() {
+            let aside = data_dir.join(format!(
+                ".sync-interrupted-{}",
+                Utc::now().format("%Y%m%d-%H%M%S")
+            ));
+            std::fs::rename(&root, &aside).map_err(|source| io_error(&root, source))?;
+            warn!(
+                "a previous sync pull did not finish; the files it replaced are in {}",
+                aside.display()
+            );
+        }
+        Ok(Self {
+            root,
+            items: Vec::new(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Writes `workspace` to the staging area, to be installed into `store`
+    /// and recorded as `record` once applied.
+    pub fn add(
+        &mut self,
+        store: &FileWorkspaceStore,
+        workspace: &Workspace,
+        remote: &RemoteWorkspace,
+        record: SyncRecord,
+    ) -> Result<(), SyncError> {
+        let staging_store = FileWorkspaceStore::new(&self.root, store.namespace().clone());
+        staging_store.save(workspace)?;
+        let staged = staging_store.path_for(&workspace.name)?;
+        self.items.push(StagedWorkspace {
+            key: sync_key(&remote.scope, &remote.name),
+            name: workspace.name.clone(),
+            record,
+            store: store.clone(),
+            backup: staged.with_extension("prev"),
+            staged,
+        });
+        Ok(())
+    }
+
+    /// Moves the staged workspaces into place, stopping early once `cancel`
+    /// is set. In [`ApplyMode::Batch`] an early stop restores every
+    /// workspace that had already been replaced.
+    pub fn apply(self, cancel: &AtomicBool, mode: ApplyMode) -> PullOutcome {
+        let mut outcome = PullOutcome::default();
+        let mut installed = Vec::new();
+
+        for item in &self.items {
+            if cancel.load(Ordering::SeqCst) {
+                outcome.interrupted = true;
+                break;
+            }
+            match install(item) {
+                Ok(()) => installed.push(item),
+                Err(e) => {
+                    outcome.error = Some(e);
+                    break;
+                },
+            }
+        }
+
+        let stopped_early = outcome.interrupted || outcome.error.is_some();
+        if stopped_early && mode == ApplyMode::Batch {
+            let mut kept = Vec::new();
+            for item in installed.into_iter().rev() {
+                if let Err(e) = roll_back(item) {
+                    warn!("could not restore '{}': {e}", item.name);
+                    kept.push(item);
+                }
+            }
+            outcome.rolled_back = kept.is_empty();
+            installed = kept;
+        }
+
+        for item in &self.items {
+            if installed.iter().any(|done| std::ptr::eq(*done, item)) {
+                outcome.applied.push(item.key.clone());
+                outcome
+                    .records
+                    .insert(item.key.clone(), item.record.clone());
+            } else {
+                outcome.pending.push(item.key.clone());
+            }
+        }
+
+        if let Err(e) = std::fs::remove_dir_all(&self.root) {
+            warn!("could not clean up {}: {e}", self.root.display());
+        }
+        outcome
+    }
+}
+
+/// Replaces the live file with the staged one in a single rename, keeping a
+/// copy of the file it replaces.
+fn install(item: &StagedWorkspace) -> Result<(), SyncError> {
+    item.store.prepare_dir()?;
+    let target = item.store.path_for(&item.name)?;
+    if target.exists() {
+        std::fs::copy(&target, &item.backup).map_err(|source| io_error(&target, source))?;
+    }
+    std::fs::rename(&item.staged, &target).map_err(|source| io_error(&target, source))
+}
+
+fn roll_back(item: &StagedWorkspace) -> Result<(), SyncError> {
+    let target = item.store.path_for(&item.name)?;
+    let result = if item.backup.exists() {
+        std::fs::rename(&item.backup, &target)
+    } else {
+        std::fs::remove_file(&target)
+    };
+    result.map_err(|source| io_error(&target, source))
+}
+
+fn io_error(path: &Path, source: std::io::Error) -> SyncError {
+    SyncError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
@@ -0,0 +1,170 @@
+//! Time spent in workspaces, interval by interval, for `desk time report`.
+//!
+//! Each session, from opening a workspace to switching away from it, is
+//! appended to `time-log.jsonl` in the data directory as one JSON line. The
+//! workspace's own `time_spent_secs` keeps the running total budgets use.
+
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::state::DeskState;
+
+const TIME_LOG_FILE: &str = "time-log.jsonl";
+
+/// A stretch of time with a workspace open.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interval {
+    /// Repository path, as used for
+    /// [`DeskState::current_workspaces`](super::state::DeskState::current_workspaces).
+    pub repo: String,
+    pub workspace: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+impl Interval {
+    /// How much of the interval falls between `since` and `until`.
+    pub fn overlap(&self, since: Option<DateTime<Utc>>, until: DateTime<Utc>) -> Duration {
+        let start = since.map_or(self.start, |since| self.start.max(since));
+        let end = self.end.min(until);
+        (end - start).to_std().unwrap_or_default()
+    }
+}
+
+/// The recorded intervals under a data directory.
+#[derive(Debug, Clone)]
+pub struct TimeLog {
+    path: PathBuf,
+}
+
+impl TimeLog {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            path: data_dir.join(TIME_LOG_FILE),
+        }
+    }
+
+    /// Appends a finished interval.
+    pub fn record(&self, interval: &Interval) -> Result<()> {
+        let mut line = serde_json::to_string(interval)?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("failed to write {}", self.path.display()))
+    }
+
+    /// Every recorded interval, oldest first. Lines that cannot be read, such
+    /// as one cut short by a crash, are skipped.
+    pub fn read(&self) -> Result<Vec<Interval>> {
+        let contents = match std::fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", self.path.display()))
+            },
+        };
+        Ok(contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .filter_map(|(i, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| {
+                        warn!("skipping line {} of {}: {e}", i + 1, self.path.display());
+                    })
+                    .ok()
+            })
+            .collect())
+    }
+}
+
+/// The start of `date` in the local time zone.
+pub fn local_midnight(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0)
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .map_or_else(Utc::now, |midnight| midnight.with_timezone(&Utc))
+}
+
+/// The sessions open right now, as intervals ending at `now`.
+pub fn open_intervals(state: &DeskState, now: DateTime<Utc>) -> Vec<Interval> {
+    state
+        .current_workspaces
+        .iter()
+        .filter_map(|(repo, workspace)| {
+            let start = *state.current_opened_at.get(repo)?;
+            Some(Interval {
+                repo: repo.clone(),
+                workspace: workspace.clone(),
+                start,
+                end: now,
+            })
+        })
+        .collect()
+}
+
+/// What a report adds time up by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Grouping {
+    #[default]
+    Workspace,
+    Repo,
+}
+
+/// Time spent in one workspace, or one repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportRow {
+    pub repo: String,
+    /// `None` when grouping by repository.
+    pub workspace: Option<String>,
+    pub total: Duration,
+}
+
+/// Adds up the time `intervals` spent between `since` (from the start, when
+/// `None`) and `until`, most time first.
+pub fn report(
+    intervals: &[Interval],
+    since: Option<DateTime<Utc>>,
+    until: DateTime<Utc>,
+    by: Grouping,
+) -> Vec<ReportRow> {
+    let mut totals: HashMap<(&str, Option<&str>), Duration> = HashMap::new();
+    for interval in intervals {
+        let spent = interval.overlap(since, until);
+        if spent.is_zero() {
+            continue;
+        }
+        let workspace = match by {
+            Grouping::Workspace => Some(interval.workspace.as_str()),
+            Grouping::Repo => None,
+        };
+        *totals
+            .entry((interval.repo.as_str(), workspace))
+            .or_default() += spent;
+    }
+    let mut rows: Vec<_> = totals
+        .into_iter()
+        .map(|((repo, workspace), total)| ReportRow {
+            repo: repo.to_string(),
+            workspace: workspace.map(String::from),
+            total,
+        })
+        .collect();
+    rows.sort_by(|a, b| {
+        b.total
+            .cmp(&a.total)
+            .then_with(|| a.repo.cmp(&b.repo))
+            .then_with(|| a.workspace.cmp(&b.workspace))
+    });
+    rows
+}
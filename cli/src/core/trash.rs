@@ -0,0 +1,196 @@
+//! Deleted workspaces, kept for a while so they can be restored.
+//!
+//! Each deleted workspace is one file under `<data_dir>/trash/`, recording the
+//! namespace it came from and when it was deleted. `desk gc` removes entries
+//! older than `trash.retention_days`.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::store::{file_stem, FileWorkspaceStore, Namespace, StoreError};
+use super::workspace::Workspace;
+
+/// Errors raised by [`Trash`].
+#[derive(Debug, Error)]
+pub enum TrashError {
+    #[error("no deleted workspace named '{0}' in the trash")]
+    NotInTrash(String),
+
+    #[error("workspace '{0}' is locked; run `desk unlock {0}` before deleting it")]
+    Locked(String),
+
+    #[error("workspace '{0}' already exists; delete or rename it before restoring")]
+    AlreadyExists(String),
+
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+/// Contents of one trash file.
+#[derive(Debug, Serialize, Deserialize)]
+struct TrashedWorkspace {
+    deleted_at: DateTime<Utc>,
+    namespace: Namespace,
+    workspace: Workspace,
+}
+
+/// A workspace in the trash.
+#[derive(Debug, Clone)]
+pub struct TrashEntry {
+    pub name: String,
+    pub namespace: Namespace,
+    pub deleted_at: DateTime<Utc>,
+    path: PathBuf,
+}
+
+impl TrashEntry {
+    /// Size of the trash file, in bytes.
+    pub fn size(&self) -> Result<u64, StoreError> {
+        std::fs::metadata(&self.path)
+            .map(|meta| meta.len())
+            .map_err(|source| StoreError::Io {
+                path: self.path.clone(),
+                source,
+            })
+    }
+}
+
+/// The trash directory.
+#[derive(Debug, Clone)]
+pub struct Trash {
+    dir: PathBuf,
+}
+
+impl Trash {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            dir: data_dir.join("trash"),
+        }
+    }
+
+    /// Moves workspace `name` out of `store` into the trash.
+    pub fn put(&self, store: &FileWorkspaceStore, name: &str) -> Result<TrashEntry, TrashError> {
+        let workspace = store.load(name)?;
+        if workspace.metadata.locked {
+            return Err(TrashError::Locked(name.to_string()));
+        }
+        let deleted_at = Utc::now();
+        let path = self.dir.join(format!(
+            "{}-{}.json",
+            deleted_at.format("%Y%m%dT%H%M%S%.6f"),
+            file_stem(name)
+        ));
+        let trashed = TrashedWorkspace {
+            deleted_at,
+            namespace: store.namespace().clone(),
+            workspace,
+        };
+
+        let json = serde_json::to_vec_pretty(&trashed).map_err(|source| StoreError::Encode {
+            name: name.to_string(),
+            source,
+        })?;
+        std::fs::create_dir_all(&self.dir)
+            .and_then(|()| std::fs::write(&path, json))
+            .map_err(|source| StoreError::Io {
+                path: path.clone(),
+                source,
+            })?;
+        store.delete(name)?;
+
+        Ok(TrashEntry {
+            name: name.to_string(),
+            namespace: trashed.namespace,
+            deleted_at,
+            path,
+        })
+    }
+
+    /// Lists everything in the trash, most recently deleted first.
+    pub fn list(&self) -> Result<Vec<TrashEntry>, TrashError> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(source) => {
+                return Err(StoreError::Io {
+                    path: self.dir.clone(),
+                    source,
+                }
+                .into())
+            },
+        };
+
+        let mut trashed = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let item = read(&path)?;
+                trashed.push(TrashEntry {
+                    name: item.workspace.name,
+                    namespace: item.namespace,
+                    deleted_at: item.deleted_at,
+                    path,
+                });
+            }
+        }
+        trashed.sort_by_key(|entry| std::cmp::Reverse(entry.deleted_at));
+        Ok(trashed)
+    }
+
+    /// Entries deleted more than `retention_days` ago, which `desk gc` removes.
+    pub fn expired(&self, retention_days: u32) -> Result<Vec<TrashEntry>, TrashError> {
+        let cutoff = Utc::now() - Duration::days(i64::from(retention_days));
+        let mut entries = self.list()?;
+        entries.retain(|entry| entry.deleted_at <= cutoff);
+        Ok(entries)
+    }
+
+    /// Finds the most recently deleted workspace called `name` in `namespace`.
+    pub fn find(&self, namespace: &Namespace, name: &str) -> Result<TrashEntry, TrashError> {
+        self.list()?
+            .into_iter()
+            .find(|entry| entry.name == name && &entry.namespace == namespace)
+            .ok_or_else(|| TrashError::NotInTrash(name.to_string()))
+    }
+
+    /// Puts `entry` back into its namespace under `data_dir`.
+    pub fn restore(&self, entry: &TrashEntry, data_dir: &Path) -> Result<Workspace, TrashError> {
+        let store = FileWorkspaceStore::new(data_dir, entry.namespace.clone());
+        if store.exists(&entry.name) {
+            return Err(TrashError::AlreadyExists(entry.name.clone()));
+        }
+        let workspace = read(&entry.path)?.workspace;
+        store.save(&workspace)?;
+        remove(&entry.path)?;
+        Ok(workspace)
+    }
+
+    /// Permanently deletes `entry`.
+    pub fn purge(&self, entry: &TrashEntry) -> Result<(), TrashError> {
+        remove(&entry.path)
+    }
+}
+
+fn read(path: &Path) -> Result<TrashedWorkspace, StoreError> {
+    let contents = std::fs::read(path).map_err(|source| StoreError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    serde_json::from_slice(&contents).map_err(|source| StoreError::Corrupt {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+fn remove(path: &Path) -> Result<(), TrashError> {
+    std::fs::remove_file(path).map_err(|source| {
+        StoreError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+        .into()
+    })
+}
@@ -0,0 +1,135 @@
+//! Disk usage of the data directory, reported by `desk du`.
+
+use std::path::{Path, PathBuf};
+
+use super::store::{FileWorkspaceStore, Namespace, StoreError};
+
+/// Prefixes of directories holding data set aside by restores and syncs.
+const LEFTOVER_PREFIXES: [&str; 2] = [".pre-restore-", ".sync-interrupted-"];
+
+/// Storage taken by one saved workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceUsage {
+    pub name: String,
+    pub namespace: Namespace,
+    /// Size of the workspace file.
+    pub bytes: u64,
+    /// Part of `bytes` taken by the workspace's notes.
+    pub notes_bytes: u64,
+}
+
+/// Data replaced by `desk restore-backup` or set aside by an interrupted
+/// `desk sync pull`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leftover {
+    pub path: PathBuf,
+    pub bytes: u64,
+}
+
+/// Storage taken by the data directory, broken down by what it holds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DataUsage {
+    /// Saved workspaces, largest first.
+    pub workspaces: Vec<WorkspaceUsage>,
+    /// Everything under `workspaces/`, including namespace markers.
+    pub workspaces_bytes: u64,
+    pub trash_bytes: u64,
+    pub state_bytes: u64,
+    pub leftovers: Vec<Leftover>,
+    /// Anything else, such as the lock file.
+    pub other_bytes: u64,
+}
+
+impl DataUsage {
+    /// Measures everything under `data_dir`.
+    pub fn measure(data_dir: &Path) -> Result<Self, StoreError> {
+        let mut usage = Self::default();
+
+        for store in FileWorkspaceStore::all(data_dir)? {
+            for workspace in store.list()? {
+                let path = store.path_for(&workspace.name)?;
+                usage.workspaces.push(WorkspaceUsage {
+                    bytes: file_size(&path)?,
+                    notes_bytes: workspace
+                        .metadata
+                        .notes
+                        .as_ref()
+                        .map_or(0, |n| n.len() as u64),
+                    name: workspace.name,
+                    namespace: store.namespace().clone(),
+                });
+            }
+        }
+        usage
+            .workspaces
+            .sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+
+        let entries = match std::fs::read_dir(data_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(usage),
+            Err(source) => {
+                return Err(StoreError::Io {
+                    path: data_dir.to_path_buf(),
+                    source,
+                })
+            },
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let bytes = disk_size(&path)?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            match name.as_ref() {
+                "workspaces" => usage.workspaces_bytes += bytes,
+                "trash" => usage.trash_bytes += bytes,
+                "state.json" => usage.state_bytes += bytes,
+                _ if LEFTOVER_PREFIXES.iter().any(|p| name.starts_with(p)) => {
+                    usage.leftovers.push(Leftover { path, bytes });
+                },
+                _ => usage.other_bytes += bytes,
+            }
+        }
+        usage.leftovers.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(usage)
+    }
+
+    pub fn leftovers_bytes(&self) -> u64 {
+        self.leftovers.iter().map(|l| l.bytes).sum()
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.workspaces_bytes
+            + self.trash_bytes
+            + self.state_bytes
+            + self.leftovers_bytes()
+            + self.other_bytes
+    }
+}
+
+/// Size of a file, or of everything below a directory. Symlinks are not
+/// followed.
+pub fn disk_size(path: &Path) -> Result<u64, StoreError> {
+    let io_err = |source| StoreError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let meta = path.symlink_metadata().map_err(io_err)?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+
+    let mut total = 0;
+    for entry in std::fs::read_dir(path).map_err(io_err)?.flatten() {
+        total += disk_size(&entry.path())?;
+    }
+    Ok(total)
+}
+
+fn file_size(path: &Path) -> Result<u64, StoreError> {
+    path.metadata()
+        .map(|meta| meta.len())
+        .map_err(|source| StoreError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+}
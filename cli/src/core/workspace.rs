@@ -0,0 +1,147 @@
+//! The saved development context for one task.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::migrations::WORKSPACE_SCHEMA_VERSION;
+
+/// A named, saved development context.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Workspace {
+    /// On-disk format version; see [`super::migrations`].
+    #[serde(default)]
+    pub schema_version: u32,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Root of the repository the workspace was captured in.
+    pub repo_path: PathBuf,
+    /// The repository's `origin` remote as `host/path` when the workspace
+    /// was captured, telling clones of it from other repositories.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+    pub state: WorkspaceState,
+    #[serde(default)]
+    pub metadata: WorkspaceMetadata,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Git state captured for a workspace.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceState {
+    pub branch: String,
+    pub commit: String,
+    /// Message of the stash holding uncommitted changes, if any were saved.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stash_name: Option<String>,
+    /// Linked worktree holding this workspace's checkout, in worktree mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worktree: Option<PathBuf>,
+    /// What each context provider saved, by provider name; see
+    /// [`crate::core::context`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub context: BTreeMap<String, serde_json::Value>,
+}
+
+/// Free-form information attached to a workspace.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkspaceMetadata {
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    /// Time allowed for this workspace, in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_budget_secs: Option<u64>,
+    /// Time spent in this workspace across closed sessions, in seconds.
+    pub time_spent_secs: u64,
+    /// Protects the workspace from being overwritten, deleted, or renamed
+    /// until `desk unlock`.
+    pub locked: bool,
+    /// Environment variables handed to direnv while the workspace is open;
+    /// see [`crate::core::envrc`].
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Jira or Linear ticket the workspace was opened for with
+    /// `desk open --issue`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issue: Option<IssueLink>,
+    /// Next actions kept with `desk todo`, in the order they were added.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub todos: Vec<TodoItem>,
+}
+
+/// An item on a workspace's to-do list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TodoItem {
+    pub text: String,
+    #[serde(default)]
+    pub done: bool,
+}
+
+/// A ticket in an issue tracker.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IssueLink {
+    pub tracker: IssueTracker,
+    /// Key of the ticket, like `PROJ-123`.
+    pub key: String,
+}
+
+impl std::fmt::Display for IssueLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.key, self.tracker)
+    }
+}
+
+/// Issue trackers desk can open workspaces for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IssueTracker {
+    Jira,
+    Linear,
+}
+
+impl std::fmt::Display for IssueTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Jira => "Jira",
+            Self::Linear => "Linear",
+        })
+    }
+}
+
+impl Workspace {
+    /// Creates a workspace stamped with the current time.
+    pub fn new(name: impl Into<String>, repo_path: PathBuf, state: WorkspaceState) -> Self {
+        let now = Utc::now();
+        Self {
+            schema_version: WORKSPACE_SCHEMA_VERSION,
+            name: name.into(),
+            description: None,
+            repo_path,
+            origin: None,
+            state,
+            metadata: WorkspaceMetadata::default(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Bumps `updated_at` to now.
+    pub fn touch(&mut self) {
+        self.updated_at = Utc::now();
+    }
+
+    /// Adds a finished session to the time spent in this workspace.
+    pub fn record_time_spent(&mut self, session: Duration) {
+        self.metadata.time_spent_secs = self
+            .metadata
+            .time_spent_secs
+            .saturating_add(session.as_secs());
+    }
+}
@@ -0,0 +1,121 @@
+//! Correlation between workspaces and the git worktrees backing them.
+
+use std::path::{Path, PathBuf};
+
+use crate::integrations::git::WorktreeInfo;
+
+use super::workspace::Workspace;
+
+/// File desk leaves in the administrative directory of each worktree it
+/// creates. Worktrees without it were added by hand and are never removed,
+/// whatever they are called.
+pub const MANAGED_MARKER: &str = "desk-managed";
+
+/// How a worktree and the workspaces relate to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorktreeStatus {
+    /// The worktree exists and a workspace points at it.
+    Linked,
+    /// A worktree desk created exists on disk but no workspace points at it.
+    Orphaned,
+    /// Git still lists the worktree but its checkout was deleted.
+    Stale,
+    /// A workspace points at a worktree whose checkout no longer exists.
+    Missing,
+    /// A worktree desk did not create.
+    Unmanaged,
+}
+
+impl WorktreeStatus {
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Linked => "linked",
+            Self::Orphaned => "orphaned",
+            Self::Stale => "stale",
+            Self::Missing => "missing",
+            Self::Unmanaged => "unmanaged",
+        }
+    }
+}
+
+/// One row of the worktree report.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorktreeEntry {
+    /// Git's name for the worktree, if git still knows about it.
+    pub name: Option<String>,
+    pub path: PathBuf,
+    pub workspace: Option<String>,
+    pub locked: bool,
+    pub status: WorktreeStatus,
+}
+
+/// Pairs the repository's worktrees with the workspaces referring to them.
+pub fn correlate(workspaces: &[Workspace], worktrees: &[WorktreeInfo]) -> Vec<WorktreeEntry> {
+    let mut entries = Vec::new();
+
+    for worktree in worktrees {
+        let workspace = workspaces
+            .iter()
+            .find(|ws| {
+                ws.state
+                    .worktree
+                    .as_deref()
+                    .is_some_and(|p| same_path(p, &worktree.path))
+            })
+            .map(|ws| ws.name.clone());
+        let status = match (&workspace, worktree.exists) {
+            (Some(_), true) => WorktreeStatus::Linked,
+            (Some(_), false) => WorktreeStatus::Missing,
+            (None, _) if !is_managed(worktree) => WorktreeStatus::Unmanaged,
+            (None, true) => WorktreeStatus::Orphaned,
+            (None, false) => WorktreeStatus::Stale,
+        };
+        entries.push(WorktreeEntry {
+            name: Some(worktree.name.clone()),
+            path: worktree.path.clone(),
+            workspace,
+            locked: worktree.locked,
+            status,
+        });
+    }
+
+    // Workspaces whose worktree git has already forgotten about.
+    for ws in workspaces {
+        let Some(path) = &ws.state.worktree else {
+            continue;
+        };
+        if worktrees.iter().any(|wt| same_path(path, &wt.path)) || path.exists() {
+            continue;
+        }
+        entries.push(WorktreeEntry {
+            name: None,
+            path: path.clone(),
+            workspace: Some(ws.name.clone()),
+            locked: false,
+            status: WorktreeStatus::Missing,
+        });
+    }
+
+    entries
+}
+
+/// Whether desk created `worktree`.
+pub fn is_managed(worktree: &WorktreeInfo) -> bool {
+    worktree.admin_dir.join(MANAGED_MARKER).is_file()
+}
+
+/// Records that desk created `worktree`, so that `desk worktree prune` may
+/// remove it once no workspace uses it.
+pub fn mark_managed(worktree: &WorktreeInfo) -> std::io::Result<()> {
+    std::fs::write(worktree.admin_dir.join(MANAGED_MARKER), "")
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    if a == b {
+        return true;
+    }
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
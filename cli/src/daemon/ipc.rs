@@ -0,0 +1,161 @@
+//! The channel between the CLI and the daemon: a Unix socket in the data
+//! directory, or on Windows a named pipe named after it.
+
+use std::path::Path;
+
+pub use platform::{connect, Listener, Stream};
+
+#[cfg(unix)]
+mod platform {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::{Path, PathBuf};
+
+    use tokio::net::{UnixListener, UnixStream};
+
+    pub type Stream = UnixStream;
+
+    /// Accepts connections from the CLI.
+    #[derive(Debug)]
+    pub struct Listener {
+        listener: UnixListener,
+        path: PathBuf,
+    }
+
+    impl Listener {
+        /// Listens for `data_dir`, replacing the socket of a daemon that is
+        /// no longer running. Only the current user may connect.
+        pub fn bind(data_dir: &Path) -> std::io::Result<Self> {
+            let path = super::socket_path(data_dir);
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {},
+            }
+            let listener = UnixListener::bind(&path)?;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+            Ok(Self { listener, path })
+        }
+
+        pub async fn accept(&mut self) -> std::io::Result<Stream> {
+            Ok(self.listener.accept().await?.0)
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    pub async fn connect(data_dir: &Path) -> std::io::Result<Stream> {
+        UnixStream::connect(super::socket_path(data_dir)).await
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::path::Path;
+
+    use tokio::net::windows::named_pipe::{
+        ClientOptions, NamedPipeClient, NamedPipeServer, ServerOptions,
+    };
+
+    /// The server and client ends differ on Windows; a connection is handed
+    /// out as whichever end this side holds.
+    pub enum Stream {
+        Server(NamedPipeServer),
+        Client(NamedPipeClient),
+    }
+
+    /// Accepts connections from the CLI.
+    #[derive(Debug)]
+    pub struct Listener {
+        name: String,
+        next: NamedPipeServer,
+    }
+
+    impl Listener {
+        /// Listens for `data_dir`; fails if another daemon already does.
+        pub fn bind(data_dir: &Path) -> std::io::Result<Self> {
+            let name = super::pipe_name(data_dir);
+            let next = ServerOptions::new()
+                .first_pipe_instance(true)
+                .create(&name)?;
+            Ok(Self { name, next })
+        }
+
+        pub async fn accept(&mut self) -> std::io::Result<Stream> {
+            self.next.connect().await?;
+            let next = ServerOptions::new().create(&self.name)?;
+            Ok(Stream::Server(std::mem::replace(&mut self.next, next)))
+        }
+    }
+
+    pub async fn connect(data_dir: &Path) -> std::io::Result<Stream> {
+        Ok(Stream::Client(
+            ClientOptions::new().open(super::pipe_name(data_dir))?,
+        ))
+    }
+
+    impl tokio::io::AsyncRead for Stream {
+        fn poll_read(
+            self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            match self.get_mut() {
+                Self::Server(pipe) => std::pin::Pin::new(pipe).poll_read(cx, buf),
+                Self::Client(pipe) => std::pin::Pin::new(pipe).poll_read(cx, buf),
+            }
+        }
+    }
+
+    impl tokio::io::AsyncWrite for Stream {
+        fn poll_write(
+            self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            match self.get_mut() {
+                Self::Server(pipe) => std::pin::Pin::new(pipe).poll_write(cx, buf),
+                Self::Client(pipe) => std::pin::Pin::new(pipe).poll_write(cx, buf),
+            }
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            match self.get_mut() {
+                Self::Server(pipe) => std::pin::Pin::new(pipe).poll_flush(cx),
+                Self::Client(pipe) => std::pin::Pin::new(pipe).poll_flush(cx),
+            }
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            cx: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            match self.get_mut() {
+                Self::Server(pipe) => std::pin::Pin::new(pipe).poll_shutdown(cx),
+                Self::Client(pipe) => std::pin::Pin::new(pipe).poll_shutdown(cx),
+            }
+        }
+    }
+}
+
+/// The socket the daemon for `data_dir` listens on.
+#[cfg(unix)]
+fn socket_path(data_dir: &Path) -> std::path::PathBuf {
+    data_dir.join("daemon.sock")
+}
+
+/// The pipe the daemon for `data_dir` listens on. Pipes live in one
+/// namespace for the whole machine, so the name includes a hash of the
+/// directory.
+#[cfg(windows)]
+fn pipe_name(data_dir: &Path) -> String {
+    use sha2::{Digest, Sha256};
+
+    let digest = Sha256::digest(data_dir.to_string_lossy().as_bytes());
+    format!(r"\\.\pipe\desk-{}", hex::encode(&digest[..8]))
+}
@@ -0,0 +1,168 @@
+//! Talking to `desk daemon`.
+//!
+//! The daemon is a background process that keeps the desk API session fresh,
+//! syncs and collects garbage on a schedule, and watches open workspaces so
+//! that prompts can ask about them cheaply.
+//!
+//! The daemon listens on a Unix socket (a named pipe on Windows) tied to
+//! the data directory; see [`ipc`]. Every connection carries one request and
+//! one response, each a line of JSON.
+
+pub mod ipc;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+use crate::core::budget::BudgetAlert;
+use crate::integrations::git::Upstream;
+
+/// How long the CLI waits for the daemon before doing the work itself.
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Longest request or response line accepted.
+const MAX_LINE: usize = 64 * 1024;
+
+/// Errors raised while talking to the daemon.
+#[derive(Debug, Error)]
+pub enum DaemonError {
+    #[error("failed to talk to the desk daemon")]
+    Io(#[from] std::io::Error),
+
+    #[error("the desk daemon did not answer in time")]
+    Timeout,
+
+    #[error("unreadable message from the desk daemon")]
+    Protocol(#[from] serde_json::Error),
+
+    #[error("the desk daemon closed the connection without answering")]
+    Closed,
+
+    #[error("the desk daemon failed: {0}")]
+    Failed(String),
+}
+
+/// What the CLI asks the daemon.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum Request {
+    /// What the daemon has been up to.
+    Status,
+    /// The workspace open in repository `repo`, for `desk prompt`.
+    Prompt { repo: PathBuf },
+    /// Stop the daemon.
+    Shutdown,
+}
+
+/// What the daemon answers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum Response {
+    Status(DaemonStatus),
+    Prompt { prompt: Option<PromptInfo> },
+    Stopping,
+    Error { message: String },
+}
+
+/// The open workspace of a repository and how its checkout drifted from
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptInfo {
+    pub workspace: String,
+    /// Branch checked out, if any.
+    pub branch: Option<String>,
+    /// How the checkout moved on since the workspace was last saved, such
+    /// as `new commits`; empty when it matches.
+    pub drift: Vec<String>,
+    /// How the branch compares to the branch it tracks, if it tracks one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<Upstream>,
+    /// How close the workspace is to its time budget, once it is nearly used
+    /// up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<BudgetAlert>,
+    /// When this was worked out.
+    pub checked_at: DateTime<Utc>,
+}
+
+/// A run of a scheduled job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JobRun {
+    pub at: DateTime<Utc>,
+    pub ok: bool,
+    /// Last line the job printed, or why it failed.
+    pub summary: String,
+}
+
+/// What `desk daemon status` shows.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub pid: u32,
+    pub version: String,
+    pub started_at: DateTime<Utc>,
+    /// Repositories with an open workspace being watched.
+    pub watched: usize,
+    pub last_sync: Option<JobRun>,
+    pub last_gc: Option<JobRun>,
+    /// Last check of the desk API session.
+    pub last_session_check: Option<JobRun>,
+}
+
+/// File the daemon writes its log to under the data directory.
+pub const LOG_FILE: &str = "daemon.log";
+
+/// Where the daemon's log goes.
+pub fn log_path(data_dir: &Path) -> PathBuf {
+    data_dir.join(LOG_FILE)
+}
+
+/// Sends `request` to the daemon serving `data_dir`. `None` if no daemon is
+/// running.
+pub async fn request(data_dir: &Path, request: &Request) -> Result<Option<Response>, DaemonError> {
+    let Ok(stream) = ipc::connect(data_dir).await else {
+        return Ok(None);
+    };
+    let exchange = async {
+        let (reader, mut writer) = tokio::io::split(stream);
+        write_message(&mut writer, request).await?;
+        read_message(&mut BufReader::new(reader))
+            .await?
+            .ok_or(DaemonError::Closed)
+    };
+    match tokio::time::timeout(REQUEST_TIMEOUT, exchange).await {
+        Ok(Ok(Response::Error { message })) => Err(DaemonError::Failed(message)),
+        Ok(response) => response.map(Some),
+        Err(_) => Err(DaemonError::Timeout),
+    }
+}
+
+/// Writes `message` as one line of JSON.
+pub async fn write_message<W, T>(writer: &mut W, message: &T) -> Result<(), DaemonError>
+where
+    W: tokio::io::AsyncWrite + Unpin + Send,
+    T: Serialize + Sync,
+{
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Reads one line of JSON; `None` if the other side hung up first.
+pub async fn read_message<R, T>(reader: &mut R) -> Result<Option<T>, DaemonError>
+where
+    R: tokio::io::AsyncBufRead + Unpin + Send,
+    T: for<'de> Deserialize<'de>,
+{
+    let mut line = Vec::new();
+    let mut limited = reader.take(MAX_LINE as u64);
+    if limited.read_until(b'\n', &mut line).await? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_slice(&line)?))
+}
@@ -0,0 +1,310 @@
+//! Docker Compose topology capture.
+//!
+//! Branches often need different service topologies. Projects usually keep a
+//! few override variants (`docker-compose.override.pg15.yml`, ...) and symlink
+//! the active one to the name Compose loads automatically. This module records
+//! which variant was active and re-points that symlink on restore. Regular
+//! (non-symlink) override files are never touched.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::output::{Reporter, StepEvent, StepKind};
+
+/// File names Compose loads by default, in lookup order.
+const COMPOSE_NAMES: [&str; 4] = [
+    "compose.yaml",
+    "compose.yml",
+    "docker-compose.yaml",
+    "docker-compose.yml",
+];
+
+/// File names Compose merges automatically, in lookup order.
+const OVERRIDE_NAMES: [&str; 4] = [
+    "compose.override.yaml",
+    "compose.override.yml",
+    "docker-compose.override.yaml",
+    "docker-compose.override.yml",
+];
+
+/// Errors raised while restoring compose state.
+#[derive(Debug, Error)]
+pub enum ComposeError {
+    #[error("{0} is a regular file; move it aside so desk can manage the override symlink")]
+    UnmanagedOverride(PathBuf),
+
+    #[error("override variant {0} does not exist in this repository")]
+    MissingVariant(PathBuf),
+
+    #[error("override variant {0} resolves outside the repository")]
+    OutsideRepository(PathBuf),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// An override symlink and the variant it points at, both relative to the
+/// repository root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OverrideLink {
+    pub link: PathBuf,
+    pub target: PathBuf,
+}
+
+/// Compose configuration active when a workspace was captured.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ComposeState {
+    /// The symlinked override, if any.
+    pub active_override: Option<OverrideLink>,
+    /// `COMPOSE_FILE` at capture time.
+    pub compose_file: Option<String>,
+    /// `COMPOSE_PROFILES` at capture time.
+    pub profiles: Vec<String>,
+}
+
+impl ComposeState {
+    /// Records the active override symlink and compose environment for the
+    /// repository at `repo_root`.
+    pub fn capture(repo_root: &Path) -> Result<Self, ComposeError> {
+        let root = repo_root.canonicalize()?;
+        let mut active_override = None;
+
+        for name in OVERRIDE_NAMES {
+            let link = root.join(name);
+            let Ok(meta) = link.symlink_metadata() else {
+                continue;
+            };
+            if !meta.file_type().is_symlink() {
+                debug!("{name} is a regular file, leaving it unmanaged");
+                continue;
+            }
+
+            match resolve_variant(&root, &std::fs::read_link(&link)?) {
+                Ok(target) => {
+                    active_override = Some(OverrideLink {
+                        link: PathBuf::from(name),
+                        target,
+                    });
+                    break;
+                },
+                Err(e) => debug!("ignoring {name}: {e}"),
+            }
+        }
+
+        Ok(Self {
+            active_override,
+            compose_file: std::env::var("COMPOSE_FILE").ok().filter(|v| !v.is_empty()),
+            profiles: std::env::var("COMPOSE_PROFILES")
+                .map(|v| parse_profiles(&v))
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Re-points the override symlink to the captured variant, or removes
+    /// the override symlinks desk manages when no override was active.
+    ///
+    /// Desk manages the override symlinks [`capture`](Self::capture) would
+    /// record, those pointing at a variant inside the repository; others,
+    /// such as a link to a file elsewhere, are the user's and left alone.
+    pub fn restore(
+        &self,
+        repo_root: &Path,
+        reporter: &mut dyn Reporter,
+    ) -> Result<(), ComposeError> {
+        let root = repo_root.canonicalize()?;
+
+        let Some(active) = &self.active_override else {
+            for name in OVERRIDE_NAMES {
+                let link = root.join(name);
+                if !is_symlink(&link) {
+                    continue;
+                }
+                if let Err(e) = resolve_variant(&root, &std::fs::read_link(&link)?) {
+                    reporter.step(StepEvent::skipped(
+                        StepKind::Service,
+                        format!("Compose override {name}"),
+                        format!("not managed by desk ({e}), left alone"),
+                    ));
+                    continue;
+                }
+                std::fs::remove_file(&link)?;
+                reporter.step(StepEvent::done(
+                    StepKind::Service,
+                    format!("Compose override {name} removed"),
+                ));
+            }
+            return Ok(());
+        };
+
+        let target = resolve_variant(&root, &active.target)?;
+        let link = root.join(&active.link);
+
+        match link.symlink_metadata() {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                if resolve_variant(&root, &std::fs::read_link(&link)?)
+                    .ok()
+                    .as_ref()
+                    == Some(&target)
+                {
+                    reporter.step(StepEvent::skipped(
+                        StepKind::Service,
+                        format!("Compose override {}", target.display()),
+                        "already active",
+                    ));
+                    return Ok(());
+                }
+                std::fs::remove_file(&link)?;
+            },
+            Ok(_) => return Err(ComposeError::UnmanagedOverride(active.link.clone())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e.into()),
+        }
+
+        symlink(&target, &link)?;
+        reporter.step(StepEvent::done(
+            StepKind::Service,
+            format!("Compose override set to {}", target.display()),
+        ));
+        Ok(())
+    }
+
+    /// Environment variables that reproduce the captured compose selection.
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = Vec::new();
+        if let Some(file) = &self.compose_file {
+            vars.push(("COMPOSE_FILE", file.clone()));
+        }
+        if !self.profiles.is_empty() {
+            vars.push(("COMPOSE_PROFILES", self.profiles.join(",")));
+        }
+        vars
+    }
+}
+
+/// Whether the repository at `repo_root` has a Compose file of its own.
+pub fn uses_compose(repo_root: &Path) -> bool {
+    COMPOSE_NAMES
+        .iter()
+        .chain(&OVERRIDE_NAMES)
+        .any(|name| repo_root.join(name).symlink_metadata().is_ok())
+}
+
+/// Validates that `target` names an existing file inside `root` and returns it
+/// relative to `root`.
+fn resolve_variant(root: &Path, target: &Path) -> Result<PathBuf, ComposeError> {
+    let absolute = root
+        .join(target)
+        .canonicalize()
+        .map_err(|_| ComposeError::MissingVariant(target.to_path_buf()))?;
+
+    if !absolute.is_file() {
+        return Err(ComposeError::MissingVariant(target.to_path_buf()));
+    }
+
+    absolute
+        .strip_prefix(root)
+        .map(Path::to_path_buf)
+        .map_err(|_| ComposeError::OutsideRepository(target.to_path_buf()))
+}
+
+fn parse_profiles(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn is_symlink(path: &Path) -> bool {
+    path.symlink_metadata()
+        .is_ok_and(|meta| meta.file_type().is_symlink())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use super::{symlink, ComposeState, OverrideLink};
+    use crate::output::{Reporter, StepEvent};
+
+    #[derive(Default)]
+    struct Steps(Vec<StepEvent>);
+
+    impl Reporter for Steps {
+        fn step(&mut self, event: StepEvent) {
+            self.0.push(event);
+        }
+
+        fn finish(&mut self, _summary: &str) {}
+    }
+
+    /// An empty directory of its own for `test` under the system temp dir.
+    fn scratch(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("desk-docker-{test}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("repo")).unwrap();
+        dir
+    }
+
+    fn write(path: &Path) {
+        std::fs::write(path, "services: {}\n").unwrap();
+    }
+
+    #[test]
+    fn restoring_no_override_leaves_foreign_symlinks() {
+        let dir = scratch("foreign");
+        let repo = dir.join("repo");
+        write(&repo.join("compose.yaml"));
+        write(&dir.join("mine.yml"));
+        let link = repo.join("docker-compose.override.yml");
+        symlink(&dir.join("mine.yml"), &link).unwrap();
+
+        let state = ComposeState::capture(&repo).unwrap();
+        assert_eq!(state.active_override, None);
+        state.restore(&repo, &mut Steps::default()).unwrap();
+
+        assert!(link.symlink_metadata().unwrap().file_type().is_symlink());
+        assert_eq!(std::fs::read_link(&link).unwrap(), dir.join("mine.yml"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn restoring_no_override_removes_a_variant_link() {
+        let dir = scratch("variant");
+        let repo = dir.join("repo");
+        write(&repo.join("compose.override.pg15.yaml"));
+        let link = repo.join("compose.override.yaml");
+        symlink(Path::new("compose.override.pg15.yaml"), &link).unwrap();
+        let state = ComposeState::capture(&repo).unwrap();
+        assert_eq!(
+            state.active_override,
+            Some(OverrideLink {
+                link: PathBuf::from("compose.override.yaml"),
+                target: PathBuf::from("compose.override.pg15.yaml"),
+            })
+        );
+
+        ComposeState::default()
+            .restore(&repo, &mut Steps::default())
+            .unwrap();
+        assert!(link.symlink_metadata().is_err());
+        state.restore(&repo, &mut Steps::default()).unwrap();
+        assert!(link.symlink_metadata().is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
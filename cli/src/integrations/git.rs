@@ -0,0 +1,922 @@
+//! Git repository access through libgit2.
+
+use std::cell::{OnceCell, RefCell, RefMut};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use git2::build::CheckoutBuilder;
+use git2::{
+    ApplyLocation, BranchType, Diff, DiffFormat, DiffOptions, ObjectType, Oid, Patch, Repository,
+    Signature, StashApplyOptions, StashFlags, StatusOptions, TreeWalkMode, TreeWalkResult,
+    WorktreeLockStatus, WorktreePruneOptions,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::debug;
+
+use crate::utils::platform::FsCapabilities;
+
+/// Hash of the empty tree, which changes in a repository without commits
+/// are compared against.
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+/// Errors raised by [`Git2Operations`].
+#[derive(Debug, Error)]
+pub enum GitError {
+    #[error("{0} is not a git repository")]
+    NotARepository(PathBuf),
+
+    #[error("HEAD is detached; check out a branch first")]
+    DetachedHead,
+
+    #[error("branch '{0}' does not exist")]
+    BranchNotFound(String),
+
+    #[error("'{0}' is not a branch, tag, or commit")]
+    RevisionNotFound(String),
+
+    #[error("no stash named '{0}'")]
+    StashNotFound(String),
+
+    #[error("the saved changes do not apply to the checked-out branch")]
+    ChangesDoNotApply(#[source] git2::Error),
+
+    #[error("restoring the saved changes would overwrite {0}")]
+    WouldOverwrite(PathBuf),
+
+    #[error("refusing to restore {0}: not a path inside the repository")]
+    UnsafePath(PathBuf),
+
+    #[error("failed to run git; is it installed?")]
+    Spawn(#[source] std::io::Error),
+
+    #[error("`git {command}` failed: {stderr}")]
+    Command { command: String, stderr: String },
+
+    #[error("`git {command}` did not finish within {}s and was stopped", timeout.as_secs())]
+    TimedOut { command: String, timeout: Duration },
+
+    #[error("failed to write {path}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// Lines added and removed in one file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileStat {
+    pub path: PathBuf,
+    pub insertions: usize,
+    pub deletions: usize,
+}
+
+/// Summary of uncommitted changes relative to `HEAD`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffStat {
+    pub files: Vec<FileStat>,
+}
+
+impl DiffStat {
+    pub fn insertions(&self) -> usize {
+        self.files.iter().map(|f| f.insertions).sum()
+    }
+
+    pub fn deletions(&self) -> usize {
+        self.files.iter().map(|f| f.deletions).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// How the checked-out branch compares to the branch it tracks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Upstream {
+    /// The tracked branch, such as `origin/main`.
+    pub name: String,
+    /// Commits on the branch that the upstream does not have.
+    pub ahead: usize,
+    /// Commits on the upstream that the branch does not have.
+    pub behind: usize,
+}
+
+/// Uncommitted changes taken out of a stash, so they can be restored in
+/// another clone of the repository.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StashContents {
+    /// Commit the changes were made on top of.
+    pub base: String,
+    /// Staged and unstaged changes to tracked files, as a git patch.
+    pub patch: Vec<u8>,
+    pub untracked: Vec<UntrackedFile>,
+}
+
+impl StashContents {
+    /// Number of files the changes touch, untracked ones included.
+    pub fn file_count(&self) -> Result<usize, GitError> {
+        let diff = Diff::from_buffer(&self.patch)?;
+        Ok(diff.deltas().len() + self.untracked.len())
+    }
+
+    /// What the changes do to each file they touch, by path: its part of
+    /// the patch, or the contents of an untracked file.
+    pub fn files(&self) -> Result<BTreeMap<PathBuf, Vec<u8>>, GitError> {
+        let diff = Diff::from_buffer(&self.patch)?;
+        let mut files = BTreeMap::new();
+        for (idx, delta) in diff.deltas().enumerate() {
+            let Some(path) = delta.new_file().path().or_else(|| delta.old_file().path()) else {
+                continue;
+            };
+            let text = match Patch::from_diff(&diff, idx)? {
+                Some(mut file_patch) => file_patch.to_buf()?.to_vec(),
+                None => Vec::new(),
+            };
+            files.insert(path.to_path_buf(), text);
+        }
+        for file in &self.untracked {
+            files.insert(file.path.clone(), file.contents.clone());
+        }
+        Ok(files)
+    }
+}
+
+/// An untracked file saved in a stash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UntrackedFile {
+    /// Path relative to the repository root.
+    pub path: PathBuf,
+    pub contents: Vec<u8>,
+    pub executable: bool,
+}
+
+/// A linked worktree registered in the repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorktreeInfo {
+    /// Name of the worktree's administrative directory under `.git/worktrees`.
+    pub name: String,
+    pub path: PathBuf,
+    /// The administrative directory itself, `.git/worktrees/<name>`.
+    pub admin_dir: PathBuf,
+    /// Whether the worktree's checkout still exists on disk.
+    pub exists: bool,
+    pub locked: bool,
+}
+
+/// Git operations on the repository at a fixed path.
+///
+/// The repository is opened by the first operation and kept for the ones
+/// after it.
+pub struct Git2Operations {
+    repo_path: PathBuf,
+    repo: RefCell<Option<Repository>>,
+    /// How long the `git` executable may run, looked up on first use.
+    git_timeout: OnceCell<Duration>,
+}
+
+impl Clone for Git2Operations {
+    fn clone(&self) -> Self {
+        Self::new(self.repo_path.clone())
+    }
+}
+
+impl fmt::Debug for Git2Operations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Git2Operations")
+            .field("repo_path", &self.repo_path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Git2Operations {
+    pub fn new(repo_path: impl Into<PathBuf>) -> Self {
+        Self {
+            repo_path: repo_path.into(),
+            repo: RefCell::new(None),
+            git_timeout: OnceCell::new(),
+        }
+    }
+
+    pub fn repo_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    /// The repository, opened on first use. Operations must not call each
+    /// other while holding it.
+    fn open(&self) -> Result<RefMut<'_, Repository>, GitError> {
+        let mut slot = self.repo.borrow_mut();
+        let repo = match slot.take() {
+            Some(repo) => repo,
+            None => Repository::open(&self.repo_path)
+                .map_err(|_| GitError::NotARepository(self.repo_path.clone()))?,
+        };
+        Ok(RefMut::map(slot, |slot| slot.insert(repo)))
+    }
+
+    /// Per-file line counts of staged and unstaged changes against `HEAD`.
+    ///
+    /// libgit2 ignores `core.fsmonitor` and `core.untrackedCache`, so in a
+    /// repository that turns either on the `git` executable works the
+    /// changes out instead, without walking the whole checkout.
+    pub fn diff_stat(&self) -> Result<DiffStat, GitError> {
+        let repo = self.open()?;
+        if uses_status_helpers(&repo) {
+            match self.git_diff_stat(&repo) {
+                Ok(stat) => return Ok(stat),
+                Err(e) => debug!("{e}; counting changes with libgit2 instead"),
+            }
+        }
+        let head_tree = match repo.head() {
+            Ok(head) => Some(head.peel_to_tree()?),
+            Err(e) if e.code() == git2::ErrorCode::UnbornBranch => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut opts = DiffOptions::new();
+        opts.include_untracked(true).recurse_untracked_dirs(true);
+        let diff = repo.diff_tree_to_workdir_with_index(head_tree.as_ref(), Some(&mut opts))?;
+
+        let mut files = Vec::new();
+        for idx in 0..diff.deltas().len() {
+            let Some(file_patch) = Patch::from_diff(&diff, idx)? else {
+                continue;
+            };
+            let delta = file_patch.delta();
+            let Some(path) = delta.new_file().path().or_else(|| delta.old_file().path()) else {
+                continue;
+            };
+            let (_, insertions, deletions) = file_patch.line_stats()?;
+            files.push(FileStat {
+                path: path.to_path_buf(),
+                insertions,
+                deletions,
+            });
+        }
+        Ok(DiffStat { files })
+    }
+
+    /// [`diff_stat`](Self::diff_stat) through the `git` executable.
+    fn git_diff_stat(&self, repo: &Repository) -> Result<DiffStat, GitError> {
+        let base = match repo.head() {
+            Ok(_) => "HEAD",
+            Err(e) if e.code() == git2::ErrorCode::UnbornBranch => EMPTY_TREE,
+            Err(e) => return Err(e.into()),
+        };
+        let mut files = Vec::new();
+        // Records are `<insertions>\t<deletions>\t<path>`, with `-` counts
+        // for binary files.
+        let numstat = self.git(&["diff", "--numstat", "--no-renames", "-z", base])?;
+        for record in numstat.split('\0').filter(|r| !r.is_empty()) {
+            let mut fields = record.splitn(3, '\t');
+            let (Some(insertions), Some(deletions), Some(path)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            files.push(FileStat {
+                path: PathBuf::from(path),
+                insertions: insertions.parse().unwrap_or(0),
+                deletions: deletions.parse().unwrap_or(0),
+            });
+        }
+        // Untracked files count without lines, as libgit2 counts them.
+        let status = self.git(&["status", "--porcelain", "-z", "--untracked-files=all"])?;
+        for path in status.split('\0').filter_map(|e| e.strip_prefix("?? ")) {
+            files.push(FileStat {
+                path: PathBuf::from(path),
+                insertions: 0,
+                deletions: 0,
+            });
+        }
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(DiffStat { files })
+    }
+
+    /// Identifies the state of `HEAD` and the index, which the change
+    /// summary of the checkout can only be reused while it stays the same.
+    pub fn status_key(&self) -> Result<String, GitError> {
+        let repo = self.open()?;
+        let head = match repo.head() {
+            Ok(head) => head.peel_to_commit()?.id().to_string(),
+            Err(e) if e.code() == git2::ErrorCode::UnbornBranch => EMPTY_TREE.to_string(),
+            Err(e) => return Err(e.into()),
+        };
+        let index = repo.path().join("index");
+        let (modified, len) = match std::fs::metadata(&index) {
+            Ok(metadata) => (
+                metadata
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_nanos()),
+                metadata.len(),
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (0, 0),
+            Err(source) => {
+                return Err(GitError::Io {
+                    path: index,
+                    source,
+                })
+            },
+        };
+        Ok(format!("{head}:{modified}:{len}"))
+    }
+
+    /// Runs `git` in the checkout, returning its output. It is stopped once
+    /// it runs longer than the filesystem the checkout is on allows.
+    fn git(&self, args: &[&str]) -> Result<String, GitError> {
+        let timeout = *self
+            .git_timeout
+            .get_or_init(|| FsCapabilities::detect(&self.repo_path).git_timeout());
+        let mut command = Command::new("git");
+        command.arg("-C").arg(&self.repo_path).args(args);
+        let output = output_within(&mut command, timeout)
+            .map_err(GitError::Spawn)?
+            .ok_or_else(|| GitError::TimedOut {
+                command: args.join(" "),
+                timeout,
+            })?;
+        if !output.status.success() {
+            return Err(GitError::Command {
+                command: args.join(" "),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// URL of the remote called `name`, if it exists.
+    pub fn remote_url(&self, name: &str) -> Result<Option<String>, GitError> {
+        let repo = self.open()?;
+        let url = match repo.find_remote(name) {
+            Ok(remote) => remote.url().map(String::from),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(url)
+    }
+
+    /// Linked worktrees of the repository, excluding the main checkout.
+    pub fn worktrees(&self) -> Result<Vec<WorktreeInfo>, GitError> {
+        let repo = self.open()?;
+        let mut worktrees = Vec::new();
+        for name in repo.worktrees()?.iter().flatten() {
+            let worktree = repo.find_worktree(name)?;
+            worktrees.push(WorktreeInfo {
+                name: name.to_string(),
+                path: worktree.path().to_path_buf(),
+                admin_dir: repo.commondir().join("worktrees").join(name),
+                exists: worktree.validate().is_ok(),
+                locked: matches!(worktree.is_locked()?, WorktreeLockStatus::Locked(_)),
+            });
+        }
+        Ok(worktrees)
+    }
+
+    /// Whether the checkout at `path` has no uncommitted or untracked changes.
+    pub fn is_clean(path: &Path) -> Result<bool, GitError> {
+        let repo =
+            Repository::open(path).map_err(|_| GitError::NotARepository(path.to_path_buf()))?;
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(true).include_ignored(false);
+        let clean = repo.statuses(Some(&mut opts))?.is_empty();
+        Ok(clean)
+    }
+
+    /// Deletes the worktree `name` together with its checkout.
+    pub fn remove_worktree(&self, name: &str) -> Result<(), GitError> {
+        let repo = self.open()?;
+        let worktree = repo.find_worktree(name)?;
+        worktree.prune(Some(
+            WorktreePruneOptions::new().valid(true).working_tree(true),
+        ))?;
+        Ok(())
+    }
+
+    /// Drops the administrative data of worktree `name` whose checkout was
+    /// deleted by hand.
+    pub fn prune_worktree(&self, name: &str) -> Result<(), GitError> {
+        let repo = self.open()?;
+        repo.find_worktree(name)?.prune(None)?;
+        Ok(())
+    }
+
+    /// Name of the checked-out branch.
+    pub fn current_branch(&self) -> Result<String, GitError> {
+        let repo = self.open()?;
+        let head = repo.head()?;
+        if !head.is_branch() {
+            return Err(GitError::DetachedHead);
+        }
+        head.shorthand()
+            .map(String::from)
+            .ok_or(GitError::DetachedHead)
+    }
+
+    /// Full hash of the commit `HEAD` points at.
+    pub fn current_commit(&self) -> Result<String, GitError> {
+        let repo = self.open()?;
+        let commit = repo.head()?.peel_to_commit()?;
+        Ok(commit.id().to_string())
+    }
+
+    /// Whether there are staged or unstaged changes, or untracked files if
+    /// they count.
+    pub fn has_changes(&self, include_untracked: bool) -> Result<bool, GitError> {
+        let repo = self.open()?;
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(include_untracked)
+            .include_ignored(false);
+        let dirty = !repo.statuses(Some(&mut opts))?.is_empty();
+        Ok(dirty)
+    }
+
+    /// Number of files with staged or unstaged changes, untracked ones
+    /// included if they count.
+    pub fn changed_files(&self, include_untracked: bool) -> Result<usize, GitError> {
+        let repo = self.open()?;
+        let mut opts = StatusOptions::new();
+        opts.include_untracked(include_untracked)
+            .recurse_untracked_dirs(include_untracked)
+            .include_ignored(false);
+        let count = repo.statuses(Some(&mut opts))?.len();
+        Ok(count)
+    }
+
+    /// How many commits `HEAD` is ahead of and behind `rev`, a branch name or
+    /// commit; `None` if either does not resolve to a commit.
+    pub fn ahead_behind(&self, rev: &str) -> Result<Option<(usize, usize)>, GitError> {
+        let repo = self.open()?;
+        let Ok(head) = repo.head().and_then(|head| head.peel_to_commit()) else {
+            return Ok(None);
+        };
+        let Ok(other) = repo
+            .revparse_single(rev)
+            .and_then(|object| object.peel_to_commit())
+        else {
+            return Ok(None);
+        };
+        Ok(Some(repo.graph_ahead_behind(head.id(), other.id())?))
+    }
+
+    /// How the checked-out branch compares to its upstream, as of the last
+    /// fetch; `None` on a detached `HEAD` or a branch that tracks nothing.
+    pub fn upstream(&self) -> Result<Option<Upstream>, GitError> {
+        let repo = self.open()?;
+        let Ok(head) = repo.head() else {
+            return Ok(None);
+        };
+        if !head.is_branch() {
+            return Ok(None);
+        }
+        let branch = git2::Branch::wrap(head);
+        let upstream = match branch.upstream() {
+            Ok(upstream) => upstream,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let (Some(local), Some(remote)) = (branch.get().target(), upstream.get().target()) else {
+            return Ok(None);
+        };
+        let (ahead, behind) = repo.graph_ahead_behind(local, remote)?;
+        Ok(Some(Upstream {
+            name: upstream.name()?.unwrap_or_default().to_string(),
+            ahead,
+            behind,
+        }))
+    }
+
+    /// Whether `commit` is in this clone, which it may not be after a
+    /// force-push rewrote it, or when it was made in another clone.
+    pub fn has_commit(&self, commit: &str) -> Result<bool, GitError> {
+        let repo = self.open()?;
+        Ok(Oid::from_str(commit).is_ok_and(|oid| repo.find_commit(oid).is_ok()))
+    }
+
+    /// Fetches from every remote, with the git command line so that the
+    /// usual credentials apply.
+    pub fn fetch_all(&self) -> Result<(), GitError> {
+        self.git(&["fetch", "--all", "--quiet"]).map(drop)
+    }
+
+    /// Fetches `branch` from `remote`, updating its remote-tracking branch.
+    pub fn fetch_branch(&self, remote: &str, branch: &str) -> Result<(), GitError> {
+        self.git(&["fetch", "--quiet", remote, branch]).map(drop)
+    }
+
+    /// The commit `rev`, a branch, tag, or commit, points to.
+    pub fn resolve_commit(&self, rev: &str) -> Result<String, GitError> {
+        let repo = self.open()?;
+        let commit = repo
+            .revparse_single(rev)
+            .and_then(|object| object.peel_to_commit())
+            .map(|commit| commit.id().to_string());
+        match commit {
+            Ok(commit) => Ok(commit),
+            Err(e)
+                if matches!(
+                    e.code(),
+                    git2::ErrorCode::NotFound | git2::ErrorCode::InvalidSpec
+                ) =>
+            {
+                Err(GitError::RevisionNotFound(rev.to_string()))
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Stashes staged and unstaged changes under `message`, and untracked
+    /// files with `include_untracked`.
+    ///
+    /// Returns `false` when there was nothing to stash.
+    pub fn stash_save(&self, message: &str, include_untracked: bool) -> Result<bool, GitError> {
+        if !self.has_changes(include_untracked)? {
+            return Ok(false);
+        }
+        let mut repo = self.open()?;
+        let signature = match repo.signature() {
+            Ok(signature) => signature,
+            Err(_) => Signature::now("desk", "desk@localhost")?,
+        };
+        let flags = if include_untracked {
+            StashFlags::INCLUDE_UNTRACKED
+        } else {
+            StashFlags::DEFAULT
+        };
+        repo.stash_save(&signature, message, Some(flags))?;
+        Ok(true)
+    }
+
+    /// Moves the newest stash saved under `message` out of the stash list to
+    /// the ref `refname`, which [`Self::stash_pop`] and
+    /// [`Self::stash_contents`] then take in place of a message.
+    pub fn stash_to_ref(&self, message: &str, refname: &str) -> Result<(), GitError> {
+        let mut repo = self.open()?;
+        let (index, oid) = find_stash(&mut repo, message)?
+            .ok_or_else(|| GitError::StashNotFound(message.to_string()))?;
+        repo.reference(refname, oid, true, message)?;
+        if let Some(index) = index {
+            repo.stash_drop(index)?;
+        }
+        Ok(())
+    }
+
+    /// Drops the newest stash saved under `message`.
+    pub fn stash_drop(&self, message: &str) -> Result<(), GitError> {
+        let mut repo = self.open()?;
+        match find_stash(&mut repo, message)? {
+            Some((Some(index), _)) => repo.stash_drop(index)?,
+            Some((None, _)) => repo.find_reference(message)?.delete()?,
+            None => return Err(GitError::StashNotFound(message.to_string())),
+        }
+        Ok(())
+    }
+
+    /// Applies and drops the newest stash saved under `message`.
+    ///
+    /// Changes kept under a ref come back unstaged.
+    pub fn stash_pop(&self, message: &str) -> Result<(), GitError> {
+        let mut repo = self.open()?;
+        let (index, oid) = find_stash(&mut repo, message)?
+            .ok_or_else(|| GitError::StashNotFound(message.to_string()))?;
+        if let Some(index) = index {
+            repo.stash_pop(index, Some(StashApplyOptions::new().reinstantiate_index()))?;
+            return Ok(());
+        }
+        let contents = stash_commit_contents(&repo, oid)?;
+        drop(repo);
+        self.apply_contents(&contents)?;
+        self.open()?.find_reference(message)?.delete()?;
+        Ok(())
+    }
+
+    /// Reads the changes in the newest stash saved under `message` without
+    /// touching the stash; `None` if there is no such stash.
+    pub fn stash_contents(&self, message: &str) -> Result<Option<StashContents>, GitError> {
+        let mut repo = self.open()?;
+        let Some((_, oid)) = find_stash(&mut repo, message)? else {
+            return Ok(None);
+        };
+        stash_commit_contents(&repo, oid).map(Some)
+    }
+
+    /// Applies `contents` to the working directory.
+    ///
+    /// Nothing is changed if the patch does not apply or an untracked file
+    /// would overwrite an existing one.
+    pub fn apply_contents(&self, contents: &StashContents) -> Result<(), GitError> {
+        let repo = self.open()?;
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| GitError::NotARepository(self.repo_path.clone()))?
+            .to_path_buf();
+        for file in &contents.untracked {
+            let inside = file.path.components().all(|c| match c {
+                Component::Normal(name) => name != ".git",
+                _ => false,
+            });
+            if !inside {
+                return Err(GitError::UnsafePath(file.path.clone()));
+            }
+            if workdir.join(&file.path).exists() {
+                return Err(GitError::WouldOverwrite(file.path.clone()));
+            }
+        }
+
+        if !contents.patch.is_empty() {
+            let diff = Diff::from_buffer(&contents.patch).map_err(GitError::ChangesDoNotApply)?;
+            repo.apply(&diff, ApplyLocation::WorkDir, None)
+                .map_err(GitError::ChangesDoNotApply)?;
+        }
+        for file in &contents.untracked {
+            write_untracked(&workdir, file).map_err(|source| GitError::Io {
+                path: file.path.clone(),
+                source,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Reads the uncommitted changes as a stash would hold them, leaving the
+    /// working directory and index alone; `None` when there are none.
+    pub fn working_changes(
+        &self,
+        include_untracked: bool,
+    ) -> Result<Option<StashContents>, GitError> {
+        if !self.has_changes(include_untracked)? {
+            return Ok(None);
+        }
+        let repo = self.open()?;
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| GitError::NotARepository(self.repo_path.clone()))?
+            .to_path_buf();
+        let base = repo.head()?.peel_to_commit()?;
+
+        let mut opts = DiffOptions::new();
+        opts.show_binary(true);
+        let diff = repo.diff_tree_to_workdir_with_index(Some(&base.tree()?), Some(&mut opts))?;
+        let patch = patch_bytes(&diff)?;
+
+        let mut untracked = Vec::new();
+        if include_untracked {
+            let mut opts = StatusOptions::new();
+            opts.include_untracked(true)
+                .recurse_untracked_dirs(true)
+                .include_ignored(false);
+            for entry in repo.statuses(Some(&mut opts))?.iter() {
+                if !entry.status().is_wt_new() {
+                    continue;
+                }
+                let Some(relative) = entry.path().map(PathBuf::from) else {
+                    continue;
+                };
+                let absolute = workdir.join(&relative);
+                let io = |source| GitError::Io {
+                    path: relative.clone(),
+                    source,
+                };
+                let metadata = std::fs::symlink_metadata(&absolute).map_err(io)?;
+                if !metadata.is_file() {
+                    continue;
+                }
+                untracked.push(UntrackedFile {
+                    contents: std::fs::read(&absolute).map_err(io)?,
+                    executable: is_executable(&metadata),
+                    path: relative,
+                });
+            }
+        }
+
+        Ok(Some(StashContents {
+            base: base.id().to_string(),
+            patch,
+            untracked,
+        }))
+    }
+
+    /// Creates branch `name` at `commit`, unless there is one already.
+    /// Whether it was created.
+    pub fn create_branch(&self, name: &str, commit: &str) -> Result<bool, GitError> {
+        let repo = self.open()?;
+        if repo.find_branch(name, BranchType::Local).is_ok() {
+            return Ok(false);
+        }
+        let commit = repo.find_commit(Oid::from_str(commit)?)?;
+        repo.branch(name, &commit, false)?;
+        Ok(true)
+    }
+
+    /// Checks out branch `name`, creating it at `HEAD` first if asked to.
+    ///
+    /// Uses a safe checkout, so local changes that would be overwritten make
+    /// the switch fail instead of being lost.
+    pub fn switch_branch(&self, name: &str, options: &SwitchOptions) -> Result<(), GitError> {
+        let repo = self.open()?;
+        let branch = match repo.find_branch(name, BranchType::Local) {
+            Ok(branch) => branch,
+            Err(e) if e.code() == git2::ErrorCode::NotFound && options.create => {
+                let head = repo.head()?.peel_to_commit()?;
+                repo.branch(name, &head, false)?
+            },
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                return Err(GitError::BranchNotFound(name.to_string()))
+            },
+            Err(e) => return Err(e.into()),
+        };
+        let reference = branch.into_reference();
+        let refname = reference
+            .name()
+            .ok_or_else(|| GitError::BranchNotFound(name.to_string()))?
+            .to_string();
+        let target = reference.peel(git2::ObjectType::Commit)?;
+
+        repo.checkout_tree(&target, Some(CheckoutBuilder::new().safe()))?;
+        repo.set_head(&refname)?;
+        Ok(())
+    }
+}
+
+/// The changes in stash commit `oid`, taken apart for restoring elsewhere.
+fn stash_commit_contents(repo: &Repository, oid: Oid) -> Result<StashContents, GitError> {
+    let stash = repo.find_commit(oid)?;
+    let base = stash.parent(0)?;
+
+    let mut opts = DiffOptions::new();
+    opts.show_binary(true);
+    let diff =
+        repo.diff_tree_to_tree(Some(&base.tree()?), Some(&stash.tree()?), Some(&mut opts))?;
+    let patch = patch_bytes(&diff)?;
+
+    // With untracked files included, the stash's third parent holds them.
+    let mut untracked = Vec::new();
+    if let Ok(commit) = stash.parent(2) {
+        let mut error = None;
+        commit.tree()?.walk(TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() != Some(ObjectType::Blob) {
+                return TreeWalkResult::Ok;
+            }
+            let relative = Path::new(dir).join(entry.name().unwrap_or_default());
+            match repo.find_blob(entry.id()) {
+                Ok(blob) => {
+                    untracked.push(UntrackedFile {
+                        path: relative,
+                        contents: blob.content().to_vec(),
+                        executable: entry.filemode() == 0o100_755,
+                    });
+                    TreeWalkResult::Ok
+                },
+                Err(e) => {
+                    error = Some(e);
+                    TreeWalkResult::Abort
+                },
+            }
+        })?;
+        if let Some(e) = error {
+            return Err(e.into());
+        }
+    }
+
+    Ok(StashContents {
+        base: base.id().to_string(),
+        patch,
+        untracked,
+    })
+}
+
+/// Finds the newest stash saved under `message`, returning its index and
+/// commit. A `message` naming a ref, as [`Git2Operations::stash_to_ref`]
+/// leaves them, finds the stash commit there, which has no index.
+fn find_stash(
+    repo: &mut Repository,
+    message: &str,
+) -> Result<Option<(Option<usize>, Oid)>, GitError> {
+    if message.starts_with("refs/") {
+        return match repo.find_reference(message) {
+            Ok(reference) => Ok(Some((None, reference.peel_to_commit()?.id()))),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        };
+    }
+    // Stash messages are stored as "On <branch>: <message>".
+    let suffix = format!(": {message}");
+    let mut found = None;
+    repo.stash_foreach(|index, stored, oid| {
+        if stored == message || stored.ends_with(&suffix) {
+            found = Some((Some(index), *oid));
+            return false;
+        }
+        true
+    })?;
+    Ok(found)
+}
+
+/// Whether the repository turns on `core.fsmonitor` or
+/// `core.untrackedCache`; the former is either a boolean or a hook.
+fn uses_status_helpers(repo: &Repository) -> bool {
+    let Ok(config) = repo.config() else {
+        return false;
+    };
+    let fsmonitor = config.get_string("core.fsmonitor").is_ok_and(|value| {
+        !matches!(
+            value.to_ascii_lowercase().as_str(),
+            "" | "false" | "no" | "off" | "0"
+        )
+    });
+    fsmonitor || config.get_bool("core.untrackedCache").unwrap_or(false)
+}
+
+/// `diff` as a patch that [`Diff::from_buffer`] reads back.
+fn patch_bytes(diff: &Diff<'_>) -> Result<Vec<u8>, GitError> {
+    let mut patch = Vec::new();
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        if matches!(line.origin(), '+' | '-' | ' ') {
+            patch.push(line.origin() as u8);
+        }
+        patch.extend_from_slice(line.content());
+        true
+    })?;
+    Ok(patch)
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+const fn is_executable(_: &std::fs::Metadata) -> bool {
+    false
+}
+
+fn write_untracked(workdir: &Path, file: &UntrackedFile) -> std::io::Result<()> {
+    let path = workdir.join(&file.path);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, &file.contents)?;
+    #[cfg(unix)]
+    if file.executable {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// How [`Git2Operations::switch_branch`] treats a missing branch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SwitchOptions {
+    /// Create the branch at `HEAD` when it does not exist.
+    pub create: bool,
+}
+
+/// Runs `command` and collects its output, or kills it and returns `None`
+/// once it has run for `timeout`.
+fn output_within(command: &mut Command, timeout: Duration) -> std::io::Result<Option<Output>> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Read on threads of their own, so that a full pipe cannot stall it.
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() >= timeout {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    Ok(Some(Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    }))
+}
+
+fn drain(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        output
+    })
+}
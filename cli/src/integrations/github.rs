@@ -0,0 +1,268 @@
+//! Pull requests and issues on GitHub, for `desk open --from-pr` and
+//! `--from-issue`.
+
+use std::path::Path;
+use std::process::Command;
+
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::issues::slug;
+use super::paste::github_token_from_env;
+
+const GITHUB_HOST: &str = "github.com";
+
+/// Errors raised while talking to GitHub.
+#[derive(Debug, Error)]
+pub enum GitHubError {
+    #[error("{0} is not a GitHub repository URL; --from-pr and --from-issue need a GitHub remote")]
+    NotGitHub(String),
+
+    #[error("{what} #{number} not found in {repo}{hint}")]
+    NotFound {
+        what: &'static str,
+        number: u64,
+        repo: String,
+        /// Private repositories answer 404 without a token.
+        hint: &'static str,
+    },
+
+    #[error("GitHub refused the request ({status}): {body}")]
+    Rejected { status: StatusCode, body: String },
+
+    #[error("failed to fetch {refspec} from {remote}: {stderr}")]
+    Fetch {
+        remote: String,
+        refspec: String,
+        stderr: String,
+    },
+
+    #[error("failed to run git")]
+    Spawn(#[source] std::io::Error),
+
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+/// A repository on GitHub or a GitHub Enterprise Server instance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitHubRepo {
+    /// Host the repository lives on, such as `github.com`.
+    pub host: String,
+    pub owner: String,
+    pub name: String,
+    /// REST API root for the host.
+    api_url: String,
+}
+
+impl GitHubRepo {
+    /// The repository a remote `url` points at, on github.com or on the
+    /// Enterprise Server at `enterprise_url`. Accepts HTTPS, `ssh://`, and
+    /// scp-like `git@host:owner/repo` URLs.
+    pub fn from_remote_url(url: &str, enterprise_url: Option<&str>) -> Result<Self, GitHubError> {
+        let not_github = || GitHubError::NotGitHub(url.to_string());
+        let (authority, path) = match url.split_once("://") {
+            // `https://user@host:443/owner/repo`
+            Some((_, rest)) => {
+                let (authority, path) = rest.split_once('/').ok_or_else(not_github)?;
+                let host = authority
+                    .split_once(':')
+                    .map_or(authority, |(host, _)| host);
+                (host, path)
+            },
+            // `git@host:owner/repo`
+            None => url.split_once(':').ok_or_else(not_github)?,
+        };
+        let host = authority
+            .rsplit_once('@')
+            .map_or(authority, |(_, host)| host);
+        let path = path.trim_matches('/');
+        let (owner, name) = path.split_once('/').ok_or_else(not_github)?;
+        let name = name.trim_end_matches(".git");
+        if owner.is_empty() || name.is_empty() || name.contains('/') {
+            return Err(not_github());
+        }
+
+        let enterprise = enterprise_url
+            .map(|url| url.trim().trim_end_matches('/'))
+            .filter(|url| !url.is_empty());
+        let api_url = if host.eq_ignore_ascii_case(GITHUB_HOST) {
+            "https://api.github.com".to_string()
+        } else if let Some(base) = enterprise.filter(|base| {
+            base.split_once("://")
+                .map_or(*base, |(_, rest)| rest)
+                .eq_ignore_ascii_case(host)
+        }) {
+            format!("{base}/api/v3")
+        } else {
+            return Err(not_github());
+        };
+        Ok(Self {
+            host: host.to_string(),
+            owner: owner.to_string(),
+            name: name.to_string(),
+            api_url,
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/repos/{}/{}/{path}", self.api_url, self.owner, self.name)
+    }
+}
+
+impl std::fmt::Display for GitHubRepo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.owner, self.name)
+    }
+}
+
+/// A pull request, as far as desk cares.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PullRequest {
+    pub number: u64,
+    pub title: String,
+    pub html_url: String,
+    pub head: PullRequestHead,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PullRequestHead {
+    /// Branch name in the repository the changes come from.
+    #[serde(rename = "ref")]
+    pub branch: String,
+    pub sha: String,
+    /// `None` when the fork was deleted.
+    pub repo: Option<HeadRepo>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HeadRepo {
+    pub full_name: String,
+}
+
+/// An issue, as far as desk cares.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Issue {
+    pub number: u64,
+    pub title: String,
+    pub html_url: String,
+    /// Set when the issue is really a pull request.
+    #[serde(default)]
+    pub pull_request: Option<serde_json::Value>,
+}
+
+/// Talks to the GitHub REST API.
+#[derive(Debug, Clone)]
+pub struct GitHubClient {
+    client: Client,
+    token: Option<String>,
+}
+
+impl GitHubClient {
+    /// A client for `host`, with the token from `GITHUB_TOKEN`, `GH_TOKEN`,
+    /// or the GitHub CLI if there is one. Without a token only public
+    /// repositories can be read.
+    pub fn new(host: &str) -> Result<Self, GitHubError> {
+        Ok(Self {
+            client: Client::builder()
+                .user_agent(concat!("desk-cli/", env!("CARGO_PKG_VERSION")))
+                .build()?,
+            token: github_token_from_env().or_else(|| gh_token(host)),
+        })
+    }
+
+    pub async fn pull_request(
+        &self,
+        repo: &GitHubRepo,
+        number: u64,
+    ) -> Result<PullRequest, GitHubError> {
+        self.get(repo, &format!("pulls/{number}"), "pull request", number)
+            .await
+    }
+
+    pub async fn issue(&self, repo: &GitHubRepo, number: u64) -> Result<Issue, GitHubError> {
+        self.get(repo, &format!("issues/{number}"), "issue", number)
+            .await
+    }
+
+    async fn get<T: for<'de> Deserialize<'de>>(
+        &self,
+        repo: &GitHubRepo,
+        path: &str,
+        what: &'static str,
+        number: u64,
+    ) -> Result<T, GitHubError> {
+        let mut request = self
+            .client
+            .get(repo.url(path))
+            .header("Accept", "application/vnd.github+json");
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Err(GitHubError::NotFound {
+                what,
+                number,
+                repo: repo.to_string(),
+                hint: if self.token.is_none() {
+                    "; for a private repository set GITHUB_TOKEN or sign in with `gh auth login`"
+                } else {
+                    ""
+                },
+            });
+        }
+        if !status.is_success() {
+            return Err(GitHubError::Rejected {
+                status,
+                body: response.text().await.unwrap_or_default(),
+            });
+        }
+        Ok(response.json().await?)
+    }
+}
+
+/// Fetches the head of pull request `number` from `remote` into the
+/// repository at `repo`, with the git command line so that the usual
+/// credentials apply.
+pub fn fetch_pull_request(repo: &Path, remote: &str, number: u64) -> Result<(), GitHubError> {
+    let refspec = format!("refs/pull/{number}/head");
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(["fetch", "--no-tags", "--quiet", remote, &refspec])
+        .output()
+        .map_err(GitHubError::Spawn)?;
+    if !output.status.success() {
+        return Err(GitHubError::Fetch {
+            remote: remote.to_string(),
+            refspec,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// The token the GitHub CLI holds for `host`, if it is installed and signed
+/// in.
+fn gh_token(host: &str) -> Option<String> {
+    let output = Command::new("gh")
+        .args(["auth", "token", "--hostname", host])
+        .output()
+        .ok()?;
+    let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !token.is_empty()).then_some(token)
+}
+
+/// A branch name for issue `number`: its number and the start of its title,
+/// lowercased with dashes, like `42-fix-login-redirect`.
+pub fn issue_branch(number: u64, title: &str) -> String {
+    let slug = slug(title);
+    if slug.is_empty() {
+        format!("issue-{number}")
+    } else {
+        format!("{number}-{slug}")
+    }
+}
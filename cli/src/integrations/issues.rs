@@ -0,0 +1,441 @@
+//! Tickets in Jira and Linear, for `desk open --issue` and moving them on
+//! `desk close`.
+
+use reqwest::{Client, RequestBuilder, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::core::config::{IssuesConfig, JiraConfig};
+use crate::core::workspace::IssueTracker;
+
+const LINEAR_API_URL: &str = "https://api.linear.app/graphql";
+
+/// Longest title part of a branch name from [`branch_name`].
+const MAX_SLUG: usize = 40;
+
+/// Errors raised while talking to an issue tracker.
+#[derive(Debug, Error)]
+pub enum IssueError {
+    #[error(
+        "no issue tracker is set up; add [issues.jira] or [issues.linear] to the configuration"
+    )]
+    NotConfigured,
+
+    #[error("both Jira and Linear are set up; choose one with issues.tracker")]
+    Ambiguous,
+
+    #[error("{tracker} needs {setting} to be set")]
+    MissingSetting {
+        tracker: IssueTracker,
+        setting: &'static str,
+    },
+
+    #[error("{key} not found in {tracker}")]
+    NotFound { tracker: IssueTracker, key: String },
+
+    #[error("{tracker} refused the request ({status}): {body}")]
+    Rejected {
+        tracker: IssueTracker,
+        status: StatusCode,
+        body: String,
+    },
+
+    #[error("{key} cannot move to '{to}' from where it is; it can move to: {available}")]
+    NoTransition {
+        key: String,
+        to: String,
+        available: String,
+    },
+
+    #[error("Linear answered: {0}")]
+    Linear(String),
+
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+/// A ticket, as far as desk cares.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ticket {
+    /// Key as the tracker spells it, like `PROJ-123`.
+    pub key: String,
+    pub title: String,
+    pub url: String,
+    /// Branch name the tracker suggests, if it has one.
+    pub branch: Option<String>,
+}
+
+/// Reads and moves tickets in one tracker.
+#[derive(Debug, Clone)]
+pub struct IssueClient {
+    http: Client,
+    backend: Backend,
+}
+
+#[derive(Debug, Clone)]
+enum Backend {
+    Jira {
+        base_url: String,
+        email: Option<String>,
+        token: String,
+    },
+    Linear {
+        token: String,
+    },
+}
+
+impl IssueClient {
+    /// A client for the tracker `config` chooses: `issues.tracker`, or else
+    /// the only one set up.
+    pub fn new(http: Client, config: &IssuesConfig) -> Result<Self, IssueError> {
+        let jira = config.jira.base_url.is_some();
+        let linear = config.linear.token.is_some();
+        let tracker = match (config.tracker, jira, linear) {
+            (Some(tracker), _, _) => tracker,
+            (None, true, false) => IssueTracker::Jira,
+            (None, false, true) => IssueTracker::Linear,
+            (None, true, true) => return Err(IssueError::Ambiguous),
+            (None, false, false) => return Err(IssueError::NotConfigured),
+        };
+        Self::for_tracker(http, config, tracker)
+    }
+
+    /// A client for `tracker`, whatever `issues.tracker` says.
+    pub fn for_tracker(
+        http: Client,
+        config: &IssuesConfig,
+        tracker: IssueTracker,
+    ) -> Result<Self, IssueError> {
+        let missing = |setting| IssueError::MissingSetting { tracker, setting };
+        let backend = match tracker {
+            IssueTracker::Jira => {
+                let JiraConfig {
+                    base_url,
+                    email,
+                    token,
+                    ..
+                } = &config.jira;
+                Backend::Jira {
+                    base_url: base_url
+                        .as_deref()
+                        .ok_or_else(|| missing("issues.jira.base_url"))?
+                        .trim_end_matches('/')
+                        .to_string(),
+                    email: email.clone(),
+                    token: token.clone().ok_or_else(|| missing("issues.jira.token"))?,
+                }
+            },
+            IssueTracker::Linear => Backend::Linear {
+                token: config
+                    .linear
+                    .token
+                    .clone()
+                    .ok_or_else(|| missing("issues.linear.token"))?,
+            },
+        };
+        Ok(Self { http, backend })
+    }
+
+    pub const fn tracker(&self) -> IssueTracker {
+        match self.backend {
+            Backend::Jira { .. } => IssueTracker::Jira,
+            Backend::Linear { .. } => IssueTracker::Linear,
+        }
+    }
+
+    /// Looks up the ticket `key`.
+    pub async fn ticket(&self, key: &str) -> Result<Ticket, IssueError> {
+        match &self.backend {
+            Backend::Jira { base_url, .. } => {
+                let url = format!("{base_url}/rest/api/2/issue/{key}?fields=summary");
+                let issue: JiraIssue = self.jira(self.http.get(url), key).await?.json().await?;
+                Ok(Ticket {
+                    url: format!("{base_url}/browse/{}", issue.key),
+                    key: issue.key,
+                    title: issue.fields.summary.unwrap_or_default(),
+                    branch: None,
+                })
+            },
+            Backend::Linear { .. } => {
+                let data = self
+                    .linear(
+                        "query($id: String!) { issue(id: $id) { identifier title url branchName } }",
+                        json!({ "id": key }),
+                        key,
+                    )
+                    .await?;
+                let issue: LinearIssue = linear_issue(data, key)?;
+                Ok(Ticket {
+                    key: issue.identifier,
+                    title: issue.title,
+                    url: issue.url,
+                    branch: issue.branch_name.filter(|branch| !branch.is_empty()),
+                })
+            },
+        }
+    }
+
+    /// Moves ticket `key` to `to`: a transition or status in Jira, a
+    /// workflow state in Linear, matched without regard to case. `false`
+    /// when the ticket is there already.
+    pub async fn transition(&self, key: &str, to: &str) -> Result<bool, IssueError> {
+        match &self.backend {
+            Backend::Jira { base_url, .. } => {
+                let url = format!("{base_url}/rest/api/2/issue/{key}?fields=status");
+                let issue: JiraIssue = self.jira(self.http.get(url), key).await?.json().await?;
+                if issue
+                    .fields
+                    .status
+                    .is_some_and(|status| status.name.eq_ignore_ascii_case(to))
+                {
+                    return Ok(false);
+                }
+                let url = format!("{base_url}/rest/api/2/issue/{key}/transitions");
+                let transitions: JiraTransitions =
+                    self.jira(self.http.get(&url), key).await?.json().await?;
+                let Some(transition) = transitions.transitions.iter().find(|t| {
+                    t.name.eq_ignore_ascii_case(to) || t.to.name.eq_ignore_ascii_case(to)
+                }) else {
+                    return Err(IssueError::NoTransition {
+                        key: key.to_string(),
+                        to: to.to_string(),
+                        available: list(transitions.transitions.iter().map(|t| &t.name)),
+                    });
+                };
+                let request = self
+                    .http
+                    .post(url)
+                    .json(&json!({ "transition": { "id": transition.id } }));
+                self.jira(request, key).await?;
+                Ok(true)
+            },
+            Backend::Linear { .. } => {
+                let data = self
+                    .linear(
+                        "query($id: String!) { issue(id: $id) { id state { name } \
+                         team { states { nodes { id name } } } } }",
+                        json!({ "id": key }),
+                        key,
+                    )
+                    .await?;
+                let issue: LinearStates = linear_issue(data, key)?;
+                if issue.state.name.eq_ignore_ascii_case(to) {
+                    return Ok(false);
+                }
+                let states = issue.team.states.nodes;
+                let Some(state) = states.iter().find(|s| s.name.eq_ignore_ascii_case(to)) else {
+                    return Err(IssueError::NoTransition {
+                        key: key.to_string(),
+                        to: to.to_string(),
+                        available: list(states.iter().map(|s| &s.name)),
+                    });
+                };
+                self.linear(
+                    "mutation($id: String!, $state: String!) { \
+                     issueUpdate(id: $id, input: { stateId: $state }) { success } }",
+                    json!({ "id": issue.id, "state": state.id }),
+                    key,
+                )
+                .await?;
+                Ok(true)
+            },
+        }
+    }
+
+    /// Sends a Jira REST request about ticket `key`.
+    async fn jira(
+        &self,
+        request: RequestBuilder,
+        key: &str,
+    ) -> Result<reqwest::Response, IssueError> {
+        let Backend::Jira { email, token, .. } = &self.backend else {
+            unreachable!("Jira request to {}", self.tracker());
+        };
+        let request = match email {
+            Some(email) => request.basic_auth(email, Some(token)),
+            None => request.bearer_auth(token),
+        };
+        let response = request.header("Accept", "application/json").send().await?;
+        check(response, IssueTracker::Jira, key).await
+    }
+
+    /// Runs a Linear GraphQL `query` about ticket `key`, returning its data.
+    async fn linear(&self, query: &str, variables: Value, key: &str) -> Result<Value, IssueError> {
+        let Backend::Linear { token } = &self.backend else {
+            unreachable!("Linear request to {}", self.tracker());
+        };
+        let response = self
+            .http
+            .post(LINEAR_API_URL)
+            // Personal API keys go without a scheme.
+            .header("Authorization", token)
+            .json(&json!({ "query": query, "variables": variables }))
+            .send()
+            .await?;
+        let mut body: Value = check(response, IssueTracker::Linear, key)
+            .await?
+            .json()
+            .await?;
+        if let Some(errors) = body["errors"].as_array().filter(|e| !e.is_empty()) {
+            let not_found = errors.iter().any(|e| {
+                e["extensions"]["code"].as_str() == Some("ENTITY_NOT_FOUND")
+                    || e["message"]
+                        .as_str()
+                        .is_some_and(|m| m.contains("not found"))
+            });
+            if not_found {
+                return Err(IssueError::NotFound {
+                    tracker: IssueTracker::Linear,
+                    key: key.to_string(),
+                });
+            }
+            return Err(IssueError::Linear(list(
+                errors.iter().filter_map(|e| e["message"].as_str()),
+            )));
+        }
+        Ok(body["data"].take())
+    }
+}
+
+/// The `issue` in the data of a Linear answer about ticket `key`.
+fn linear_issue<T: for<'de> Deserialize<'de>>(mut data: Value, key: &str) -> Result<T, IssueError> {
+    let issue = data["issue"].take();
+    if issue.is_null() {
+        return Err(IssueError::NotFound {
+            tracker: IssueTracker::Linear,
+            key: key.to_string(),
+        });
+    }
+    serde_json::from_value(issue).map_err(|e| IssueError::Linear(e.to_string()))
+}
+
+/// `response`, unless it failed.
+async fn check(
+    response: reqwest::Response,
+    tracker: IssueTracker,
+    key: &str,
+) -> Result<reqwest::Response, IssueError> {
+    let status = response.status();
+    if status == StatusCode::NOT_FOUND {
+        return Err(IssueError::NotFound {
+            tracker,
+            key: key.to_string(),
+        });
+    }
+    if !status.is_success() {
+        return Err(IssueError::Rejected {
+            tracker,
+            status,
+            body: response.text().await.unwrap_or_default(),
+        });
+    }
+    Ok(response)
+}
+
+fn list<S: AsRef<str>>(items: impl Iterator<Item = S>) -> String {
+    let items: Vec<_> = items.map(|item| item.as_ref().to_string()).collect();
+    if items.is_empty() {
+        "nothing".to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+/// The start of `title`, lowercased with dashes: `Fix login redirect!`
+/// becomes `fix-login-redirect`. Empty when the title has no ASCII letters
+/// or digits.
+pub fn slug(title: &str) -> String {
+    let mut slug = String::new();
+    for word in title
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        if !slug.is_empty() && slug.len() + 1 + word.len() > MAX_SLUG {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word.to_ascii_lowercase());
+    }
+    slug.truncate(MAX_SLUG);
+    slug
+}
+
+/// A branch name for `ticket`: the tracker's suggestion, or else its key
+/// and the start of its title, like `proj-123-fix-login-redirect`.
+pub fn branch_name(ticket: &Ticket) -> String {
+    if let Some(branch) = &ticket.branch {
+        return branch.clone();
+    }
+    let key = ticket.key.to_ascii_lowercase();
+    let slug = slug(&ticket.title);
+    if slug.is_empty() {
+        key
+    } else {
+        format!("{key}-{slug}")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct JiraIssue {
+    key: String,
+    fields: JiraFields,
+}
+
+#[derive(Debug, Deserialize)]
+struct JiraFields {
+    summary: Option<String>,
+    status: Option<JiraStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JiraStatus {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JiraTransitions {
+    transitions: Vec<JiraTransition>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JiraTransition {
+    id: String,
+    name: String,
+    to: JiraStatus,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LinearIssue {
+    identifier: String,
+    title: String,
+    url: String,
+    branch_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LinearStates {
+    id: String,
+    state: LinearState,
+    team: LinearTeam,
+}
+
+#[derive(Debug, Deserialize)]
+struct LinearState {
+    #[serde(default)]
+    id: String,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct LinearTeam {
+    states: LinearNodes,
+}
+
+#[derive(Debug, Deserialize)]
+struct LinearNodes {
+    nodes: Vec<LinearState>,
+}
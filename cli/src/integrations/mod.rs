@@ -0,0 +1,7 @@
+//! Integrations with external tools whose state makes up a workspace.
+
+pub mod docker;
+pub mod git;
+pub mod github;
+pub mod issues;
+pub mod paste;
@@ -0,0 +1,134 @@
+//! Publishing text to GitHub gists and paste services.
+
+use std::collections::HashMap;
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// GitHub REST endpoint for creating gists.
+const GISTS_URL: &str = "https://api.github.com/gists";
+
+/// Errors raised while publishing a snapshot.
+#[derive(Debug, Error)]
+pub enum ShareError {
+    #[error("sharing to a gist needs a GitHub token; set GITHUB_TOKEN or GH_TOKEN")]
+    MissingToken,
+
+    #[error("no paste endpoint configured; set share.paste_url in config.toml")]
+    MissingPasteUrl,
+
+    #[error("{service} rejected the upload ({status}): {body}")]
+    Rejected {
+        service: &'static str,
+        status: reqwest::StatusCode,
+        body: String,
+    },
+
+    #[error("{0} did not return a URL")]
+    NoUrl(&'static str),
+
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+}
+
+#[derive(Serialize)]
+struct GistFile<'a> {
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct CreateGist<'a> {
+    description: &'a str,
+    public: bool,
+    files: HashMap<&'a str, GistFile<'a>>,
+}
+
+#[derive(Deserialize)]
+struct GistResponse {
+    html_url: String,
+}
+
+/// Reads a GitHub token from the environment.
+pub fn github_token_from_env() -> Option<String> {
+    ["GITHUB_TOKEN", "GH_TOKEN"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))
+}
+
+/// Builds the HTTP client used for uploads.
+pub fn http_client() -> Result<Client, ShareError> {
+    Ok(Client::builder()
+        .user_agent(concat!("desk-cli/", env!("CARGO_PKG_VERSION")))
+        .build()?)
+}
+
+/// Creates a gist containing `content` as `filename` and returns its URL.
+pub async fn create_gist(
+    client: &Client,
+    token: &str,
+    filename: &str,
+    description: &str,
+    content: &str,
+    public: bool,
+) -> Result<String, ShareError> {
+    let body = CreateGist {
+        description,
+        public,
+        files: HashMap::from([(filename, GistFile { content })]),
+    };
+
+    let response = client
+        .post(GISTS_URL)
+        .bearer_auth(token)
+        .header("Accept", "application/vnd.github+json")
+        .json(&body)
+        .send()
+        .await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(ShareError::Rejected {
+            service: "GitHub",
+            status,
+            body: response.text().await.unwrap_or_default(),
+        });
+    }
+    Ok(response.json::<GistResponse>().await?.html_url)
+}
+
+/// Posts `content` to a paste endpoint and returns the URL it answers with,
+/// taken from the `Location` header or the first line of the body.
+pub async fn post_paste(client: &Client, url: &str, content: &str) -> Result<String, ShareError> {
+    let response = client
+        .post(url)
+        .header("Content-Type", "text/markdown; charset=utf-8")
+        .body(content.to_string())
+        .send()
+        .await?;
+
+    let status = response.status();
+    let location = response
+        .headers()
+        .get(reqwest::header::LOCATION)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let body = response.text().await.unwrap_or_default();
+
+    if !status.is_success() {
+        return Err(ShareError::Rejected {
+            service: "paste service",
+            status,
+            body,
+        });
+    }
+
+    location
+        .or_else(|| {
+            body.lines()
+                .map(str::trim)
+                .find(|line| line.starts_with("http://") || line.starts_with("https://"))
+                .map(String::from)
+        })
+        .ok_or(ShareError::NoUrl("paste service"))
+}
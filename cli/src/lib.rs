@@ -0,0 +1,19 @@
+//! Desk CLI - Developer Context Switching Tool
+//!
+//! Library half of the `desk` binary. Command handlers live in [`cli`], the
+//! storage and configuration model in [`core`], external tools in
+//! [`integrations`], the desk API and signing in to it in [`client`] and
+//! [`auth`], the background process and how to reach it in [`daemon`], the
+//! Model Context Protocol server for coding assistants in [`mcp`],
+//! user-facing reporting in [`output`] so that other front-ends (daemon,
+//! TUI) can share it, and shared helpers in [`utils`].
+
+pub mod auth;
+pub mod cli;
+pub mod client;
+pub mod core;
+pub mod daemon;
+pub mod integrations;
+pub mod mcp;
+pub mod output;
+pub mod utils;
@@ -4,9 +4,42 @@
 //! restoring complete development contexts—git state, open files, running
 //! services, and more.
 
+use desk::cli::{self, error, Cli};
+use desk::core::logs;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
 fn main() {
-    println!("desk-cli v{}", env!("CARGO_PKG_VERSION"));
-    println!("Developer context switching tool");
-    println!();
-    println!("This is a placeholder. Features coming soon!");
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_filter(EnvFilter::from_default_env()),
+        )
+        .with(logs::layer())
+        .init();
+
+    let (cli, command) = match Cli::try_parse_named() {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            let _ = e.print();
+            // Not clap's 2, which desk uses for authentication errors.
+            std::process::exit(if e.use_stderr() {
+                error::USAGE_EXIT_CODE
+            } else {
+                0
+            });
+        },
+    };
+    let runtime = match cli.runtime() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Error: failed to start the async runtime: {e}");
+            std::process::exit(1);
+        },
+    };
+    if let Err(e) = runtime.block_on(cli::run(&cli, &command)) {
+        std::process::exit(error::report(&e, cli.errors));
+    }
 }
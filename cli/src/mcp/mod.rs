@@ -0,0 +1,174 @@
+//! A Model Context Protocol server, so coding assistants can use desk.
+//!
+//! The assistant starts `desk mcp` and talks JSON-RPC 2.0 with it over stdin
+//! and stdout, one message per line. This module speaks the protocol; the
+//! tools it offers come from a [`ToolProvider`], see
+//! [`crate::cli::commands::mcp`].
+
+use std::io::{BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use tracing::debug;
+
+/// Protocol revisions this server speaks, newest first.
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// JSON-RPC error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Errors that end the server.
+#[derive(Debug, Error)]
+pub enum McpError {
+    #[error("failed to talk to the MCP client")]
+    Io(#[from] std::io::Error),
+
+    #[error("failed to encode an MCP message")]
+    Encode(#[from] serde_json::Error),
+}
+
+/// A tool an assistant can call.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tool {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// JSON Schema of the arguments.
+    pub input_schema: Value,
+}
+
+/// What a tool call produced: text for the assistant, and whether the tool
+/// failed. Failures are reported to the assistant rather than as protocol
+/// errors, so it can see what went wrong.
+#[derive(Debug, Clone)]
+pub struct ToolOutput {
+    pub text: String,
+    pub is_error: bool,
+}
+
+impl ToolOutput {
+    pub fn ok(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            is_error: false,
+        }
+    }
+
+    pub fn error(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            is_error: true,
+        }
+    }
+}
+
+/// Supplies the tools the server offers.
+pub trait ToolProvider {
+    fn tools(&self) -> Vec<Tool>;
+
+    /// Runs tool `name`; `None` if there is no such tool.
+    fn call(&self, name: &str, arguments: &Value) -> Option<ToolOutput>;
+}
+
+/// A JSON-RPC request or notification; notifications have no `id`.
+#[derive(Debug, Deserialize)]
+struct Message {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct CallParams {
+    name: String,
+    #[serde(default)]
+    arguments: Value,
+}
+
+/// Answers requests from `input` on `output` until the client hangs up.
+pub fn serve(
+    input: impl BufRead,
+    mut output: impl Write,
+    provider: &dyn ToolProvider,
+) -> Result<(), McpError> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = respond(&line, provider) {
+            serde_json::to_writer(&mut output, &response)?;
+            output.write_all(b"\n")?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// The response to one line from the client; `None` for notifications.
+fn respond(line: &str, provider: &dyn ToolProvider) -> Option<Value> {
+    let message: Message = match serde_json::from_str::<Value>(line) {
+        Err(e) => return Some(error(&Value::Null, PARSE_ERROR, &e.to_string())),
+        Ok(value) => match serde_json::from_value(value) {
+            Ok(message) => message,
+            Err(e) => return Some(error(&Value::Null, INVALID_REQUEST, &e.to_string())),
+        },
+    };
+    debug!("MCP {}", message.method);
+    // Notifications such as `notifications/initialized` need no answer.
+    let id = message.id?;
+    let result = match message.method.as_str() {
+        "initialize" => Ok(initialize(&message.params)),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": provider.tools() })),
+        "tools/call" => call(&message.params, provider),
+        method => Err((METHOD_NOT_FOUND, format!("unknown method {method}"))),
+    };
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, text)) => error(&id, code, &text),
+    })
+}
+
+/// Agrees on the protocol revision the client asked for if this server
+/// speaks it, or else offers the newest one.
+fn initialize(params: &Value) -> Value {
+    let requested = params.get("protocolVersion").and_then(Value::as_str);
+    let version = requested
+        .filter(|v| PROTOCOL_VERSIONS.contains(v))
+        .unwrap_or(PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": {} },
+        "serverInfo": { "name": "desk", "version": env!("CARGO_PKG_VERSION") },
+        "instructions": "desk saves and restores development contexts (workspaces) \
+            per git repository: branch, commit, and uncommitted changes. Pass `repo` \
+            to work on a repository other than the one desk was started in.",
+    })
+}
+
+fn call(params: &Value, provider: &dyn ToolProvider) -> Result<Value, (i64, String)> {
+    let params: CallParams =
+        serde_json::from_value(params.clone()).map_err(|e| (INVALID_PARAMS, e.to_string()))?;
+    let output = provider
+        .call(&params.name, &params.arguments)
+        .ok_or_else(|| (INVALID_PARAMS, format!("unknown tool {}", params.name)))?;
+    Ok(json!({
+        "content": [{ "type": "text", "text": output.text }],
+        "isError": output.is_error,
+    }))
+}
+
+fn error(id: &Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
@@ -0,0 +1,81 @@
+//! Copying text to the clipboard, for `desk standup --copy`.
+//!
+//! Like notifications, copying goes through the tools each platform ships
+//! with: `pbcopy` on macOS, `clip` on Windows, and `wl-copy`, `xclip`, or
+//! `xsel` elsewhere, whichever is installed.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use thiserror::Error;
+
+/// Errors raised while copying to the clipboard.
+#[derive(Debug, Error)]
+pub enum ClipboardError {
+    #[error("no clipboard tool found; install {0}")]
+    NoTool(&'static str),
+
+    #[error("failed to run {tool}")]
+    Run {
+        tool: &'static str,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("{tool} failed ({status})")]
+    Failed {
+        tool: &'static str,
+        status: std::process::ExitStatus,
+    },
+}
+
+#[cfg(target_os = "macos")]
+const TOOLS: &[(&str, &[&str])] = &[("pbcopy", &[])];
+
+#[cfg(windows)]
+const TOOLS: &[(&str, &[&str])] = &[("clip", &[])];
+
+#[cfg(not(any(target_os = "macos", windows)))]
+const TOOLS: &[(&str, &[&str])] = &[
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+];
+
+/// What to install when none of [`TOOLS`] is.
+#[cfg(target_os = "macos")]
+const INSTALL_HINT: &str = "pbcopy";
+#[cfg(windows)]
+const INSTALL_HINT: &str = "clip";
+#[cfg(not(any(target_os = "macos", windows)))]
+const INSTALL_HINT: &str = "wl-clipboard, xclip, or xsel";
+
+/// Puts `text` on the clipboard with the first tool that is installed.
+pub fn copy(text: &str) -> Result<(), ClipboardError> {
+    for (tool, args) in TOOLS {
+        let spawned = Command::new(tool)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(source) => return Err(ClipboardError::Run { tool, source }),
+        };
+        let written = child
+            .stdin
+            .take()
+            .map_or(Ok(()), |mut stdin| stdin.write_all(text.as_bytes()));
+        let status = child
+            .wait()
+            .and_then(|status| written.map(|()| status))
+            .map_err(|source| ClipboardError::Run { tool, source })?;
+        if !status.success() {
+            return Err(ClipboardError::Failed { tool, status });
+        }
+        return Ok(());
+    }
+    Err(ClipboardError::NoTool(INSTALL_HINT))
+}
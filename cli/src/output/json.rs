@@ -0,0 +1,101 @@
+//! Machine-readable progress as JSON lines.
+
+use std::io::Write;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use super::{Reporter, StepEvent};
+
+/// A single line of `--progress json-lines` output.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum ProgressEvent<'a> {
+    Begin {
+        operation: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        total: Option<usize>,
+    },
+    Progress {
+        current: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        total: Option<usize>,
+        message: &'a str,
+    },
+    Step(&'a StepEvent),
+    Finish {
+        summary: &'a str,
+        failures: usize,
+    },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    event: ProgressEvent<'a>,
+}
+
+/// Writes every event as a JSON object on its own line.
+///
+/// Nothing else is printed, so consumers can parse the stream line by line.
+pub struct JsonLinesReporter {
+    out: Box<dyn Write + Send>,
+    failures: usize,
+}
+
+impl std::fmt::Debug for JsonLinesReporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonLinesReporter")
+            .field("failures", &self.failures)
+            .finish_non_exhaustive()
+    }
+}
+
+impl JsonLinesReporter {
+    pub fn new(out: Box<dyn Write + Send>) -> Self {
+        Self { out, failures: 0 }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(Box::new(std::io::stdout()))
+    }
+
+    fn emit(&mut self, event: ProgressEvent<'_>) {
+        let line = Line {
+            timestamp: Utc::now(),
+            event,
+        };
+        // Progress output is best-effort; a closed pipe must not abort the operation.
+        if let Ok(json) = serde_json::to_string(&line) {
+            let _ = writeln!(self.out, "{json}");
+            let _ = self.out.flush();
+        }
+    }
+}
+
+impl Reporter for JsonLinesReporter {
+    fn begin(&mut self, operation: &str, total: Option<usize>) {
+        self.emit(ProgressEvent::Begin { operation, total });
+    }
+
+    fn progress(&mut self, current: usize, total: Option<usize>, message: &str) {
+        self.emit(ProgressEvent::Progress {
+            current,
+            total,
+            message,
+        });
+    }
+
+    fn step(&mut self, event: StepEvent) {
+        if event.is_failure() {
+            self.failures += 1;
+        }
+        self.emit(ProgressEvent::Step(&event));
+    }
+
+    fn finish(&mut self, summary: &str) {
+        let failures = self.failures;
+        self.emit(ProgressEvent::Finish { summary, failures });
+    }
+}
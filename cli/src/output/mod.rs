@@ -0,0 +1,134 @@
+//! User-facing output for multi-step operations.
+//!
+//! Commands describe what they do as a stream of [`StepEvent`]s sent to a
+//! [`Reporter`]. The terminal reporter decides how much of that stream to show
+//! based on [`Verbosity`]; other front-ends can implement [`Reporter`] to render
+//! the same events their own way. With `--progress json-lines` the events are
+//! written to stdout as JSON, one per line, for wrappers that draw their own UI.
+
+pub mod clipboard;
+mod json;
+pub mod notify;
+mod reporter;
+pub mod style;
+pub mod table;
+pub mod timestamp;
+
+use serde::{Deserialize, Serialize};
+
+pub use json::JsonLinesReporter;
+pub use reporter::{Reporter, TerminalReporter};
+
+/// How progress of long operations is rendered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProgressFormat {
+    /// Human-readable output shaped by the verbosity setting.
+    #[default]
+    Human,
+    /// One JSON event per line on stdout.
+    JsonLines,
+}
+
+/// Creates the reporter for the selected format.
+pub fn reporter(format: ProgressFormat, verbosity: Verbosity) -> Box<dyn Reporter> {
+    match format {
+        ProgressFormat::Human => Box::new(TerminalReporter::new(verbosity)),
+        ProgressFormat::JsonLines => Box::new(JsonLinesReporter::stdout()),
+    }
+}
+
+/// How timestamps are printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampStyle {
+    /// Relative to now, like "2 hours ago".
+    #[default]
+    Relative,
+    /// RFC 3339 in UTC, like "2026-01-31T09:30:00Z", for scripts.
+    Absolute,
+}
+
+/// How chatty a command is while it runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    /// Print nothing when everything succeeds.
+    Quiet,
+    /// Print a single line once the operation finishes.
+    #[default]
+    Summary,
+    /// Print every git, hook, and service action as it happens.
+    Steps,
+}
+
+impl Verbosity {
+    /// Picks the effective verbosity: an explicit flag wins over the config.
+    pub fn resolve(flag: Option<Self>, quiet: bool, configured: Self) -> Self {
+        if quiet {
+            Self::Quiet
+        } else {
+            flag.unwrap_or(configured)
+        }
+    }
+}
+
+/// The subsystem a step belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepKind {
+    Git,
+    Hook,
+    Service,
+    Editor,
+    State,
+    Sync,
+    Context,
+}
+
+/// How a step ended.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", content = "reason", rename_all = "lowercase")]
+pub enum StepOutcome {
+    Done,
+    Skipped(String),
+    Failed(String),
+}
+
+/// A single action performed while capturing or restoring a workspace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepEvent {
+    pub kind: StepKind,
+    pub message: String,
+    #[serde(flatten)]
+    pub outcome: StepOutcome,
+}
+
+impl StepEvent {
+    pub fn done(kind: StepKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            outcome: StepOutcome::Done,
+        }
+    }
+
+    pub fn skipped(kind: StepKind, message: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            outcome: StepOutcome::Skipped(reason.into()),
+        }
+    }
+
+    pub fn failed(kind: StepKind, message: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            outcome: StepOutcome::Failed(reason.into()),
+        }
+    }
+
+    pub const fn is_failure(&self) -> bool {
+        matches!(self.outcome, StepOutcome::Failed(_))
+    }
+}
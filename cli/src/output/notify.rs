@@ -0,0 +1,97 @@
+//! Desktop notifications for long-running commands such as `desk sync watch`
+//! and slow hooks, as the `[notifications]` section allows.
+//!
+//! Notifications go through the tool each platform ships with
+//! (`notify-send`, `osascript`, PowerShell), so a missing tool only costs the
+//! notification, never the command.
+
+#[cfg(any(unix, windows))]
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use tracing::debug;
+
+use crate::core::config::NotificationsConfig;
+
+static SETTINGS: OnceLock<NotificationsConfig> = OnceLock::new();
+
+/// Applies the `[notifications]` settings. Called once, right after the
+/// configuration is loaded.
+pub fn configure(config: &NotificationsConfig) {
+    let _ = SETTINGS.set(config.clone());
+}
+
+/// The `[notifications]` settings in effect.
+pub fn settings() -> &'static NotificationsConfig {
+    SETTINGS.get_or_init(NotificationsConfig::default)
+}
+
+/// Shows a desktop notification unless they are turned off, ignoring
+/// failures.
+pub fn desktop(title: &str, body: &str) {
+    if !settings().enabled {
+        debug!("desktop notifications are off: {title}: {body}");
+        return;
+    }
+    #[cfg(any(unix, windows))]
+    spawn(command(title, body));
+    #[cfg(not(any(unix, windows)))]
+    debug!("no desktop notifications on this platform: {title}: {body}");
+}
+
+#[cfg(any(unix, windows))]
+fn spawn(mut command: Command) {
+    let spawned = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    match spawned {
+        // Reaped in the background so a slow notifier never holds up the
+        // command that sent the notification.
+        Ok(mut child) => {
+            std::thread::spawn(move || child.wait());
+        },
+        Err(e) => debug!("cannot show desktop notification: {e}"),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn command(title: &str, body: &str) -> Command {
+    let mut command = Command::new("osascript");
+    command.arg("-e").arg(format!(
+        "display notification {} with title {}",
+        applescript_string(body),
+        applescript_string(title)
+    ));
+    command
+}
+
+#[cfg(target_os = "macos")]
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(windows)]
+fn command(title: &str, body: &str) -> Command {
+    // A balloon tip from the notification area; needs no extra modules.
+    let script = "Add-Type -AssemblyName System.Windows.Forms; \
+        $n = New-Object System.Windows.Forms.NotifyIcon; \
+        $n.Icon = [System.Drawing.SystemIcons]::Information; \
+        $n.Visible = $true; \
+        $n.ShowBalloonTip(5000, $env:DESK_NOTIFY_TITLE, $env:DESK_NOTIFY_BODY, 'Info'); \
+        Start-Sleep -Seconds 5; $n.Dispose()";
+    let mut command = Command::new("powershell");
+    command
+        .args(["-NoProfile", "-NonInteractive", "-Command", script])
+        .env("DESK_NOTIFY_TITLE", title)
+        .env("DESK_NOTIFY_BODY", body);
+    command
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn command(title: &str, body: &str) -> Command {
+    let mut command = Command::new("notify-send");
+    command.args(["--app-name=desk", title, body]);
+    command
+}
@@ -0,0 +1,91 @@
+//! Sinks for [`StepEvent`]s.
+
+use super::{StepEvent, StepOutcome, Verbosity};
+
+/// Receives step events from a running operation.
+///
+/// Reporters are `Send` so async commands can hold one across `.await`s.
+pub trait Reporter: Send {
+    /// Called when a long operation starts, with the number of items it will
+    /// process when known.
+    fn begin(&mut self, _operation: &str, _total: Option<usize>) {}
+
+    /// Called as an operation advances through its items (e.g. workspace 3 of
+    /// 30 during a sync push, or each poll while waiting for a login).
+    fn progress(&mut self, _current: usize, _total: Option<usize>, _message: &str) {}
+
+    /// Called as each step completes.
+    fn step(&mut self, event: StepEvent);
+
+    /// Called once with a one-line description of the finished operation.
+    fn finish(&mut self, summary: &str);
+}
+
+/// Renders step events to the terminal according to a [`Verbosity`].
+///
+/// Failures are always written to stderr, even in quiet mode.
+#[derive(Debug)]
+pub struct TerminalReporter {
+    verbosity: Verbosity,
+    completed: usize,
+    failures: Vec<StepEvent>,
+}
+
+impl TerminalReporter {
+    pub const fn new(verbosity: Verbosity) -> Self {
+        Self {
+            verbosity,
+            completed: 0,
+            failures: Vec::new(),
+        }
+    }
+
+    pub const fn verbosity(&self) -> Verbosity {
+        self.verbosity
+    }
+
+    /// Whether any step failed so far.
+    pub fn has_failures(&self) -> bool {
+        !self.failures.is_empty()
+    }
+}
+
+impl Reporter for TerminalReporter {
+    fn step(&mut self, event: StepEvent) {
+        if event.is_failure() {
+            if let StepOutcome::Failed(reason) = &event.outcome {
+                eprintln!("  ✗ {}: {reason}", event.message);
+            }
+            self.failures.push(event);
+            return;
+        }
+
+        self.completed += 1;
+        if self.verbosity == Verbosity::Steps {
+            match &event.outcome {
+                StepOutcome::Skipped(reason) => println!("  - {} ({reason})", event.message),
+                _ => println!("  ✓ {}", event.message),
+            }
+        }
+    }
+
+    fn finish(&mut self, summary: &str) {
+        let failed = self.failures.len();
+        if failed > 0 {
+            eprintln!(
+                "{summary} ({failed} of {} steps failed)",
+                self.completed + failed
+            );
+            return;
+        }
+
+        match self.verbosity {
+            Verbosity::Quiet => {},
+            Verbosity::Summary => println!("{summary}"),
+            Verbosity::Steps => {
+                println!();
+                println!("{summary}");
+            },
+        }
+    }
+}
@@ -0,0 +1,35 @@
+//! Terminal styling helpers.
+
+use std::io::IsTerminal;
+use std::ops::Range;
+
+const HIGHLIGHT: &str = "\x1b[1;33m";
+const RESET: &str = "\x1b[0m";
+
+/// Whether stdout should receive ANSI colors (a terminal, and `NO_COLOR` unset).
+pub fn use_color() -> bool {
+    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+/// Wraps the sorted, non-overlapping byte `ranges` of `text` in highlight
+/// escapes. Returns `text` unchanged when `color` is off.
+pub fn highlight(text: &str, ranges: &[Range<usize>], color: bool) -> String {
+    if !color {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len() + ranges.len() * 8);
+    let mut pos = 0;
+    for range in ranges {
+        let (Some(before), Some(matched)) = (text.get(pos..range.start), text.get(range.clone()))
+        else {
+            continue;
+        };
+        out.push_str(before);
+        out.push_str(HIGHLIGHT);
+        out.push_str(matched);
+        out.push_str(RESET);
+        pos = range.end;
+    }
+    out.push_str(text.get(pos..).unwrap_or_default());
+    out
+}
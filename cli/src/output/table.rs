@@ -0,0 +1,97 @@
+//! Aligned columns for listings, fitted to the terminal's width.
+
+use std::io::IsTerminal;
+
+use terminal_size::{terminal_size, Width};
+
+/// Narrowest a column is truncated to when the table does not fit.
+const MIN_COLUMN_WIDTH: usize = 6;
+
+/// Space between columns.
+const GAP: &str = "  ";
+
+/// Rows of cells under a header, printed with every column aligned.
+#[derive(Debug, Clone)]
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&'static str]) -> Self {
+        Self {
+            headers: headers.to_vec(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Adds a row; missing cells are left empty.
+    pub fn row(&mut self, cells: Vec<String>) {
+        self.rows.push(cells);
+    }
+
+    /// The table as lines, with the widest columns truncated until it fits
+    /// in `max_width` characters if one is given. The last column is never
+    /// padded.
+    pub fn render(&self, max_width: Option<usize>) -> Vec<String> {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        if let Some(max_width) = max_width {
+            let gaps = GAP.len() * widths.len().saturating_sub(1);
+            while widths.iter().sum::<usize>() + gaps > max_width {
+                let Some(widest) = widths
+                    .iter_mut()
+                    .filter(|width| **width > MIN_COLUMN_WIDTH)
+                    .max_by_key(|width| **width)
+                else {
+                    break;
+                };
+                *widest -= 1;
+            }
+        }
+
+        let headers = self.headers.iter().map(|h| (*h).to_string()).collect();
+        std::iter::once(&headers)
+            .chain(&self.rows)
+            .map(|cells| {
+                let last = widths.len().saturating_sub(1);
+                let line: Vec<_> = widths
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &width)| {
+                        let cell = truncate(cells.get(i).map_or("", String::as_str), width);
+                        if i == last {
+                            cell
+                        } else {
+                            format!("{cell:<width$}")
+                        }
+                    })
+                    .collect();
+                line.join(GAP).trim_end().to_string()
+            })
+            .collect()
+    }
+}
+
+/// Width of the terminal stdout is printed to, or `None` when it is not
+/// one.
+pub fn terminal_width() -> Option<usize> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    terminal_size().map(|(Width(width), _)| usize::from(width))
+}
+
+/// `text` cut to `width` characters, ending in `…` if anything was cut.
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
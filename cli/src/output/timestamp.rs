@@ -0,0 +1,46 @@
+//! Timestamps as people read them ("2 hours ago") or as scripts do
+//! (RFC 3339), following `--timestamps` and `output.timestamps`.
+
+use std::sync::OnceLock;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use super::TimestampStyle;
+
+static STYLE: OnceLock<TimestampStyle> = OnceLock::new();
+
+/// Selects how [`timestamp`] formats. Called once, right after the
+/// configuration is loaded.
+pub fn configure(style: TimestampStyle) {
+    let _ = STYLE.set(style);
+}
+
+/// `at` in the configured style.
+pub fn timestamp(at: DateTime<Utc>) -> String {
+    match STYLE.get().copied().unwrap_or_default() {
+        TimestampStyle::Relative => relative(at, Utc::now()),
+        TimestampStyle::Absolute => at.to_rfc3339_opts(SecondsFormat::Secs, true),
+    }
+}
+
+/// `at` relative to `now`, e.g. `3 days ago` or `in 5 minutes`, in the
+/// largest whole unit.
+pub fn relative(at: DateTime<Utc>, now: DateTime<Utc>) -> String {
+    let seconds = (now - at).num_seconds();
+    let (future, seconds) = (seconds < 0, seconds.unsigned_abs());
+    let (count, unit) = match seconds {
+        0..=44 => return "just now".to_string(),
+        45..=3_599 => (seconds / 60, "minute"),
+        3_600..=86_399 => (seconds / 3_600, "hour"),
+        86_400..=2_591_999 => (seconds / 86_400, "day"),
+        2_592_000..=31_535_999 => (seconds / 2_592_000, "month"),
+        _ => (seconds / 31_536_000, "year"),
+    };
+    let count = count.max(1);
+    let plural = if count == 1 { "" } else { "s" };
+    if future {
+        format!("in {count} {unit}{plural}")
+    } else {
+        format!("{count} {unit}{plural} ago")
+    }
+}
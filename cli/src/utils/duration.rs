@@ -0,0 +1,52 @@
+//! Parsing and formatting of human-friendly durations such as `1h30m`.
+
+use std::time::Duration;
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("invalid duration '{0}': use a number followed by d, h, m, or s (e.g. 4h, 1h30m)")]
+pub struct ParseDurationError(String);
+
+/// Parses durations like `4h`, `90m`, `1h30m`, or `2d`.
+pub fn parse_duration(input: &str) -> Result<Duration, ParseDurationError> {
+    let err = || ParseDurationError(input.to_string());
+    let mut total: u64 = 0;
+    let mut digits = String::new();
+
+    for c in input.trim().chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let unit = match c {
+            'd' => 86_400,
+            'h' => 3_600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(err()),
+        };
+        let value: u64 = digits.parse().map_err(|_| err())?;
+        total = value
+            .checked_mul(unit)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(err)?;
+        digits.clear();
+    }
+
+    if !digits.is_empty() || total == 0 {
+        return Err(err());
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// Formats a duration as hours and minutes, e.g. `3h 05m` or `42m`.
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    let (hours, minutes) = (minutes / 60, minutes % 60);
+    if hours == 0 {
+        format!("{minutes}m")
+    } else {
+        format!("{hours}h {minutes:02}m")
+    }
+}
@@ -0,0 +1,63 @@
+//! Expansion of `~` and environment variables in user-supplied paths.
+
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ExpandError {
+    #[error("cannot expand '~' in '{0}': home directory unknown")]
+    NoHome(String),
+
+    #[error("environment variable ${var} in '{path}' is not set")]
+    UnsetVar { path: String, var: String },
+}
+
+/// Expands a leading `~` and `$VAR` / `${VAR}` references in `input`.
+pub fn expand_path(input: &str) -> Result<PathBuf, ExpandError> {
+    let mut expanded = String::with_capacity(input.len());
+    let mut rest = input;
+
+    if rest == "~" || rest.starts_with("~/") || rest.starts_with("~\\") {
+        let home = dirs::home_dir().ok_or_else(|| ExpandError::NoHome(input.to_string()))?;
+        expanded.push_str(&home.to_string_lossy());
+        rest = &rest[1..];
+    }
+
+    while let Some(pos) = rest.find('$') {
+        expanded.push_str(&rest[..pos]);
+        let (var, remainder) = split_var(&rest[pos + 1..]);
+
+        if var.is_empty() {
+            // A lone `$` is kept as written.
+            expanded.push('$');
+        } else {
+            let value = std::env::var(var).map_err(|_| ExpandError::UnsetVar {
+                path: input.to_string(),
+                var: var.to_string(),
+            })?;
+            expanded.push_str(&value);
+        }
+        rest = remainder;
+    }
+    expanded.push_str(rest);
+
+    Ok(PathBuf::from(expanded))
+}
+
+/// Splits the text after a `$` into the variable name and what follows it.
+fn split_var(after: &str) -> (&str, &str) {
+    after.strip_prefix('{').map_or_else(
+        || {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        },
+        |braced| {
+            braced
+                .find('}')
+                .map_or((braced, ""), |end| (&braced[..end], &braced[end + 1..]))
+        },
+    )
+}
@@ -0,0 +1,6 @@
+//! Small helpers shared across modules.
+
+pub mod duration;
+pub mod expand;
+pub mod platform;
+pub mod size;
@@ -0,0 +1,164 @@
+//! Filesystem capability detection.
+//!
+//! Repositories and data directories on NFS/SMB mounts behave differently from
+//! local disks: `flock`-style locks may be silently ignored, and git operations
+//! take far longer. The git and storage layers ask [`FsCapabilities`] how to
+//! behave for a given path instead of assuming a local disk, and `desk open`
+//! and `desk status` warn about network mounts.
+
+use std::path::Path;
+use std::time::Duration;
+
+use tracing::debug;
+
+/// Filesystem types that are backed by a remote server.
+const NETWORK_FS_TYPES: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb",
+    "smb2",
+    "smb3",
+    "smbfs",
+    "afpfs",
+    "webdav",
+    "davfs",
+    "fuse.sshfs",
+    "9p",
+    "afs",
+    "ceph",
+    "glusterfs",
+    "lustre",
+];
+
+/// Timeout for git operations on local disks.
+const LOCAL_GIT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Timeout for git operations on network mounts.
+const NETWORK_GIT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Broad classification of the filesystem a path lives on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilesystemKind {
+    Local,
+    /// A network mount, with the filesystem type when known (e.g. `nfs4`).
+    Network(String),
+    /// Detection is not supported on this platform or failed.
+    Unknown,
+}
+
+/// What desk can rely on for a particular path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsCapabilities {
+    pub kind: FilesystemKind,
+}
+
+impl FsCapabilities {
+    /// Inspects the filesystem holding `path`.
+    pub fn detect(path: &Path) -> Self {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let kind = detect_kind(&path);
+        debug!("{} is on a {kind:?} filesystem", path.display());
+        Self { kind }
+    }
+
+    pub const fn is_network(&self) -> bool {
+        matches!(self.kind, FilesystemKind::Network(_))
+    }
+
+    /// Whether OS advisory locks (`flock`/`LockFileEx`) can be trusted.
+    pub const fn reliable_locking(&self) -> bool {
+        !self.is_network()
+    }
+
+    /// How long a single git operation may run before it is considered hung.
+    pub const fn git_timeout(&self) -> Duration {
+        if self.is_network() {
+            NETWORK_GIT_TIMEOUT
+        } else {
+            LOCAL_GIT_TIMEOUT
+        }
+    }
+
+    /// A warning to print when running against this filesystem.
+    pub fn advice(&self) -> Option<String> {
+        match &self.kind {
+            FilesystemKind::Network(fs) => Some(format!(
+                "this repository is on a network filesystem ({fs}); switching rewrites many \
+                 files and may be slow"
+            )),
+            _ => None,
+        }
+    }
+}
+
+fn is_network_type(fs_type: &str) -> bool {
+    NETWORK_FS_TYPES.contains(&fs_type)
+}
+
+/// Finds the filesystem type of the longest mount point containing `path`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn longest_mount<'a>(
+    path: &Path,
+    mounts: impl Iterator<Item = (&'a str, &'a str)>,
+) -> Option<&'a str> {
+    mounts
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .map(|(_, fs_type)| fs_type)
+}
+
+fn classify(fs_type: Option<&str>) -> FilesystemKind {
+    match fs_type {
+        Some(t) if is_network_type(t) => FilesystemKind::Network(t.to_string()),
+        Some(_) => FilesystemKind::Local,
+        None => FilesystemKind::Unknown,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn detect_kind(path: &Path) -> FilesystemKind {
+    // mountinfo: `id parent major:minor root mount_point options ... - fstype source ...`
+    let Ok(mountinfo) = std::fs::read_to_string("/proc/self/mountinfo") else {
+        return FilesystemKind::Unknown;
+    };
+    let mounts = mountinfo.lines().filter_map(|line| {
+        let (left, right) = line.split_once(" - ")?;
+        let mount_point = left.split(' ').nth(4)?;
+        let fs_type = right.split(' ').next()?;
+        Some((mount_point, fs_type))
+    });
+    classify(longest_mount(path, mounts))
+}
+
+#[cfg(target_os = "macos")]
+fn detect_kind(path: &Path) -> FilesystemKind {
+    // `mount` prints `//user@host/share on /Volumes/share (smbfs, nodev, ...)`.
+    let Ok(output) = std::process::Command::new("mount").output() else {
+        return FilesystemKind::Unknown;
+    };
+    let listing = String::from_utf8_lossy(&output.stdout);
+    let mounts = listing.lines().filter_map(|line| {
+        let (_, rest) = line.split_once(" on ")?;
+        let (mount_point, options) = rest.rsplit_once(" (")?;
+        let fs_type = options.split([',', ')']).next()?;
+        Some((mount_point, fs_type))
+    });
+    classify(longest_mount(path, mounts))
+}
+
+#[cfg(windows)]
+fn detect_kind(path: &Path) -> FilesystemKind {
+    // Mapped drive letters need the Win32 API; UNC paths are unambiguous.
+    let raw = path.as_os_str().to_string_lossy();
+    if raw.starts_with(r"\\?\UNC\") || (raw.starts_with(r"\\") && !raw.starts_with(r"\\?\")) {
+        classify(Some("smb"))
+    } else {
+        FilesystemKind::Local
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn detect_kind(_path: &Path) -> FilesystemKind {
+    classify(None)
+}